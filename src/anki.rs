@@ -0,0 +1,1727 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    io::{self, Cursor, Read, Write},
+};
+
+use bytes::Bytes;
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use libsql::{Builder, Connection, Row, Transaction};
+use serde::Deserialize;
+use serde_json::json;
+use sha1::{Digest, Sha1};
+use tempfile::NamedTempFile;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    clock::{format_date, DATE_FORMAT},
+    domain::{
+        Flashcard, FlashcardImport, FlashcardInfo, FlashcardProgress, ReviewGrade, ReviewImport,
+        ReviewLog, SchedulerKind, StudyTopic, StudyTopicImport, StudyTopicSchedule, SubjectImport,
+    },
+    err::{StudyServiceError, StudyServiceResult},
+};
+
+/// Note type of exported cards. Its notes are grouped back into their topic
+/// when the package is imported again.
+const NOTETYPE_NAME: &str = "Study App";
+const NOTETYPE_FIELDS: [&str; 5] = ["Topic", "Description", "Front", "Back", "Hint"];
+/// The same for every export, so Anki recognizes the note type the second
+/// time a deck is imported.
+const NOTETYPE_ID: i64 = 1_735_689_600_000;
+
+/// Separates the fields of a note, and the levels of a deck name in newer
+/// collections.
+const FIELD_SEPARATOR: char = '\x1f';
+
+/// Topic names taken from a note's sort field are cut to this many
+/// characters.
+const MAX_TOPIC_NAME_CHARS: usize = 120;
+
+/// Collections in an `.apkg`, newest first. Newer Anki versions write a
+/// zstd compressed `collection.anki21b` next to a placeholder
+/// `collection.anki2` asking to update Anki.
+const COLLECTION_FILES: [&str; 3] = [
+    "collection.anki21b",
+    "collection.anki21",
+    "collection.anki2",
+];
+
+/// Largest collection a package may unpack to, well past any real deck but
+/// stopping a small package from filling the disk.
+const MAX_COLLECTION_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+
+/// Collection schema 11, which every Anki version still imports.
+const COLLECTION_SCHEMA: &str = "CREATE TABLE col (
+    id INTEGER PRIMARY KEY, crt INTEGER NOT NULL, mod INTEGER NOT NULL, scm INTEGER NOT NULL,
+    ver INTEGER NOT NULL, dty INTEGER NOT NULL, usn INTEGER NOT NULL, ls INTEGER NOT NULL,
+    conf TEXT NOT NULL, models TEXT NOT NULL, decks TEXT NOT NULL, dconf TEXT NOT NULL,
+    tags TEXT NOT NULL
+);
+CREATE TABLE notes (
+    id INTEGER PRIMARY KEY, guid TEXT NOT NULL, mid INTEGER NOT NULL, mod INTEGER NOT NULL,
+    usn INTEGER NOT NULL, tags TEXT NOT NULL, flds TEXT NOT NULL, sfld INTEGER NOT NULL,
+    csum INTEGER NOT NULL, flags INTEGER NOT NULL, data TEXT NOT NULL
+);
+CREATE TABLE cards (
+    id INTEGER PRIMARY KEY, nid INTEGER NOT NULL, did INTEGER NOT NULL, ord INTEGER NOT NULL,
+    mod INTEGER NOT NULL, usn INTEGER NOT NULL, type INTEGER NOT NULL, queue INTEGER NOT NULL,
+    due INTEGER NOT NULL, ivl INTEGER NOT NULL, factor INTEGER NOT NULL, reps INTEGER NOT NULL,
+    lapses INTEGER NOT NULL, left INTEGER NOT NULL, odue INTEGER NOT NULL, odid INTEGER NOT NULL,
+    flags INTEGER NOT NULL, data TEXT NOT NULL
+);
+CREATE TABLE revlog (
+    id INTEGER PRIMARY KEY, cid INTEGER NOT NULL, usn INTEGER NOT NULL, ease INTEGER NOT NULL,
+    ivl INTEGER NOT NULL, lastIvl INTEGER NOT NULL, factor INTEGER NOT NULL, time INTEGER NOT NULL,
+    type INTEGER NOT NULL
+);
+CREATE TABLE graves (usn INTEGER NOT NULL, oid INTEGER NOT NULL, type INTEGER NOT NULL);
+CREATE INDEX ix_notes_usn ON notes (usn);
+CREATE INDEX ix_cards_usn ON cards (usn);
+CREATE INDEX ix_revlog_usn ON revlog (usn);
+CREATE INDEX ix_cards_nid ON cards (nid);
+CREATE INDEX ix_cards_sched ON cards (did, queue, due);
+CREATE INDEX ix_revlog_cid ON revlog (cid);
+CREATE INDEX ix_notes_csum ON notes (csum);";
+
+/// Subjects held in an Anki package (`.apkg`): every deck becomes a
+/// subject, its notes topics and their cards the topics' cards, with the
+/// cards' review state and history. Dates are days in `time_zone`.
+pub async fn read_package(package: Bytes, time_zone: Tz) -> StudyServiceResult<Vec<SubjectImport>> {
+    let collection =
+        tokio::task::spawn_blocking(move || extract_collection(&package, MAX_COLLECTION_SIZE))
+            .await
+            .map_err(|err| StudyServiceError::Internal(format!("Reading Anki package: {err}")))??;
+
+    let db = Builder::new_local(collection.path())
+        .build()
+        .await
+        .map_err(invalid_package)?;
+    let conn = db.connect().map_err(invalid_package)?;
+
+    read_collection(&conn, time_zone).await
+}
+
+/// Subject to export, with the progress and review history of the user
+/// exporting it.
+pub struct DeckExport {
+    pub subject_name: String,
+    pub study_topics: Vec<StudyTopic>,
+    pub flashcards: Vec<Flashcard>,
+    pub flashcard_progress: Vec<FlashcardProgress>,
+    pub review_logs: Vec<ReviewLog>,
+}
+
+/// Anki package (`.apkg`) holding the subject as a deck of the same name.
+/// Every card becomes a note that also carries its topic, topics without
+/// cards become a note of their own. Cards keep their review state and
+/// history, reviews a topic had before it got cards are left out.
+pub async fn write_package(
+    deck: DeckExport,
+    now: DateTime<Utc>,
+    time_zone: Tz,
+) -> StudyServiceResult<Vec<u8>> {
+    let collection = NamedTempFile::new().map_err(write_error)?;
+
+    let db = Builder::new_local(collection.path())
+        .build()
+        .await
+        .map_err(write_error)?;
+    let conn = db.connect().map_err(write_error)?;
+    write_collection(&conn, deck, now, time_zone).await?;
+    drop(conn);
+    drop(db);
+
+    tokio::task::spawn_blocking(move || zip_collection(&collection))
+        .await
+        .map_err(write_error)?
+}
+
+/// Unpacks the newest collection of the package into a temporary file, so
+/// it can be opened as a database. Collections unpacking to more than
+/// `max_size` bytes are rejected.
+fn extract_collection(package: &[u8], max_size: u64) -> StudyServiceResult<NamedTempFile> {
+    let mut archive = ZipArchive::new(Cursor::new(package)).map_err(invalid_package)?;
+    let Some(name) = COLLECTION_FILES
+        .into_iter()
+        .find(|name| archive.index_for_name(name).is_some())
+    else {
+        return Err(invalid_package("it holds no Anki collection"));
+    };
+
+    let file = archive.by_name(name).map_err(invalid_package)?;
+    let collection: Box<dyn Read> = if name.ends_with('b') {
+        Box::new(zstd::stream::read::Decoder::new(file).map_err(invalid_package)?)
+    } else {
+        Box::new(file)
+    };
+
+    let mut collection_file = NamedTempFile::new()
+        .map_err(|err| StudyServiceError::Internal(format!("Reading Anki package: {err}")))?;
+    // One byte past the limit tells a collection at the limit from a larger
+    // one.
+    let size = io::copy(&mut collection.take(max_size + 1), &mut collection_file)
+        .map_err(invalid_package)?;
+    if size > max_size {
+        return Err(invalid_package(format!(
+            "its collection is larger than {max_size} bytes"
+        )));
+    }
+
+    Ok(collection_file)
+}
+
+struct Notetype {
+    name: String,
+    cloze: bool,
+    fields: Vec<String>,
+    /// Question and answer format of each card ordinal.
+    templates: Vec<(String, String)>,
+}
+
+impl Notetype {
+    /// Whether the notes were exported from here, see [`write_package`].
+    fn is_exported(&self) -> bool {
+        self.name == NOTETYPE_NAME && self.fields == NOTETYPE_FIELDS
+    }
+}
+
+struct Note {
+    id: i64,
+    notetype_id: i64,
+    fields: Vec<String>,
+    sort_field: String,
+}
+
+impl Note {
+    fn field(&self, notetype: &Notetype, name: &str) -> String {
+        notetype
+            .fields
+            .iter()
+            .position(|field| field == name)
+            .and_then(|index| self.fields.get(index))
+            .map(|value| html_to_markdown(value))
+            .unwrap_or_default()
+    }
+}
+
+struct Card {
+    id: i64,
+    note_id: i64,
+    deck_id: i64,
+    ord: usize,
+    card_type: i64,
+    due: i64,
+    interval: i64,
+    factor: i64,
+    data: String,
+}
+
+/// FSRS memory state Anki keeps in `cards.data`.
+#[derive(Deserialize)]
+struct MemoryState {
+    s: Option<f64>,
+    d: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct LegacyDeck {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct LegacyNotetype {
+    name: String,
+    #[serde(rename = "type", default)]
+    kind: i64,
+    flds: Vec<LegacyField>,
+    tmpls: Vec<LegacyTemplate>,
+}
+
+#[derive(Deserialize)]
+struct LegacyField {
+    name: String,
+    ord: i64,
+}
+
+#[derive(Deserialize)]
+struct LegacyTemplate {
+    qfmt: String,
+    afmt: String,
+    ord: i64,
+}
+
+/// Topic the cards of a note go to: their own, or the one an exported note
+/// names.
+#[derive(PartialEq, Eq, Hash)]
+enum TopicKey {
+    Note(i64),
+    Exported(String),
+}
+
+async fn read_collection(
+    conn: &Connection,
+    time_zone: Tz,
+) -> StudyServiceResult<Vec<SubjectImport>> {
+    let col = query(conn, "SELECT ver, crt, decks, models FROM col", |row| {
+        Ok((
+            row.get::<i64>(0)?,
+            row.get::<i64>(1)?,
+            row.get::<String>(2)?,
+            row.get::<String>(3)?,
+        ))
+    })
+    .await?;
+    let Some((version, created_at, decks, models)) = col.into_iter().next() else {
+        return Err(invalid_package("the collection is empty"));
+    };
+    // Days of review cards count from the day the collection was created.
+    let created_on = local_time(created_at * 1000, time_zone)?.date_naive();
+
+    // Schema 15 moved decks and note types out of the col row.
+    let (decks, notetypes) = if version >= 15 {
+        (read_decks(conn).await?, read_notetypes(conn).await?)
+    } else {
+        (legacy_decks(&decks)?, legacy_notetypes(&models)?)
+    };
+
+    let notes: HashMap<i64, Note> = query(
+        conn,
+        "SELECT id, mid, flds, CAST(sfld AS TEXT) FROM notes",
+        |row| {
+            Ok(Note {
+                id: row.get(0)?,
+                notetype_id: row.get(1)?,
+                fields: row
+                    .get::<String>(2)?
+                    .split(FIELD_SEPARATOR)
+                    .map(str::to_string)
+                    .collect(),
+                sort_field: row.get(3)?,
+            })
+        },
+    )
+    .await?
+    .into_iter()
+    .map(|note| (note.id, note))
+    .collect();
+
+    // Manual reschedules have no grade and are left out.
+    let mut reviews: HashMap<i64, Vec<ReviewImport>> = HashMap::new();
+    for (id, card_id, ease, time) in query(
+        conn,
+        "SELECT id, cid, ease, time FROM revlog WHERE ease BETWEEN 1 AND 4 AND type BETWEEN 0 AND 3 ORDER BY id",
+        |row| {
+            Ok((
+                row.get::<i64>(0)?,
+                row.get::<i64>(1)?,
+                row.get::<i64>(2)?,
+                row.get::<i64>(3)?,
+            ))
+        },
+    )
+    .await?
+    {
+        let reviewed_at = local_time(id, time_zone)?;
+        let grade = match ease {
+            1 => ReviewGrade::Again,
+            2 => ReviewGrade::Hard,
+            3 => ReviewGrade::Good,
+            _ => ReviewGrade::Easy,
+        };
+
+        reviews.entry(card_id).or_default().push(ReviewImport {
+            review_date: format_date(reviewed_at.date_naive()),
+            completed_at: reviewed_at.with_timezone(&Utc).to_rfc3339(),
+            grade,
+            time_spent_seconds: u32::try_from(time / 1000)
+                .ok()
+                .filter(|seconds| *seconds > 0),
+        });
+    }
+
+    // Cards in a filtered deck are read as if they were back home.
+    let cards = query(
+        conn,
+        "SELECT id, nid, CASE WHEN odid != 0 THEN odid ELSE did END, ord, type, CASE WHEN odid != 0 AND odue != 0 THEN odue ELSE due END, ivl, factor, data FROM cards ORDER BY nid, ord",
+        |row| {
+            Ok(Card {
+                id: row.get(0)?,
+                note_id: row.get(1)?,
+                deck_id: row.get(2)?,
+                ord: row.get::<u32>(3)? as usize,
+                card_type: row.get(4)?,
+                due: row.get(5)?,
+                interval: row.get(6)?,
+                factor: row.get(7)?,
+                data: row.get(8)?,
+            })
+        },
+    )
+    .await?;
+
+    let mut subjects: Vec<SubjectImport> = Vec::new();
+    let mut study_topics: HashMap<(usize, TopicKey), usize> = HashMap::new();
+
+    for card in cards {
+        let Some(note) = notes.get(&card.note_id) else {
+            continue;
+        };
+        let Some(notetype) = notetypes.get(&note.notetype_id) else {
+            continue;
+        };
+
+        let subject_name = decks
+            .get(&card.deck_id)
+            .cloned()
+            .unwrap_or_else(|| format!("Deck {}", card.deck_id));
+        let subject = match subjects
+            .iter()
+            .position(|subject| subject.subject_name == subject_name)
+        {
+            Some(subject) => subject,
+            None => {
+                subjects.push(SubjectImport {
+                    subject_name,
+                    scheduler: SchedulerKind::Sm2,
+                    study_topics: Vec::new(),
+                });
+                subjects.len() - 1
+            }
+        };
+
+        let (key, name, description) = if notetype.is_exported() {
+            let name = note.field(notetype, "Topic");
+            let description = Some(note.field(notetype, "Description"))
+                .filter(|description| !description.is_empty());
+            (TopicKey::Exported(name.clone()), name, description)
+        } else {
+            (TopicKey::Note(note.id), topic_name(note), None)
+        };
+        let study_topic_index = *study_topics.entry((subject, key)).or_insert_with(|| {
+            subjects[subject].study_topics.push(StudyTopicImport {
+                name,
+                description,
+                creation_date: String::new(),
+                flashcards: Vec::new(),
+            });
+            subjects[subject].study_topics.len() - 1
+        });
+        let study_topic = &mut subjects[subject].study_topics[study_topic_index];
+        if study_topic.creation_date.is_empty() {
+            study_topic.creation_date = format_date(local_time(note.id, time_zone)?.date_naive());
+        }
+
+        let Some(flashcard) = render_card(notetype, note, card.ord) else {
+            continue;
+        };
+        let reviews = reviews.remove(&card.id).unwrap_or_default();
+
+        study_topic.flashcards.push(FlashcardImport {
+            flashcard,
+            created_at: local_time(card.id, time_zone)?
+                .with_timezone(&Utc)
+                .to_rfc3339(),
+            schedule: card_schedule(&card, &reviews, created_on, time_zone)?,
+            reviews,
+        });
+    }
+
+    // Decks studied with FSRS carry the memory state of their cards.
+    for subject in &mut subjects {
+        if subject
+            .study_topics
+            .iter()
+            .flat_map(|study_topic| &study_topic.flashcards)
+            .any(|flashcard| {
+                flashcard
+                    .schedule
+                    .as_ref()
+                    .is_some_and(|schedule| schedule.stability.is_some())
+            })
+        {
+            subject.scheduler = SchedulerKind::Fsrs;
+        }
+    }
+
+    Ok(subjects)
+}
+
+async fn read_decks(conn: &Connection) -> StudyServiceResult<HashMap<i64, String>> {
+    let decks = query(conn, "SELECT id, name FROM decks", |row| {
+        Ok((
+            row.get::<i64>(0)?,
+            row.get::<String>(1)?.replace(FIELD_SEPARATOR, "::"),
+        ))
+    })
+    .await?;
+
+    Ok(decks.into_iter().collect())
+}
+
+/// Note types of a schema 15 or later collection, whose settings are
+/// protobuf messages.
+async fn read_notetypes(conn: &Connection) -> StudyServiceResult<HashMap<i64, Notetype>> {
+    let mut notetypes: HashMap<i64, Notetype> =
+        query(conn, "SELECT id, name, config FROM notetypes", |row| {
+            let config: Vec<u8> = row.get(2)?;
+            Ok((
+                row.get::<i64>(0)?,
+                Notetype {
+                    name: row.get(1)?,
+                    cloze: protobuf_varint(&config, 1) == Some(1),
+                    fields: Vec::new(),
+                    templates: Vec::new(),
+                },
+            ))
+        })
+        .await?
+        .into_iter()
+        .collect();
+
+    let fields = query(
+        conn,
+        "SELECT ntid, name FROM fields ORDER BY ntid, ord",
+        |row| Ok((row.get::<i64>(0)?, row.get::<String>(1)?)),
+    )
+    .await?;
+    for (notetype_id, name) in fields {
+        if let Some(notetype) = notetypes.get_mut(&notetype_id) {
+            notetype.fields.push(name);
+        }
+    }
+
+    let templates = query(
+        conn,
+        "SELECT ntid, config FROM templates ORDER BY ntid, ord",
+        |row| Ok((row.get::<i64>(0)?, row.get::<Vec<u8>>(1)?)),
+    )
+    .await?;
+    for (notetype_id, config) in templates {
+        if let Some(notetype) = notetypes.get_mut(&notetype_id) {
+            notetype.templates.push((
+                protobuf_string(&config, 1).unwrap_or_default(),
+                protobuf_string(&config, 2).unwrap_or_default(),
+            ));
+        }
+    }
+
+    Ok(notetypes)
+}
+
+fn legacy_decks(decks: &str) -> StudyServiceResult<HashMap<i64, String>> {
+    let decks: HashMap<String, LegacyDeck> =
+        serde_json::from_str(decks).map_err(invalid_package)?;
+
+    Ok(decks
+        .into_iter()
+        .filter_map(|(id, deck)| Some((id.parse().ok()?, deck.name)))
+        .collect())
+}
+
+fn legacy_notetypes(models: &str) -> StudyServiceResult<HashMap<i64, Notetype>> {
+    let models: HashMap<String, LegacyNotetype> =
+        serde_json::from_str(models).map_err(invalid_package)?;
+
+    Ok(models
+        .into_iter()
+        .filter_map(|(id, mut model)| {
+            model.flds.sort_by_key(|field| field.ord);
+            model.tmpls.sort_by_key(|template| template.ord);
+
+            Some((
+                id.parse().ok()?,
+                Notetype {
+                    name: model.name,
+                    cloze: model.kind == 1,
+                    fields: model.flds.into_iter().map(|field| field.name).collect(),
+                    templates: model
+                        .tmpls
+                        .into_iter()
+                        .map(|template| (template.qfmt, template.afmt))
+                        .collect(),
+                },
+            ))
+        })
+        .collect())
+}
+
+/// First line of the note's sort field with cloze deletions filled in, or
+/// the note's id when that is empty.
+fn topic_name(note: &Note) -> String {
+    let sort_field = html_to_markdown(&render_cloze(&note.sort_field, None));
+    let Some(line) = sort_field
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+    else {
+        return format!("Note {}", note.id);
+    };
+
+    if line.chars().count() > MAX_TOPIC_NAME_CHARS {
+        let cut: String = line.chars().take(MAX_TOPIC_NAME_CHARS - 1).collect();
+        format!("{}…", cut.trim_end())
+    } else {
+        line.to_string()
+    }
+}
+
+/// Front and back of a card as Anki would show them, without the question
+/// repeated on the back. `None` for a card whose front is empty.
+fn render_card(notetype: &Notetype, note: &Note, ord: usize) -> Option<FlashcardInfo> {
+    if notetype.is_exported() {
+        let flashcard = FlashcardInfo {
+            front: note.field(notetype, "Front"),
+            back: note.field(notetype, "Back"),
+            hint: Some(note.field(notetype, "Hint")),
+        }
+        .normalized();
+
+        return (!flashcard.front.is_empty()).then_some(flashcard);
+    }
+
+    let fields: HashMap<&str, &str> = notetype
+        .fields
+        .iter()
+        .map(String::as_str)
+        .zip(note.fields.iter().map(String::as_str))
+        .collect();
+
+    // Cloze notes have a single template, the ordinal picks the deletion.
+    let (template, cloze) = if notetype.cloze {
+        (notetype.templates.first()?, Some(ord as u32 + 1))
+    } else {
+        (notetype.templates.get(ord)?, None)
+    };
+
+    let front = html_to_markdown(&render_template(
+        &template.0,
+        &fields,
+        cloze.map(|number| (number, true)),
+    ));
+    let back = html_to_markdown(&render_template(
+        &template.1,
+        &fields,
+        cloze.map(|number| (number, false)),
+    ));
+
+    let flashcard = FlashcardInfo {
+        front,
+        back,
+        hint: None,
+    }
+    .normalized();
+
+    (!flashcard.front.is_empty()).then_some(flashcard)
+}
+
+/// Fills in an Anki card template: `{{Field}}` with the `cloze` and `type`
+/// filters, other filters show the plain field, and `{{#Field}}` and
+/// `{{^Field}}` sections. `{{FrontSide}}` is left empty. `cloze` is the
+/// number of the card's deletion and whether the question is rendered.
+fn render_template(
+    template: &str,
+    fields: &HashMap<&str, &str>,
+    cloze: Option<(u32, bool)>,
+) -> String {
+    let mut rendered = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let tag = rest[start + 2..start + end].trim();
+        rest = &rest[start + end + 2..];
+
+        if let Some(name) = tag.strip_prefix('#').or_else(|| tag.strip_prefix('^')) {
+            let closing = format!("{{{{/{name}}}}}");
+            let (section, after) = match rest.find(&closing) {
+                Some(section_end) => (&rest[..section_end], &rest[section_end + closing.len()..]),
+                None => (rest, ""),
+            };
+            let filled = fields
+                .get(name.trim())
+                .is_some_and(|value| !html_to_markdown(value).is_empty());
+            if filled == tag.starts_with('#') {
+                rendered.push_str(&render_template(section, fields, cloze));
+            }
+            rest = after;
+            continue;
+        }
+        if tag.starts_with('/') || tag == "FrontSide" {
+            continue;
+        }
+
+        let mut filters: Vec<&str> = tag.split(':').collect();
+        let name = filters.pop().unwrap_or_default().trim();
+        let value = fields.get(name).copied().unwrap_or_default();
+        if filters.contains(&"type") {
+            continue;
+        }
+        if filters.contains(&"cloze") {
+            rendered.push_str(&render_cloze(value, cloze));
+        } else {
+            rendered.push_str(value);
+        }
+    }
+    rendered.push_str(rest);
+
+    rendered
+}
+
+/// Cloze deletions `{{c1::answer::hint}}` of a field: the card's own one
+/// hidden as `[...]`, or `[hint]`, on the question, all shown otherwise.
+fn render_cloze(text: &str, cloze: Option<(u32, bool)>) -> String {
+    let mut rendered = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{c") {
+        rendered.push_str(&rest[..start]);
+        let deletion = &rest[start + 3..];
+        let digits = deletion.chars().take_while(char::is_ascii_digit).count();
+        let (Ok(number), Some(content)) = (
+            deletion[..digits].parse::<u32>(),
+            deletion[digits..].strip_prefix("::"),
+        ) else {
+            rendered.push_str("{{c");
+            rest = deletion;
+            continue;
+        };
+        let Some(end) = content.find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+
+        let (answer, hint) = match content[..end].split_once("::") {
+            Some((answer, hint)) => (answer, Some(hint)),
+            None => (&content[..end], None),
+        };
+        match cloze {
+            Some((current, true)) if current == number => {
+                rendered.push('[');
+                rendered.push_str(hint.unwrap_or("..."));
+                rendered.push(']');
+            }
+            _ => rendered.push_str(answer),
+        }
+        rest = &content[end + 2..];
+    }
+    rendered.push_str(rest);
+
+    rendered
+}
+
+/// Markdown of an Anki field or rendered card: line breaks and blocks
+/// become new lines, bold and italics are kept, images become image links
+/// and every other tag is dropped.
+fn html_to_markdown(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find(['<', '&']) {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with('&') {
+            let entity = rest
+                .char_indices()
+                .take(12)
+                .find(|(_, c)| *c == ';')
+                .and_then(|(end, _)| Some((end, decode_entity(&rest[1..end])?)));
+            match entity {
+                Some((end, decoded)) => {
+                    text.push(decoded);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    text.push('&');
+                    rest = &rest[1..];
+                }
+            }
+            continue;
+        }
+
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match name.as_str() {
+            "br" => text.push('\n'),
+            // Blocks start on a line of their own, without adding an empty
+            // one between consecutive blocks.
+            "div" | "p" | "hr" | "tr" | "ul" | "ol"
+                if !text.is_empty() && !text.ends_with('\n') =>
+            {
+                text.push('\n');
+            }
+            "li" if !closing => text.push_str("\n- "),
+            "b" | "strong" => text.push_str("**"),
+            "i" | "em" => text.push('*'),
+            "img" => {
+                if let Some(source) = attribute(tag, "src") {
+                    text.push_str(&format!("![]({source})"));
+                }
+            }
+            "style" | "script" if !closing => {
+                let closing_tag = format!("</{name}");
+                rest = match rest.to_ascii_lowercase().find(&closing_tag) {
+                    Some(content_end) => &rest[content_end..],
+                    None => "",
+                };
+            }
+            _ => {}
+        }
+    }
+    text.push_str(rest);
+
+    let mut markdown = String::new();
+    let mut blank_line = false;
+    for line in text.lines().map(str::trim_end) {
+        if line.is_empty() {
+            if blank_line {
+                continue;
+            }
+            blank_line = true;
+        } else {
+            blank_line = false;
+        }
+        markdown.push_str(line);
+        markdown.push('\n');
+    }
+
+    markdown.trim().to_string()
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "nbsp" => Some(' '),
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => {
+            let code = entity.strip_prefix('#')?;
+            let code = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let start = tag.find(&format!("{name}="))? + name.len() + 1;
+    let value = &tag[start..];
+    match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next(),
+        _ => value.split(|c: char| c.is_whitespace()).next(),
+    }
+}
+
+/// Review state of a card that has left the new queue. Learning cards are
+/// due at a timestamp, review cards on a day counted from the collection's
+/// creation.
+fn card_schedule(
+    card: &Card,
+    reviews: &[ReviewImport],
+    created_on: NaiveDate,
+    time_zone: Tz,
+) -> StudyServiceResult<Option<StudyTopicSchedule>> {
+    if card.card_type == 0 {
+        return Ok(None);
+    }
+
+    let next_due_date = if card.due > 1_000_000_000 {
+        local_time(card.due * 1000, time_zone)?.date_naive()
+    } else {
+        created_on
+            .checked_add_signed(Duration::days(card.due))
+            .ok_or_else(|| invalid_package(format!("card {} is due out of range", card.id)))?
+    };
+    let memory_state: Option<MemoryState> = serde_json::from_str(&card.data).ok();
+
+    Ok(Some(StudyTopicSchedule {
+        ease_factor: if card.factor > 0 {
+            card.factor as f64 / 1000.0
+        } else {
+            2.5
+        },
+        interval_days: card.interval.max(0),
+        repetitions: reviews
+            .iter()
+            .rev()
+            .take_while(|review| review.grade != ReviewGrade::Again)
+            .count() as i64,
+        stability: memory_state.as_ref().and_then(|state| state.s),
+        difficulty: memory_state.as_ref().and_then(|state| state.d),
+        next_due_date: Some(format_date(next_due_date)),
+        last_review_date: reviews.last().map(|review| review.review_date.clone()),
+    }))
+}
+
+/// Value of a varint field of a protobuf message.
+fn protobuf_varint(message: &[u8], number: u64) -> Option<u64> {
+    protobuf_fields(message)
+        .into_iter()
+        .find_map(|(field, value)| match value {
+            ProtobufValue::Varint(value) if field == number => Some(value),
+            _ => None,
+        })
+}
+
+/// Value of a string field of a protobuf message.
+fn protobuf_string(message: &[u8], number: u64) -> Option<String> {
+    protobuf_fields(message)
+        .into_iter()
+        .find_map(|(field, value)| match value {
+            ProtobufValue::Bytes(bytes) if field == number => {
+                String::from_utf8(bytes.to_vec()).ok()
+            }
+            _ => None,
+        })
+}
+
+enum ProtobufValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+/// Top level fields of a protobuf message by number, as far as they can be
+/// read. Fixed size fields are skipped.
+fn protobuf_fields(mut message: &[u8]) -> Vec<(u64, ProtobufValue<'_>)> {
+    let mut fields = Vec::new();
+
+    while let Some(key) = read_varint(&mut message) {
+        let value = match key & 7 {
+            0 => match read_varint(&mut message) {
+                Some(value) => ProtobufValue::Varint(value),
+                None => break,
+            },
+            2 => {
+                let Some(length) = read_varint(&mut message) else {
+                    break;
+                };
+                let Some((value, rest)) = usize::try_from(length)
+                    .ok()
+                    .filter(|length| *length <= message.len())
+                    .map(|length| message.split_at(length))
+                else {
+                    break;
+                };
+                message = rest;
+                ProtobufValue::Bytes(value)
+            }
+            wire_type @ (1 | 5) => {
+                let size = if wire_type == 1 { 8 } else { 4 };
+                if size > message.len() {
+                    break;
+                }
+                message = &message[size..];
+                continue;
+            }
+            _ => break,
+        };
+        fields.push((key >> 3, value));
+    }
+
+    fields
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+
+    None
+}
+
+/// Review state of an exported card, taken from the card's progress or,
+/// for a topic without cards, from the topic's.
+#[derive(Default)]
+struct CardState {
+    completed_sessions: i64,
+    ease_factor: f64,
+    interval_days: i64,
+    next_due_date: Option<String>,
+    stability: Option<f64>,
+    difficulty: Option<f64>,
+}
+
+impl From<&FlashcardProgress> for CardState {
+    fn from(progress: &FlashcardProgress) -> Self {
+        CardState {
+            completed_sessions: progress.completed_sessions,
+            ease_factor: progress.ease_factor,
+            interval_days: progress.interval_days,
+            next_due_date: progress.next_due_date.clone(),
+            stability: progress.stability,
+            difficulty: progress.difficulty,
+        }
+    }
+}
+
+impl From<&StudyTopic> for CardState {
+    fn from(study_topic: &StudyTopic) -> Self {
+        CardState {
+            completed_sessions: study_topic.completed_sessions,
+            ease_factor: study_topic.ease_factor,
+            interval_days: study_topic.interval_days,
+            next_due_date: study_topic.next_due_date.clone(),
+            stability: study_topic.stability,
+            difficulty: study_topic.difficulty,
+        }
+    }
+}
+
+/// Adds the notes of one deck to a schema 11 collection.
+struct CollectionWriter {
+    tx: Transaction,
+    deck_id: i64,
+    created_on: NaiveDate,
+    today: NaiveDate,
+    modified: i64,
+    next_id: i64,
+    new_position: i64,
+    revlog_ids: HashSet<i64>,
+    time_zone: Tz,
+}
+
+impl CollectionWriter {
+    /// Adds a note of the exported note type with its single card.
+    /// `guid` stays the same across exports, so Anki updates the notes it
+    /// already has instead of adding them again.
+    async fn add_note(
+        &mut self,
+        guid: String,
+        fields: [&str; 5],
+        state: CardState,
+        reviews: &[&ReviewLog],
+    ) -> StudyServiceResult<()> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let [topic, _, front, ..] = fields;
+        let sort_field = if front.is_empty() { topic } else { front };
+        let flds = fields
+            .iter()
+            .map(|field| markdown_to_html(field))
+            .collect::<Vec<_>>()
+            .join(&FIELD_SEPARATOR.to_string());
+        self.tx
+            .execute(
+                "INSERT INTO notes (id, guid, mid, mod, usn, tags, flds, sfld, csum, flags, data) VALUES (?1, ?2, ?3, ?4, -1, '', ?5, ?6, ?7, 0, '')",
+                libsql::params![
+                    id,
+                    guid,
+                    NOTETYPE_ID,
+                    self.modified,
+                    flds,
+                    sort_field,
+                    checksum(topic)
+                ],
+            )
+            .await
+            .map_err(write_error)?;
+
+        let reviewed = state.completed_sessions > 0 || state.next_due_date.is_some();
+        let (card_type, due, interval, factor) = if reviewed {
+            let due_date = state
+                .next_due_date
+                .as_deref()
+                .and_then(|date| NaiveDate::parse_from_str(date, DATE_FORMAT).ok())
+                .unwrap_or(self.today);
+            (
+                2,
+                (due_date - self.created_on).num_days(),
+                state.interval_days.max(1),
+                (state.ease_factor * 1000.0).round() as i64,
+            )
+        } else {
+            self.new_position += 1;
+            (0, self.new_position, 0, 0)
+        };
+        let lapses = reviews
+            .iter()
+            .filter(|review| review.grade == Some(ReviewGrade::Again))
+            .count() as i64;
+        let data = match state.stability {
+            Some(stability) => json!({ "s": stability, "d": state.difficulty }).to_string(),
+            None => String::new(),
+        };
+        self.tx
+            .execute(
+                "INSERT INTO cards (id, nid, did, ord, mod, usn, type, queue, due, ivl, factor, reps, lapses, left, odue, odid, flags, data) VALUES (?1, ?1, ?2, 0, ?3, -1, ?4, ?4, ?5, ?6, ?7, ?8, ?9, 0, 0, 0, 0, ?10)",
+                libsql::params![
+                    id,
+                    self.deck_id,
+                    self.modified,
+                    card_type,
+                    due,
+                    interval,
+                    factor,
+                    state.completed_sessions,
+                    lapses,
+                    data
+                ],
+            )
+            .await
+            .map_err(write_error)?;
+
+        // The id of a review is its timestamp in milliseconds, nudged when
+        // two reviews share one.
+        for (index, review) in reviews.iter().enumerate() {
+            let Some(grade) = review.grade else {
+                continue;
+            };
+            let mut revlog_id = self.reviewed_at(review);
+            while !self.revlog_ids.insert(revlog_id) {
+                revlog_id += 1;
+            }
+            let ease = match grade {
+                ReviewGrade::Again => 1,
+                ReviewGrade::Hard => 2,
+                ReviewGrade::Good => 3,
+                ReviewGrade::Easy => 4,
+            };
+
+            self.tx
+                .execute(
+                    "INSERT INTO revlog (id, cid, usn, ease, ivl, lastIvl, factor, time, type) VALUES (?1, ?2, -1, ?3, 0, 0, 0, ?4, ?5)",
+                    libsql::params![
+                        revlog_id,
+                        id,
+                        ease,
+                        i64::from(review.time_spent_seconds.unwrap_or_default()) * 1000,
+                        if index == 0 { 0 } else { 1 }
+                    ],
+                )
+                .await
+                .map_err(write_error)?;
+        }
+
+        Ok(())
+    }
+
+    /// Completion time of the review in milliseconds, noon of its day when
+    /// it was not recorded.
+    fn reviewed_at(&self, review: &ReviewLog) -> i64 {
+        review
+            .completed_at
+            .as_deref()
+            .and_then(|completed_at| DateTime::parse_from_rfc3339(completed_at).ok())
+            .map(|completed_at| completed_at.timestamp_millis())
+            .or_else(|| {
+                let date = NaiveDate::parse_from_str(&review.review_date, DATE_FORMAT).ok()?;
+                let noon = date.and_time(NaiveTime::from_hms_opt(12, 0, 0)?);
+                Some(
+                    self.time_zone
+                        .from_local_datetime(&noon)
+                        .earliest()?
+                        .timestamp_millis(),
+                )
+            })
+            .unwrap_or(self.modified * 1000)
+    }
+}
+
+async fn write_collection(
+    conn: &Connection,
+    deck: DeckExport,
+    now: DateTime<Utc>,
+    time_zone: Tz,
+) -> StudyServiceResult<()> {
+    conn.execute_batch(COLLECTION_SCHEMA)
+        .await
+        .map_err(write_error)?;
+
+    // Review cards are due a number of days after the collection was
+    // created, so it is created on the earliest due date. Anki's day starts
+    // at 4 am.
+    let today = now.with_timezone(&time_zone).date_naive();
+    let created_on = deck
+        .flashcard_progress
+        .iter()
+        .filter_map(|progress| progress.next_due_date.as_deref())
+        .chain(
+            deck.study_topics
+                .iter()
+                .filter_map(|study_topic| study_topic.next_due_date.as_deref()),
+        )
+        .filter_map(|date| NaiveDate::parse_from_str(date, DATE_FORMAT).ok())
+        .fold(today, NaiveDate::min);
+    let created_at = created_on
+        .and_time(NaiveTime::from_hms_opt(4, 0, 0).unwrap_or_default())
+        .and_local_timezone(time_zone)
+        .earliest()
+        .map_or(now.timestamp(), |created_at| created_at.timestamp());
+
+    let deck_id = now.timestamp_millis();
+    let modified = now.timestamp();
+    conn.execute(
+        "INSERT INTO col (id, crt, mod, scm, ver, dty, usn, ls, conf, models, decks, dconf, tags) VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')",
+        libsql::params![
+            created_at,
+            now.timestamp_millis(),
+            collection_conf(deck_id).to_string(),
+            notetype_json(deck_id, modified).to_string(),
+            decks_json(deck_id, &deck.subject_name, modified).to_string(),
+            deck_config_json().to_string()
+        ],
+    )
+    .await
+    .map_err(write_error)?;
+
+    let tx = conn.transaction().await.map_err(write_error)?;
+    let mut writer = CollectionWriter {
+        tx,
+        deck_id,
+        created_on,
+        today,
+        modified,
+        next_id: deck_id,
+        new_position: 0,
+        revlog_ids: HashSet::new(),
+        time_zone,
+    };
+    let flashcard_progress: HashMap<i64, &FlashcardProgress> = deck
+        .flashcard_progress
+        .iter()
+        .map(|progress| (progress.flashcard_id, progress))
+        .collect();
+
+    for study_topic in &deck.study_topics {
+        let description = study_topic.description.as_deref().unwrap_or_default();
+        let flashcards: Vec<&Flashcard> = deck
+            .flashcards
+            .iter()
+            .filter(|flashcard| flashcard.study_topic_id == study_topic.id)
+            .collect();
+
+        if flashcards.is_empty() {
+            let reviews: Vec<&ReviewLog> = deck
+                .review_logs
+                .iter()
+                .filter(|review| {
+                    review.study_topic_id == study_topic.id && review.flashcard_id.is_none()
+                })
+                .collect();
+            writer
+                .add_note(
+                    format!("study-topic-{}", study_topic.id),
+                    [&study_topic.name, description, "", "", ""],
+                    CardState::from(study_topic),
+                    &reviews,
+                )
+                .await?;
+        }
+
+        for flashcard in flashcards {
+            let reviews: Vec<&ReviewLog> = deck
+                .review_logs
+                .iter()
+                .filter(|review| review.flashcard_id == Some(flashcard.id))
+                .collect();
+            writer
+                .add_note(
+                    format!("flashcard-{}", flashcard.id),
+                    [
+                        &study_topic.name,
+                        description,
+                        &flashcard.front,
+                        &flashcard.back,
+                        flashcard.hint.as_deref().unwrap_or_default(),
+                    ],
+                    flashcard_progress
+                        .get(&flashcard.id)
+                        .map(|progress| CardState::from(*progress))
+                        .unwrap_or_default(),
+                    &reviews,
+                )
+                .await?;
+        }
+    }
+
+    writer.tx.commit().await.map_err(write_error)?;
+
+    Ok(())
+}
+
+fn collection_conf(deck_id: i64) -> serde_json::Value {
+    json!({
+        "activeDecks": [deck_id],
+        "curDeck": deck_id,
+        "newSpread": 0,
+        "collapseTime": 1200,
+        "timeLim": 0,
+        "estTimes": true,
+        "dueCounts": true,
+        "curModel": NOTETYPE_ID,
+        "nextPos": 1,
+        "sortType": "noteFld",
+        "sortBackwards": false,
+        "addToCur": true
+    })
+}
+
+fn notetype_json(deck_id: i64, modified: i64) -> serde_json::Value {
+    let fields: Vec<serde_json::Value> = NOTETYPE_FIELDS
+        .iter()
+        .enumerate()
+        .map(|(ord, name)| {
+            json!({
+                "name": name,
+                "ord": ord,
+                "sticky": false,
+                "rtl": false,
+                "font": "Arial",
+                "size": 20,
+                "media": []
+            })
+        })
+        .collect();
+
+    json!({
+        NOTETYPE_ID.to_string(): {
+            "id": NOTETYPE_ID,
+            "name": NOTETYPE_NAME,
+            "type": 0,
+            "mod": modified,
+            "usn": -1,
+            "sortf": 2,
+            "did": deck_id,
+            "tmpls": [{
+                "name": "Card",
+                "ord": 0,
+                "qfmt": "<div class=\"topic\">{{Topic}}</div>\n{{#Front}}{{Front}}{{/Front}}\n{{#Hint}}<div class=\"hint\">{{hint:Hint}}</div>{{/Hint}}",
+                "afmt": "{{FrontSide}}\n\n<hr id=answer>\n\n{{#Front}}{{Back}}{{/Front}}{{^Front}}{{Description}}{{/Front}}",
+                "bqfmt": "",
+                "bafmt": "",
+                "did": null
+            }],
+            "flds": fields,
+            "css": ".card { font-family: arial; font-size: 20px; text-align: center; color: black; background-color: white; }\n.topic { font-size: 14px; color: grey; }",
+            "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+            "latexPost": "\\end{document}",
+            "latexsvg": false,
+            "req": [[0, "any", [0]]],
+            "tags": [],
+            "vers": []
+        }
+    })
+}
+
+fn decks_json(deck_id: i64, name: &str, modified: i64) -> serde_json::Value {
+    let deck = |id: i64, name: &str| {
+        json!({
+            "id": id,
+            "name": name,
+            "mod": modified,
+            "usn": -1,
+            "lrnToday": [0, 0],
+            "revToday": [0, 0],
+            "newToday": [0, 0],
+            "timeToday": [0, 0],
+            "collapsed": false,
+            "browserCollapsed": false,
+            "desc": "",
+            "dyn": 0,
+            "conf": 1,
+            "extendNew": 0,
+            "extendRev": 0
+        })
+    };
+
+    json!({
+        "1": deck(1, "Default"),
+        deck_id.to_string(): deck(deck_id, name)
+    })
+}
+
+fn deck_config_json() -> serde_json::Value {
+    json!({
+        "1": {
+            "id": 1,
+            "name": "Default",
+            "mod": 0,
+            "usn": 0,
+            "maxTaken": 60,
+            "autoplay": true,
+            "timer": 0,
+            "replayq": true,
+            "dyn": false,
+            "new": {
+                "bury": true,
+                "delays": [1.0, 10.0],
+                "initialFactor": 2500,
+                "ints": [1, 4, 7],
+                "order": 1,
+                "perDay": 20,
+                "separate": true
+            },
+            "rev": {
+                "bury": true,
+                "ease4": 1.3,
+                "fuzz": 0.05,
+                "ivlFct": 1.0,
+                "maxIvl": 36500,
+                "minSpace": 1,
+                "perDay": 200
+            },
+            "lapse": {
+                "delays": [10.0],
+                "leechAction": 1,
+                "leechFails": 8,
+                "minInt": 1,
+                "mult": 0.0
+            }
+        }
+    })
+}
+
+/// Field content for Anki, which shows fields as HTML.
+fn markdown_to_html(markdown: &str) -> String {
+    markdown
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\n', "<br>")
+}
+
+/// Anki's duplicate check: the first 8 hex digits of the SHA-1 of the
+/// first field.
+fn checksum(field: &str) -> i64 {
+    let digest = Sha1::digest(field.as_bytes());
+
+    i64::from(u32::from_be_bytes([
+        digest[0], digest[1], digest[2], digest[3],
+    ]))
+}
+
+fn zip_collection(collection: &NamedTempFile) -> StudyServiceResult<Vec<u8>> {
+    let collection = std::fs::read(collection.path()).map_err(write_error)?;
+
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut package = ZipWriter::new(Cursor::new(Vec::new()));
+    package
+        .start_file("collection.anki2", options)
+        .map_err(write_error)?;
+    package.write_all(&collection).map_err(write_error)?;
+    // No media is exported.
+    package.start_file("media", options).map_err(write_error)?;
+    package.write_all(b"{}").map_err(write_error)?;
+
+    Ok(package.finish().map_err(write_error)?.into_inner())
+}
+
+/// Rows of a query on the collection, each mapped while it is current.
+async fn query<T>(
+    conn: &Connection,
+    sql: &str,
+    mut map: impl FnMut(&Row) -> libsql::Result<T>,
+) -> StudyServiceResult<Vec<T>> {
+    let mut rows = conn.query(sql, ()).await.map_err(invalid_package)?;
+
+    let mut result = Vec::new();
+    while let Some(row) = rows.next().await.map_err(invalid_package)? {
+        result.push(map(&row).map_err(invalid_package)?);
+    }
+
+    Ok(result)
+}
+
+/// Anki ids and review log entries are timestamps in milliseconds.
+fn local_time(millis: i64, time_zone: Tz) -> StudyServiceResult<DateTime<Tz>> {
+    Utc.timestamp_millis_opt(millis)
+        .single()
+        .map(|time| time.with_timezone(&time_zone))
+        .ok_or_else(|| invalid_package(format!("timestamp {millis} is out of range")))
+}
+
+fn invalid_package(err: impl Display) -> StudyServiceError {
+    StudyServiceError::InvalidRequest(format!("Invalid Anki package: {err}"))
+}
+
+fn write_error(err: impl Display) -> StudyServiceError {
+    StudyServiceError::Internal(format!("Writing Anki package: {err}"))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Write};
+
+    use bytes::Bytes;
+    use chrono::{TimeZone, Utc};
+    use chrono_tz::Tz;
+    use libsql::Builder;
+    use serde_json::json;
+    use tempfile::NamedTempFile;
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use crate::{
+        anki::{
+            extract_collection, html_to_markdown, protobuf_string, protobuf_varint, read_package,
+            render_template, zip_collection, COLLECTION_SCHEMA,
+        },
+        domain::{ReviewGrade, SchedulerKind},
+        err::StudyServiceError,
+    };
+
+    fn millis(day: u32, hour: u32) -> i64 {
+        Utc.with_ymd_and_hms(2025, 1, day, hour, 0, 0)
+            .unwrap()
+            .timestamp_millis()
+    }
+
+    /// Package of a collection as older Anki versions write it, with a
+    /// reversed and a cloze note type.
+    async fn legacy_package() -> Vec<u8> {
+        let collection = NamedTempFile::new().unwrap();
+        let db = Builder::new_local(collection.path()).build().await.unwrap();
+        let conn = db.connect().unwrap();
+        conn.execute_batch(COLLECTION_SCHEMA).await.unwrap();
+
+        let decks = json!({
+            "1": { "name": "Default" },
+            "10": { "name": "Languages::Spanish" },
+            "99": { "name": "Filtered" }
+        });
+        let models = json!({
+            "20": {
+                "name": "Basic (and reversed card)",
+                "type": 0,
+                "flds": [{ "name": "Back", "ord": 1 }, { "name": "Front", "ord": 0 }],
+                "tmpls": [
+                    { "qfmt": "{{Back}}", "afmt": "{{FrontSide}}<hr id=answer>{{Front}}", "ord": 1 },
+                    { "qfmt": "{{Front}}", "afmt": "{{FrontSide}}<hr id=answer>{{Back}}", "ord": 0 }
+                ]
+            },
+            "30": {
+                "name": "Cloze",
+                "type": 1,
+                "flds": [{ "name": "Text", "ord": 0 }, { "name": "Back Extra", "ord": 1 }],
+                "tmpls": [{ "qfmt": "{{cloze:Text}}", "afmt": "{{cloze:Text}}<br>{{Back Extra}}", "ord": 0 }]
+            }
+        });
+        conn.execute(
+            "INSERT INTO col VALUES (1, ?1, 0, 0, 11, 0, 0, 0, '{}', ?2, ?3, '{}', '{}')",
+            libsql::params![millis(1, 4) / 1000, models.to_string(), decks.to_string()],
+        )
+        .await
+        .unwrap();
+
+        let cloze = "{{c1::Madrid}} is the capital of {{c2::Spain::country}}";
+        for (id, notetype_id, fields, sort_field) in [
+            (millis(1, 10), 20, "<b>perro</b>\x1fdog", "perro"),
+            (millis(1, 11), 30, &format!("{cloze}\x1f"), cloze),
+        ] {
+            conn.execute(
+                "INSERT INTO notes VALUES (?1, 'guid', ?2, 0, 0, '', ?3, ?4, 0, 0, '')",
+                libsql::params![id, notetype_id, fields, sort_field],
+            )
+            .await
+            .unwrap();
+        }
+
+        // A learned card, a new one, a new cloze and one in a filtered deck.
+        for (id, note_id, deck_id, ord, card_type, due, odue, odid, data) in [
+            (
+                1,
+                millis(1, 10),
+                10,
+                0,
+                2,
+                10,
+                0,
+                0,
+                r#"{"s":12.5,"d":4.2}"#,
+            ),
+            (2, millis(1, 10), 10, 1, 0, 1, 0, 0, ""),
+            (3, millis(1, 11), 10, 0, 0, 2, 0, 0, ""),
+            (4, millis(1, 11), 99, 1, 2, 0, 3, 10, ""),
+        ] {
+            conn.execute(
+                "INSERT INTO cards VALUES (?1, ?2, ?3, ?4, 0, 0, ?5, ?5, ?6, 5, 2300, 3, 1, 0, ?7, ?8, 0, ?9)",
+                libsql::params![id, note_id, deck_id, ord, card_type, due, odue, odid, data],
+            )
+            .await
+            .unwrap();
+        }
+
+        // The last entry is a manual reschedule.
+        for (id, ease, time, review_type) in [
+            (millis(2, 10), 3, 8000, 0),
+            (millis(3, 10), 1, 4000, 1),
+            (millis(4, 10), 4, 0, 1),
+            (millis(5, 10), 0, 0, 4),
+        ] {
+            conn.execute(
+                "INSERT INTO revlog VALUES (?1, 1, 0, ?2, 0, 0, 0, ?3, ?4)",
+                libsql::params![id, ease, time, review_type],
+            )
+            .await
+            .unwrap();
+        }
+        drop(conn);
+        drop(db);
+
+        zip_collection(&collection).unwrap()
+    }
+
+    #[tokio::test]
+    async fn reads_legacy_collections() {
+        let subjects = read_package(legacy_package().await.into(), Tz::UTC)
+            .await
+            .unwrap();
+
+        // The filtered deck holds no cards of its own.
+        assert_eq!(subjects.len(), 1);
+        let subject = &subjects[0];
+        assert_eq!(subject.subject_name, "Languages::Spanish");
+        assert_eq!(subject.scheduler, SchedulerKind::Fsrs);
+        assert_eq!(subject.study_topics.len(), 2);
+
+        let words = &subject.study_topics[0];
+        assert_eq!(words.name, "perro");
+        assert_eq!(words.creation_date, "2025-01-01");
+        assert_eq!(words.flashcards.len(), 2);
+        assert_eq!(words.flashcards[0].flashcard.front, "**perro**");
+        assert_eq!(words.flashcards[0].flashcard.back, "dog");
+        assert_eq!(words.flashcards[1].flashcard.front, "dog");
+        assert_eq!(words.flashcards[1].flashcard.back, "**perro**");
+        assert!(words.flashcards[1].schedule.is_none());
+
+        let learned = &words.flashcards[0];
+        let grades: Vec<ReviewGrade> = learned.reviews.iter().map(|review| review.grade).collect();
+        assert_eq!(
+            grades,
+            vec![ReviewGrade::Good, ReviewGrade::Again, ReviewGrade::Easy]
+        );
+        assert_eq!(learned.reviews[0].review_date, "2025-01-02");
+        assert_eq!(learned.reviews[0].time_spent_seconds, Some(8));
+        assert_eq!(learned.reviews[2].time_spent_seconds, None);
+        let schedule = learned.schedule.as_ref().unwrap();
+        assert_eq!(schedule.next_due_date.as_deref(), Some("2025-01-11"));
+        assert_eq!(schedule.ease_factor, 2.3);
+        assert_eq!(schedule.interval_days, 5);
+        assert_eq!(schedule.repetitions, 1);
+        assert_eq!(schedule.stability, Some(12.5));
+        assert_eq!(schedule.difficulty, Some(4.2));
+        assert_eq!(schedule.last_review_date.as_deref(), Some("2025-01-04"));
+
+        let capitals = &subject.study_topics[1];
+        assert_eq!(capitals.name, "Madrid is the capital of Spain");
+        assert_eq!(
+            capitals.flashcards[0].flashcard.front,
+            "[...] is the capital of Spain"
+        );
+        assert_eq!(
+            capitals.flashcards[0].flashcard.back,
+            "Madrid is the capital of Spain"
+        );
+        assert_eq!(
+            capitals.flashcards[1].flashcard.front,
+            "Madrid is the capital of [country]"
+        );
+        assert_eq!(
+            capitals.flashcards[1]
+                .schedule
+                .as_ref()
+                .unwrap()
+                .next_due_date
+                .as_deref(),
+            Some("2025-01-04")
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_other_files() {
+        assert!(read_package(Bytes::from_static(b"not a zip"), Tz::UTC)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn limits_unpacked_collections() {
+        let mut package = ZipWriter::new(Cursor::new(Vec::new()));
+        package
+            .start_file("collection.anki21b", SimpleFileOptions::default())
+            .unwrap();
+        package
+            .write_all(&zstd::encode_all(&[0; 4096][..], 0).unwrap())
+            .unwrap();
+        let package = package.finish().unwrap().into_inner();
+
+        let collection = extract_collection(&package, 4096).unwrap();
+        assert_eq!(collection.as_file().metadata().unwrap().len(), 4096);
+        assert!(matches!(
+            extract_collection(&package, 4095),
+            Err(StudyServiceError::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn renders_templates_as_markdown() {
+        let fields = [
+            ("Word", "<div>uno&nbsp;&amp; dos</div>"),
+            ("Hint", ""),
+            ("Image", "<img src=\"one.png\">"),
+        ]
+        .into_iter()
+        .collect();
+
+        let rendered = render_template(
+            "{{Word}}{{#Hint}}Hint: {{Hint}}{{/Hint}}{{^Hint}}<br><i>No hint</i>{{/Hint}}{{type:Word}}<br><br><br>{{Image}}<style>.card {}</style>",
+            &fields,
+            None,
+        );
+
+        assert_eq!(
+            html_to_markdown(&rendered),
+            "uno & dos\n\n*No hint*\n\n![](one.png)"
+        );
+        assert_eq!(
+            html_to_markdown("<ul><li>one</li><li>two</li></ul>&#x263A;&bogus;"),
+            "- one\n- two\n☺&bogus;"
+        );
+        assert_eq!(
+            html_to_markdown("one<div>two</div><div>three</div>"),
+            "one\ntwo\nthree"
+        );
+    }
+
+    #[test]
+    fn reads_protobuf_fields() {
+        // kind 1, a fixed64 field, then two strings.
+        let mut message = vec![0x08, 0x01, 0x11, 0, 0, 0, 0, 0, 0, 0, 0];
+        message.extend([0x0a, 0x05]);
+        message.extend(b"{{Q}}");
+        message.extend([0x12, 0x03]);
+        message.extend(b"{{A");
+
+        assert_eq!(protobuf_varint(&message, 1), Some(1));
+        assert_eq!(protobuf_string(&message, 1).as_deref(), Some("{{Q}}"));
+        assert_eq!(protobuf_string(&message, 2).as_deref(), Some("{{A"));
+        assert_eq!(protobuf_string(&message, 3), None);
+    }
+}
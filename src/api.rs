@@ -1,33 +1,100 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    routing::{delete, get, post},
+    body::Bytes,
+    extract::{
+        rejection::{JsonRejection, QueryRejection},
+        DefaultBodyLimit, FromRequestParts, Path, Query, State,
+    },
+    http::{
+        header::{HeaderName, AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE},
+        request::Parts,
+        StatusCode,
+    },
+    middleware,
+    routing::{delete, get, post, put},
     Json, Router,
 };
+use chrono_tz::Tz;
 use tower_http::cors::CorsLayer;
-use tracing::{error, info};
+use tracing::info;
 
 use crate::{
-    domain::{StudyTopic, StudyTopicInfo, Subject},
-    study_service::{StudyService, StudySessionResponse},
+    auth_service::{AuthService, Authentication, Credential},
+    domain::{
+        ApiKey, ApiKeyInfo, ApiKeyScope, CreatedApiKey, Credentials, Flashcard, FlashcardInfo,
+        FlashcardUpdate, ImportedSubject, IntervalSchedule, IntervalScheduleAssignment,
+        IntervalScheduleInfo, RefreshRequest, ReviewLog, SearchQuery, SearchResult,
+        StudySessionCompletion, StudySessionQuery, StudyTopic, StudyTopicInfo, StudyTopicQuery,
+        StudyTopicUpdate, Subject, SubjectInvitation, SubjectMember, SubjectMemberInfo,
+        SubjectRename, SubjectSchedulerSettings, TimeZoneUpdate, TokenPair, User,
+    },
+    err::{StudyServiceError, StudyServiceResult},
+    pagination::Page,
+    study_service::{StudyService, StudySessionResponse, StudyTopicDetail, SubjectDetail},
 };
 
+/// Largest Anki package that can be imported. Collections with years of
+/// reviews run to tens of megabytes.
+const ANKI_PACKAGE_LIMIT: usize = 200 * 1024 * 1024;
+
 #[derive(Clone)]
 struct ApiState {
     study_service: StudyService,
+    auth_service: AuthService,
 }
 
-pub async fn start_api(study_service: StudyService, port: String) {
-    let state = ApiState { study_service };
+pub async fn start_api(study_service: StudyService, auth_service: AuthService, port: String) {
+    let state = ApiState {
+        study_service,
+        auth_service,
+    };
 
     let cors = CorsLayer::very_permissive();
 
-    let app = Router::new()
-        .route("/", get(health_check))
+    // Everything but the health check and the routes handing out tokens
+    // needs a valid access token.
+    let authenticated = Router::new()
+        .route("/auth/me", get(get_current_user))
+        .route("/auth/me/time_zone", put(set_time_zone))
+        .route("/api_keys", get(get_api_keys))
+        .route("/api_key", post(create_api_key))
+        .route("/api_key/{api_key_id}", delete(revoke_api_key))
         .route("/study_topics", get(get_study_topics))
         .route("/study_topic", post(add_study_topic))
-        .route("/study_topic/{study_topic_id}", delete(delete_study_topic))
+        .route(
+            "/study_topic/{study_topic_id}",
+            get(get_study_topic)
+                .patch(update_study_topic)
+                .delete(delete_study_topic),
+        )
+        .route(
+            "/study_topic/{study_topic_id}/history",
+            get(get_study_topic_history),
+        )
+        .route(
+            "/study_topic/{study_topic_id}/flashcards",
+            get(get_flashcards),
+        )
+        .route(
+            "/study_topic/{study_topic_id}/flashcard",
+            post(add_flashcard),
+        )
+        .route(
+            "/flashcard/{flashcard_id}",
+            get(get_flashcard)
+                .patch(update_flashcard)
+                .delete(delete_flashcard),
+        )
+        .route("/due_cards", get(get_due_flashcards))
+        .route(
+            "/import/anki",
+            post(import_anki_package).layer(DefaultBodyLimit::max(ANKI_PACKAGE_LIMIT)),
+        )
+        .route(
+            "/subject/{subject_name}/export/anki",
+            get(export_anki_package),
+        )
         .route("/study_topics_today", get(get_study_topics_today))
+        .route("/search", get(search_study_topics))
         .route("/subjects", get(get_subjects))
         .route(
             "/study_topic/subject/{subject_name}",
@@ -37,12 +104,68 @@ pub async fn start_api(study_service: StudyService, port: String) {
             "/study_session/{subject_name}",
             get(get_study_sessions_for_subject),
         )
-        .route("/subject/{subject_name}", post(add_subject))
-        .route("/subject/{subject_name}", delete(delete_subject))
+        .route(
+            "/subject/{subject_name}",
+            get(get_subject)
+                .post(add_subject)
+                .patch(rename_subject)
+                .delete(delete_subject),
+        )
+        .route(
+            "/subject/{subject_name}/scheduler",
+            put(update_subject_scheduler),
+        )
+        .route(
+            "/subject/{subject_name}/fsrs/optimize",
+            post(optimize_fsrs_parameters),
+        )
+        .route(
+            "/subject/{subject_name}/interval_schedule",
+            put(set_subject_interval_schedule),
+        )
+        .route("/subject/{subject_name}/members", get(get_subject_members))
+        .route(
+            "/subject/{subject_name}/member",
+            post(invite_subject_member),
+        )
+        .route(
+            "/subject/{subject_name}/member/{member_id}",
+            delete(remove_subject_member),
+        )
+        .route("/invitations", get(get_subject_invitations))
+        .route(
+            "/invitation/{invitation_id}/accept",
+            post(accept_subject_invitation),
+        )
+        .route(
+            "/invitation/{invitation_id}",
+            delete(decline_subject_invitation),
+        )
+        .route(
+            "/study_topic/{study_topic_id}/interval_schedule",
+            put(set_study_topic_interval_schedule),
+        )
+        .route("/interval_schedules", get(get_interval_schedules))
+        .route("/interval_schedule", post(add_interval_schedule))
+        .route(
+            "/interval_schedule/{interval_schedule_id}",
+            put(update_interval_schedule).delete(delete_interval_schedule),
+        )
         .route(
             "/study_session/complete/{study_session_id}",
             post(complete_study_session),
         )
+        .route_layer(middleware::from_extractor_with_state::<
+            AuthenticatedUser,
+            ApiState,
+        >(state.clone()));
+
+    let app = Router::new()
+        .route("/", get(health_check))
+        .route("/auth/register", post(register))
+        .route("/auth/login", post(login))
+        .route("/auth/refresh", post(refresh_tokens))
+        .merge(authenticated)
         .layer(cors)
         .with_state(state);
 
@@ -56,138 +179,753 @@ pub async fn start_api(study_service: StudyService, port: String) {
     axum::serve(listener, app).await.unwrap();
 }
 
+/// Caller's IANA time zone, taken from the optional `X-Time-Zone` header
+/// and used to decide which calendar day "today" is for the request. It
+/// overrides the time zone stored for the user.
+struct RequestTimeZone(Option<Tz>);
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestTimeZone {
+    type Rejection = StudyServiceError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(header) = parts.headers.get("x-time-zone") else {
+            return Ok(RequestTimeZone(None));
+        };
+
+        let time_zone = header
+            .to_str()
+            .ok()
+            .and_then(|value| value.parse::<Tz>().ok())
+            .ok_or_else(|| {
+                StudyServiceError::InvalidRequest(format!("Invalid X-Time-Zone header: {header:?}"))
+            })?;
+
+        Ok(RequestTimeZone(Some(time_zone)))
+    }
+}
+
+/// User the `Authorization: Bearer` access token or API key of the request
+/// belongs to. Read-only API keys are turned away from anything but safe
+/// methods here, so handlers only need to care about the user.
+struct AuthenticatedUser {
+    user_id: i64,
+    credential: Credential,
+}
+
+impl AuthenticatedUser {
+    /// API keys cannot manage API keys, or a leaked key could mint more.
+    fn require_access_token(&self) -> StudyServiceResult<()> {
+        match self.credential {
+            Credential::AccessToken => Ok(()),
+            Credential::ApiKey { .. } => Err(StudyServiceError::Forbidden(
+                "API keys can only be managed with an access token".to_string(),
+            )),
+        }
+    }
+}
+
+impl FromRequestParts<ApiState> for AuthenticatedUser {
+    type Rejection = StudyServiceError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ApiState,
+    ) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|header| header.to_str().ok())
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .ok_or_else(|| {
+                StudyServiceError::Unauthorized(
+                    "Missing bearer access token or API key".to_string(),
+                )
+            })?;
+
+        let Authentication {
+            user_id,
+            credential,
+        } = state.auth_service.authenticate(token.trim()).await?;
+
+        if let Credential::ApiKey {
+            scope: ApiKeyScope::ReadOnly,
+            ..
+        } = credential
+        {
+            if !parts.method.is_safe() {
+                return Err(StudyServiceError::Forbidden(
+                    "Read-only API key cannot modify data".to_string(),
+                ));
+            }
+        }
+
+        Ok(AuthenticatedUser {
+            user_id,
+            credential,
+        })
+    }
+}
+
 async fn health_check() -> &'static str {
     "I am alive"
 }
 
+async fn register(
+    State(state): State<ApiState>,
+    body: Result<Json<Credentials>, JsonRejection>,
+) -> StudyServiceResult<(StatusCode, Json<TokenPair>)> {
+    let Json(credentials) = body?;
+    let tokens = state.auth_service.register(credentials).await?;
+
+    Ok((StatusCode::CREATED, Json(tokens)))
+}
+
+async fn login(
+    State(state): State<ApiState>,
+    body: Result<Json<Credentials>, JsonRejection>,
+) -> StudyServiceResult<Json<TokenPair>> {
+    let Json(credentials) = body?;
+    let tokens = state.auth_service.login(credentials).await?;
+
+    Ok(Json(tokens))
+}
+
+async fn refresh_tokens(
+    State(state): State<ApiState>,
+    body: Result<Json<RefreshRequest>, JsonRejection>,
+) -> StudyServiceResult<Json<TokenPair>> {
+    let Json(request) = body?;
+    let tokens = state.auth_service.refresh(&request.refresh_token).await?;
+
+    Ok(Json(tokens))
+}
+
+async fn get_current_user(
+    State(state): State<ApiState>,
+    user: AuthenticatedUser,
+) -> StudyServiceResult<Json<User>> {
+    let user = state.auth_service.get_user(user.user_id).await?;
+
+    Ok(Json(user))
+}
+
+async fn set_time_zone(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    body: Result<Json<TimeZoneUpdate>, JsonRejection>,
+) -> StudyServiceResult<Json<User>> {
+    let Json(update) = body?;
+    let user = state.auth_service.set_time_zone(user_id, update).await?;
+
+    Ok(Json(user))
+}
+
+async fn get_api_keys(
+    State(state): State<ApiState>,
+    user: AuthenticatedUser,
+) -> StudyServiceResult<Json<Vec<ApiKey>>> {
+    user.require_access_token()?;
+
+    let api_keys = state.auth_service.get_api_keys(user.user_id).await?;
+
+    Ok(Json(api_keys))
+}
+
+async fn create_api_key(
+    State(state): State<ApiState>,
+    user: AuthenticatedUser,
+    body: Result<Json<ApiKeyInfo>, JsonRejection>,
+) -> StudyServiceResult<(StatusCode, Json<CreatedApiKey>)> {
+    user.require_access_token()?;
+
+    let Json(info) = body?;
+    let api_key = state
+        .auth_service
+        .create_api_key(user.user_id, info)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(api_key)))
+}
+
+async fn revoke_api_key(
+    State(state): State<ApiState>,
+    user: AuthenticatedUser,
+    Path(api_key_id): Path<i64>,
+) -> StudyServiceResult<StatusCode> {
+    user.require_access_token()?;
+
+    state
+        .auth_service
+        .revoke_api_key(user.user_id, api_key_id)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
+
 async fn complete_study_session(
     State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    RequestTimeZone(time_zone): RequestTimeZone,
     Path(study_session_id): Path<i64>,
-) -> StatusCode {
-    match state
+    body: Bytes,
+) -> StudyServiceResult<StatusCode> {
+    // The body is optional so older clients can keep posting an empty one.
+    let completion = if body.is_empty() {
+        StudySessionCompletion::default()
+    } else {
+        serde_json::from_slice::<StudySessionCompletion>(&body).map_err(|err| {
+            StudyServiceError::InvalidRequest(format!(
+                "Invalid study session completion body: {err}"
+            ))
+        })?
+    };
+
+    state
         .study_service
-        .complete_study_session(study_session_id)
-        .await
-    {
-        Ok(_) => StatusCode::OK,
-        Err(err) => {
-            error!("Error completing study session: {err}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        }
-    }
+        .complete_study_session(user_id, study_session_id, completion, time_zone)
+        .await?;
+
+    Ok(StatusCode::OK)
 }
 
 async fn delete_subject(
     State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
     Path(subject_name): Path<String>,
-) -> StatusCode {
-    match state.study_service.delete_subject(subject_name).await {
-        Ok(_) => StatusCode::OK,
-        Err(err) => {
-            error!("Error deleting subject: {err}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        }
-    }
+) -> StudyServiceResult<StatusCode> {
+    state
+        .study_service
+        .delete_subject(user_id, subject_name)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn rename_subject(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path(subject_name): Path<String>,
+    body: Result<Json<SubjectRename>, JsonRejection>,
+) -> StudyServiceResult<StatusCode> {
+    let Json(body) = body?;
+
+    state
+        .study_service
+        .rename_subject(user_id, subject_name, body.subject_name)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn update_subject_scheduler(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path(subject_name): Path<String>,
+    body: Result<Json<SubjectSchedulerSettings>, JsonRejection>,
+) -> StudyServiceResult<StatusCode> {
+    let Json(body) = body?;
+
+    state
+        .study_service
+        .update_subject_scheduler(user_id, subject_name, body)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn optimize_fsrs_parameters(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path(subject_name): Path<String>,
+) -> StudyServiceResult<Json<Vec<f64>>> {
+    let weights = state
+        .study_service
+        .optimize_fsrs_parameters(user_id, subject_name)
+        .await?;
+
+    Ok(Json(weights))
 }
 
 async fn add_subject(
     State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
     Path(subject_name): Path<String>,
-) -> StatusCode {
-    match state.study_service.add_subject(subject_name).await {
-        Ok(_) => StatusCode::OK,
-        Err(err) => {
-            error!("Error adding subject: {err}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        }
-    }
+) -> StudyServiceResult<StatusCode> {
+    state
+        .study_service
+        .add_subject(user_id, subject_name)
+        .await?;
+
+    Ok(StatusCode::OK)
 }
 
-async fn get_subjects(State(state): State<ApiState>) -> (StatusCode, Json<Vec<Subject>>) {
-    match state.study_service.get_study_subjects().await {
-        Ok(subjects) => (StatusCode::OK, Json(subjects)),
-        Err(err) => {
-            error!("Error in get subjects function: {err}");
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(Vec::new()))
-        }
-    }
+async fn get_subject(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    RequestTimeZone(time_zone): RequestTimeZone,
+    Path(subject_name): Path<String>,
+) -> StudyServiceResult<Json<SubjectDetail>> {
+    let subject = state
+        .study_service
+        .get_subject_detail(user_id, subject_name, time_zone)
+        .await?;
+
+    Ok(Json(subject))
+}
+
+async fn get_subject_members(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path(subject_name): Path<String>,
+) -> StudyServiceResult<Json<Vec<SubjectMember>>> {
+    let subject_members = state
+        .study_service
+        .get_subject_members(user_id, subject_name)
+        .await?;
+
+    Ok(Json(subject_members))
+}
+
+async fn invite_subject_member(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path(subject_name): Path<String>,
+    body: Result<Json<SubjectMemberInfo>, JsonRejection>,
+) -> StudyServiceResult<(StatusCode, Json<SubjectMember>)> {
+    let Json(member_info) = body?;
+
+    let subject_member = state
+        .study_service
+        .invite_subject_member(user_id, subject_name, member_info)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(subject_member)))
+}
+
+async fn remove_subject_member(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path((subject_name, member_id)): Path<(String, i64)>,
+) -> StudyServiceResult<StatusCode> {
+    state
+        .study_service
+        .remove_subject_member(user_id, subject_name, member_id)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn get_subject_invitations(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+) -> StudyServiceResult<Json<Vec<SubjectInvitation>>> {
+    let invitations = state.study_service.get_subject_invitations(user_id).await?;
+
+    Ok(Json(invitations))
+}
+
+async fn accept_subject_invitation(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path(invitation_id): Path<i64>,
+) -> StudyServiceResult<StatusCode> {
+    state
+        .study_service
+        .accept_subject_invitation(user_id, invitation_id)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn decline_subject_invitation(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path(invitation_id): Path<i64>,
+) -> StudyServiceResult<StatusCode> {
+    state
+        .study_service
+        .decline_subject_invitation(user_id, invitation_id)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn get_subjects(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+) -> StudyServiceResult<Json<Vec<Subject>>> {
+    let subjects = state.study_service.get_study_subjects(user_id).await?;
+
+    Ok(Json(subjects))
 }
 
 async fn get_study_topics_for_subject(
     State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
     Path(subject_name): Path<String>,
-) -> (StatusCode, Json<Vec<StudyTopic>>) {
-    match state
+    query: Result<Query<StudyTopicQuery>, QueryRejection>,
+) -> StudyServiceResult<Json<Page<StudyTopic>>> {
+    let Query(query) = query?;
+
+    let study_topics = state
         .study_service
-        .get_study_topics_for_subject(subject_name)
-        .await
-    {
-        Ok(study_topics) => (StatusCode::OK, Json(study_topics)),
-        Err(err) => {
-            error!("Error getting study topic for subject: {err}");
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(Vec::new()))
-        }
-    }
+        .get_study_topics_for_subject(user_id, subject_name, query)
+        .await?;
+
+    Ok(Json(study_topics))
 }
 
 async fn get_study_sessions_for_subject(
     State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    RequestTimeZone(time_zone): RequestTimeZone,
     Path(subject_name): Path<String>,
-) -> (StatusCode, Json<Vec<StudySessionResponse>>) {
-    match state
+    query: Result<Query<StudySessionQuery>, QueryRejection>,
+) -> StudyServiceResult<Json<Page<StudySessionResponse>>> {
+    let Query(query) = query?;
+
+    let study_sessions = state
         .study_service
-        .get_study_sessions_for_subject(subject_name)
-        .await
-    {
-        Ok(study_sessions) => (StatusCode::OK, Json(study_sessions)),
-        Err(err) => {
-            error!("Error getting study session for subject: {err}");
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(Vec::new()))
-        }
-    }
+        .get_study_sessions_for_subject(user_id, subject_name, query, time_zone)
+        .await?;
+
+    Ok(Json(study_sessions))
+}
+
+async fn get_due_flashcards(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    RequestTimeZone(time_zone): RequestTimeZone,
+    query: Result<Query<StudySessionQuery>, QueryRejection>,
+) -> StudyServiceResult<Json<Page<StudySessionResponse>>> {
+    let Query(query) = query?;
+
+    let study_sessions = state
+        .study_service
+        .get_due_flashcards(user_id, query, time_zone)
+        .await?;
+
+    Ok(Json(study_sessions))
 }
 
 async fn get_study_topics_today(
     State(state): State<ApiState>,
-) -> (StatusCode, Json<Vec<StudyTopic>>) {
-    match state.study_service.get_study_topics_for_today().await {
-        Ok(study_topics_today) => (StatusCode::OK, Json(study_topics_today)),
-        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, Json(Vec::new())),
-    }
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    RequestTimeZone(time_zone): RequestTimeZone,
+    query: Result<Query<StudyTopicQuery>, QueryRejection>,
+) -> StudyServiceResult<Json<Page<StudyTopic>>> {
+    let Query(query) = query?;
+
+    let study_topics_today = state
+        .study_service
+        .get_study_topics_for_today(user_id, query, time_zone)
+        .await?;
+
+    Ok(Json(study_topics_today))
 }
 
-async fn get_study_topics(State(state): State<ApiState>) -> (StatusCode, Json<Vec<StudyTopic>>) {
-    match state.study_service.get_study_topics().await {
-        Ok(study_topics) => (StatusCode::OK, Json(study_topics)),
-        Err(err) => {
-            error!("Error: {err}");
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(Vec::new()))
-        }
-    }
+async fn get_study_topics(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    query: Result<Query<StudyTopicQuery>, QueryRejection>,
+) -> StudyServiceResult<Json<Page<StudyTopic>>> {
+    let Query(query) = query?;
+
+    let study_topics = state.study_service.get_study_topics(user_id, query).await?;
+
+    Ok(Json(study_topics))
+}
+
+async fn search_study_topics(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    query: Result<Query<SearchQuery>, QueryRejection>,
+) -> StudyServiceResult<Json<Vec<SearchResult>>> {
+    let Query(query) = query?;
+
+    let search_results = state
+        .study_service
+        .search_study_topics(user_id, query)
+        .await?;
+
+    Ok(Json(search_results))
+}
+
+async fn get_study_topic(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    RequestTimeZone(time_zone): RequestTimeZone,
+    Path(study_topic_id): Path<i64>,
+) -> StudyServiceResult<Json<StudyTopicDetail>> {
+    let study_topic = state
+        .study_service
+        .get_study_topic_detail(user_id, study_topic_id, time_zone)
+        .await?;
+
+    Ok(Json(study_topic))
+}
+
+async fn get_study_topic_history(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path(study_topic_id): Path<i64>,
+) -> StudyServiceResult<Json<Vec<ReviewLog>>> {
+    let review_logs = state
+        .study_service
+        .get_study_topic_history(user_id, study_topic_id)
+        .await?;
+
+    Ok(Json(review_logs))
 }
 
 async fn delete_study_topic(
     State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
     Path(study_topic_id): Path<i64>,
-) -> StatusCode {
-    match state.study_service.delete_study_topic(study_topic_id).await {
-        Ok(_) => StatusCode::OK,
-        Err(err) => {
-            error!("Error adding study topic with error: {err}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        }
-    }
+) -> StudyServiceResult<StatusCode> {
+    state
+        .study_service
+        .delete_study_topic(user_id, study_topic_id)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn update_study_topic(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path(study_topic_id): Path<i64>,
+    body: Result<Json<StudyTopicUpdate>, JsonRejection>,
+) -> StudyServiceResult<StatusCode> {
+    let Json(body) = body?;
+
+    state
+        .study_service
+        .update_study_topic(user_id, study_topic_id, body)
+        .await?;
+
+    Ok(StatusCode::OK)
 }
 
 async fn add_study_topic(
     State(state): State<ApiState>,
-    Json(body): Json<StudyTopicInfo>,
-) -> StatusCode {
-    match state.study_service.add_study_topic(body).await {
-        Ok(_) => StatusCode::CREATED,
-        Err(err) => {
-            error!("Error adding study topic with error: {err}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        }
-    }
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    RequestTimeZone(time_zone): RequestTimeZone,
+    body: Result<Json<StudyTopicInfo>, JsonRejection>,
+) -> StudyServiceResult<StatusCode> {
+    let Json(body) = body?;
+
+    state
+        .study_service
+        .add_study_topic(user_id, body, time_zone)
+        .await?;
+
+    Ok(StatusCode::CREATED)
+}
+
+async fn get_flashcards(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path(study_topic_id): Path<i64>,
+) -> StudyServiceResult<Json<Vec<Flashcard>>> {
+    let flashcards = state
+        .study_service
+        .get_flashcards(user_id, study_topic_id)
+        .await?;
+
+    Ok(Json(flashcards))
+}
+
+async fn add_flashcard(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path(study_topic_id): Path<i64>,
+    body: Result<Json<FlashcardInfo>, JsonRejection>,
+) -> StudyServiceResult<(StatusCode, Json<Flashcard>)> {
+    let Json(flashcard_info) = body?;
+
+    let flashcard = state
+        .study_service
+        .add_flashcard(user_id, study_topic_id, flashcard_info)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(flashcard)))
+}
+
+async fn get_flashcard(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path(flashcard_id): Path<i64>,
+) -> StudyServiceResult<Json<Flashcard>> {
+    let flashcard = state
+        .study_service
+        .get_flashcard(user_id, flashcard_id)
+        .await?;
+
+    Ok(Json(flashcard))
+}
+
+async fn update_flashcard(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path(flashcard_id): Path<i64>,
+    body: Result<Json<FlashcardUpdate>, JsonRejection>,
+) -> StudyServiceResult<StatusCode> {
+    let Json(update) = body?;
+
+    state
+        .study_service
+        .update_flashcard(user_id, flashcard_id, update)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn delete_flashcard(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path(flashcard_id): Path<i64>,
+) -> StudyServiceResult<StatusCode> {
+    state
+        .study_service
+        .delete_flashcard(user_id, flashcard_id)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Takes the `.apkg` file itself as the body.
+async fn import_anki_package(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    RequestTimeZone(time_zone): RequestTimeZone,
+    body: Bytes,
+) -> StudyServiceResult<(StatusCode, Json<Vec<ImportedSubject>>)> {
+    let imported = state
+        .study_service
+        .import_anki_package(user_id, body, time_zone)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(imported)))
+}
+
+async fn export_anki_package(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    RequestTimeZone(time_zone): RequestTimeZone,
+    Path(subject_name): Path<String>,
+) -> StudyServiceResult<([(HeaderName, String); 2], Vec<u8>)> {
+    let package = state
+        .study_service
+        .export_anki_package(user_id, subject_name.clone(), time_zone)
+        .await?;
+
+    // Anki names the deck after the subject, the file name only has to be
+    // safe to save.
+    let file_name: String = subject_name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    Ok((
+        [
+            (CONTENT_TYPE, "application/octet-stream".to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}.apkg\""),
+            ),
+        ],
+        package,
+    ))
+}
+
+async fn get_interval_schedules(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+) -> StudyServiceResult<Json<Vec<IntervalSchedule>>> {
+    let interval_schedules = state.study_service.get_interval_schedules(user_id).await?;
+
+    Ok(Json(interval_schedules))
+}
+
+async fn add_interval_schedule(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    body: Result<Json<IntervalScheduleInfo>, JsonRejection>,
+) -> StudyServiceResult<(StatusCode, Json<IntervalSchedule>)> {
+    let Json(body) = body?;
+
+    let interval_schedule = state
+        .study_service
+        .add_interval_schedule(user_id, body)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(interval_schedule)))
+}
+
+async fn update_interval_schedule(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path(interval_schedule_id): Path<i64>,
+    body: Result<Json<IntervalScheduleInfo>, JsonRejection>,
+) -> StudyServiceResult<StatusCode> {
+    let Json(body) = body?;
+
+    state
+        .study_service
+        .update_interval_schedule(user_id, interval_schedule_id, body)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn delete_interval_schedule(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path(interval_schedule_id): Path<i64>,
+) -> StudyServiceResult<StatusCode> {
+    state
+        .study_service
+        .delete_interval_schedule(user_id, interval_schedule_id)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn set_subject_interval_schedule(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path(subject_name): Path<String>,
+    body: Result<Json<IntervalScheduleAssignment>, JsonRejection>,
+) -> StudyServiceResult<StatusCode> {
+    let Json(body) = body?;
+
+    state
+        .study_service
+        .set_subject_interval_schedule(user_id, subject_name, body.interval_schedule_id)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn set_study_topic_interval_schedule(
+    State(state): State<ApiState>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Path(study_topic_id): Path<i64>,
+    body: Result<Json<IntervalScheduleAssignment>, JsonRejection>,
+) -> StudyServiceResult<StatusCode> {
+    let Json(body) = body?;
+
+    state
+        .study_service
+        .set_study_topic_interval_schedule(user_id, study_topic_id, body.interval_schedule_id)
+        .await?;
+
+    Ok(StatusCode::OK)
 }
@@ -0,0 +1,724 @@
+use std::sync::Arc;
+
+use argon2::{
+    password_hash::{
+        rand_core::{OsRng, RngCore},
+        PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+    },
+    Argon2,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration};
+use chrono_tz::Tz;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::{
+    clock::Clock,
+    domain::{
+        ApiKey, ApiKeyInfo, ApiKeyScope, CreatedApiKey, Credentials, TimeZoneUpdate, TokenPair,
+        User,
+    },
+    err::{StudyServiceError, StudyServiceResult},
+    repository::StudyRepository,
+};
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+pub const MAX_USERNAME_LENGTH: usize = 64;
+
+/// Owner of the rows that existed before user accounts, created by the
+/// migration without a password.
+pub const DEFAULT_USERNAME: &str = "default";
+
+/// Marks a bearer credential as an API key rather than a JWT. Keys look like
+/// `sak_<prefix>_<secret>`.
+const API_KEY_MARKER: &str = "sak";
+const API_KEY_PREFIX_BYTES: usize = 6;
+const API_KEY_SECRET_BYTES: usize = 32;
+
+/// `last_used_at` is only written when it is older than this, so a busy
+/// script does not turn every read into a write.
+const API_KEY_LAST_USED_RESOLUTION_SECS: i64 = 60;
+
+/// How a request proved who it comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Credential {
+    AccessToken,
+    ApiKey { api_key_id: i64, scope: ApiKeyScope },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Authentication {
+    pub user_id: i64,
+    pub credential: Credential,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum TokenKind {
+    Access,
+    Refresh,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct Claims {
+    /// Id of the user the token was issued to.
+    sub: String,
+    kind: TokenKind,
+    iat: i64,
+    exp: i64,
+}
+
+/// Lifetimes of the issued tokens.
+#[derive(Clone, Copy, Debug)]
+pub struct TokenLifetimes {
+    pub access: Duration,
+    pub refresh: Duration,
+}
+
+impl Default for TokenLifetimes {
+    fn default() -> Self {
+        Self {
+            access: Duration::minutes(15),
+            refresh: Duration::days(30),
+        }
+    }
+}
+
+struct TokenKeys {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+}
+
+/// Registers users, checks their passwords and issues the HS256 signed JWTs
+/// that authenticate every other request.
+#[derive(Clone)]
+pub struct AuthService {
+    repo: Arc<dyn StudyRepository>,
+    clock: Arc<dyn Clock>,
+    keys: Arc<TokenKeys>,
+    lifetimes: TokenLifetimes,
+}
+
+impl AuthService {
+    pub fn new(
+        repo: Arc<dyn StudyRepository>,
+        clock: Arc<dyn Clock>,
+        secret: &[u8],
+        lifetimes: TokenLifetimes,
+    ) -> Self {
+        Self {
+            repo,
+            clock,
+            keys: Arc::new(TokenKeys {
+                encoding: EncodingKey::from_secret(secret),
+                decoding: DecodingKey::from_secret(secret),
+            }),
+            lifetimes,
+        }
+    }
+
+    pub async fn register(&self, credentials: Credentials) -> StudyServiceResult<TokenPair> {
+        let username = credentials.username.trim().to_string();
+
+        if username.is_empty() || username.chars().count() > MAX_USERNAME_LENGTH {
+            return Err(StudyServiceError::InvalidRequest(format!(
+                "username must be between 1 and {MAX_USERNAME_LENGTH} characters"
+            )));
+        }
+
+        validate_password(&credentials.password)?;
+
+        let password_hash = hash_password(credentials.password).await?;
+        let created_at = self.clock.now().to_rfc3339();
+
+        let user_id = self
+            .repo
+            .add_user(username.clone(), password_hash, created_at)
+            .await?;
+
+        info!("Registered user {username} ({user_id})");
+
+        self.issue_tokens(user_id)
+    }
+
+    /// Unknown usernames and wrong passwords get the same error, so clients
+    /// cannot probe which accounts exist.
+    pub async fn login(&self, credentials: Credentials) -> StudyServiceResult<TokenPair> {
+        let invalid =
+            || StudyServiceError::Unauthorized("Invalid username or password".to_string());
+
+        let user = self
+            .repo
+            .get_user_by_username(credentials.username.trim().to_string())
+            .await?
+            .ok_or_else(invalid)?;
+
+        // An empty hash is an account nobody can log in to yet.
+        if user.password_hash.is_empty()
+            || !verify_password(credentials.password, user.password_hash).await?
+        {
+            return Err(invalid());
+        }
+
+        self.issue_tokens(user.id)
+    }
+
+    /// Sets the password of the default user if it has none yet, so the
+    /// data it owns since the upgrade can be claimed. Returns whether the
+    /// password was set.
+    pub async fn set_default_user_password(&self, password: String) -> StudyServiceResult<bool> {
+        validate_password(&password)?;
+
+        let Some(user) = self
+            .repo
+            .get_user_by_username(DEFAULT_USERNAME.to_string())
+            .await?
+        else {
+            return Ok(false);
+        };
+
+        if !user.password_hash.is_empty() {
+            return Ok(false);
+        }
+
+        let password_hash = hash_password(password).await?;
+        self.repo
+            .update_user_password(user.id, password_hash)
+            .await?;
+
+        info!("Set the password of the {DEFAULT_USERNAME} user");
+
+        Ok(true)
+    }
+
+    /// Trades a valid refresh token for a new token pair.
+    pub async fn refresh(&self, refresh_token: &str) -> StudyServiceResult<TokenPair> {
+        let user_id = self.verify_token(refresh_token, TokenKind::Refresh)?;
+
+        // Tokens outlive deleted accounts, the user has to still exist.
+        self.repo.get_user(user_id).await?.ok_or_else(|| {
+            StudyServiceError::Unauthorized("Token user no longer exists".to_string())
+        })?;
+
+        self.issue_tokens(user_id)
+    }
+
+    /// Checks a bearer credential, either an access token or an API key.
+    pub async fn authenticate(&self, credential: &str) -> StudyServiceResult<Authentication> {
+        if credential.starts_with(&format!("{API_KEY_MARKER}_")) {
+            return self.authenticate_api_key(credential).await;
+        }
+
+        Ok(Authentication {
+            user_id: self.verify_token(credential, TokenKind::Access)?,
+            credential: Credential::AccessToken,
+        })
+    }
+
+    /// Creates an API key. The key itself is only part of this response.
+    pub async fn create_api_key(
+        &self,
+        user_id: i64,
+        info: ApiKeyInfo,
+    ) -> StudyServiceResult<CreatedApiKey> {
+        let name = info.name.trim().to_string();
+
+        if name.is_empty() {
+            return Err(StudyServiceError::InvalidRequest(
+                "API key name must not be empty".to_string(),
+            ));
+        }
+
+        let prefix = hex(&random_bytes(API_KEY_PREFIX_BYTES));
+        let secret = URL_SAFE_NO_PAD.encode(random_bytes(API_KEY_SECRET_BYTES));
+        let key = format!("{API_KEY_MARKER}_{prefix}_{secret}");
+        let created_at = self.clock.now().to_rfc3339();
+
+        let key_hash = hash_api_key(&key);
+
+        let id = self
+            .repo
+            .add_api_key(
+                user_id,
+                name.clone(),
+                prefix.clone(),
+                key_hash.clone(),
+                info.scope,
+                created_at.clone(),
+            )
+            .await?;
+
+        info!("Created API key {prefix} ({id}) for user {user_id}");
+
+        Ok(CreatedApiKey {
+            api_key: ApiKey {
+                id,
+                user_id,
+                name,
+                prefix,
+                key_hash,
+                scope: info.scope,
+                created_at,
+                last_used_at: None,
+                revoked_at: None,
+            },
+            key,
+        })
+    }
+
+    pub async fn get_api_keys(&self, user_id: i64) -> StudyServiceResult<Vec<ApiKey>> {
+        Ok(self.repo.get_api_keys(user_id).await?)
+    }
+
+    /// Revoked keys stay listed for auditing but no longer authenticate.
+    pub async fn revoke_api_key(&self, user_id: i64, api_key_id: i64) -> StudyServiceResult<()> {
+        let revoked_at = self.clock.now().to_rfc3339();
+
+        self.repo
+            .revoke_api_key(user_id, api_key_id, revoked_at)
+            .await?;
+
+        info!("Revoked API key {api_key_id} of user {user_id}");
+
+        Ok(())
+    }
+
+    pub async fn get_user(&self, user_id: i64) -> StudyServiceResult<User> {
+        self.repo.get_user(user_id).await?.ok_or_else(|| {
+            StudyServiceError::Unauthorized("Token user no longer exists".to_string())
+        })
+    }
+
+    /// Sets the time zone the user's days follow, stored under its
+    /// canonical IANA name.
+    pub async fn set_time_zone(
+        &self,
+        user_id: i64,
+        update: TimeZoneUpdate,
+    ) -> StudyServiceResult<User> {
+        let time_zone = update
+            .time_zone
+            .map(|time_zone| {
+                time_zone
+                    .trim()
+                    .parse::<Tz>()
+                    .map(|time_zone| time_zone.name().to_string())
+                    .map_err(|_| {
+                        StudyServiceError::InvalidRequest(format!("Unknown time zone: {time_zone}"))
+                    })
+            })
+            .transpose()?;
+
+        self.repo.update_user_time_zone(user_id, time_zone).await?;
+
+        self.get_user(user_id).await
+    }
+
+    async fn authenticate_api_key(&self, key: &str) -> StudyServiceResult<Authentication> {
+        let invalid = || StudyServiceError::Unauthorized("Invalid API key".to_string());
+
+        let prefix = key.split('_').nth(1).ok_or_else(invalid)?;
+        let api_key = self
+            .repo
+            .get_api_key_by_prefix(prefix.to_string())
+            .await?
+            .ok_or_else(invalid)?;
+
+        if !constant_time_eq(hash_api_key(key).as_bytes(), api_key.key_hash.as_bytes()) {
+            return Err(invalid());
+        }
+
+        if api_key.revoked_at.is_some() {
+            return Err(StudyServiceError::Unauthorized(
+                "API key has been revoked".to_string(),
+            ));
+        }
+
+        let now = self.clock.now();
+        let recently_used = api_key
+            .last_used_at
+            .as_deref()
+            .and_then(|last_used_at| DateTime::parse_from_rfc3339(last_used_at).ok())
+            .is_some_and(|last_used_at| {
+                (now - last_used_at.to_utc()).num_seconds() < API_KEY_LAST_USED_RESOLUTION_SECS
+            });
+
+        if !recently_used {
+            self.repo
+                .update_api_key_last_used(api_key.id, now.to_rfc3339())
+                .await?;
+        }
+
+        Ok(Authentication {
+            user_id: api_key.user_id,
+            credential: Credential::ApiKey {
+                api_key_id: api_key.id,
+                scope: api_key.scope,
+            },
+        })
+    }
+
+    fn issue_tokens(&self, user_id: i64) -> StudyServiceResult<TokenPair> {
+        Ok(TokenPair {
+            access_token: self.sign(user_id, TokenKind::Access, self.lifetimes.access)?,
+            refresh_token: self.sign(user_id, TokenKind::Refresh, self.lifetimes.refresh)?,
+            token_type: "Bearer".to_string(),
+            expires_in: self.lifetimes.access.num_seconds(),
+        })
+    }
+
+    fn sign(
+        &self,
+        user_id: i64,
+        kind: TokenKind,
+        lifetime: Duration,
+    ) -> StudyServiceResult<String> {
+        let now = self.clock.now();
+        let claims = Claims {
+            sub: user_id.to_string(),
+            kind,
+            iat: now.timestamp(),
+            exp: (now + lifetime).timestamp(),
+        };
+
+        jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims, &self.keys.encoding)
+            .map_err(|err| StudyServiceError::Internal(format!("Signing token: {err}")))
+    }
+
+    fn verify_token(&self, token: &str, kind: TokenKind) -> StudyServiceResult<i64> {
+        let invalid = || StudyServiceError::Unauthorized("Invalid token".to_string());
+
+        // Expiry is checked against our clock rather than the system one.
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.validate_exp = false;
+
+        let claims = jsonwebtoken::decode::<Claims>(token, &self.keys.decoding, &validation)
+            .map_err(|_| invalid())?
+            .claims;
+
+        if claims.kind != kind {
+            return Err(invalid());
+        }
+
+        if claims.exp <= self.clock.now().timestamp() {
+            return Err(StudyServiceError::Unauthorized("Token expired".to_string()));
+        }
+
+        claims.sub.parse().map_err(|_| invalid())
+    }
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Keys are long and random, so a plain SHA-256 is enough, unlike passwords.
+fn hash_api_key(key: &str) -> String {
+    hex(&Sha256::digest(key.as_bytes()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn validate_password(password: &str) -> StudyServiceResult<()> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(StudyServiceError::InvalidRequest(format!(
+            "password must be at least {MIN_PASSWORD_LENGTH} characters"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Argon2 is deliberately slow, so hashing runs off the async workers.
+async fn hash_password(password: String) -> StudyServiceResult<String> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|err| StudyServiceError::Internal(format!("Hashing password: {err}")))
+    })
+    .await
+    .map_err(|err| StudyServiceError::Internal(format!("Hashing password: {err}")))?
+}
+
+async fn verify_password(password: String, password_hash: String) -> StudyServiceResult<bool> {
+    tokio::task::spawn_blocking(move || {
+        let hash = PasswordHash::new(&password_hash)
+            .map_err(|err| StudyServiceError::Internal(format!("Stored password hash: {err}")))?;
+
+        Ok(Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok())
+    })
+    .await
+    .map_err(|err| StudyServiceError::Internal(format!("Verifying password: {err}")))?
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use chrono::{Duration, NaiveDate};
+
+    use crate::{
+        auth_service::{AuthService, Credential, TokenLifetimes},
+        clock::{Clock, FixedClock},
+        domain::{ApiKeyInfo, ApiKeyScope, Credentials, TimeZoneUpdate},
+        err::{RepositoryError, StudyServiceError},
+        repository::InMemoryRepository,
+    };
+
+    fn credentials(username: &str, password: &str) -> Credentials {
+        Credentials {
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    fn service(clock: Arc<FixedClock>) -> AuthService {
+        AuthService::new(
+            Arc::new(InMemoryRepository::new()),
+            clock,
+            b"test secret",
+            TokenLifetimes::default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn registered_user_can_log_in() {
+        let clock = Arc::new(FixedClock::at_date(
+            NaiveDate::from_ymd_opt(2025, 3, 1).unwrap(),
+        ));
+        let auth = service(clock);
+
+        let tokens = auth
+            .register(credentials("ada", "correct horse"))
+            .await
+            .unwrap();
+        let user_id = auth
+            .authenticate(&tokens.access_token)
+            .await
+            .unwrap()
+            .user_id;
+        assert_eq!(auth.get_user(user_id).await.unwrap().username, "ada");
+
+        let tokens = auth
+            .login(credentials("ADA", "correct horse"))
+            .await
+            .unwrap();
+        assert_eq!(
+            auth.authenticate(&tokens.access_token)
+                .await
+                .unwrap()
+                .user_id,
+            user_id
+        );
+
+        assert!(matches!(
+            auth.login(credentials("ada", "wrong horse")).await,
+            Err(StudyServiceError::Unauthorized(_))
+        ));
+        assert!(matches!(
+            auth.login(credentials("grace", "correct horse")).await,
+            Err(StudyServiceError::Unauthorized(_))
+        ));
+        assert!(matches!(
+            auth.register(credentials("Ada", "another password")).await,
+            Err(StudyServiceError::RepositoryError(
+                RepositoryError::Conflict(_)
+            ))
+        ));
+        assert!(matches!(
+            auth.register(credentials("grace", "short")).await,
+            Err(StudyServiceError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            auth.register(credentials("  ", "long enough")).await,
+            Err(StudyServiceError::InvalidRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn tokens_expire_and_keep_their_kind() {
+        let clock = Arc::new(FixedClock::at_date(
+            NaiveDate::from_ymd_opt(2025, 3, 1).unwrap(),
+        ));
+        let auth = service(clock.clone());
+
+        let tokens = auth
+            .register(credentials("ada", "correct horse"))
+            .await
+            .unwrap();
+
+        // A refresh token does not authenticate requests, an access token
+        // does not refresh.
+        assert!(auth.authenticate(&tokens.refresh_token).await.is_err());
+        assert!(auth.refresh(&tokens.access_token).await.is_err());
+        assert!(auth.authenticate("not a token").await.is_err());
+
+        let other = AuthService::new(
+            Arc::new(InMemoryRepository::new()),
+            clock.clone(),
+            b"another secret",
+            TokenLifetimes::default(),
+        );
+        assert!(other.authenticate(&tokens.access_token).await.is_err());
+
+        clock.advance(Duration::minutes(16));
+        assert!(matches!(
+            auth.authenticate(&tokens.access_token).await,
+            Err(StudyServiceError::Unauthorized(_))
+        ));
+
+        let refreshed = auth.refresh(&tokens.refresh_token).await.unwrap();
+        assert!(auth.authenticate(&refreshed.access_token).await.is_ok());
+
+        clock.advance(Duration::days(31));
+        assert!(auth.refresh(&refreshed.refresh_token).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn api_keys_authenticate_until_revoked() {
+        let clock = Arc::new(FixedClock::at_date(
+            NaiveDate::from_ymd_opt(2025, 3, 1).unwrap(),
+        ));
+        let auth = service(clock.clone());
+
+        let tokens = auth
+            .register(credentials("ada", "correct horse"))
+            .await
+            .unwrap();
+        let user_id = auth
+            .authenticate(&tokens.access_token)
+            .await
+            .unwrap()
+            .user_id;
+
+        let created = auth
+            .create_api_key(
+                user_id,
+                ApiKeyInfo {
+                    name: "backup script".to_string(),
+                    scope: ApiKeyScope::ReadOnly,
+                },
+            )
+            .await
+            .unwrap();
+        assert!(created
+            .key
+            .starts_with(&format!("sak_{}_", created.api_key.prefix)));
+        assert_ne!(created.api_key.key_hash, created.key);
+
+        let authentication = auth.authenticate(&created.key).await.unwrap();
+        assert_eq!(authentication.user_id, user_id);
+        assert_eq!(
+            authentication.credential,
+            Credential::ApiKey {
+                api_key_id: created.api_key.id,
+                scope: ApiKeyScope::ReadOnly,
+            }
+        );
+
+        let api_keys = auth.get_api_keys(user_id).await.unwrap();
+        assert_eq!(api_keys.len(), 1);
+        assert_eq!(
+            api_keys[0].last_used_at.as_deref(),
+            Some(clock.now().to_rfc3339().as_str())
+        );
+
+        // A key with the right prefix but the wrong secret is rejected.
+        let forged = format!("sak_{}_forged", created.api_key.prefix);
+        assert!(matches!(
+            auth.authenticate(&forged).await,
+            Err(StudyServiceError::Unauthorized(_))
+        ));
+
+        clock.advance(Duration::days(1));
+        auth.revoke_api_key(user_id, created.api_key.id)
+            .await
+            .unwrap();
+        assert!(matches!(
+            auth.authenticate(&created.key).await,
+            Err(StudyServiceError::Unauthorized(_))
+        ));
+
+        assert!(matches!(
+            auth.revoke_api_key(user_id + 1, created.api_key.id).await,
+            Err(StudyServiceError::RepositoryError(
+                RepositoryError::NotFound(_)
+            ))
+        ));
+        assert!(matches!(
+            auth.create_api_key(
+                user_id,
+                ApiKeyInfo {
+                    name: " ".to_string(),
+                    scope: ApiKeyScope::ReadWrite,
+                },
+            )
+            .await,
+            Err(StudyServiceError::InvalidRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn users_set_their_time_zone() {
+        let auth = service(Arc::new(FixedClock::at_date(
+            NaiveDate::from_ymd_opt(2025, 3, 1).unwrap(),
+        )));
+        let tokens = auth
+            .register(credentials("ada", "correct horse"))
+            .await
+            .unwrap();
+        let user_id = auth
+            .authenticate(&tokens.access_token)
+            .await
+            .unwrap()
+            .user_id;
+
+        let user = auth
+            .set_time_zone(
+                user_id,
+                TimeZoneUpdate {
+                    time_zone: Some(" America/Bogota ".to_string()),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(user.time_zone.as_deref(), Some("America/Bogota"));
+
+        assert!(matches!(
+            auth.set_time_zone(
+                user_id,
+                TimeZoneUpdate {
+                    time_zone: Some("Mars/Olympus_Mons".to_string()),
+                },
+            )
+            .await,
+            Err(StudyServiceError::InvalidRequest(_))
+        ));
+        assert_eq!(
+            auth.get_user(user_id).await.unwrap().time_zone.as_deref(),
+            Some("America/Bogota")
+        );
+
+        let user = auth
+            .set_time_zone(user_id, TimeZoneUpdate { time_zone: None })
+            .await
+            .unwrap();
+        assert!(user.time_zone.is_none());
+    }
+}
@@ -0,0 +1,106 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Single source of the current time, so every "today" calculation in the
+/// service and the repository agrees and can be controlled in tests.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Calendar date at this instant in the given time zone, so the day
+    /// flips at local midnight rather than at UTC midnight.
+    fn today_in(&self, time_zone: Tz) -> NaiveDate {
+        self.now().with_timezone(&time_zone).date_naive()
+    }
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+pub fn format_date(date: NaiveDate) -> String {
+    date.format(DATE_FORMAT).to_string()
+}
+
+#[cfg(test)]
+pub use fixed::FixedClock;
+
+#[cfg(test)]
+mod fixed {
+    use std::sync::Mutex;
+
+    use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+    use super::Clock;
+
+    /// Clock frozen at a given instant that only moves when told to.
+    pub struct FixedClock {
+        now: Mutex<DateTime<Utc>>,
+    }
+
+    impl FixedClock {
+        pub fn new(now: DateTime<Utc>) -> Self {
+            Self {
+                now: Mutex::new(now),
+            }
+        }
+
+        pub fn at_date(date: NaiveDate) -> Self {
+            Self::new(date.and_hms_opt(12, 0, 0).unwrap().and_utc())
+        }
+
+        pub fn advance(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+        }
+
+        pub fn advance_days(&self, days: i64) {
+            self.advance(Duration::days(days));
+        }
+    }
+
+    impl Clock for FixedClock {
+        fn now(&self) -> DateTime<Utc> {
+            *self.now.lock().unwrap()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use chrono_tz::{America::Bogota, Tz};
+
+    use crate::clock::{Clock, FixedClock};
+
+    #[test]
+    fn today_in_follows_local_midnight() {
+        // 2025-01-02 01:00 UTC is still the evening of 2025-01-01 in Bogotá.
+        let clock = FixedClock::new(Utc.with_ymd_and_hms(2025, 1, 2, 1, 0, 0).unwrap());
+
+        assert_eq!(
+            clock.today_in(Tz::UTC),
+            NaiveDate::from_ymd_opt(2025, 1, 2).unwrap()
+        );
+        assert_eq!(
+            clock.today_in(Bogota),
+            NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()
+        );
+
+        clock.advance(chrono::Duration::hours(3));
+        assert_eq!(
+            clock.today_in(Bogota),
+            NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()
+        );
+
+        clock.advance(chrono::Duration::hours(1));
+        assert_eq!(
+            clock.today_in(Bogota),
+            NaiveDate::from_ymd_opt(2025, 1, 2).unwrap()
+        );
+    }
+}
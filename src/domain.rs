@@ -1,15 +1,34 @@
 use serde::{Deserialize, Serialize};
 
+use crate::pagination::{SortKey, SortOrder};
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct StudyTopic {
     pub id: i64,
+    /// Owner of the topic's subject.
+    pub user_id: i64,
     pub name: String,
     pub description: Option<String>,
     pub creation_date: String,
     pub subject_name: String,
+    /// From here on the fields are the progress of the user the topic was
+    /// loaded for, members of a shared subject each study on their own.
     pub last_session_date: Option<String>,
+    /// With card scheduling the sessions of the topic's cards count here
+    /// too, so both counters add up those of its cards.
     pub total_sessions: i64,
     pub completed_sessions: i64,
+    pub ease_factor: f64,
+    pub interval_days: i64,
+    pub repetitions: i64,
+    /// Set once the topic has been graded; until then the fixed review
+    /// offsets from `creation_date` apply.
+    pub next_due_date: Option<String>,
+    pub stability: Option<f64>,
+    pub difficulty: Option<f64>,
+    pub last_review_date: Option<String>,
+    /// Overrides the interval schedule of the subject.
+    pub interval_schedule_id: Option<i64>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
@@ -17,16 +36,635 @@ pub struct StudyTopicInfo {
     pub name: String,
     pub description: Option<String>,
     pub subject_name: String,
+    #[serde(default)]
+    pub interval_schedule_id: Option<i64>,
+}
+
+/// Query string of the study topic list endpoints. Dates are inclusive.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct StudyTopicQuery {
+    pub subject_name: Option<String>,
+    pub created_from: Option<String>,
+    pub created_to: Option<String>,
+    /// Topics with a pending session or a graded next due date on or
+    /// before this date.
+    pub due_before: Option<String>,
+    pub has_pending_session: Option<bool>,
+    /// Topics with a review planned or due on this date, which is how the
+    /// topics for today are listed. Not taken from the query string.
+    #[serde(skip)]
+    pub review_on: Option<String>,
+    #[serde(default)]
+    pub sort: StudyTopicSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub limit: Option<u32>,
+    pub cursor: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StudyTopicSort {
+    #[default]
+    Id,
+    Name,
+    CreationDate,
+    /// Topics that never had a session come first.
+    LastSessionDate,
+    /// Completed over created sessions, 0 before the first session.
+    CompletionRatio,
+}
+
+impl StudyTopicSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StudyTopicSort::Id => "id",
+            StudyTopicSort::Name => "name",
+            StudyTopicSort::CreationDate => "creation_date",
+            StudyTopicSort::LastSessionDate => "last_session_date",
+            StudyTopicSort::CompletionRatio => "completion_ratio",
+        }
+    }
+
+    /// Value the topic is sorted by.
+    pub fn key(&self, study_topic: &StudyTopic) -> SortKey {
+        match self {
+            StudyTopicSort::Id => SortKey::Integer(study_topic.id),
+            StudyTopicSort::Name => SortKey::Text(study_topic.name.clone()),
+            StudyTopicSort::CreationDate => SortKey::Text(study_topic.creation_date.clone()),
+            StudyTopicSort::LastSessionDate => {
+                SortKey::Text(study_topic.last_session_date.clone().unwrap_or_default())
+            }
+            StudyTopicSort::CompletionRatio => SortKey::Real(if study_topic.total_sessions > 0 {
+                study_topic.completed_sessions as f64 / study_topic.total_sessions as f64
+            } else {
+                0.0
+            }),
+        }
+    }
+}
+
+/// Query string of the topic search.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct SearchQuery {
+    /// Words to look for, each one also matches words it is a prefix of.
+    #[serde(default)]
+    pub q: String,
+    pub limit: Option<u32>,
+}
+
+/// Topic matching a search.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct SearchResult {
+    pub study_topic_id: i64,
+    pub name: String,
+    pub subject_name: String,
+    /// Name as HTML: escaped, with the matched words wrapped in `<mark>`
+    /// tags.
+    pub name_highlight: String,
+    /// Part of the description around the matches, highlighted like the
+    /// name. `None` when the topic has no description.
+    pub description_snippet: Option<String>,
+    /// BM25 score, lower is a better match.
+    pub rank: f64,
+}
+
+/// Partial update of a study topic, absent fields are left unchanged.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct StudyTopicUpdate {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// `Some(None)` clears the description, sent as an explicit `null`.
+    #[serde(default, deserialize_with = "present")]
+    pub description: Option<Option<String>>,
+    /// Moves the topic, with its sessions and history, to another subject.
+    #[serde(default)]
+    pub subject_name: Option<String>,
+}
+
+/// Card of a topic. `front`, `back` and `hint` are markdown, rendering is
+/// left to the client.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Flashcard {
+    pub id: i64,
+    pub study_topic_id: i64,
+    pub front: String,
+    pub back: String,
+    pub hint: Option<String>,
+    /// RFC 3339 timestamp.
+    pub created_at: String,
+    /// RFC 3339 timestamp of the last change, `created_at` until then.
+    pub updated_at: String,
+}
+
+/// Content of a new card, markdown like the sides of [`Flashcard`].
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct FlashcardInfo {
+    /// Markdown of the question.
+    pub front: String,
+    /// Markdown of the answer.
+    pub back: String,
+    /// Markdown shown on request before the answer, a blank one is none.
+    #[serde(default)]
+    pub hint: Option<String>,
+}
+
+impl FlashcardInfo {
+    /// The card with its markdown normalised by [`normalize_markdown`].
+    pub fn normalized(self) -> Self {
+        FlashcardInfo {
+            front: normalize_markdown(&self.front),
+            back: normalize_markdown(&self.back),
+            hint: self.hint.as_deref().and_then(normalize_hint),
+        }
+    }
+}
+
+/// Review state of a card for one user, the card counterpart of the
+/// progress fields of [`StudyTopic`]. Cards the user never studied have the
+/// defaults.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct FlashcardProgress {
+    pub flashcard_id: i64,
+    pub study_topic_id: i64,
+    /// RFC 3339 timestamp the card was added at, the fixed review offsets
+    /// count from its date.
+    pub created_at: String,
+    pub last_session_date: Option<String>,
+    pub total_sessions: i64,
+    pub completed_sessions: i64,
+    pub ease_factor: f64,
+    pub interval_days: i64,
+    pub repetitions: i64,
+    pub next_due_date: Option<String>,
+    pub stability: Option<f64>,
+    pub difficulty: Option<f64>,
+    pub last_review_date: Option<String>,
+}
+
+/// Partial update of a flashcard, absent fields are left unchanged.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct FlashcardUpdate {
+    #[serde(default)]
+    pub front: Option<String>,
+    #[serde(default)]
+    pub back: Option<String>,
+    /// `Some(None)` clears the hint, sent as an explicit `null`.
+    #[serde(default, deserialize_with = "present")]
+    pub hint: Option<Option<String>>,
+}
+
+impl FlashcardUpdate {
+    /// The update with its markdown normalised by [`normalize_markdown`], a
+    /// blank hint clears it.
+    pub fn normalized(self) -> Self {
+        FlashcardUpdate {
+            front: self.front.as_deref().map(normalize_markdown),
+            back: self.back.as_deref().map(normalize_markdown),
+            hint: self
+                .hint
+                .map(|hint| hint.as_deref().and_then(normalize_hint)),
+        }
+    }
+}
+
+/// Stores markdown the same way whatever client sent it: `\n` line endings,
+/// without blank lines or whitespace around the content. Indentation of
+/// the first line is kept, it is significant in markdown.
+pub fn normalize_markdown(markdown: &str) -> String {
+    let markdown = markdown.replace("\r\n", "\n").replace('\r', "\n");
+    let markdown = markdown.trim_end();
+    let content_start = markdown.len() - markdown.trim_start().len();
+    let line_start = markdown[..content_start]
+        .rfind('\n')
+        .map_or(0, |newline| newline + 1);
+
+    markdown[line_start..].to_string()
+}
+
+fn normalize_hint(hint: &str) -> Option<String> {
+    Some(normalize_markdown(hint)).filter(|hint| !hint.is_empty())
+}
+
+/// Deserializes a field that is present in the body, so an explicit `null`
+/// becomes `Some(None)` while a missing field keeps its `None` default.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Subject read from another app's export, with its topics, their cards and
+/// the cards' review history. An import is written in one go.
+#[derive(Clone, Debug)]
+pub struct SubjectImport {
+    pub subject_name: String,
+    pub scheduler: SchedulerKind,
+    pub study_topics: Vec<StudyTopicImport>,
+}
+
+#[derive(Clone, Debug)]
+pub struct StudyTopicImport {
+    pub name: String,
+    pub description: Option<String>,
+    pub creation_date: String,
+    pub flashcards: Vec<FlashcardImport>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FlashcardImport {
+    pub flashcard: FlashcardInfo,
+    /// RFC 3339 timestamp.
+    pub created_at: String,
+    /// State the card was left in, `None` when it was never studied.
+    pub schedule: Option<StudyTopicSchedule>,
+    /// Oldest first.
+    pub reviews: Vec<ReviewImport>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReviewImport {
+    pub review_date: String,
+    /// RFC 3339 timestamp.
+    pub completed_at: String,
+    pub grade: ReviewGrade,
+    pub time_spent_seconds: Option<u32>,
+}
+
+/// What an import added to one subject.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct ImportedSubject {
+    pub subject_name: String,
+    pub study_topics: usize,
+    pub flashcards: usize,
+    pub review_logs: usize,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct Subject {
+    /// Owner of the subject. Names are unique among the subjects a user
+    /// can see, owned or shared with them.
+    pub user_id: i64,
+    pub subject_name: String,
+    pub scheduler: SchedulerKind,
+    pub desired_retention: f64,
+    /// JSON array with the 17 FSRS weights, `None` for the defaults.
+    pub fsrs_parameters: Option<String>,
+    /// Fixed review offsets of the subject's topics, `None` for the built-in
+    /// 0, 1, 3, 7, 21, 30, 45, 60 days repeating every 60 days.
+    pub interval_schedule_id: Option<i64>,
+    /// Whether topics with flashcards are scheduled card by card, each card
+    /// with its own review state, instead of as a whole.
+    pub card_scheduling: bool,
+    /// Role of the user the subject was loaded for.
+    pub role: SubjectRole,
+}
+
+/// What a user may do with a subject. Every role studies the topics on
+/// their own schedule.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SubjectRole {
+    /// Also renames, deletes and shares the subject and sets its scheduling.
+    Owner,
+    /// Adds, edits and deletes topics.
+    Editor,
+    Viewer,
+}
+
+impl SubjectRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubjectRole::Owner => "owner",
+            SubjectRole::Editor => "editor",
+            SubjectRole::Viewer => "viewer",
+        }
+    }
+
+    pub fn can_edit_study_topics(&self) -> bool {
+        matches!(self, SubjectRole::Owner | SubjectRole::Editor)
+    }
+}
+
+/// Someone a subject is shared with, or was invited to it.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct SubjectMember {
+    pub user_id: i64,
+    pub username: String,
+    pub role: SubjectRole,
+    /// RFC 3339 timestamps, both `None` for the owner. `accepted_at` is
+    /// `None` while the invitation is pending.
+    pub invited_at: Option<String>,
+    pub accepted_at: Option<String>,
+}
+
+/// Body of the endpoint inviting a user to a subject.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct SubjectMemberInfo {
+    pub username: String,
+    pub role: SubjectRole,
+}
+
+/// A pending invitation to someone else's subject.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct SubjectInvitation {
+    pub id: i64,
     pub subject_name: String,
+    pub owner_username: String,
+    pub role: SubjectRole,
+    pub invited_at: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct SubjectRename {
+    pub subject_name: String,
+}
+
+/// Scheduling algorithm used for the topics of a subject.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SchedulerKind {
+    FixedOffsets,
+    #[default]
+    Sm2,
+    Fsrs,
+}
+
+impl SchedulerKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SchedulerKind::FixedOffsets => "fixed_offsets",
+            SchedulerKind::Sm2 => "sm2",
+            SchedulerKind::Fsrs => "fsrs",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct SubjectSchedulerSettings {
+    pub scheduler: SchedulerKind,
+    pub desired_retention: Option<f64>,
+    pub fsrs_parameters: Option<Vec<f64>>,
+    /// `None` keeps the current setting.
+    #[serde(default)]
+    pub card_scheduling: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct StudySession {
+    pub id: i64,
+    pub study_topic_id: i64,
+    /// Member studying in the session.
+    pub user_id: i64,
+    /// Card the session reviews, `None` when it reviews the whole topic.
+    pub flashcard_id: Option<i64>,
+    pub due_date: String,
+    pub missed_reviews: i64,
+    /// RFC 3339 timestamp, `None` while the session is pending.
+    pub completed_at: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct StudySessionInfo {
     pub id: i64,
+    pub study_topic_id: i64,
+    pub flashcard_id: Option<i64>,
     pub due_date: String,
+    pub subject_name: String,
     pub study_topic_name: String,
+    pub missed_reviews: i64,
+}
+
+/// Query string of the pending session list of a subject, and of the due
+/// card list across all subjects.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct StudySessionQuery {
+    /// Sessions due on or before this date.
+    pub due_before: Option<String>,
+    #[serde(default)]
+    pub sort: StudySessionSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub limit: Option<u32>,
+    pub cursor: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StudySessionSort {
+    #[default]
+    DueDate,
+    StudyTopicName,
+}
+
+impl StudySessionSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StudySessionSort::DueDate => "due_date",
+            StudySessionSort::StudyTopicName => "study_topic_name",
+        }
+    }
+
+    /// Value the session is sorted by.
+    pub fn key(&self, study_session: &StudySessionInfo) -> SortKey {
+        match self {
+            StudySessionSort::DueDate => SortKey::Text(study_session.due_date.clone()),
+            StudySessionSort::StudyTopicName => {
+                SortKey::Text(study_session.study_topic_name.clone())
+            }
+        }
+    }
+}
+
+/// How well the user recalled a topic when completing a session.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewGrade {
+    Again,
+    Hard,
+    Good,
+    Easy,
+}
+
+impl ReviewGrade {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReviewGrade::Again => "again",
+            ReviewGrade::Hard => "hard",
+            ReviewGrade::Good => "good",
+            ReviewGrade::Easy => "easy",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct StudySessionCompletion {
+    /// Ungraded completions leave the topic on its current schedule.
+    #[serde(default)]
+    pub grade: Option<ReviewGrade>,
+    #[serde(default)]
+    pub time_spent_seconds: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct StudyTopicSchedule {
+    pub ease_factor: f64,
+    pub interval_days: i64,
+    pub repetitions: i64,
+    pub stability: Option<f64>,
+    pub difficulty: Option<f64>,
+    pub next_due_date: Option<String>,
+    pub last_review_date: Option<String>,
+}
+
+/// A completed review of a topic.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct ReviewLog {
+    pub id: i64,
+    pub study_topic_id: i64,
+    /// Member who reviewed the topic.
+    pub user_id: i64,
+    /// Card that was reviewed, `None` for reviews of the whole topic.
+    pub flashcard_id: Option<i64>,
+    /// `None` for reviews logged before sessions were kept.
+    pub study_session_id: Option<i64>,
+    /// Date the session was due on.
+    pub scheduled_date: Option<String>,
+    /// Date, in the user's time zone, the review actually happened on.
+    pub review_date: String,
+    /// RFC 3339 timestamp of the completion.
+    pub completed_at: Option<String>,
+    pub grade: Option<ReviewGrade>,
+    pub time_spent_seconds: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ReviewLogInfo {
+    pub study_topic_id: i64,
+    pub flashcard_id: Option<i64>,
+    pub study_session_id: Option<i64>,
+    pub scheduled_date: Option<String>,
+    pub review_date: String,
+    pub completed_at: Option<String>,
+    pub grade: Option<ReviewGrade>,
+    pub time_spent_seconds: Option<u32>,
+}
+
+/// Named list of review offsets, in days since a topic was created.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct IntervalSchedule {
+    pub id: i64,
+    /// Owner of the schedule, names are unique per user.
+    pub user_id: i64,
+    pub name: String,
+    pub offsets: Vec<u32>,
+    /// Keeps reviewing every this many days after the last offset.
+    pub repeat_every_days: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct IntervalScheduleInfo {
+    pub name: String,
+    pub offsets: Vec<u32>,
+    pub repeat_every_days: Option<u32>,
+}
+
+/// Body of the endpoints assigning an interval schedule, `null` restores
+/// the default.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct IntervalScheduleAssignment {
+    pub interval_schedule_id: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+    /// Argon2 PHC string, never sent to clients.
+    #[serde(skip_serializing)]
+    pub password_hash: String,
+    /// RFC 3339 timestamp.
+    pub created_at: String,
+    /// IANA time zone the user's days follow, the server's default when
+    /// `None`. The `X-Time-Zone` header overrides it for a request.
+    pub time_zone: Option<String>,
+}
+
+/// Sets the user's time zone, `None` goes back to the server's default.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct TimeZoneUpdate {
+    pub time_zone: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+/// Tokens issued on registration, login and refresh. The access token goes
+/// in the `Authorization: Bearer` header, the refresh token only buys a new
+/// pair once the access token expired.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: String,
+    /// Lifetime of the access token in seconds.
+    pub expires_in: i64,
+}
+
+/// What an API key may do: read-only keys are limited to `GET` requests.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyScope {
+    ReadOnly,
+    ReadWrite,
+}
+
+impl ApiKeyScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiKeyScope::ReadOnly => "read_only",
+            ApiKeyScope::ReadWrite => "read_write",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ApiKey {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    /// Public part of the key, shown to tell keys apart.
+    pub prefix: String,
+    /// SHA-256 of the key, never sent to clients.
+    #[serde(skip_serializing)]
+    pub key_hash: String,
+    pub scope: ApiKeyScope,
+    /// RFC 3339 timestamps.
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ApiKeyInfo {
+    pub name: String,
+    pub scope: ApiKeyScope,
+}
+
+/// A newly created key. `key` is only ever returned here, the server keeps
+/// just its hash.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct CreatedApiKey {
+    pub api_key: ApiKey,
+    pub key: String,
 }
@@ -1,5 +1,13 @@
+use axum::{
+    extract::rejection::{JsonRejection, QueryRejection},
+    http::{header::WWW_AUTHENTICATE, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
 use chrono::ParseError;
+use serde::Serialize;
 use thiserror::Error;
+use tracing::{error, warn};
 
 pub type RepoResult<T> = Result<T, RepositoryError>;
 
@@ -9,6 +17,14 @@ pub enum RepositoryError {
     InternalLibSqlError(#[from] libsql::Error),
     #[error("Deserializing error {0}")]
     DeserializationError(#[from] serde::de::value::Error),
+    #[error("Stored JSON error {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("{0} not found")]
+    NotFound(String),
+    #[error("{0} already exists")]
+    Conflict(String),
+    #[error("Migration error: {0}")]
+    MigrationError(String),
 }
 
 pub type StudyServiceResult<T> = Result<T, StudyServiceError>;
@@ -19,4 +35,179 @@ pub enum StudyServiceError {
     RepositoryError(#[from] RepositoryError),
     #[error("Error parsing date: {0}")]
     ParseDateError(#[from] ParseError),
+    #[error("Invalid scheduler settings: {0}")]
+    InvalidSchedulerSettings(String),
+    #[error("Invalid interval schedule: {0}")]
+    InvalidIntervalSchedule(String),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl From<JsonRejection> for StudyServiceError {
+    fn from(rejection: JsonRejection) -> Self {
+        StudyServiceError::InvalidRequest(rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for StudyServiceError {
+    fn from(rejection: QueryRejection) -> Self {
+        StudyServiceError::InvalidRequest(rejection.body_text())
+    }
+}
+
+/// Body of every error response: `code` is stable for clients to match on,
+/// `message` is meant for humans.
+#[derive(Serialize)]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+}
+
+impl StudyServiceError {
+    fn status_and_code(&self) -> (StatusCode, &'static str) {
+        match self {
+            StudyServiceError::RepositoryError(err) => match err {
+                RepositoryError::NotFound(_) => (StatusCode::NOT_FOUND, "not_found"),
+                RepositoryError::Conflict(_) => (StatusCode::CONFLICT, "conflict"),
+                RepositoryError::InternalLibSqlError(err) if is_unavailable(err) => {
+                    (StatusCode::SERVICE_UNAVAILABLE, "database_unavailable")
+                }
+                _ => (StatusCode::INTERNAL_SERVER_ERROR, "internal_error"),
+            },
+            StudyServiceError::ParseDateError(_) => (StatusCode::BAD_REQUEST, "invalid_date"),
+            StudyServiceError::InvalidSchedulerSettings(_) => {
+                (StatusCode::BAD_REQUEST, "invalid_scheduler_settings")
+            }
+            StudyServiceError::InvalidIntervalSchedule(_) => {
+                (StatusCode::BAD_REQUEST, "invalid_interval_schedule")
+            }
+            StudyServiceError::InvalidRequest(_) => (StatusCode::BAD_REQUEST, "invalid_request"),
+            StudyServiceError::Unauthorized(_) => (StatusCode::UNAUTHORIZED, "unauthorized"),
+            StudyServiceError::Forbidden(_) => (StatusCode::FORBIDDEN, "forbidden"),
+            StudyServiceError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, "internal_error"),
+        }
+    }
+}
+
+impl IntoResponse for StudyServiceError {
+    fn into_response(self) -> Response {
+        let (status, code) = self.status_and_code();
+
+        // Server side failures are logged in full but not echoed to clients.
+        let client_message = match &self {
+            StudyServiceError::RepositoryError(err) if !status.is_server_error() => {
+                Some(err.to_string())
+            }
+            StudyServiceError::RepositoryError(_) | StudyServiceError::Internal(_) => None,
+            StudyServiceError::ParseDateError(err) => Some(format!("Invalid date: {err}")),
+            StudyServiceError::InvalidSchedulerSettings(message)
+            | StudyServiceError::InvalidIntervalSchedule(message)
+            | StudyServiceError::InvalidRequest(message)
+            | StudyServiceError::Unauthorized(message)
+            | StudyServiceError::Forbidden(message) => Some(message.clone()),
+        };
+        let message = match client_message {
+            Some(message) => {
+                warn!("{self}");
+                message
+            }
+            None => {
+                error!("{self}");
+                match status {
+                    StatusCode::SERVICE_UNAVAILABLE => "The database is unavailable".to_string(),
+                    _ => "Internal server error".to_string(),
+                }
+            }
+        };
+
+        let mut response = (status, Json(ErrorBody { code, message })).into_response();
+
+        if status == StatusCode::UNAUTHORIZED {
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+
+        response
+    }
+}
+
+impl IntoResponse for RepositoryError {
+    fn into_response(self) -> Response {
+        StudyServiceError::from(self).into_response()
+    }
+}
+
+/// Errors that mean the database could not be reached, rather than that the
+/// statement itself failed.
+fn is_unavailable(err: &libsql::Error) -> bool {
+    matches!(
+        err,
+        libsql::Error::ConnectionFailed(_)
+            | libsql::Error::Hrana(_)
+            | libsql::Error::Replication(_)
+            | libsql::Error::WriteDelegation(_)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{http::StatusCode, response::IntoResponse};
+
+    use crate::err::{RepositoryError, StudyServiceError};
+
+    async fn body_json(err: StudyServiceError) -> (StatusCode, serde_json::Value) {
+        let response = err.into_response();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn maps_errors_to_status_and_code() {
+        let (status, body) =
+            body_json(RepositoryError::NotFound("subject math".to_string()).into()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "not_found");
+        assert_eq!(body["message"], "subject math not found");
+
+        let (status, body) =
+            body_json(RepositoryError::Conflict("subject math".to_string()).into()).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], "conflict");
+
+        let (status, body) = body_json(
+            RepositoryError::from(libsql::Error::ConnectionFailed("timeout".to_string())).into(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["code"], "database_unavailable");
+        assert_eq!(body["message"], "The database is unavailable");
+
+        let (status, body) = body_json(StudyServiceError::Internal(
+            "Hashing password: panic".to_string(),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["code"], "internal_error");
+        assert_eq!(body["message"], "Internal server error");
+
+        let (status, body) =
+            body_json(StudyServiceError::InvalidRequest("bad body".to_string())).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "invalid_request");
+
+        let response = StudyServiceError::Unauthorized("Token expired".to_string()).into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()["www-authenticate"], "Bearer");
+    }
 }
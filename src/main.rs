@@ -1,19 +1,45 @@
-use std::error::Error;
+use std::{error::Error, sync::Arc, time::Duration};
 
-use repository::Repository;
+use auth_service::{AuthService, TokenLifetimes};
+use chrono::NaiveTime;
+use chrono_tz::Tz;
+use clock::{Clock, SystemClock};
+use repository::{DatabaseConfig, DatabaseMode, LibSqlRepository};
+use scheduler::SessionScheduler;
 use serde::Deserialize;
 use study_service::StudyService;
+mod anki;
 mod api;
+mod auth_service;
+mod clock;
 pub mod domain;
 pub mod err;
+mod migrations;
+mod pagination;
 mod repository;
+mod scheduler;
+mod scheduling;
 mod study_service;
 
 #[derive(Debug, Deserialize)]
 struct Config {
-    db_url: String,
-    db_token: String,
+    #[serde(default)]
+    db_mode: DatabaseMode,
+    db_url: Option<String>,
+    db_token: Option<String>,
+    db_path: Option<String>,
+    db_sync_interval_secs: Option<u64>,
+    time_zone: Option<String>,
+    /// Local time (`HH:MM`) at which each day's sessions are generated.
+    session_generation_time: Option<String>,
     port: String,
+    /// Key the JWTs are signed with.
+    jwt_secret: String,
+    access_token_ttl_secs: Option<i64>,
+    refresh_token_ttl_secs: Option<i64>,
+    /// Password for the user owning the data from before user accounts.
+    /// Only applied while that user has none.
+    default_user_password: Option<String>,
 }
 
 #[tokio::main]
@@ -23,11 +49,58 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     tracing_subscriber::fmt().init();
 
-    let repository = Repository::new(config.db_url, config.db_token).await?;
+    let repository = LibSqlRepository::new(DatabaseConfig {
+        mode: config.db_mode,
+        url: config.db_url,
+        token: config.db_token,
+        path: config.db_path,
+        sync_interval: config.db_sync_interval_secs.map(Duration::from_secs),
+    })
+    .await?;
 
-    let study_service = StudyService::new(repository);
+    let time_zone = match config.time_zone {
+        Some(time_zone) => time_zone
+            .parse::<Tz>()
+            .map_err(|err| format!("Invalid TIME_ZONE {time_zone}: {err}"))?,
+        None => Tz::UTC,
+    };
 
-    api::start_api(study_service, config.port).await;
+    let generation_time = match config.session_generation_time {
+        Some(generation_time) => NaiveTime::parse_from_str(&generation_time, "%H:%M")
+            .map_err(|err| format!("Invalid SESSION_GENERATION_TIME {generation_time}: {err}"))?,
+        None => NaiveTime::MIN,
+    };
+
+    if config.jwt_secret.len() < 32 {
+        return Err("JWT_SECRET must be at least 32 bytes".into());
+    }
+
+    let mut token_lifetimes = TokenLifetimes::default();
+    if let Some(secs) = config.access_token_ttl_secs {
+        token_lifetimes.access = chrono::Duration::seconds(secs);
+    }
+    if let Some(secs) = config.refresh_token_ttl_secs {
+        token_lifetimes.refresh = chrono::Duration::seconds(secs);
+    }
+
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+    let repository = Arc::new(repository);
+
+    let study_service = StudyService::new(repository.clone(), clock.clone(), time_zone);
+    let auth_service = AuthService::new(
+        repository,
+        clock.clone(),
+        config.jwt_secret.as_bytes(),
+        token_lifetimes,
+    );
+
+    if let Some(password) = config.default_user_password {
+        auth_service.set_default_user_password(password).await?;
+    }
+
+    SessionScheduler::new(study_service.clone(), clock, time_zone, generation_time).spawn();
+
+    api::start_api(study_service, auth_service, config.port).await;
 
     Ok(())
 }
@@ -0,0 +1,347 @@
+use libsql::Connection;
+use tracing::info;
+
+use crate::err::{RepoResult, RepositoryError};
+
+struct Migration {
+    version: i64,
+    name: &'static str,
+    sql: &'static str,
+    /// Runs with foreign key enforcement off, for migrations that rebuild
+    /// tables other tables reference. The keys are checked before commit.
+    rebuilds_referenced_tables: bool,
+}
+
+/// Ordered list of the schema migrations embedded in the binary.
+///
+/// New migrations must be appended with the next version number, applied
+/// migrations must never be edited.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial_schema",
+        sql: include_str!("migrations/0001_initial_schema.sql"),
+        rebuilds_referenced_tables: false,
+    },
+    Migration {
+        version: 2,
+        name: "study_session_missed_reviews",
+        sql: include_str!("migrations/0002_study_session_missed_reviews.sql"),
+        rebuilds_referenced_tables: false,
+    },
+    Migration {
+        version: 3,
+        name: "unique_study_session_due_date",
+        sql: include_str!("migrations/0003_unique_study_session_due_date.sql"),
+        rebuilds_referenced_tables: false,
+    },
+    Migration {
+        version: 4,
+        name: "study_topic_sm2",
+        sql: include_str!("migrations/0004_study_topic_sm2.sql"),
+        rebuilds_referenced_tables: false,
+    },
+    Migration {
+        version: 5,
+        name: "schedulers_and_review_log",
+        sql: include_str!("migrations/0005_schedulers_and_review_log.sql"),
+        rebuilds_referenced_tables: false,
+    },
+    Migration {
+        version: 6,
+        name: "interval_schedules",
+        sql: include_str!("migrations/0006_interval_schedules.sql"),
+        rebuilds_referenced_tables: false,
+    },
+    Migration {
+        version: 7,
+        name: "review_history",
+        sql: include_str!("migrations/0007_review_history.sql"),
+        rebuilds_referenced_tables: false,
+    },
+    Migration {
+        version: 8,
+        name: "study_topic_search",
+        sql: include_str!("migrations/0008_study_topic_search.sql"),
+        rebuilds_referenced_tables: false,
+    },
+    Migration {
+        version: 9,
+        name: "users",
+        sql: include_str!("migrations/0009_users.sql"),
+        rebuilds_referenced_tables: false,
+    },
+    Migration {
+        version: 10,
+        name: "user_ownership",
+        sql: include_str!("migrations/0010_user_ownership.sql"),
+        rebuilds_referenced_tables: true,
+    },
+    Migration {
+        version: 11,
+        name: "api_keys",
+        sql: include_str!("migrations/0011_api_keys.sql"),
+        rebuilds_referenced_tables: false,
+    },
+    Migration {
+        version: 12,
+        name: "shared_subjects",
+        sql: include_str!("migrations/0012_shared_subjects.sql"),
+        rebuilds_referenced_tables: true,
+    },
+    Migration {
+        version: 13,
+        name: "flashcards",
+        sql: include_str!("migrations/0013_flashcards.sql"),
+        rebuilds_referenced_tables: false,
+    },
+    Migration {
+        version: 14,
+        name: "flashcard_scheduling",
+        sql: include_str!("migrations/0014_flashcard_scheduling.sql"),
+        rebuilds_referenced_tables: false,
+    },
+    Migration {
+        version: 15,
+        name: "user_time_zone",
+        sql: include_str!("migrations/0015_user_time_zone.sql"),
+        rebuilds_referenced_tables: false,
+    },
+];
+
+/// Applies every migration whose version is not yet recorded in the
+/// `schema_migrations` table, each one inside its own transaction.
+pub async fn run_migrations(conn: &Connection) -> RepoResult<()> {
+    create_migrations_table(conn).await?;
+
+    let current_version = get_current_version(conn).await?;
+
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > current_version)
+    {
+        info!(
+            "Applying migration {:04}_{}",
+            migration.version, migration.name
+        );
+
+        // The pragma is a no-op inside a transaction.
+        if migration.rebuilds_referenced_tables {
+            conn.execute("PRAGMA foreign_keys = OFF", ()).await?;
+        }
+
+        let applied = apply_migration(conn, migration).await;
+
+        if migration.rebuilds_referenced_tables {
+            conn.execute("PRAGMA foreign_keys = ON", ()).await?;
+        }
+
+        applied?;
+    }
+
+    Ok(())
+}
+
+async fn create_migrations_table(conn: &Connection) -> RepoResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+    version INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+)",
+        (),
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn apply_migration(conn: &Connection, migration: &Migration) -> RepoResult<()> {
+    let tx = conn.transaction().await?;
+    tx.execute_batch(migration.sql).await?;
+
+    if migration.rebuilds_referenced_tables {
+        let mut violations = tx.query("PRAGMA foreign_key_check", ()).await?;
+
+        if let Some(row) = violations.next().await? {
+            let table: String = row.get(0)?;
+            return Err(RepositoryError::MigrationError(format!(
+                "{:04}_{} leaves rows of {table} with dangling foreign keys",
+                migration.version, migration.name
+            )));
+        }
+    }
+
+    tx.execute(
+        "INSERT INTO schema_migrations (version, name) VALUES (?1, ?2)",
+        libsql::params![migration.version, migration.name],
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+async fn get_current_version(conn: &Connection) -> RepoResult<i64> {
+    let mut rows = conn
+        .query("SELECT COALESCE(MAX(version), 0) FROM schema_migrations", ())
+        .await?;
+
+    let mut version = 0;
+
+    if let Ok(Some(row)) = rows.next().await {
+        version = row.get(0)?;
+    }
+
+    Ok(version)
+}
+
+#[cfg(test)]
+mod test {
+    use libsql::Builder;
+
+    use crate::migrations::{
+        apply_migration, create_migrations_table, get_current_version, run_migrations, MIGRATIONS,
+    };
+
+    #[tokio::test]
+    async fn migrations_are_applied_once() {
+        let db = Builder::new_local(":memory:").build().await.unwrap();
+        let conn = db.connect().unwrap();
+
+        run_migrations(&conn).await.unwrap();
+        run_migrations(&conn).await.unwrap();
+
+        let latest_version = MIGRATIONS.last().unwrap().version;
+        assert_eq!(get_current_version(&conn).await.unwrap(), latest_version);
+
+        let mut rows = conn
+            .query("SELECT COUNT(*) FROM schema_migrations", ())
+            .await
+            .unwrap();
+        let applied: i64 = rows.next().await.unwrap().unwrap().get(0).unwrap();
+        assert_eq!(applied, MIGRATIONS.len() as i64);
+    }
+
+    #[tokio::test]
+    async fn migrations_create_schema() {
+        let db = Builder::new_local(":memory:").build().await.unwrap();
+        let conn = db.connect().unwrap();
+
+        run_migrations(&conn).await.unwrap();
+
+        conn.execute(
+            "INSERT INTO user (username, password_hash, created_at) VALUES ('ada', '', '2025-01-01T00:00:00+00:00')",
+            (),
+        )
+        .await
+        .unwrap();
+        conn.execute(
+            "INSERT INTO subject (user_id, subject_name) VALUES (1, 'math')",
+            (),
+        )
+        .await
+        .unwrap();
+        conn.execute(
+            "INSERT INTO study_topic (user_id, name, subject_name) VALUES (1, 'limits', 'math')",
+            (),
+        )
+        .await
+        .unwrap();
+        conn.execute(
+            "INSERT INTO study_session (study_topic_id, user_id) VALUES (1, 1)",
+            (),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn rows_from_before_users_move_to_the_default_user() {
+        let db = Builder::new_local(":memory:").build().await.unwrap();
+        let conn = db.connect().unwrap();
+
+        create_migrations_table(&conn).await.unwrap();
+        for migration in MIGRATIONS.iter().filter(|migration| migration.version < 10) {
+            apply_migration(&conn, migration).await.unwrap();
+        }
+
+        conn.execute_batch(
+            "INSERT INTO interval_schedule (name, offsets) VALUES ('weekly', '[0,7]');
+INSERT INTO subject (subject_name, interval_schedule_id) VALUES ('math', 1);
+INSERT INTO study_topic (name, description, subject_name) VALUES ('limits', 'epsilon delta', 'math');
+INSERT INTO study_session (study_topic_id, due_date) VALUES (1, '2025-01-01');
+INSERT INTO review_log (study_topic_id, study_session_id, review_date) VALUES (1, 1, '2025-01-01');",
+        )
+        .await
+        .unwrap();
+
+        run_migrations(&conn).await.unwrap();
+
+        let count = |sql: &'static str| {
+            let conn = conn.clone();
+            async move {
+                let mut rows = conn.query(sql, ()).await.unwrap();
+                rows.next().await.unwrap().unwrap().get::<i64>(0).unwrap()
+            }
+        };
+
+        // Sessions and reviews survive the rebuilt topics they reference,
+        // and like the topic's progress they belong to its owner.
+        assert_eq!(count("SELECT COUNT(*) FROM study_session").await, 1);
+        assert_eq!(count("SELECT COUNT(*) FROM review_log").await, 1);
+        assert_eq!(
+            count(
+                "SELECT COUNT(*) FROM study_session AS ss
+INNER JOIN review_log AS rl ON rl.study_session_id = ss.id AND rl.user_id = ss.user_id
+INNER JOIN study_topic_progress AS p ON p.study_topic_id = ss.study_topic_id AND p.user_id = ss.user_id
+INNER JOIN user AS u ON u.id = ss.user_id AND u.username = 'default'"
+            )
+            .await,
+            1
+        );
+        assert_eq!(
+            count("SELECT COUNT(*) FROM user WHERE username = 'default' AND password_hash = ''")
+                .await,
+            1
+        );
+        assert_eq!(
+            count(
+                "SELECT COUNT(*) FROM study_topic AS st
+INNER JOIN subject AS s ON s.user_id = st.user_id AND s.subject_name = st.subject_name
+INNER JOIN interval_schedule AS i ON i.id = s.interval_schedule_id AND i.user_id = s.user_id
+INNER JOIN user AS u ON u.id = st.user_id AND u.username = 'default'"
+            )
+            .await,
+            1
+        );
+        assert_eq!(
+            count(
+                "SELECT COUNT(*) FROM study_topic_search WHERE study_topic_search MATCH 'epsilon'"
+            )
+            .await,
+            1
+        );
+
+        // Foreign keys are enforced again once the migration is done.
+        assert!(conn
+            .execute(
+                "INSERT INTO study_session (study_topic_id, user_id) VALUES (42, 1)",
+                ()
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn fresh_database_has_no_default_user() {
+        let db = Builder::new_local(":memory:").build().await.unwrap();
+        let conn = db.connect().unwrap();
+
+        run_migrations(&conn).await.unwrap();
+
+        let mut rows = conn.query("SELECT COUNT(*) FROM user", ()).await.unwrap();
+        let users: i64 = rows.next().await.unwrap().unwrap().get(0).unwrap();
+        assert_eq!(users, 0);
+    }
+}
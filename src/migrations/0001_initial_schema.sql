@@ -0,0 +1,20 @@
+CREATE TABLE IF NOT EXISTS subject (
+    subject_name TEXT PRIMARY KEY NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS study_topic (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    description TEXT,
+    creation_date TEXT NOT NULL DEFAULT CURRENT_DATE,
+    subject_name TEXT NOT NULL REFERENCES subject (subject_name) ON DELETE CASCADE,
+    last_session_date TEXT,
+    total_sessions INTEGER NOT NULL DEFAULT 0,
+    completed_sessions INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS study_session (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    study_topic_id INTEGER NOT NULL REFERENCES study_topic (id) ON DELETE CASCADE,
+    due_date TEXT NOT NULL DEFAULT CURRENT_DATE
+);
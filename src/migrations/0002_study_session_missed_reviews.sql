@@ -0,0 +1 @@
+ALTER TABLE study_session ADD COLUMN missed_reviews INTEGER NOT NULL DEFAULT 0;
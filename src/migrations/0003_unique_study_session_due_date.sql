@@ -0,0 +1,7 @@
+DELETE FROM study_session
+WHERE id NOT IN (
+    SELECT MIN(id) FROM study_session GROUP BY study_topic_id, due_date
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS study_session_topic_due_date
+ON study_session (study_topic_id, due_date);
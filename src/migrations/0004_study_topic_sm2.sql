@@ -0,0 +1,4 @@
+ALTER TABLE study_topic ADD COLUMN ease_factor REAL NOT NULL DEFAULT 2.5;
+ALTER TABLE study_topic ADD COLUMN interval_days INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE study_topic ADD COLUMN repetitions INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE study_topic ADD COLUMN next_due_date TEXT;
@@ -0,0 +1,16 @@
+ALTER TABLE subject ADD COLUMN scheduler TEXT NOT NULL DEFAULT 'sm2';
+ALTER TABLE subject ADD COLUMN desired_retention REAL NOT NULL DEFAULT 0.9;
+ALTER TABLE subject ADD COLUMN fsrs_parameters TEXT;
+
+ALTER TABLE study_topic ADD COLUMN stability REAL;
+ALTER TABLE study_topic ADD COLUMN difficulty REAL;
+ALTER TABLE study_topic ADD COLUMN last_review_date TEXT;
+
+CREATE TABLE IF NOT EXISTS review_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    study_topic_id INTEGER NOT NULL REFERENCES study_topic (id) ON DELETE CASCADE,
+    review_date TEXT NOT NULL,
+    grade TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS review_log_study_topic ON review_log (study_topic_id, review_date);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS interval_schedule (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    offsets TEXT NOT NULL,
+    repeat_every_days INTEGER
+);
+
+ALTER TABLE subject ADD COLUMN interval_schedule_id INTEGER REFERENCES interval_schedule (id) ON DELETE SET NULL;
+ALTER TABLE study_topic ADD COLUMN interval_schedule_id INTEGER REFERENCES interval_schedule (id) ON DELETE SET NULL;
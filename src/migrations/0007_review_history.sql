@@ -0,0 +1,23 @@
+ALTER TABLE study_session ADD COLUMN completed_at TEXT;
+
+-- SQLite cannot drop NOT NULL from a column, so review_log is rebuilt to
+-- make the grade optional and record the session the review completed.
+CREATE TABLE review_log_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    study_topic_id INTEGER NOT NULL REFERENCES study_topic (id) ON DELETE CASCADE,
+    study_session_id INTEGER REFERENCES study_session (id) ON DELETE SET NULL,
+    scheduled_date TEXT,
+    review_date TEXT NOT NULL,
+    completed_at TEXT,
+    grade TEXT,
+    time_spent_seconds INTEGER
+);
+
+INSERT INTO review_log_new (id, study_topic_id, review_date, grade)
+SELECT id, study_topic_id, review_date, grade FROM review_log;
+
+DROP TABLE review_log;
+
+ALTER TABLE review_log_new RENAME TO review_log;
+
+CREATE INDEX IF NOT EXISTS review_log_study_topic ON review_log (study_topic_id, review_date);
@@ -0,0 +1,23 @@
+-- Full-text index over the topics, reading its content from study_topic.
+CREATE VIRTUAL TABLE study_topic_search USING fts5(
+    name,
+    description,
+    content = 'study_topic',
+    content_rowid = 'id',
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+INSERT INTO study_topic_search (study_topic_search) VALUES ('rebuild');
+
+CREATE TRIGGER study_topic_search_insert AFTER INSERT ON study_topic BEGIN
+    INSERT INTO study_topic_search (rowid, name, description) VALUES (new.id, new.name, new.description);
+END;
+
+CREATE TRIGGER study_topic_search_delete AFTER DELETE ON study_topic BEGIN
+    INSERT INTO study_topic_search (study_topic_search, rowid, name, description) VALUES ('delete', old.id, old.name, old.description);
+END;
+
+CREATE TRIGGER study_topic_search_update AFTER UPDATE OF name, description ON study_topic BEGIN
+    INSERT INTO study_topic_search (study_topic_search, rowid, name, description) VALUES ('delete', old.id, old.name, old.description);
+    INSERT INTO study_topic_search (rowid, name, description) VALUES (new.id, new.name, new.description);
+END;
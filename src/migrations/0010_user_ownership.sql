@@ -0,0 +1,99 @@
+-- Rows created before users existed are handed to a "default" user, only
+-- created when there is data to own. It cannot log in until a password is
+-- set through DEFAULT_USER_PASSWORD.
+INSERT OR IGNORE INTO user (username, password_hash, created_at)
+SELECT 'default', '', strftime('%Y-%m-%dT%H:%M:%S+00:00', 'now')
+WHERE EXISTS (SELECT 1 FROM subject) OR EXISTS (SELECT 1 FROM interval_schedule);
+
+-- Names become unique per user, so the tables are rebuilt with the owner
+-- in their keys. Runs with foreign keys off: dropping a referenced table
+-- would otherwise cascade into the sessions and review logs.
+CREATE TABLE interval_schedule_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL REFERENCES user (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    offsets TEXT NOT NULL,
+    repeat_every_days INTEGER,
+    UNIQUE (user_id, name)
+);
+
+INSERT INTO interval_schedule_new (id, user_id, name, offsets, repeat_every_days)
+SELECT id, (SELECT id FROM user WHERE username = 'default'), name, offsets, repeat_every_days
+FROM interval_schedule;
+
+DROP TABLE interval_schedule;
+
+ALTER TABLE interval_schedule_new RENAME TO interval_schedule;
+
+CREATE TABLE subject_new (
+    user_id INTEGER NOT NULL REFERENCES user (id) ON DELETE CASCADE,
+    subject_name TEXT NOT NULL,
+    scheduler TEXT NOT NULL DEFAULT 'sm2',
+    desired_retention REAL NOT NULL DEFAULT 0.9,
+    fsrs_parameters TEXT,
+    interval_schedule_id INTEGER REFERENCES interval_schedule (id) ON DELETE SET NULL,
+    PRIMARY KEY (user_id, subject_name)
+);
+
+INSERT INTO subject_new (user_id, subject_name, scheduler, desired_retention, fsrs_parameters, interval_schedule_id)
+SELECT (SELECT id FROM user WHERE username = 'default'), subject_name, scheduler, desired_retention, fsrs_parameters, interval_schedule_id
+FROM subject;
+
+DROP TABLE subject;
+
+ALTER TABLE subject_new RENAME TO subject;
+
+CREATE TABLE study_topic_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    creation_date TEXT NOT NULL DEFAULT CURRENT_DATE,
+    subject_name TEXT NOT NULL,
+    last_session_date TEXT,
+    total_sessions INTEGER NOT NULL DEFAULT 0,
+    completed_sessions INTEGER NOT NULL DEFAULT 0,
+    ease_factor REAL NOT NULL DEFAULT 2.5,
+    interval_days INTEGER NOT NULL DEFAULT 0,
+    repetitions INTEGER NOT NULL DEFAULT 0,
+    next_due_date TEXT,
+    stability REAL,
+    difficulty REAL,
+    last_review_date TEXT,
+    interval_schedule_id INTEGER REFERENCES interval_schedule (id) ON DELETE SET NULL,
+    FOREIGN KEY (user_id, subject_name) REFERENCES subject (user_id, subject_name) ON DELETE CASCADE
+);
+
+INSERT INTO study_topic_new (
+    id, user_id, name, description, creation_date, subject_name, last_session_date,
+    total_sessions, completed_sessions, ease_factor, interval_days, repetitions,
+    next_due_date, stability, difficulty, last_review_date, interval_schedule_id
+)
+SELECT
+    id, (SELECT id FROM user WHERE username = 'default'), name, description, creation_date,
+    subject_name, last_session_date, total_sessions, completed_sessions, ease_factor,
+    interval_days, repetitions, next_due_date, stability, difficulty, last_review_date,
+    interval_schedule_id
+FROM study_topic;
+
+-- Dropping the table drops the search triggers with it.
+DROP TABLE study_topic;
+
+ALTER TABLE study_topic_new RENAME TO study_topic;
+
+CREATE INDEX IF NOT EXISTS study_topic_subject ON study_topic (user_id, subject_name);
+
+CREATE TRIGGER study_topic_search_insert AFTER INSERT ON study_topic BEGIN
+    INSERT INTO study_topic_search (rowid, name, description) VALUES (new.id, new.name, new.description);
+END;
+
+CREATE TRIGGER study_topic_search_delete AFTER DELETE ON study_topic BEGIN
+    INSERT INTO study_topic_search (study_topic_search, rowid, name, description) VALUES ('delete', old.id, old.name, old.description);
+END;
+
+CREATE TRIGGER study_topic_search_update AFTER UPDATE OF name, description ON study_topic BEGIN
+    INSERT INTO study_topic_search (study_topic_search, rowid, name, description) VALUES ('delete', old.id, old.name, old.description);
+    INSERT INTO study_topic_search (rowid, name, description) VALUES (new.id, new.name, new.description);
+END;
+
+INSERT INTO study_topic_search (study_topic_search) VALUES ('rebuild');
@@ -0,0 +1,133 @@
+-- Subjects can be shared by invitation. Members see the owner's subject
+-- and topics, a row stays an invitation until `accepted_at` is set.
+CREATE TABLE subject_member (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    subject_owner_id INTEGER NOT NULL,
+    subject_name TEXT NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES user (id) ON DELETE CASCADE,
+    role TEXT NOT NULL CHECK (role IN ('editor', 'viewer')),
+    invited_at TEXT NOT NULL,
+    accepted_at TEXT,
+    UNIQUE (subject_owner_id, subject_name, user_id),
+    FOREIGN KEY (subject_owner_id, subject_name) REFERENCES subject (user_id, subject_name) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS subject_member_user ON subject_member (user_id);
+
+-- Topics are shared content, the scheduling state and counters are kept
+-- per member. A missing row means the member never studied the topic.
+CREATE TABLE study_topic_progress (
+    study_topic_id INTEGER NOT NULL REFERENCES study_topic (id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES user (id) ON DELETE CASCADE,
+    last_session_date TEXT,
+    total_sessions INTEGER NOT NULL DEFAULT 0,
+    completed_sessions INTEGER NOT NULL DEFAULT 0,
+    ease_factor REAL NOT NULL DEFAULT 2.5,
+    interval_days INTEGER NOT NULL DEFAULT 0,
+    repetitions INTEGER NOT NULL DEFAULT 0,
+    next_due_date TEXT,
+    stability REAL,
+    difficulty REAL,
+    last_review_date TEXT,
+    PRIMARY KEY (study_topic_id, user_id)
+);
+
+INSERT INTO study_topic_progress (
+    study_topic_id, user_id, last_session_date, total_sessions, completed_sessions,
+    ease_factor, interval_days, repetitions, next_due_date, stability, difficulty,
+    last_review_date
+)
+SELECT
+    id, user_id, last_session_date, total_sessions, completed_sessions, ease_factor,
+    interval_days, repetitions, next_due_date, stability, difficulty, last_review_date
+FROM study_topic;
+
+ALTER TABLE study_topic DROP COLUMN last_session_date;
+ALTER TABLE study_topic DROP COLUMN total_sessions;
+ALTER TABLE study_topic DROP COLUMN completed_sessions;
+ALTER TABLE study_topic DROP COLUMN ease_factor;
+ALTER TABLE study_topic DROP COLUMN interval_days;
+ALTER TABLE study_topic DROP COLUMN repetitions;
+ALTER TABLE study_topic DROP COLUMN next_due_date;
+ALTER TABLE study_topic DROP COLUMN stability;
+ALTER TABLE study_topic DROP COLUMN difficulty;
+ALTER TABLE study_topic DROP COLUMN last_review_date;
+
+-- Sessions and reviews belong to the member who studies. Runs with
+-- foreign keys off: review_log references study_session.
+CREATE TABLE study_session_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    study_topic_id INTEGER NOT NULL REFERENCES study_topic (id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES user (id) ON DELETE CASCADE,
+    due_date TEXT NOT NULL DEFAULT CURRENT_DATE,
+    missed_reviews INTEGER NOT NULL DEFAULT 0,
+    completed_at TEXT
+);
+
+INSERT INTO study_session_new (id, study_topic_id, user_id, due_date, missed_reviews, completed_at)
+SELECT ss.id, ss.study_topic_id, st.user_id, ss.due_date, ss.missed_reviews, ss.completed_at
+FROM study_session AS ss
+INNER JOIN study_topic AS st ON st.id = ss.study_topic_id;
+
+DROP TABLE study_session;
+
+ALTER TABLE study_session_new RENAME TO study_session;
+
+CREATE UNIQUE INDEX IF NOT EXISTS study_session_topic_due_date
+ON study_session (study_topic_id, user_id, due_date);
+
+CREATE TABLE review_log_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    study_topic_id INTEGER NOT NULL REFERENCES study_topic (id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES user (id) ON DELETE CASCADE,
+    study_session_id INTEGER REFERENCES study_session (id) ON DELETE SET NULL,
+    scheduled_date TEXT,
+    review_date TEXT NOT NULL,
+    completed_at TEXT,
+    grade TEXT,
+    time_spent_seconds INTEGER
+);
+
+INSERT INTO review_log_new (
+    id, study_topic_id, user_id, study_session_id, scheduled_date, review_date,
+    completed_at, grade, time_spent_seconds
+)
+SELECT
+    rl.id, rl.study_topic_id, st.user_id, rl.study_session_id, rl.scheduled_date,
+    rl.review_date, rl.completed_at, rl.grade, rl.time_spent_seconds
+FROM review_log AS rl
+INNER JOIN study_topic AS st ON st.id = rl.study_topic_id;
+
+DROP TABLE review_log;
+
+ALTER TABLE review_log_new RENAME TO review_log;
+
+CREATE INDEX IF NOT EXISTS review_log_study_topic ON review_log (study_topic_id, user_id, review_date);
+
+-- Every subject once per user who can see it, the owner and the members
+-- that accepted their invitation, with that user's role.
+CREATE VIEW subject_view AS
+SELECT s.user_id AS viewer_id, s.*, 'owner' AS role
+FROM subject AS s
+UNION ALL
+SELECT m.user_id AS viewer_id, s.*, m.role
+FROM subject AS s
+INNER JOIN subject_member AS m ON m.subject_owner_id = s.user_id AND m.subject_name = s.subject_name
+WHERE m.accepted_at IS NOT NULL;
+
+-- Every topic once per user who can see it, with that user's progress.
+-- The fallbacks are the column defaults of study_topic_progress.
+CREATE VIEW study_topic_view AS
+SELECT
+    sv.viewer_id, st.id, st.user_id, st.name, st.description, st.creation_date,
+    st.subject_name, p.last_session_date,
+    COALESCE(p.total_sessions, 0) AS total_sessions,
+    COALESCE(p.completed_sessions, 0) AS completed_sessions,
+    COALESCE(p.ease_factor, 2.5) AS ease_factor,
+    COALESCE(p.interval_days, 0) AS interval_days,
+    COALESCE(p.repetitions, 0) AS repetitions,
+    p.next_due_date, p.stability, p.difficulty, p.last_review_date,
+    st.interval_schedule_id
+FROM study_topic AS st
+INNER JOIN subject_view AS sv ON sv.user_id = st.user_id AND sv.subject_name = st.subject_name
+LEFT JOIN study_topic_progress AS p ON p.study_topic_id = st.id AND p.user_id = sv.viewer_id;
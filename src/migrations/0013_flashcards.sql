@@ -0,0 +1,13 @@
+-- Review material of a topic. Cards are shared content like the topic,
+-- every member of a shared subject sees the same cards.
+CREATE TABLE flashcard (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    study_topic_id INTEGER NOT NULL REFERENCES study_topic (id) ON DELETE CASCADE,
+    front TEXT NOT NULL,
+    back TEXT NOT NULL,
+    hint TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS flashcard_study_topic ON flashcard (study_topic_id, id);
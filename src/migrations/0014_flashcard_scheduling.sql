@@ -0,0 +1,46 @@
+-- Subjects can schedule the cards of their topics one by one instead of
+-- each topic as a whole.
+ALTER TABLE subject ADD COLUMN card_scheduling INTEGER NOT NULL DEFAULT 0;
+
+-- Review state of a card per member, the card counterpart of
+-- study_topic_progress. A missing row means the member never studied it.
+CREATE TABLE flashcard_progress (
+    flashcard_id INTEGER NOT NULL REFERENCES flashcard (id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES user (id) ON DELETE CASCADE,
+    last_session_date TEXT,
+    total_sessions INTEGER NOT NULL DEFAULT 0,
+    completed_sessions INTEGER NOT NULL DEFAULT 0,
+    ease_factor REAL NOT NULL DEFAULT 2.5,
+    interval_days INTEGER NOT NULL DEFAULT 0,
+    repetitions INTEGER NOT NULL DEFAULT 0,
+    next_due_date TEXT,
+    stability REAL,
+    difficulty REAL,
+    last_review_date TEXT,
+    PRIMARY KEY (flashcard_id, user_id)
+);
+
+-- Sessions and reviews of a single card. They still count towards the
+-- topic, whose counters add up the sessions of its cards.
+ALTER TABLE study_session ADD COLUMN flashcard_id INTEGER REFERENCES flashcard (id) ON DELETE CASCADE;
+ALTER TABLE review_log ADD COLUMN flashcard_id INTEGER REFERENCES flashcard (id) ON DELETE CASCADE;
+
+DROP INDEX study_session_topic_due_date;
+
+CREATE UNIQUE INDEX IF NOT EXISTS study_session_topic_due_date
+ON study_session (study_topic_id, user_id, due_date) WHERE flashcard_id IS NULL;
+
+CREATE UNIQUE INDEX IF NOT EXISTS study_session_flashcard_due_date
+ON study_session (flashcard_id, user_id, due_date) WHERE flashcard_id IS NOT NULL;
+
+-- Recreated so `s.*` picks up card_scheduling.
+DROP VIEW subject_view;
+
+CREATE VIEW subject_view AS
+SELECT s.user_id AS viewer_id, s.*, 'owner' AS role
+FROM subject AS s
+UNION ALL
+SELECT m.user_id AS viewer_id, s.*, m.role
+FROM subject AS s
+INNER JOIN subject_member AS m ON m.subject_owner_id = s.user_id AND m.subject_name = s.subject_name
+WHERE m.accepted_at IS NOT NULL;
@@ -0,0 +1,2 @@
+-- IANA time zone the user's days follow, the server's default when NULL.
+ALTER TABLE user ADD COLUMN time_zone TEXT;
@@ -0,0 +1,144 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::err::{StudyServiceError, StudyServiceResult};
+
+pub const DEFAULT_PAGE_SIZE: u32 = 50;
+pub const MAX_PAGE_SIZE: u32 = 200;
+
+/// One page of a list endpoint. `next_cursor` is passed back as `cursor` to
+/// get the following page and is `None` on the last one.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Value of the sort column of a row, the position a page continues from.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, PartialOrd)]
+#[serde(untagged)]
+pub enum SortKey {
+    Integer(i64),
+    Real(f64),
+    Text(String),
+}
+
+impl From<SortKey> for libsql::Value {
+    fn from(key: SortKey) -> Self {
+        match key {
+            SortKey::Integer(value) => libsql::Value::Integer(value),
+            SortKey::Real(value) => libsql::Value::Real(value),
+            SortKey::Text(value) => libsql::Value::Text(value),
+        }
+    }
+}
+
+/// Last row of a page: its sort key and id, which breaks ties between rows
+/// with the same key. Only valid for the sort it was created with.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Cursor {
+    pub sort: String,
+    pub key: SortKey,
+    pub id: i64,
+}
+
+impl Cursor {
+    /// Opaque URL safe form handed to clients.
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).expect("cursor serializes to JSON");
+
+        URL_SAFE_NO_PAD.encode(json)
+    }
+
+    /// Parses a cursor from a client, rejecting it if it was made for
+    /// another sort.
+    pub fn decode(cursor: &str, sort: &str) -> StudyServiceResult<Cursor> {
+        let invalid = || StudyServiceError::InvalidRequest(format!("Invalid cursor: {cursor}"));
+
+        let json = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+        let cursor: Cursor = serde_json::from_slice(&json).map_err(|_| invalid())?;
+
+        if cursor.sort != sort {
+            return Err(invalid());
+        }
+
+        Ok(cursor)
+    }
+}
+
+/// Requested page size, capped at [`MAX_PAGE_SIZE`].
+pub fn page_size(limit: Option<u32>) -> StudyServiceResult<u32> {
+    match limit {
+        Some(0) => Err(StudyServiceError::InvalidRequest(
+            "limit must be positive".to_string(),
+        )),
+        Some(limit) => Ok(limit.min(MAX_PAGE_SIZE)),
+        None => Ok(DEFAULT_PAGE_SIZE),
+    }
+}
+
+/// Builds the page from rows fetched with one more than `limit`, the extra
+/// row only tells that another page follows.
+pub fn into_page<T>(mut rows: Vec<T>, limit: u32, cursor: impl Fn(&T) -> Cursor) -> Page<T> {
+    let next_cursor = if rows.len() > limit as usize {
+        rows.truncate(limit as usize);
+        rows.last().map(|row| cursor(row).encode())
+    } else {
+        None
+    };
+
+    Page {
+        items: rows,
+        next_cursor,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pagination::{into_page, page_size, Cursor, SortKey, MAX_PAGE_SIZE};
+
+    #[test]
+    fn cursor_round_trip() {
+        let cursor = Cursor {
+            sort: "name".to_string(),
+            key: SortKey::Text("limits".to_string()),
+            id: 7,
+        };
+
+        let encoded = cursor.encode();
+        assert_eq!(Cursor::decode(&encoded, "name").unwrap(), cursor);
+
+        assert!(Cursor::decode(&encoded, "creation_date").is_err());
+        assert!(Cursor::decode("not a cursor", "name").is_err());
+    }
+
+    #[test]
+    fn pages_only_link_to_existing_rows() {
+        let cursor = |id: &i64| Cursor {
+            sort: "id".to_string(),
+            key: SortKey::Integer(*id),
+            id: *id,
+        };
+
+        let page = into_page(vec![1, 2, 3], 2, cursor);
+        assert_eq!(page.items, vec![1, 2]);
+        assert_eq!(
+            Cursor::decode(&page.next_cursor.unwrap(), "id").unwrap().id,
+            2
+        );
+
+        let page = into_page(vec![1, 2], 2, cursor);
+        assert_eq!(page.next_cursor, None);
+
+        assert_eq!(page_size(Some(1000)).unwrap(), MAX_PAGE_SIZE);
+        assert!(page_size(Some(0)).is_err());
+    }
+}
@@ -0,0 +1,3825 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use libsql::{de, Builder, Connection, Database, TransactionBehavior};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::{
+    domain::{
+        ApiKey, ApiKeyScope, Flashcard, FlashcardInfo, FlashcardProgress, FlashcardUpdate,
+        IntervalSchedule, IntervalScheduleInfo, ReviewLog, ReviewLogInfo, SchedulerKind,
+        SearchResult, StudySession, StudySessionInfo, StudySessionQuery, StudySessionSort,
+        StudyTopic, StudyTopicInfo, StudyTopicQuery, StudyTopicSchedule, StudyTopicSort,
+        StudyTopicUpdate, Subject, SubjectImport, SubjectInvitation, SubjectMember, SubjectRole,
+        User,
+    },
+    err::{RepoResult, RepositoryError},
+    migrations::run_migrations,
+    pagination::{Cursor, SortOrder},
+    repository::{escape_html, StudyRepository},
+};
+
+/// Where the libsql database lives, selected with the `DB_MODE` variable.
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DatabaseMode {
+    /// Remote libsql server (Turso), needs `url` and `token`.
+    #[default]
+    Remote,
+    /// Local SQLite file at `path`.
+    Local,
+    /// Throwaway in-memory database, lost when the process exits.
+    Memory,
+    /// Local file at `path` kept in sync with the remote at `url`.
+    Replica,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DatabaseConfig {
+    pub mode: DatabaseMode,
+    pub url: Option<String>,
+    pub token: Option<String>,
+    pub path: Option<String>,
+    pub sync_interval: Option<Duration>,
+}
+
+/// `interval_schedule` row, the offsets are stored as a JSON array.
+#[derive(Deserialize)]
+struct IntervalScheduleRow {
+    id: i64,
+    user_id: i64,
+    name: String,
+    offsets: String,
+    repeat_every_days: Option<u32>,
+}
+
+impl TryFrom<IntervalScheduleRow> for IntervalSchedule {
+    type Error = RepositoryError;
+
+    fn try_from(row: IntervalScheduleRow) -> RepoResult<Self> {
+        Ok(IntervalSchedule {
+            id: row.id,
+            user_id: row.user_id,
+            name: row.name,
+            offsets: serde_json::from_str(&row.offsets)?,
+            repeat_every_days: row.repeat_every_days,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct LibSqlRepository {
+    db: Arc<Database>,
+    /// In-memory databases only live as long as their connection, so that
+    /// mode reuses a single connection instead of opening one per call.
+    shared_conn: Option<Connection>,
+    /// Serialises this process's write transactions, a shared connection
+    /// cannot open a second one while another is in progress.
+    write_lock: Arc<Mutex<()>>,
+}
+
+impl LibSqlRepository {
+    pub async fn new(config: DatabaseConfig) -> Result<LibSqlRepository, String> {
+        let db = match config.mode {
+            DatabaseMode::Remote => {
+                let (url, token) = remote_credentials(&config)?;
+                Builder::new_remote(url, token)
+                    .build()
+                    .await
+                    .map_err(|err| format!("Error creating new remote daabase for libsql: {err}"))?
+            }
+            DatabaseMode::Local => Builder::new_local(local_path(&config)?)
+                .build()
+                .await
+                .map_err(|err| format!("Error creating new local database for libsql: {err}"))?,
+            DatabaseMode::Memory => {
+                Builder::new_local(":memory:")
+                    .build()
+                    .await
+                    .map_err(|err| {
+                        format!("Error creating new in-memory database for libsql: {err}")
+                    })?
+            }
+            DatabaseMode::Replica => {
+                let (url, token) = remote_credentials(&config)?;
+                let mut builder = Builder::new_remote_replica(local_path(&config)?, url, token);
+                if let Some(sync_interval) = config.sync_interval {
+                    builder = builder.sync_interval(sync_interval);
+                }
+                let db = builder.build().await.map_err(|err| {
+                    format!("Error creating new embedded replica for libsql: {err}")
+                })?;
+                db.sync()
+                    .await
+                    .map_err(|err| format!("Error syncing embedded replica: {err}"))?;
+                db
+            }
+        };
+
+        let shared_conn = match config.mode {
+            DatabaseMode::Memory => Some(
+                db.connect()
+                    .map_err(|err| format!("Error connecting to in-memory database: {err}"))?,
+            ),
+            _ => None,
+        };
+
+        let repo = LibSqlRepository {
+            db: Arc::new(db),
+            shared_conn,
+            write_lock: Arc::new(Mutex::new(())),
+        };
+
+        let conn = repo
+            .get_connection()
+            .await
+            .map_err(|err| format!("Error connecting to the database: {err}"))?;
+        run_migrations(&conn)
+            .await
+            .map_err(|err| format!("Error running database migrations: {err}"))?;
+
+        info!("Connected to the database in {:?} mode", config.mode);
+
+        Ok(repo)
+    }
+
+    pub async fn get_connection(&self) -> RepoResult<Connection> {
+        if let Some(conn) = &self.shared_conn {
+            return Ok(conn.clone());
+        }
+
+        let conn = self.db.connect()?;
+
+        Ok(conn)
+    }
+
+    /// Up to `limit` pending sessions matching `conditions` and `query`,
+    /// the shared part of the pending session lists.
+    async fn query_pending_study_sessions(
+        &self,
+        mut conditions: Vec<String>,
+        mut params: Vec<libsql::Value>,
+        query: StudySessionQuery,
+        after: Option<Cursor>,
+        limit: u32,
+    ) -> RepoResult<Vec<StudySessionInfo>> {
+        conditions.push("ss.completed_at IS NULL".to_string());
+
+        if let Some(due_before) = query.due_before {
+            conditions.push(format!(
+                "ss.due_date <= {}",
+                push_param(&mut params, due_before)
+            ));
+        }
+
+        let key = match query.sort {
+            StudySessionSort::DueDate => "ss.due_date",
+            StudySessionSort::StudyTopicName => "st.name",
+        };
+        let mut sql = "SELECT ss.id, ss.study_topic_id, ss.flashcard_id, ss.due_date, st.subject_name, st.name AS study_topic_name, ss.missed_reviews FROM study_session AS ss
+INNER JOIN study_topic_view AS st ON ss.study_topic_id = st.id AND ss.user_id = st.viewer_id"
+            .to_string();
+        push_page(
+            &mut sql,
+            conditions,
+            &mut params,
+            key,
+            "ss.id",
+            query.order,
+            after,
+            limit,
+        );
+
+        let conn = self.get_connection().await?;
+        let mut rows = conn.query(&sql, params).await?;
+
+        let mut study_sessions = Vec::new();
+
+        while let Ok(Some(row)) = rows.next().await {
+            let study_session = de::from_row(&row)?;
+
+            study_sessions.push(study_session);
+        }
+
+        Ok(study_sessions)
+    }
+}
+
+#[async_trait]
+impl StudyRepository for LibSqlRepository {
+    async fn get_study_session(
+        &self,
+        user_id: i64,
+        study_session_id: i64,
+    ) -> RepoResult<Option<StudySession>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT id, study_topic_id, user_id, flashcard_id, due_date, missed_reviews, completed_at FROM study_session WHERE id = ?1 AND user_id = ?2 AND study_topic_id IN (SELECT id FROM study_topic_view WHERE viewer_id = ?2)",
+                libsql::params![study_session_id, user_id],
+            )
+            .await?;
+
+        let mut study_session = None;
+
+        if let Ok(Some(row)) = rows.next().await {
+            study_session = Some(de::from_row(&row)?);
+        }
+
+        Ok(study_session)
+    }
+
+    async fn get_study_topic(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+    ) -> RepoResult<Option<StudyTopic>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT * FROM study_topic_view WHERE id = ?1 AND viewer_id = ?2",
+                libsql::params![study_topic_id, user_id],
+            )
+            .await?;
+
+        let mut study_topic = None;
+
+        if let Ok(Some(row)) = rows.next().await {
+            study_topic = Some(de::from_row(&row)?);
+        }
+
+        Ok(study_topic)
+    }
+
+    async fn get_pending_study_sessions_for_study_topic(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+    ) -> RepoResult<Vec<StudySession>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT id, study_topic_id, user_id, flashcard_id, due_date, missed_reviews, completed_at FROM study_session WHERE study_topic_id = ?1 AND user_id = ?2 AND study_topic_id IN (SELECT id FROM study_topic_view WHERE viewer_id = ?2) AND completed_at IS NULL ORDER BY due_date, id",
+                libsql::params![study_topic_id, user_id],
+            )
+            .await?;
+
+        let mut study_sessions = Vec::new();
+
+        while let Ok(Some(row)) = rows.next().await {
+            let study_session = de::from_row(&row)?;
+
+            study_sessions.push(study_session);
+        }
+
+        Ok(study_sessions)
+    }
+
+    async fn create_study_session(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+        flashcard_id: Option<i64>,
+        due_date: String,
+        missed_reviews: i64,
+        created_on: String,
+    ) -> RepoResult<bool> {
+        let conn = self.get_connection().await?;
+        let _write_guard = self.write_lock.lock().await;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await?;
+
+        let inserted = tx
+            .execute(
+                "INSERT OR IGNORE into study_session (study_topic_id, user_id, flashcard_id, due_date, missed_reviews)
+SELECT st.id, st.viewer_id, ?5, ?2, ?3 FROM study_topic_view AS st
+WHERE st.id = ?1 AND st.viewer_id = ?4 AND (?5 IS NULL OR EXISTS (SELECT 1 FROM flashcard AS f WHERE f.id = ?5 AND f.study_topic_id = st.id))",
+                libsql::params![study_topic_id, due_date, missed_reviews, user_id, flashcard_id],
+            )
+            .await?;
+
+        if inserted > 0 {
+            ensure_study_topic_progress(&tx, user_id, study_topic_id).await?;
+            tx.execute(
+                "UPDATE study_topic_progress SET last_session_date = ?3, total_sessions = total_sessions + 1 WHERE study_topic_id = ?1 AND user_id = ?2",
+                libsql::params![study_topic_id, user_id, created_on.clone()],
+            )
+            .await?;
+
+            if let Some(flashcard_id) = flashcard_id {
+                ensure_flashcard_progress(&tx, user_id, flashcard_id).await?;
+                tx.execute(
+                    "UPDATE flashcard_progress SET last_session_date = ?3, total_sessions = total_sessions + 1 WHERE flashcard_id = ?1 AND user_id = ?2",
+                    libsql::params![flashcard_id, user_id, created_on],
+                )
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+
+        Ok(inserted > 0)
+    }
+
+    async fn complete_study_session(
+        &self,
+        user_id: i64,
+        study_session_id: i64,
+        schedule: Option<StudyTopicSchedule>,
+        review_log: ReviewLogInfo,
+    ) -> RepoResult<bool> {
+        let conn = self.get_connection().await?;
+        let _write_guard = self.write_lock.lock().await;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await?;
+
+        // Only a pending session is updated, so a second completion finds
+        // nothing to change. Returning early on an error drops the
+        // transaction, which rolls it back.
+        let mut rows = tx
+            .query(
+                "SELECT study_topic_id, user_id, flashcard_id FROM study_session WHERE id = ?1 AND user_id = ?2 AND completed_at IS NULL AND study_topic_id IN (SELECT id FROM study_topic_view WHERE viewer_id = ?2)",
+                libsql::params![study_session_id, user_id],
+            )
+            .await?;
+        let Some(row) = rows.next().await? else {
+            tx.rollback().await?;
+            return Ok(false);
+        };
+        // The review is logged for the session's own topic and card, not
+        // whatever the caller passed.
+        let study_topic_id: i64 = row.get(0)?;
+        let user_id: i64 = row.get(1)?;
+        let flashcard_id: Option<i64> = row.get(2)?;
+
+        tx.execute(
+            "UPDATE study_session SET completed_at = ?2 WHERE id = ?1",
+            libsql::params![study_session_id, review_log.completed_at.clone()],
+        )
+        .await?;
+
+        ensure_study_topic_progress(&tx, user_id, study_topic_id).await?;
+
+        // A card session schedules the card, the topic only counts it.
+        let (schedule_sql, scheduled_id) = match flashcard_id {
+            Some(flashcard_id) => {
+                ensure_flashcard_progress(&tx, user_id, flashcard_id).await?;
+                tx.execute(
+                    "UPDATE flashcard_progress SET completed_sessions = completed_sessions + 1 WHERE flashcard_id = ?1 AND user_id = ?2",
+                    libsql::params![flashcard_id, user_id],
+                )
+                .await?;
+
+                (
+                    "UPDATE flashcard_progress SET ease_factor = ?3, interval_days = ?4, repetitions = ?5, stability = ?6, difficulty = ?7, next_due_date = ?8, last_review_date = ?9 WHERE flashcard_id = ?1 AND user_id = ?2",
+                    flashcard_id,
+                )
+            }
+            None => (
+                "UPDATE study_topic_progress SET ease_factor = ?3, interval_days = ?4, repetitions = ?5, stability = ?6, difficulty = ?7, next_due_date = ?8, last_review_date = ?9 WHERE study_topic_id = ?1 AND user_id = ?2",
+                study_topic_id,
+            ),
+        };
+
+        if let Some(schedule) = schedule {
+            tx.execute(
+                schedule_sql,
+                libsql::params![
+                    scheduled_id,
+                    user_id,
+                    schedule.ease_factor,
+                    schedule.interval_days,
+                    schedule.repetitions,
+                    schedule.stability,
+                    schedule.difficulty,
+                    schedule.next_due_date,
+                    schedule.last_review_date
+                ],
+            )
+            .await?;
+        }
+
+        tx.execute(
+            "UPDATE study_topic_progress SET completed_sessions = completed_sessions + 1 WHERE study_topic_id = ?1 AND user_id = ?2",
+            libsql::params![study_topic_id, user_id],
+        )
+        .await?;
+
+        tx.execute(
+            "INSERT INTO review_log (study_topic_id, user_id, flashcard_id, study_session_id, scheduled_date, review_date, completed_at, grade, time_spent_seconds) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            libsql::params![
+                study_topic_id,
+                user_id,
+                flashcard_id,
+                study_session_id,
+                review_log.scheduled_date,
+                review_log.review_date,
+                review_log.completed_at,
+                review_log.grade.map(|grade| grade.as_str()),
+                review_log.time_spent_seconds
+            ],
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn get_subjects(&self, user_id: i64) -> RepoResult<Vec<Subject>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT * FROM subject_view WHERE viewer_id = ?1",
+                libsql::params![user_id],
+            )
+            .await?;
+
+        let mut subjects = Vec::new();
+
+        while let Ok(Some(row)) = rows.next().await {
+            let subject = de::from_row(&row)?;
+
+            subjects.push(subject);
+        }
+
+        Ok(subjects)
+    }
+
+    async fn get_subject(&self, user_id: i64, subject_name: String) -> RepoResult<Option<Subject>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT * FROM subject_view WHERE viewer_id = ?1 AND subject_name = ?2",
+                libsql::params![user_id, subject_name],
+            )
+            .await?;
+
+        let mut subject = None;
+
+        if let Ok(Some(row)) = rows.next().await {
+            subject = Some(de::from_row(&row)?);
+        }
+
+        Ok(subject)
+    }
+
+    async fn update_subject_scheduler(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        scheduler: SchedulerKind,
+        desired_retention: f64,
+        fsrs_parameters: Option<String>,
+        card_scheduling: bool,
+    ) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+        let updated = conn
+            .execute(
+                "UPDATE subject SET scheduler = ?2, desired_retention = ?3, fsrs_parameters = ?4, card_scheduling = ?6 WHERE subject_name = ?1 AND user_id = ?5",
+                libsql::params![
+                    subject_name.clone(),
+                    scheduler.as_str(),
+                    desired_retention,
+                    fsrs_parameters,
+                    user_id,
+                    card_scheduling
+                ],
+            )
+            .await?;
+
+        ensure_affected(updated, || format!("subject {subject_name}"))
+    }
+
+    async fn add_subject(&self, user_id: i64, subject_name: String) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+        let inserted = conn
+            .execute(
+                "INSERT INTO subject (user_id, subject_name) SELECT ?1, ?2 WHERE NOT EXISTS (SELECT 1 FROM subject_view WHERE viewer_id = ?1 AND subject_name = ?2)",
+                libsql::params!(user_id, subject_name.clone()),
+            )
+            .await
+            .map_err(|err| {
+                constraint_error(err, format!("subject {subject_name}"), String::new())
+            })?;
+
+        // Nothing is inserted when a subject shared with the user has the
+        // name.
+        if inserted == 0 {
+            return Err(RepositoryError::Conflict(format!("subject {subject_name}")));
+        }
+
+        Ok(())
+    }
+
+    async fn import_subjects(&self, user_id: i64, subjects: Vec<SubjectImport>) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+        let _write_guard = self.write_lock.lock().await;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await?;
+
+        // Returning early on an error drops the transaction, which rolls
+        // back the subjects imported so far.
+        for subject in subjects {
+            let subject_name = subject.subject_name;
+            let inserted = tx
+                .execute(
+                    "INSERT INTO subject (user_id, subject_name, scheduler, card_scheduling) SELECT ?1, ?2, ?3, 1 WHERE NOT EXISTS (SELECT 1 FROM subject_view WHERE viewer_id = ?1 AND subject_name = ?2)",
+                    libsql::params![user_id, subject_name.clone(), subject.scheduler.as_str()],
+                )
+                .await
+                .map_err(|err| {
+                    constraint_error(err, format!("subject {subject_name}"), String::new())
+                })?;
+            if inserted == 0 {
+                return Err(RepositoryError::Conflict(format!("subject {subject_name}")));
+            }
+
+            for study_topic in subject.study_topics {
+                tx.execute(
+                    "INSERT INTO study_topic (user_id, name, description, subject_name, creation_date) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    libsql::params![
+                        user_id,
+                        study_topic.name,
+                        study_topic.description,
+                        subject_name.clone(),
+                        study_topic.creation_date
+                    ],
+                )
+                .await?;
+                let study_topic_id = tx.last_insert_rowid();
+
+                // The topic counts the reviews of its cards.
+                let mut reviews = 0;
+                let mut last_review_date = None;
+
+                for flashcard in study_topic.flashcards {
+                    tx.execute(
+                        "INSERT INTO flashcard (study_topic_id, front, back, hint, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
+                        libsql::params![
+                            study_topic_id,
+                            flashcard.flashcard.front,
+                            flashcard.flashcard.back,
+                            flashcard.flashcard.hint,
+                            flashcard.created_at
+                        ],
+                    )
+                    .await?;
+                    let flashcard_id = tx.last_insert_rowid();
+
+                    for review in &flashcard.reviews {
+                        tx.execute(
+                            "INSERT INTO review_log (study_topic_id, user_id, flashcard_id, review_date, completed_at, grade, time_spent_seconds) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                            libsql::params![
+                                study_topic_id,
+                                user_id,
+                                flashcard_id,
+                                review.review_date.clone(),
+                                review.completed_at.clone(),
+                                review.grade.as_str(),
+                                review.time_spent_seconds
+                            ],
+                        )
+                        .await?;
+                    }
+
+                    let card_reviews = flashcard.reviews.len() as i64;
+                    let card_last_review_date = flashcard
+                        .reviews
+                        .last()
+                        .map(|review| review.review_date.clone());
+                    reviews += card_reviews;
+                    last_review_date = last_review_date.max(card_last_review_date.clone());
+
+                    if flashcard.schedule.is_none() && card_reviews == 0 {
+                        continue;
+                    }
+                    ensure_flashcard_progress(&tx, user_id, flashcard_id).await?;
+                    tx.execute(
+                        "UPDATE flashcard_progress SET last_session_date = ?3, total_sessions = ?4, completed_sessions = ?4 WHERE flashcard_id = ?1 AND user_id = ?2",
+                        libsql::params![flashcard_id, user_id, card_last_review_date, card_reviews],
+                    )
+                    .await?;
+                    if let Some(schedule) = flashcard.schedule {
+                        tx.execute(
+                            "UPDATE flashcard_progress SET ease_factor = ?3, interval_days = ?4, repetitions = ?5, stability = ?6, difficulty = ?7, next_due_date = ?8, last_review_date = ?9 WHERE flashcard_id = ?1 AND user_id = ?2",
+                            libsql::params![
+                                flashcard_id,
+                                user_id,
+                                schedule.ease_factor,
+                                schedule.interval_days,
+                                schedule.repetitions,
+                                schedule.stability,
+                                schedule.difficulty,
+                                schedule.next_due_date,
+                                schedule.last_review_date
+                            ],
+                        )
+                        .await?;
+                    }
+                }
+
+                if reviews > 0 {
+                    ensure_study_topic_progress(&tx, user_id, study_topic_id).await?;
+                    tx.execute(
+                        "UPDATE study_topic_progress SET last_session_date = ?3, total_sessions = ?4, completed_sessions = ?4 WHERE study_topic_id = ?1 AND user_id = ?2",
+                        libsql::params![study_topic_id, user_id, last_review_date, reviews],
+                    )
+                    .await?;
+                }
+            }
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn update_subject_interval_schedule(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        interval_schedule_id: Option<i64>,
+    ) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+        let updated = conn
+            .execute(
+                "UPDATE subject SET interval_schedule_id = ?2 WHERE subject_name = ?1 AND user_id = ?3",
+                libsql::params![subject_name.clone(), interval_schedule_id, user_id],
+            )
+            .await
+            .map_err(|err| {
+                constraint_error(
+                    err,
+                    String::new(),
+                    interval_schedule_not_found(interval_schedule_id),
+                )
+            })?;
+
+        ensure_affected(updated, || format!("subject {subject_name}"))
+    }
+
+    async fn rename_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        new_subject_name: String,
+    ) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+        let _write_guard = self.write_lock.lock().await;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await?;
+
+        // Neither the owner nor the members may end up with two subjects of
+        // the same name, subjects shared with them included.
+        let mut taken = tx
+            .query(
+                "SELECT 1 FROM subject_view WHERE subject_name = ?2 AND (viewer_id = ?3 OR viewer_id IN (SELECT user_id FROM subject_member WHERE subject_owner_id = ?3 AND subject_name = ?1 AND accepted_at IS NOT NULL))",
+                libsql::params![subject_name.clone(), new_subject_name.clone(), user_id],
+            )
+            .await?;
+        if taken.next().await?.is_some() {
+            return Err(RepositoryError::Conflict(format!(
+                "subject {new_subject_name}"
+            )));
+        }
+
+        // The topics and members reference the subject by name without ON
+        // UPDATE CASCADE, so the subject is copied under the new name, they
+        // are moved over and only then is the old row deleted.
+        let copied = tx
+            .execute(
+                "INSERT INTO subject (user_id, subject_name, scheduler, desired_retention, fsrs_parameters, interval_schedule_id, card_scheduling)
+SELECT user_id, ?2, scheduler, desired_retention, fsrs_parameters, interval_schedule_id, card_scheduling FROM subject WHERE subject_name = ?1 AND user_id = ?3",
+                libsql::params![subject_name.clone(), new_subject_name.clone(), user_id],
+            )
+            .await
+            .map_err(|err| {
+                constraint_error(err, format!("subject {new_subject_name}"), String::new())
+            })?;
+        ensure_affected(copied, || format!("subject {subject_name}"))?;
+
+        tx.execute(
+            "UPDATE study_topic SET subject_name = ?2 WHERE subject_name = ?1 AND user_id = ?3",
+            libsql::params![subject_name.clone(), new_subject_name.clone(), user_id],
+        )
+        .await?;
+
+        tx.execute(
+            "UPDATE subject_member SET subject_name = ?2 WHERE subject_name = ?1 AND subject_owner_id = ?3",
+            libsql::params![subject_name.clone(), new_subject_name, user_id],
+        )
+        .await?;
+
+        tx.execute(
+            "DELETE FROM subject WHERE subject_name = ?1 AND user_id = ?2",
+            libsql::params![subject_name, user_id],
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn delete_subject(&self, user_id: i64, subject_name: String) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+        let deleted = conn
+            .execute(
+                "DELETE FROM subject WHERE subject_name = ?1 AND user_id = ?2",
+                libsql::params!(subject_name.clone(), user_id),
+            )
+            .await?;
+
+        ensure_affected(deleted, || format!("subject {subject_name}"))
+    }
+
+    async fn get_subject_members(
+        &self,
+        user_id: i64,
+        subject_name: String,
+    ) -> RepoResult<Vec<SubjectMember>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT u.id AS user_id, u.username, m.role, m.invited_at, m.accepted_at FROM subject_member AS m
+INNER JOIN subject_view AS sv ON sv.user_id = m.subject_owner_id AND sv.subject_name = m.subject_name
+INNER JOIN user AS u ON u.id = m.user_id
+WHERE sv.viewer_id = ?1 AND sv.subject_name = ?2
+ORDER BY m.id",
+                libsql::params![user_id, subject_name],
+            )
+            .await?;
+
+        let mut subject_members = Vec::new();
+
+        while let Ok(Some(row)) = rows.next().await {
+            let subject_member = de::from_row(&row)?;
+
+            subject_members.push(subject_member);
+        }
+
+        Ok(subject_members)
+    }
+
+    async fn add_subject_member(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        member_id: i64,
+        role: SubjectRole,
+        invited_at: String,
+    ) -> RepoResult<i64> {
+        let conn = self.get_connection().await?;
+        let inserted = conn
+            .execute(
+                "INSERT INTO subject_member (subject_owner_id, subject_name, user_id, role, invited_at) SELECT user_id, subject_name, ?3, ?4, ?5 FROM subject WHERE user_id = ?1 AND subject_name = ?2",
+                libsql::params![
+                    user_id,
+                    subject_name.clone(),
+                    member_id,
+                    role.as_str(),
+                    invited_at
+                ],
+            )
+            .await
+            .map_err(|err| {
+                constraint_error(
+                    err,
+                    format!("member {member_id} of subject {subject_name}"),
+                    format!("user {member_id}"),
+                )
+            })?;
+        ensure_affected(inserted, || format!("subject {subject_name}"))?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    async fn delete_subject_member(
+        &self,
+        owner_id: i64,
+        subject_name: String,
+        member_id: i64,
+    ) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+        let _write_guard = self.write_lock.lock().await;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await?;
+
+        let deleted = tx
+            .execute(
+                "DELETE FROM subject_member WHERE subject_owner_id = ?1 AND subject_name = ?2 AND user_id = ?3",
+                libsql::params![owner_id, subject_name.clone(), member_id],
+            )
+            .await?;
+        ensure_affected(deleted, || {
+            format!("member {member_id} of subject {subject_name}")
+        })?;
+
+        for table in ["review_log", "study_session", "study_topic_progress"] {
+            tx.execute(
+                &format!("DELETE FROM {table} WHERE user_id = ?3 AND study_topic_id IN (SELECT id FROM study_topic WHERE user_id = ?1 AND subject_name = ?2)"),
+                libsql::params![owner_id, subject_name.clone(), member_id],
+            )
+            .await?;
+        }
+        tx.execute(
+            "DELETE FROM flashcard_progress WHERE user_id = ?3 AND flashcard_id IN (SELECT f.id FROM flashcard AS f INNER JOIN study_topic AS st ON st.id = f.study_topic_id WHERE st.user_id = ?1 AND st.subject_name = ?2)",
+            libsql::params![owner_id, subject_name.clone(), member_id],
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_subject_invitations(&self, user_id: i64) -> RepoResult<Vec<SubjectInvitation>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT m.id, m.subject_name, u.username AS owner_username, m.role, m.invited_at FROM subject_member AS m
+INNER JOIN user AS u ON u.id = m.subject_owner_id
+WHERE m.user_id = ?1 AND m.accepted_at IS NULL
+ORDER BY m.id",
+                libsql::params![user_id],
+            )
+            .await?;
+
+        let mut subject_invitations = Vec::new();
+
+        while let Ok(Some(row)) = rows.next().await {
+            let subject_invitation = de::from_row(&row)?;
+
+            subject_invitations.push(subject_invitation);
+        }
+
+        Ok(subject_invitations)
+    }
+
+    async fn accept_subject_invitation(
+        &self,
+        user_id: i64,
+        invitation_id: i64,
+        accepted_at: String,
+    ) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+        let _write_guard = self.write_lock.lock().await;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .await?;
+
+        let mut rows = tx
+            .query(
+                "SELECT subject_name, EXISTS (SELECT 1 FROM subject_view AS sv WHERE sv.viewer_id = m.user_id AND sv.subject_name = m.subject_name) FROM subject_member AS m WHERE id = ?1 AND user_id = ?2 AND accepted_at IS NULL",
+                libsql::params![invitation_id, user_id],
+            )
+            .await?;
+
+        let Some(row) = rows.next().await? else {
+            return Err(RepositoryError::NotFound(format!(
+                "invitation {invitation_id}"
+            )));
+        };
+        let subject_name: String = row.get(0)?;
+        let taken: bool = row.get(1)?;
+
+        if taken {
+            return Err(RepositoryError::Conflict(format!("subject {subject_name}")));
+        }
+
+        tx.execute(
+            "UPDATE subject_member SET accepted_at = ?2 WHERE id = ?1",
+            libsql::params![invitation_id, accepted_at],
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn delete_subject_invitation(&self, user_id: i64, invitation_id: i64) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+        let deleted = conn
+            .execute(
+                "DELETE FROM subject_member WHERE id = ?1 AND user_id = ?2 AND accepted_at IS NULL",
+                libsql::params![invitation_id, user_id],
+            )
+            .await?;
+
+        ensure_affected(deleted, || format!("invitation {invitation_id}"))
+    }
+
+    async fn get_study_topics_for_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+    ) -> RepoResult<Vec<StudyTopic>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT * FROM study_topic_view WHERE viewer_id = ?1 AND subject_name = ?2",
+                libsql::params![user_id, subject_name],
+            )
+            .await?;
+
+        let mut study_topics = Vec::new();
+
+        while let Ok(Some(row)) = rows.next().await {
+            let study_topic = de::from_row(&row)?;
+
+            study_topics.push(study_topic);
+        }
+
+        Ok(study_topics)
+    }
+
+    async fn get_study_sessions_for_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+    ) -> RepoResult<Vec<StudySessionInfo>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT ss.id, ss.study_topic_id, ss.flashcard_id, ss.due_date, st.subject_name, st.name AS study_topic_name, ss.missed_reviews FROM study_session AS ss
+INNER JOIN study_topic_view AS st ON ss.study_topic_id = st.id AND ss.user_id = st.viewer_id
+WHERE st.viewer_id = ?1 AND st.subject_name = ?2 AND ss.completed_at IS NULL",
+                libsql::params![user_id, subject_name],
+            )
+            .await?;
+
+        let mut study_sessions = Vec::new();
+
+        while let Ok(Some(row)) = rows.next().await {
+            let study_session = de::from_row(&row)?;
+
+            study_sessions.push(study_session);
+        }
+
+        Ok(study_sessions)
+    }
+
+    async fn query_study_sessions_for_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        query: StudySessionQuery,
+        after: Option<Cursor>,
+        limit: u32,
+    ) -> RepoResult<Vec<StudySessionInfo>> {
+        let mut params = Vec::new();
+        let conditions = vec![
+            format!("st.viewer_id = {}", push_param(&mut params, user_id)),
+            format!(
+                "st.subject_name = {}",
+                push_param(&mut params, subject_name)
+            ),
+        ];
+
+        self.query_pending_study_sessions(conditions, params, query, after, limit)
+            .await
+    }
+
+    async fn query_flashcard_sessions(
+        &self,
+        user_id: i64,
+        query: StudySessionQuery,
+        after: Option<Cursor>,
+        limit: u32,
+    ) -> RepoResult<Vec<StudySessionInfo>> {
+        let mut params = Vec::new();
+        let conditions = vec![
+            format!("st.viewer_id = {}", push_param(&mut params, user_id)),
+            "ss.flashcard_id IS NOT NULL".to_string(),
+        ];
+
+        self.query_pending_study_sessions(conditions, params, query, after, limit)
+            .await
+    }
+
+    async fn get_study_topics(&self, user_id: i64) -> RepoResult<Vec<StudyTopic>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT * FROM study_topic_view WHERE viewer_id = ?1",
+                libsql::params![user_id],
+            )
+            .await?;
+
+        let mut study_topics = Vec::new();
+
+        while let Ok(Some(row)) = rows.next().await {
+            let study_topic = de::from_row(&row)?;
+
+            study_topics.push(study_topic);
+        }
+
+        Ok(study_topics)
+    }
+
+    async fn query_study_topics(
+        &self,
+        user_id: i64,
+        query: StudyTopicQuery,
+        after: Option<Cursor>,
+        limit: u32,
+    ) -> RepoResult<Vec<StudyTopic>> {
+        let mut sql = String::new();
+        let mut params = Vec::new();
+        let mut conditions = vec![format!(
+            "st.viewer_id = {}",
+            push_param(&mut params, user_id)
+        )];
+
+        if let Some(review_on) = query.review_on {
+            // Bound as ?2, after the user as ?1.
+            push_param(&mut params, review_on);
+            sql.push_str(REVIEW_ON_SQL);
+            conditions.push("st.id IN (SELECT study_topic_id FROM review_on)".to_string());
+        }
+        if let Some(subject_name) = query.subject_name {
+            conditions.push(format!(
+                "st.subject_name = {}",
+                push_param(&mut params, subject_name)
+            ));
+        }
+        if let Some(created_from) = query.created_from {
+            conditions.push(format!(
+                "st.creation_date >= {}",
+                push_param(&mut params, created_from)
+            ));
+        }
+        if let Some(created_to) = query.created_to {
+            conditions.push(format!(
+                "st.creation_date <= {}",
+                push_param(&mut params, created_to)
+            ));
+        }
+        if let Some(due_before) = query.due_before {
+            let date = push_param(&mut params, due_before);
+            conditions.push(format!(
+                "(st.next_due_date <= {date} OR EXISTS (SELECT 1 FROM study_session AS ss WHERE ss.study_topic_id = st.id AND ss.user_id = st.viewer_id AND ss.completed_at IS NULL AND ss.due_date <= {date}))"
+            ));
+        }
+        if let Some(has_pending_session) = query.has_pending_session {
+            conditions.push(format!(
+                "{}EXISTS (SELECT 1 FROM study_session AS ss WHERE ss.study_topic_id = st.id AND ss.user_id = st.viewer_id AND ss.completed_at IS NULL)",
+                if has_pending_session { "" } else { "NOT " }
+            ));
+        }
+
+        let key = match query.sort {
+            StudyTopicSort::Id => "st.id",
+            StudyTopicSort::Name => "st.name",
+            StudyTopicSort::CreationDate => "st.creation_date",
+            StudyTopicSort::LastSessionDate => "COALESCE(st.last_session_date, '')",
+            StudyTopicSort::CompletionRatio => {
+                "CASE WHEN st.total_sessions > 0 THEN CAST(st.completed_sessions AS REAL) / st.total_sessions ELSE 0.0 END"
+            }
+        };
+        sql.push_str("SELECT st.* FROM study_topic_view AS st");
+        push_page(
+            &mut sql,
+            conditions,
+            &mut params,
+            key,
+            "st.id",
+            query.order,
+            after,
+            limit,
+        );
+
+        let conn = self.get_connection().await?;
+        let mut rows = conn.query(&sql, params).await?;
+
+        let mut study_topics = Vec::new();
+
+        while let Ok(Some(row)) = rows.next().await {
+            let study_topic = de::from_row(&row)?;
+
+            study_topics.push(study_topic);
+        }
+
+        Ok(study_topics)
+    }
+
+    async fn search_study_topics(
+        &self,
+        user_id: i64,
+        terms: Vec<String>,
+        limit: u32,
+    ) -> RepoResult<Vec<SearchResult>> {
+        // Every term is quoted so FTS5 syntax in the input is matched
+        // literally, the trailing * makes it a prefix query.
+        let match_query = terms
+            .iter()
+            .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT st.id AS study_topic_id, st.name, st.subject_name,
+    highlight(study_topic_search, 0, ?4, ?5) AS name_highlight,
+    CASE WHEN st.description IS NULL THEN NULL ELSE snippet(study_topic_search, 1, ?4, ?5, '…', 16) END AS description_snippet,
+    bm25(study_topic_search) AS rank
+FROM study_topic_search
+INNER JOIN study_topic_view AS st ON st.id = study_topic_search.rowid
+WHERE study_topic_search MATCH ?1 AND st.viewer_id = ?3
+ORDER BY rank, st.id
+LIMIT ?2",
+                libsql::params![
+                    match_query,
+                    limit,
+                    user_id,
+                    MATCH_START.to_string(),
+                    MATCH_END.to_string()
+                ],
+            )
+            .await?;
+
+        let mut search_results = Vec::new();
+
+        while let Ok(Some(row)) = rows.next().await {
+            let mut search_result: SearchResult = de::from_row(&row)?;
+            search_result.name_highlight = mark_matches(&search_result.name_highlight);
+            search_result.description_snippet = search_result
+                .description_snippet
+                .as_deref()
+                .map(mark_matches);
+
+            search_results.push(search_result);
+        }
+
+        Ok(search_results)
+    }
+
+    async fn add_study_topic(
+        &self,
+        user_id: i64,
+        study_topic: StudyTopicInfo,
+        creation_date: String,
+    ) -> RepoResult<i64> {
+        let conn = self.get_connection().await?;
+        let inserted = conn
+            .execute(
+                "INSERT INTO study_topic (user_id, name, description, subject_name, creation_date, interval_schedule_id) SELECT user_id, ?2, ?3, subject_name, ?5, ?6 FROM subject_view WHERE viewer_id = ?1 AND subject_name = ?4",
+                libsql::params![
+                    user_id,
+                    study_topic.name,
+                    study_topic.description,
+                    study_topic.subject_name.clone(),
+                    creation_date,
+                    study_topic.interval_schedule_id
+                ],
+            )
+            .await
+            .map_err(|err| {
+                constraint_error(
+                    err,
+                    String::new(),
+                    format!("subject {} or interval schedule", study_topic.subject_name),
+                )
+            })?;
+
+        ensure_affected(inserted, || format!("subject {}", study_topic.subject_name))?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    async fn update_study_topic(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+        update: StudyTopicUpdate,
+    ) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+        let updated = conn
+            .execute(
+                "UPDATE study_topic SET name = COALESCE(?2, name), description = CASE WHEN ?3 THEN ?4 ELSE description END, subject_name = COALESCE(?5, subject_name) WHERE id = ?1 AND id IN (SELECT id FROM study_topic_view WHERE viewer_id = ?6)",
+                libsql::params![
+                    study_topic_id,
+                    update.name,
+                    update.description.is_some(),
+                    update.description.flatten(),
+                    update.subject_name.clone(),
+                    user_id
+                ],
+            )
+            .await
+            .map_err(|err| {
+                constraint_error(
+                    err,
+                    String::new(),
+                    format!("subject {}", update.subject_name.unwrap_or_default()),
+                )
+            })?;
+
+        ensure_affected(updated, || format!("study topic {study_topic_id}"))
+    }
+
+    async fn update_study_topic_interval_schedule(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+        interval_schedule_id: Option<i64>,
+    ) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+        let updated = conn
+            .execute(
+                "UPDATE study_topic SET interval_schedule_id = ?2 WHERE id = ?1 AND id IN (SELECT id FROM study_topic_view WHERE viewer_id = ?3)",
+                libsql::params![study_topic_id, interval_schedule_id, user_id],
+            )
+            .await
+            .map_err(|err| {
+                constraint_error(
+                    err,
+                    String::new(),
+                    interval_schedule_not_found(interval_schedule_id),
+                )
+            })?;
+
+        ensure_affected(updated, || format!("study topic {study_topic_id}"))
+    }
+
+    async fn delete_study_topic(&self, user_id: i64, study_topic_id: i64) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+
+        let deleted = conn
+            .execute(
+                "DELETE FROM study_topic WHERE id = ?1 AND id IN (SELECT id FROM study_topic_view WHERE viewer_id = ?2)",
+                libsql::params![study_topic_id, user_id],
+            )
+            .await?;
+
+        ensure_affected(deleted, || format!("study topic {study_topic_id}"))
+    }
+
+    async fn get_flashcards(
+        &self,
+        user_id: i64,
+        study_topic_ids: Vec<i64>,
+    ) -> RepoResult<Vec<Flashcard>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT * FROM flashcard WHERE study_topic_id IN (SELECT value FROM json_each(?1)) AND study_topic_id IN (SELECT id FROM study_topic_view WHERE viewer_id = ?2) ORDER BY study_topic_id, id",
+                libsql::params![serde_json::to_string(&study_topic_ids)?, user_id],
+            )
+            .await?;
+
+        let mut flashcards = Vec::new();
+
+        while let Ok(Some(row)) = rows.next().await {
+            let flashcard = de::from_row(&row)?;
+
+            flashcards.push(flashcard);
+        }
+
+        Ok(flashcards)
+    }
+
+    async fn get_flashcard(
+        &self,
+        user_id: i64,
+        flashcard_id: i64,
+    ) -> RepoResult<Option<Flashcard>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT * FROM flashcard WHERE id = ?1 AND study_topic_id IN (SELECT id FROM study_topic_view WHERE viewer_id = ?2)",
+                libsql::params![flashcard_id, user_id],
+            )
+            .await?;
+
+        let mut flashcard = None;
+
+        if let Ok(Some(row)) = rows.next().await {
+            flashcard = Some(de::from_row(&row)?);
+        }
+
+        Ok(flashcard)
+    }
+
+    async fn get_flashcard_progress(
+        &self,
+        user_id: i64,
+        study_topic_ids: Vec<i64>,
+    ) -> RepoResult<Vec<FlashcardProgress>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT f.id AS flashcard_id, f.study_topic_id, f.created_at, p.last_session_date,
+    COALESCE(p.total_sessions, 0) AS total_sessions,
+    COALESCE(p.completed_sessions, 0) AS completed_sessions,
+    COALESCE(p.ease_factor, 2.5) AS ease_factor,
+    COALESCE(p.interval_days, 0) AS interval_days,
+    COALESCE(p.repetitions, 0) AS repetitions,
+    p.next_due_date, p.stability, p.difficulty, p.last_review_date
+FROM flashcard AS f
+LEFT JOIN flashcard_progress AS p ON p.flashcard_id = f.id AND p.user_id = ?2
+WHERE f.study_topic_id IN (SELECT value FROM json_each(?1)) AND f.study_topic_id IN (SELECT id FROM study_topic_view WHERE viewer_id = ?2)
+ORDER BY f.study_topic_id, f.id",
+                libsql::params![serde_json::to_string(&study_topic_ids)?, user_id],
+            )
+            .await?;
+
+        let mut flashcard_progress = Vec::new();
+
+        while let Ok(Some(row)) = rows.next().await {
+            let progress = de::from_row(&row)?;
+
+            flashcard_progress.push(progress);
+        }
+
+        Ok(flashcard_progress)
+    }
+
+    async fn add_flashcard(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+        flashcard: FlashcardInfo,
+        created_at: String,
+    ) -> RepoResult<i64> {
+        let conn = self.get_connection().await?;
+        let inserted = conn
+            .execute(
+                "INSERT INTO flashcard (study_topic_id, front, back, hint, created_at, updated_at)
+SELECT id, ?3, ?4, ?5, ?6, ?6 FROM study_topic_view WHERE id = ?1 AND viewer_id = ?2",
+                libsql::params![
+                    study_topic_id,
+                    user_id,
+                    flashcard.front,
+                    flashcard.back,
+                    flashcard.hint,
+                    created_at
+                ],
+            )
+            .await?;
+
+        ensure_affected(inserted, || format!("study topic {study_topic_id}"))?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    async fn update_flashcard(
+        &self,
+        user_id: i64,
+        flashcard_id: i64,
+        update: FlashcardUpdate,
+        updated_at: String,
+    ) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+        let updated = conn
+            .execute(
+                "UPDATE flashcard SET front = COALESCE(?2, front), back = COALESCE(?3, back), hint = CASE WHEN ?4 THEN ?5 ELSE hint END, updated_at = ?6 WHERE id = ?1 AND study_topic_id IN (SELECT id FROM study_topic_view WHERE viewer_id = ?7)",
+                libsql::params![
+                    flashcard_id,
+                    update.front,
+                    update.back,
+                    update.hint.is_some(),
+                    update.hint.flatten(),
+                    updated_at,
+                    user_id
+                ],
+            )
+            .await?;
+
+        ensure_affected(updated, || format!("flashcard {flashcard_id}"))
+    }
+
+    async fn delete_flashcard(&self, user_id: i64, flashcard_id: i64) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+
+        let deleted = conn
+            .execute(
+                "DELETE FROM flashcard WHERE id = ?1 AND study_topic_id IN (SELECT id FROM study_topic_view WHERE viewer_id = ?2)",
+                libsql::params![flashcard_id, user_id],
+            )
+            .await?;
+
+        ensure_affected(deleted, || format!("flashcard {flashcard_id}"))
+    }
+
+    async fn get_review_logs_for_study_topic(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+    ) -> RepoResult<Vec<ReviewLog>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT * FROM review_log WHERE study_topic_id = ?1 AND user_id = ?2 AND study_topic_id IN (SELECT id FROM study_topic_view WHERE viewer_id = ?2) ORDER BY review_date, id",
+                libsql::params![study_topic_id, user_id],
+            )
+            .await?;
+
+        let mut review_logs = Vec::new();
+
+        while let Ok(Some(row)) = rows.next().await {
+            let review_log = de::from_row(&row)?;
+
+            review_logs.push(review_log);
+        }
+
+        Ok(review_logs)
+    }
+
+    async fn get_review_logs_for_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+    ) -> RepoResult<Vec<ReviewLog>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT rl.* FROM review_log AS rl
+INNER JOIN study_topic_view AS st ON rl.study_topic_id = st.id AND rl.user_id = st.viewer_id
+WHERE st.viewer_id = ?1 AND st.subject_name = ?2
+ORDER BY rl.study_topic_id, rl.flashcard_id, rl.review_date, rl.id",
+                libsql::params![user_id, subject_name],
+            )
+            .await?;
+
+        let mut review_logs = Vec::new();
+
+        while let Ok(Some(row)) = rows.next().await {
+            let review_log = de::from_row(&row)?;
+
+            review_logs.push(review_log);
+        }
+
+        Ok(review_logs)
+    }
+
+    async fn get_interval_schedules(&self, user_id: i64) -> RepoResult<Vec<IntervalSchedule>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT * FROM interval_schedule WHERE user_id = ?1 ORDER BY id",
+                libsql::params![user_id],
+            )
+            .await?;
+
+        let mut interval_schedules = Vec::new();
+
+        while let Ok(Some(row)) = rows.next().await {
+            let interval_schedule = de::from_row::<IntervalScheduleRow>(&row)?.try_into()?;
+
+            interval_schedules.push(interval_schedule);
+        }
+
+        Ok(interval_schedules)
+    }
+
+    async fn get_interval_schedule(
+        &self,
+        user_id: i64,
+        interval_schedule_id: i64,
+    ) -> RepoResult<Option<IntervalSchedule>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT * FROM interval_schedule WHERE id = ?1 AND user_id = ?2",
+                libsql::params![interval_schedule_id, user_id],
+            )
+            .await?;
+
+        let mut interval_schedule = None;
+
+        if let Ok(Some(row)) = rows.next().await {
+            interval_schedule = Some(de::from_row::<IntervalScheduleRow>(&row)?.try_into()?);
+        }
+
+        Ok(interval_schedule)
+    }
+
+    async fn add_interval_schedule(
+        &self,
+        user_id: i64,
+        interval_schedule: IntervalScheduleInfo,
+    ) -> RepoResult<i64> {
+        let conn = self.get_connection().await?;
+        conn.execute(
+            "INSERT INTO interval_schedule (user_id, name, offsets, repeat_every_days) VALUES (?1, ?2, ?3, ?4)",
+            libsql::params![
+                user_id,
+                interval_schedule.name.clone(),
+                serde_json::to_string(&interval_schedule.offsets)?,
+                interval_schedule.repeat_every_days
+            ],
+        )
+        .await
+        .map_err(|err| {
+            constraint_error(
+                err,
+                format!("interval schedule {}", interval_schedule.name),
+                String::new(),
+            )
+        })?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    async fn update_interval_schedule(
+        &self,
+        user_id: i64,
+        interval_schedule_id: i64,
+        interval_schedule: IntervalScheduleInfo,
+    ) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+        let updated = conn
+            .execute(
+                "UPDATE interval_schedule SET name = ?2, offsets = ?3, repeat_every_days = ?4 WHERE id = ?1 AND user_id = ?5",
+                libsql::params![
+                    interval_schedule_id,
+                    interval_schedule.name.clone(),
+                    serde_json::to_string(&interval_schedule.offsets)?,
+                    interval_schedule.repeat_every_days,
+                    user_id
+                ],
+            )
+            .await
+            .map_err(|err| {
+                constraint_error(
+                    err,
+                    format!("interval schedule {}", interval_schedule.name),
+                    String::new(),
+                )
+            })?;
+
+        ensure_affected(updated, || {
+            format!("interval schedule {interval_schedule_id}")
+        })
+    }
+
+    async fn add_user(
+        &self,
+        username: String,
+        password_hash: String,
+        created_at: String,
+    ) -> RepoResult<i64> {
+        let conn = self.get_connection().await?;
+        conn.execute(
+            "INSERT INTO user (username, password_hash, created_at) VALUES (?1, ?2, ?3)",
+            libsql::params![username.clone(), password_hash, created_at],
+        )
+        .await
+        .map_err(|err| constraint_error(err, format!("user {username}"), String::new()))?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    async fn get_user_by_username(&self, username: String) -> RepoResult<Option<User>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT * FROM user WHERE username = ?1",
+                libsql::params![username],
+            )
+            .await?;
+
+        let mut user = None;
+
+        if let Ok(Some(row)) = rows.next().await {
+            user = Some(de::from_row(&row)?);
+        }
+
+        Ok(user)
+    }
+
+    async fn get_user(&self, user_id: i64) -> RepoResult<Option<User>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query("SELECT * FROM user WHERE id = ?1", libsql::params![user_id])
+            .await?;
+
+        let mut user = None;
+
+        if let Ok(Some(row)) = rows.next().await {
+            user = Some(de::from_row(&row)?);
+        }
+
+        Ok(user)
+    }
+
+    async fn get_users(&self) -> RepoResult<Vec<User>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn.query("SELECT * FROM user ORDER BY id", ()).await?;
+
+        let mut users = Vec::new();
+
+        while let Ok(Some(row)) = rows.next().await {
+            let user = de::from_row(&row)?;
+
+            users.push(user);
+        }
+
+        Ok(users)
+    }
+
+    async fn update_user_password(&self, user_id: i64, password_hash: String) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+        let updated = conn
+            .execute(
+                "UPDATE user SET password_hash = ?2 WHERE id = ?1",
+                libsql::params![user_id, password_hash],
+            )
+            .await?;
+
+        ensure_affected(updated, || format!("user {user_id}"))
+    }
+
+    async fn update_user_time_zone(
+        &self,
+        user_id: i64,
+        time_zone: Option<String>,
+    ) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+        let updated = conn
+            .execute(
+                "UPDATE user SET time_zone = ?2 WHERE id = ?1",
+                libsql::params![user_id, time_zone],
+            )
+            .await?;
+
+        ensure_affected(updated, || format!("user {user_id}"))
+    }
+
+    async fn add_api_key(
+        &self,
+        user_id: i64,
+        name: String,
+        prefix: String,
+        key_hash: String,
+        scope: ApiKeyScope,
+        created_at: String,
+    ) -> RepoResult<i64> {
+        let conn = self.get_connection().await?;
+        conn.execute(
+            "INSERT INTO api_key (user_id, name, prefix, key_hash, scope, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            libsql::params![
+                user_id,
+                name,
+                prefix.clone(),
+                key_hash,
+                scope.as_str(),
+                created_at
+            ],
+        )
+        .await
+        .map_err(|err| {
+            constraint_error(err, format!("API key {prefix}"), format!("user {user_id}"))
+        })?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    async fn get_api_keys(&self, user_id: i64) -> RepoResult<Vec<ApiKey>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT * FROM api_key WHERE user_id = ?1 ORDER BY id",
+                libsql::params![user_id],
+            )
+            .await?;
+
+        let mut api_keys = Vec::new();
+
+        while let Ok(Some(row)) = rows.next().await {
+            let api_key = de::from_row(&row)?;
+
+            api_keys.push(api_key);
+        }
+
+        Ok(api_keys)
+    }
+
+    async fn get_api_key_by_prefix(&self, prefix: String) -> RepoResult<Option<ApiKey>> {
+        let conn = self.get_connection().await?;
+        let mut rows = conn
+            .query(
+                "SELECT * FROM api_key WHERE prefix = ?1",
+                libsql::params![prefix],
+            )
+            .await?;
+
+        let mut api_key = None;
+
+        if let Ok(Some(row)) = rows.next().await {
+            api_key = Some(de::from_row(&row)?);
+        }
+
+        Ok(api_key)
+    }
+
+    async fn update_api_key_last_used(
+        &self,
+        api_key_id: i64,
+        last_used_at: String,
+    ) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+        let updated = conn
+            .execute(
+                "UPDATE api_key SET last_used_at = ?2 WHERE id = ?1",
+                libsql::params![api_key_id, last_used_at],
+            )
+            .await?;
+
+        ensure_affected(updated, || format!("API key {api_key_id}"))
+    }
+
+    async fn revoke_api_key(
+        &self,
+        user_id: i64,
+        api_key_id: i64,
+        revoked_at: String,
+    ) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+        let updated = conn
+            .execute(
+                "UPDATE api_key SET revoked_at = COALESCE(revoked_at, ?3) WHERE id = ?1 AND user_id = ?2",
+                libsql::params![api_key_id, user_id, revoked_at],
+            )
+            .await?;
+
+        ensure_affected(updated, || format!("API key {api_key_id}"))
+    }
+
+    async fn delete_interval_schedule(
+        &self,
+        user_id: i64,
+        interval_schedule_id: i64,
+    ) -> RepoResult<()> {
+        let conn = self.get_connection().await?;
+
+        // Subjects and topics using it are reset by ON DELETE SET NULL.
+        let deleted = conn
+            .execute(
+                "DELETE FROM interval_schedule WHERE id = ?1 AND user_id = ?2",
+                libsql::params![interval_schedule_id, user_id],
+            )
+            .await?;
+
+        ensure_affected(deleted, || {
+            format!("interval schedule {interval_schedule_id}")
+        })
+    }
+}
+
+/// Turns constraint violations into `Conflict` for `row` (UNIQUE) or
+/// `NotFound` for `referenced` (FOREIGN KEY), other errors pass through.
+fn constraint_error(err: libsql::Error, row: String, referenced: String) -> RepositoryError {
+    let message = err.to_string();
+
+    if message.contains("UNIQUE constraint failed") {
+        RepositoryError::Conflict(row)
+    } else if message.contains("FOREIGN KEY constraint failed") {
+        RepositoryError::NotFound(referenced)
+    } else {
+        RepositoryError::InternalLibSqlError(err)
+    }
+}
+
+/// Topics with a review planned or due on `?2`: one is due by then
+/// following [`Scheduler::due_review`](crate::scheduling::Scheduler::due_review),
+/// or [`Scheduler::next_review_date`](crate::scheduling::Scheduler::next_review_date)
+/// falls on that day when the topic's sessions are left aside. Graded
+/// topics of adaptive schedulers follow their `next_due_date`, the rest the
+/// fixed offsets of the topic's, the subject's or the default interval
+/// schedule; the tests check this against the schedulers day by day.
+/// Topics scheduled card by card are reviewed when one of their cards is,
+/// each card counted from the day it was added with its own progress. Uses
+/// the progress of the user bound as `?1`.
+const REVIEW_ON_SQL: &str = "WITH scheduled AS (
+    SELECT st.id AS study_topic_id, st.user_id, st.subject_name, st.interval_schedule_id,
+        st.creation_date, st.next_due_date, st.last_session_date
+    FROM study_topic_view AS st
+    INNER JOIN subject AS s ON s.user_id = st.user_id AND s.subject_name = st.subject_name
+    WHERE st.viewer_id = ?1
+        AND NOT (s.card_scheduling AND EXISTS (SELECT 1 FROM flashcard AS f WHERE f.study_topic_id = st.id))
+    UNION ALL
+    SELECT st.id, st.user_id, st.subject_name, st.interval_schedule_id,
+        substr(f.created_at, 1, 10), p.next_due_date, p.last_session_date
+    FROM study_topic_view AS st
+    INNER JOIN subject AS s ON s.user_id = st.user_id AND s.subject_name = st.subject_name
+    INNER JOIN flashcard AS f ON f.study_topic_id = st.id
+    LEFT JOIN flashcard_progress AS p ON p.flashcard_id = f.id AND p.user_id = ?1
+    WHERE st.viewer_id = ?1 AND s.card_scheduling
+),
+schedule AS (
+    SELECT sc.study_topic_id, s.scheduler, sc.next_due_date, sc.last_session_date,
+        CAST(julianday(?2) - julianday(sc.creation_date) AS INTEGER) AS days,
+        COALESCE(CAST(julianday(sc.last_session_date) - julianday(sc.creation_date) AS INTEGER), -1) AS last_session_days,
+        COALESCE(tis.offsets, sis.offsets, '[0,1,3,7,21,30,45,60]') AS offsets,
+        CASE WHEN tis.id IS NOT NULL THEN tis.repeat_every_days WHEN sis.id IS NOT NULL THEN sis.repeat_every_days ELSE 60 END AS repeat_every_days
+    FROM scheduled AS sc
+    INNER JOIN subject AS s ON s.user_id = sc.user_id AND s.subject_name = sc.subject_name
+    LEFT JOIN interval_schedule AS tis ON tis.id = sc.interval_schedule_id
+    LEFT JOIN interval_schedule AS sis ON sis.id = s.interval_schedule_id
+),
+review_point AS (
+    SELECT *, (SELECT MAX(value) FROM json_each(offsets)) AS last_offset FROM schedule
+),
+review_on AS (
+    SELECT study_topic_id FROM review_point WHERE CASE
+        WHEN scheduler != 'fixed_offsets' AND next_due_date IS NOT NULL THEN
+            next_due_date = ?2
+            OR (next_due_date <= ?2 AND (last_session_date IS NULL OR last_session_date < next_due_date))
+        ELSE days >= 0 AND (
+            days IN (SELECT value FROM json_each(offsets))
+            OR (repeat_every_days IS NOT NULL AND days > last_offset AND (days - last_offset) % repeat_every_days = 0)
+            OR CASE
+                WHEN repeat_every_days IS NOT NULL AND days >= last_offset
+                    THEN days - (days - last_offset) % repeat_every_days
+                ELSE (SELECT MAX(value) FROM json_each(offsets) WHERE value <= days)
+            END > last_session_days
+        )
+    END
+)
+";
+
+/// Wrap the matches of `highlight()` and `snippet()`, so the text around
+/// them can be escaped before the `<mark>` tags go in.
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+
+/// Highlighted text as HTML: the topic's own text escaped, the matches
+/// wrapped in `<mark>` tags.
+fn mark_matches(highlighted: &str) -> String {
+    escape_html(highlighted)
+        .replace(MATCH_START, "<mark>")
+        .replace(MATCH_END, "</mark>")
+}
+
+/// Adds `value` to the positional parameters, returning its placeholder.
+fn push_param(params: &mut Vec<libsql::Value>, value: impl Into<libsql::Value>) -> String {
+    params.push(value.into());
+
+    format!("?{}", params.len())
+}
+
+/// Appends the WHERE, keyset and ORDER BY clauses of a page sorted by `key`
+/// with `id` breaking ties. One row more than `limit` is fetched so the
+/// caller knows whether another page follows.
+#[allow(clippy::too_many_arguments)]
+fn push_page(
+    sql: &mut String,
+    mut conditions: Vec<String>,
+    params: &mut Vec<libsql::Value>,
+    key: &str,
+    id: &str,
+    order: SortOrder,
+    after: Option<Cursor>,
+    limit: u32,
+) {
+    let (comparison, direction) = match order {
+        SortOrder::Asc => (">", "ASC"),
+        SortOrder::Desc => ("<", "DESC"),
+    };
+
+    if let Some(after) = after {
+        let after_key = push_param(params, after.key);
+        let after_id = push_param(params, after.id);
+        conditions.push(format!(
+            "({key}, {id}) {comparison} ({after_key}, {after_id})"
+        ));
+    }
+
+    if !conditions.is_empty() {
+        sql.push_str(" WHERE ");
+        sql.push_str(&conditions.join(" AND "));
+    }
+
+    let limit = push_param(params, i64::from(limit) + 1);
+    sql.push_str(&format!(
+        " ORDER BY {key} {direction}, {id} {direction} LIMIT {limit}"
+    ));
+}
+
+/// Creates the user's progress row of the card unless it exists, like
+/// [`ensure_study_topic_progress`].
+async fn ensure_flashcard_progress(
+    tx: &libsql::Transaction,
+    user_id: i64,
+    flashcard_id: i64,
+) -> RepoResult<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO flashcard_progress (flashcard_id, user_id) VALUES (?1, ?2)",
+        libsql::params![flashcard_id, user_id],
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Creates the user's progress row of the topic unless it exists, so it
+/// can be updated in place.
+async fn ensure_study_topic_progress(
+    tx: &libsql::Transaction,
+    user_id: i64,
+    study_topic_id: i64,
+) -> RepoResult<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO study_topic_progress (study_topic_id, user_id) VALUES (?1, ?2)",
+        libsql::params![study_topic_id, user_id],
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Reports the row described by `row` as missing when a statement matched
+/// nothing.
+fn ensure_affected(affected: u64, row: impl FnOnce() -> String) -> RepoResult<()> {
+    if affected == 0 {
+        return Err(RepositoryError::NotFound(row()));
+    }
+
+    Ok(())
+}
+
+fn interval_schedule_not_found(interval_schedule_id: Option<i64>) -> String {
+    format!(
+        "interval schedule {}",
+        interval_schedule_id.unwrap_or_default()
+    )
+}
+
+fn remote_credentials(config: &DatabaseConfig) -> Result<(String, String), String> {
+    let url = config
+        .url
+        .clone()
+        .ok_or_else(|| format!("DB_URL is required in {:?} mode", config.mode))?;
+    let token = config
+        .token
+        .clone()
+        .ok_or_else(|| format!("DB_TOKEN is required in {:?} mode", config.mode))?;
+
+    Ok((url, token))
+}
+
+fn local_path(config: &DatabaseConfig) -> Result<String, String> {
+    config
+        .path
+        .clone()
+        .ok_or_else(|| format!("DB_PATH is required in {:?} mode", config.mode))
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, NaiveDate};
+
+    use crate::{
+        clock::format_date,
+        domain::{
+            ApiKeyScope, FlashcardImport, FlashcardInfo, FlashcardUpdate, IntervalScheduleInfo,
+            ReviewGrade, ReviewImport, ReviewLogInfo, SchedulerKind, StudySessionQuery,
+            StudySessionSort, StudyTopicImport, StudyTopicInfo, StudyTopicQuery,
+            StudyTopicSchedule, StudyTopicSort, StudyTopicUpdate, SubjectImport, SubjectRole,
+        },
+        err::RepositoryError,
+        pagination::{Cursor, SortOrder},
+        repository::{
+            DatabaseConfig, DatabaseMode, InMemoryRepository, LibSqlRepository, StudyRepository,
+        },
+    };
+
+    const USER: i64 = 1;
+
+    /// Fresh in-memory database holding only the user [`USER`].
+    async fn memory_repository() -> LibSqlRepository {
+        let repo = LibSqlRepository::new(DatabaseConfig {
+            mode: DatabaseMode::Memory,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        repo.add_user(
+            "ada".to_string(),
+            String::new(),
+            "2025-01-01T00:00:00+00:00".to_string(),
+        )
+        .await
+        .unwrap();
+
+        repo
+    }
+
+    #[tokio::test]
+    async fn memory_mode_keeps_data_between_calls() {
+        let repo = memory_repository().await;
+
+        repo.add_subject(USER, "math".to_string()).await.unwrap();
+
+        let subjects = repo.get_subjects(USER).await.unwrap();
+        assert_eq!(subjects.len(), 1);
+        assert_eq!(subjects[0].subject_name, "math");
+    }
+
+    #[tokio::test]
+    async fn remote_mode_requires_credentials() {
+        let result = LibSqlRepository::new(DatabaseConfig::default()).await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn study_session_is_unique_per_due_date() {
+        let repo = memory_repository().await;
+
+        repo.add_subject(USER, "math".to_string()).await.unwrap();
+        repo.add_study_topic(
+            USER,
+            StudyTopicInfo {
+                name: "limits".to_string(),
+                description: None,
+                subject_name: "math".to_string(),
+                interval_schedule_id: None,
+            },
+            "2025-01-01".to_string(),
+        )
+        .await
+        .unwrap();
+
+        let study_topic_id = repo.get_study_topics(USER).await.unwrap()[0].id;
+
+        assert!(repo
+            .create_study_session(
+                USER,
+                study_topic_id,
+                None,
+                "2025-01-01".to_string(),
+                0,
+                "2025-01-01".to_string()
+            )
+            .await
+            .unwrap());
+        assert!(!repo
+            .create_study_session(
+                USER,
+                study_topic_id,
+                None,
+                "2025-01-01".to_string(),
+                0,
+                "2025-01-01".to_string()
+            )
+            .await
+            .unwrap());
+        assert!(repo
+            .create_study_session(
+                USER,
+                study_topic_id,
+                None,
+                "2025-01-02".to_string(),
+                0,
+                "2025-01-01".to_string()
+            )
+            .await
+            .unwrap());
+
+        let study_sessions = repo
+            .get_study_sessions_for_subject(USER, "math".to_string())
+            .await
+            .unwrap();
+        assert_eq!(study_sessions.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn interval_schedule_round_trip() {
+        let repo = memory_repository().await;
+
+        repo.add_subject(USER, "math".to_string()).await.unwrap();
+
+        let interval_schedule_id = repo
+            .add_interval_schedule(
+                USER,
+                IntervalScheduleInfo {
+                    name: "exam cram".to_string(),
+                    offsets: vec![0, 1, 2, 4, 7],
+                    repeat_every_days: None,
+                },
+            )
+            .await
+            .unwrap();
+        repo.update_subject_interval_schedule(USER, "math".to_string(), Some(interval_schedule_id))
+            .await
+            .unwrap();
+
+        let interval_schedule = repo
+            .get_interval_schedule(USER, interval_schedule_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(interval_schedule.offsets, vec![0, 1, 2, 4, 7]);
+        assert_eq!(
+            repo.get_subjects(USER).await.unwrap()[0].interval_schedule_id,
+            Some(interval_schedule_id)
+        );
+
+        repo.delete_interval_schedule(USER, interval_schedule_id)
+            .await
+            .unwrap();
+
+        assert!(repo.get_interval_schedules(USER).await.unwrap().is_empty());
+        assert_eq!(
+            repo.get_subjects(USER).await.unwrap()[0].interval_schedule_id,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn completing_session_is_atomic_and_idempotent() {
+        let repo = memory_repository().await;
+
+        repo.add_subject(USER, "math".to_string()).await.unwrap();
+        repo.add_study_topic(
+            USER,
+            StudyTopicInfo {
+                name: "limits".to_string(),
+                description: None,
+                subject_name: "math".to_string(),
+                interval_schedule_id: None,
+            },
+            "2025-01-01".to_string(),
+        )
+        .await
+        .unwrap();
+
+        let study_topic_id = repo.get_study_topics(USER).await.unwrap()[0].id;
+        repo.create_study_session(
+            USER,
+            study_topic_id,
+            None,
+            "2025-01-02".to_string(),
+            0,
+            "2025-01-01".to_string(),
+        )
+        .await
+        .unwrap();
+        let study_session_id = repo
+            .get_study_sessions_for_subject(USER, "math".to_string())
+            .await
+            .unwrap()[0]
+            .id;
+
+        // The topic, card and session logged are the session's own, whatever
+        // the caller says.
+        let review_log = ReviewLogInfo {
+            study_topic_id: study_topic_id + 1,
+            flashcard_id: Some(42),
+            study_session_id: None,
+            scheduled_date: Some("2025-01-02".to_string()),
+            review_date: "2025-01-03".to_string(),
+            completed_at: Some("2025-01-03T10:00:00+00:00".to_string()),
+            grade: Some(ReviewGrade::Hard),
+            time_spent_seconds: Some(90),
+        };
+
+        assert!(repo
+            .complete_study_session(USER, study_session_id, None, review_log.clone())
+            .await
+            .unwrap());
+        // A repeated completion is ignored.
+        assert!(!repo
+            .complete_study_session(USER, study_session_id, None, review_log)
+            .await
+            .unwrap());
+
+        assert!(repo
+            .get_study_sessions_for_subject(USER, "math".to_string())
+            .await
+            .unwrap()
+            .is_empty());
+
+        let study_session = repo
+            .get_study_session(USER, study_session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(study_session.completed_at.is_some());
+
+        let review_logs = repo
+            .get_review_logs_for_study_topic(USER, study_topic_id)
+            .await
+            .unwrap();
+        assert_eq!(review_logs.len(), 1);
+        assert_eq!(review_logs[0].study_topic_id, study_topic_id);
+        assert_eq!(review_logs[0].user_id, USER);
+        assert_eq!(review_logs[0].flashcard_id, None);
+        assert_eq!(review_logs[0].study_session_id, Some(study_session_id));
+        assert_eq!(review_logs[0].grade, Some(ReviewGrade::Hard));
+        assert_eq!(review_logs[0].time_spent_seconds, Some(90));
+        assert_eq!(review_logs[0].scheduled_date.as_deref(), Some("2025-01-02"));
+
+        let study_topic = &repo.get_study_topics(USER).await.unwrap()[0];
+        assert_eq!(study_topic.total_sessions, 1);
+        assert_eq!(study_topic.completed_sessions, 1);
+        assert_eq!(study_topic.last_session_date.as_deref(), Some("2025-01-01"));
+    }
+
+    #[tokio::test]
+    async fn constraint_violations_are_classified() {
+        let repo = memory_repository().await;
+
+        repo.add_subject(USER, "math".to_string()).await.unwrap();
+
+        assert!(matches!(
+            repo.add_subject(USER, "math".to_string()).await,
+            Err(RepositoryError::Conflict(_))
+        ));
+        assert!(matches!(
+            repo.add_study_topic(
+                USER,
+                StudyTopicInfo {
+                    name: "limits".to_string(),
+                    description: None,
+                    subject_name: "physics".to_string(),
+                    interval_schedule_id: None,
+                },
+                "2025-01-01".to_string(),
+            )
+            .await,
+            Err(RepositoryError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn usernames_are_unique_ignoring_case() {
+        let repo = memory_repository().await;
+
+        let id = repo
+            .add_user(
+                "Grace".to_string(),
+                "hash".to_string(),
+                "2025-01-01T00:00:00+00:00".to_string(),
+            )
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            repo.add_user(
+                "grace".to_string(),
+                "other hash".to_string(),
+                "2025-01-02T00:00:00+00:00".to_string(),
+            )
+            .await,
+            Err(RepositoryError::Conflict(_))
+        ));
+
+        let user = repo
+            .get_user_by_username("GRACE".to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.id, id);
+        assert_eq!(user.username, "Grace");
+        assert_eq!(
+            repo.get_user(id).await.unwrap().unwrap().password_hash,
+            "hash"
+        );
+        assert!(repo.get_user(id + 1).await.unwrap().is_none());
+
+        assert!(user.time_zone.is_none());
+        repo.update_user_time_zone(id, Some("America/Bogota".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(
+            repo.get_user(id)
+                .await
+                .unwrap()
+                .unwrap()
+                .time_zone
+                .as_deref(),
+            Some("America/Bogota")
+        );
+        assert!(matches!(
+            repo.update_user_time_zone(id + 1, None).await,
+            Err(RepositoryError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn api_keys_are_found_by_prefix_and_revoked_once() {
+        let repo = memory_repository().await;
+
+        let id = repo
+            .add_api_key(
+                USER,
+                "sync".to_string(),
+                "0123456789ab".to_string(),
+                "hash".to_string(),
+                ApiKeyScope::ReadOnly,
+                "2025-01-01T00:00:00+00:00".to_string(),
+            )
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            repo.add_api_key(
+                USER,
+                "duplicate".to_string(),
+                "0123456789ab".to_string(),
+                "other hash".to_string(),
+                ApiKeyScope::ReadWrite,
+                "2025-01-01T00:00:00+00:00".to_string(),
+            )
+            .await,
+            Err(RepositoryError::Conflict(_))
+        ));
+
+        let api_key = repo
+            .get_api_key_by_prefix("0123456789ab".to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(api_key.id, id);
+        assert_eq!(api_key.scope, ApiKeyScope::ReadOnly);
+        assert!(api_key.last_used_at.is_none());
+
+        repo.update_api_key_last_used(id, "2025-01-02T00:00:00+00:00".to_string())
+            .await
+            .unwrap();
+        assert!(matches!(
+            repo.revoke_api_key(USER + 1, id, "2025-01-03T00:00:00+00:00".to_string())
+                .await,
+            Err(RepositoryError::NotFound(_))
+        ));
+        repo.revoke_api_key(USER, id, "2025-01-03T00:00:00+00:00".to_string())
+            .await
+            .unwrap();
+        repo.revoke_api_key(USER, id, "2025-01-04T00:00:00+00:00".to_string())
+            .await
+            .unwrap();
+
+        let api_keys = repo.get_api_keys(USER).await.unwrap();
+        assert_eq!(api_keys.len(), 1);
+        assert_eq!(
+            api_keys[0].last_used_at.as_deref(),
+            Some("2025-01-02T00:00:00+00:00")
+        );
+        assert_eq!(
+            api_keys[0].revoked_at.as_deref(),
+            Some("2025-01-03T00:00:00+00:00")
+        );
+        assert!(repo.get_api_keys(USER + 1).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn users_only_see_their_own_rows() {
+        let repo = memory_repository().await;
+        let other = repo
+            .add_user(
+                "grace".to_string(),
+                String::new(),
+                "2025-01-01T00:00:00+00:00".to_string(),
+            )
+            .await
+            .unwrap();
+
+        // Names only have to be unique per user.
+        for user_id in [USER, other] {
+            repo.add_subject(user_id, "math".to_string()).await.unwrap();
+            repo.add_interval_schedule(
+                user_id,
+                IntervalScheduleInfo {
+                    name: "weekly".to_string(),
+                    offsets: vec![0, 7],
+                    repeat_every_days: Some(7),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        repo.add_study_topic(
+            USER,
+            StudyTopicInfo {
+                name: "limits".to_string(),
+                description: None,
+                subject_name: "math".to_string(),
+                interval_schedule_id: None,
+            },
+            "2025-01-01".to_string(),
+        )
+        .await
+        .unwrap();
+        assert!(repo
+            .create_study_session(
+                USER,
+                1,
+                None,
+                "2025-01-01".to_string(),
+                0,
+                "2025-01-01".to_string()
+            )
+            .await
+            .unwrap());
+
+        assert_eq!(repo.get_subjects(other).await.unwrap().len(), 1);
+        assert_eq!(repo.get_interval_schedules(other).await.unwrap().len(), 1);
+        assert!(repo.get_study_topics(other).await.unwrap().is_empty());
+        assert!(repo.get_study_topic(other, 1).await.unwrap().is_none());
+        assert!(repo.get_study_session(other, 1).await.unwrap().is_none());
+        assert!(repo
+            .query_study_topics(other, StudyTopicQuery::default(), None, 10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(repo
+            .get_study_sessions_for_subject(other, "math".to_string())
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(repo
+            .search_study_topics(other, vec!["limits".to_string()], 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert!(!repo
+            .create_study_session(
+                other,
+                1,
+                None,
+                "2025-01-02".to_string(),
+                0,
+                "2025-01-02".to_string()
+            )
+            .await
+            .unwrap());
+        assert!(!repo
+            .complete_study_session(
+                other,
+                1,
+                None,
+                ReviewLogInfo {
+                    study_topic_id: 1,
+                    flashcard_id: None,
+                    study_session_id: Some(1),
+                    scheduled_date: None,
+                    review_date: "2025-01-01".to_string(),
+                    completed_at: None,
+                    grade: None,
+                    time_spent_seconds: None,
+                },
+            )
+            .await
+            .unwrap());
+        assert!(matches!(
+            repo.update_study_topic(other, 1, StudyTopicUpdate::default())
+                .await,
+            Err(RepositoryError::NotFound(_))
+        ));
+        assert!(matches!(
+            repo.delete_study_topic(other, 1).await,
+            Err(RepositoryError::NotFound(_))
+        ));
+        assert!(matches!(
+            repo.delete_interval_schedule(other, 1).await,
+            Err(RepositoryError::NotFound(_))
+        ));
+
+        // Deleting the other user's subject of the same name leaves ours.
+        repo.delete_subject(other, "math".to_string())
+            .await
+            .unwrap();
+        assert!(repo.get_study_topic(USER, 1).await.unwrap().is_some());
+        assert_eq!(
+            repo.get_pending_study_sessions_for_study_topic(USER, 1)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_rows_are_not_found() {
+        let repo = memory_repository().await;
+
+        assert!(matches!(
+            repo.delete_subject(USER, "math".to_string()).await,
+            Err(RepositoryError::NotFound(_))
+        ));
+        assert!(matches!(
+            repo.delete_study_topic(USER, 42).await,
+            Err(RepositoryError::NotFound(_))
+        ));
+        assert!(matches!(
+            repo.delete_interval_schedule(USER, 42).await,
+            Err(RepositoryError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn renaming_subject_moves_its_topics() {
+        let repo = memory_repository().await;
+
+        repo.add_subject(USER, "math".to_string()).await.unwrap();
+        repo.add_subject(USER, "physics".to_string()).await.unwrap();
+        repo.add_study_topic(
+            USER,
+            StudyTopicInfo {
+                name: "limits".to_string(),
+                description: Some("epsilon delta".to_string()),
+                subject_name: "math".to_string(),
+                interval_schedule_id: None,
+            },
+            "2025-01-01".to_string(),
+        )
+        .await
+        .unwrap();
+        repo.create_study_session(
+            USER,
+            1,
+            None,
+            "2025-01-01".to_string(),
+            0,
+            "2025-01-01".to_string(),
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(
+            repo.rename_subject(USER, "math".to_string(), "physics".to_string())
+                .await,
+            Err(RepositoryError::Conflict(_))
+        ));
+        assert!(matches!(
+            repo.rename_subject(USER, "chemistry".to_string(), "biology".to_string())
+                .await,
+            Err(RepositoryError::NotFound(_))
+        ));
+
+        repo.rename_subject(USER, "math".to_string(), "calculus".to_string())
+            .await
+            .unwrap();
+
+        assert!(repo
+            .get_subject(USER, "math".to_string())
+            .await
+            .unwrap()
+            .is_none());
+        let study_topics = repo
+            .get_study_topics_for_subject(USER, "calculus".to_string())
+            .await
+            .unwrap();
+        assert_eq!(study_topics.len(), 1);
+        assert_eq!(study_topics[0].total_sessions, 1);
+        assert_eq!(
+            repo.get_study_sessions_for_subject(USER, "calculus".to_string())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn renaming_subject_keeps_its_settings() {
+        let repo = memory_repository().await;
+
+        repo.add_subject(USER, "math".to_string()).await.unwrap();
+        repo.update_subject_scheduler(
+            USER,
+            "math".to_string(),
+            SchedulerKind::Fsrs,
+            0.85,
+            None,
+            true,
+        )
+        .await
+        .unwrap();
+
+        repo.rename_subject(USER, "math".to_string(), "calculus".to_string())
+            .await
+            .unwrap();
+
+        let subject = repo
+            .get_subject(USER, "calculus".to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(subject.scheduler, SchedulerKind::Fsrs);
+        assert_eq!(subject.desired_retention, 0.85);
+        assert!(subject.card_scheduling);
+    }
+
+    #[tokio::test]
+    async fn renaming_subject_checks_subjects_shared_with_the_owner() {
+        let repo = memory_repository().await;
+        let other = repo
+            .add_user("grace".to_string(), String::new(), String::new())
+            .await
+            .unwrap();
+
+        repo.add_subject(other, "algebra".to_string())
+            .await
+            .unwrap();
+        let invitation_id = repo
+            .add_subject_member(
+                other,
+                "algebra".to_string(),
+                USER,
+                SubjectRole::Viewer,
+                "2025-01-01T00:00:00+00:00".to_string(),
+            )
+            .await
+            .unwrap();
+        repo.accept_subject_invitation(
+            USER,
+            invitation_id,
+            "2025-01-01T00:00:00+00:00".to_string(),
+        )
+        .await
+        .unwrap();
+        repo.add_subject(USER, "math".to_string()).await.unwrap();
+
+        assert!(matches!(
+            repo.rename_subject(USER, "math".to_string(), "algebra".to_string())
+                .await,
+            Err(RepositoryError::Conflict(_))
+        ));
+        assert!(repo
+            .get_subject(USER, "math".to_string())
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn study_topic_update_changes_only_given_fields() {
+        let repo = memory_repository().await;
+
+        repo.add_subject(USER, "math".to_string()).await.unwrap();
+        repo.add_subject(USER, "physics".to_string()).await.unwrap();
+        repo.add_study_topic(
+            USER,
+            StudyTopicInfo {
+                name: "limits".to_string(),
+                description: Some("epsilon delta".to_string()),
+                subject_name: "math".to_string(),
+                interval_schedule_id: None,
+            },
+            "2025-01-01".to_string(),
+        )
+        .await
+        .unwrap();
+
+        repo.update_study_topic(
+            USER,
+            1,
+            StudyTopicUpdate {
+                name: Some("kinematics".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let study_topic = repo.get_study_topic(USER, 1).await.unwrap().unwrap();
+        assert_eq!(study_topic.name, "kinematics");
+        assert_eq!(study_topic.description.as_deref(), Some("epsilon delta"));
+        assert_eq!(study_topic.subject_name, "math");
+
+        repo.update_study_topic(
+            USER,
+            1,
+            StudyTopicUpdate {
+                description: Some(None),
+                subject_name: Some("physics".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let study_topic = repo.get_study_topic(USER, 1).await.unwrap().unwrap();
+        assert_eq!(study_topic.name, "kinematics");
+        assert_eq!(study_topic.description, None);
+        assert_eq!(study_topic.subject_name, "physics");
+
+        assert!(matches!(
+            repo.update_study_topic(
+                USER,
+                1,
+                StudyTopicUpdate {
+                    subject_name: Some("chemistry".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await,
+            Err(RepositoryError::NotFound(_))
+        ));
+        assert!(matches!(
+            repo.update_study_topic(USER, 42, StudyTopicUpdate::default())
+                .await,
+            Err(RepositoryError::NotFound(_))
+        ));
+    }
+
+    /// Topics on fixed, custom, adaptive and graded schedules, with pending
+    /// and completed sessions.
+    async fn seed(repo: &dyn StudyRepository) {
+        let weekly = repo
+            .add_interval_schedule(
+                USER,
+                IntervalScheduleInfo {
+                    name: "weekly".to_string(),
+                    offsets: vec![0, 7, 14],
+                    repeat_every_days: Some(7),
+                },
+            )
+            .await
+            .unwrap();
+        let once = repo
+            .add_interval_schedule(
+                USER,
+                IntervalScheduleInfo {
+                    name: "once".to_string(),
+                    offsets: vec![2, 5],
+                    repeat_every_days: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        for subject_name in ["math", "music", "physics"] {
+            repo.add_subject(USER, subject_name.to_string())
+                .await
+                .unwrap();
+        }
+        repo.update_subject_scheduler(
+            USER,
+            "music".to_string(),
+            SchedulerKind::FixedOffsets,
+            0.9,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+        repo.update_subject_interval_schedule(USER, "music".to_string(), Some(once))
+            .await
+            .unwrap();
+        repo.update_subject_scheduler(
+            USER,
+            "physics".to_string(),
+            SchedulerKind::Fsrs,
+            0.9,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+
+        let study_topics = [
+            ("limits", "math", "2025-01-01", None),
+            ("series", "math", "2025-01-03", Some(weekly)),
+            ("scales", "music", "2025-01-02", None),
+            ("chords", "music", "2025-01-02", Some(weekly)),
+            ("optics", "physics", "2025-01-05", None),
+        ];
+        for (name, subject_name, creation_date, interval_schedule_id) in study_topics {
+            repo.add_study_topic(
+                USER,
+                StudyTopicInfo {
+                    name: name.to_string(),
+                    description: None,
+                    subject_name: subject_name.to_string(),
+                    interval_schedule_id,
+                },
+                creation_date.to_string(),
+            )
+            .await
+            .unwrap();
+        }
+
+        // Limits was graded on its first day and is next due on the 4th.
+        repo.create_study_session(
+            USER,
+            1,
+            None,
+            "2025-01-01".to_string(),
+            0,
+            "2025-01-01".to_string(),
+        )
+        .await
+        .unwrap();
+        repo.complete_study_session(
+            USER,
+            1,
+            Some(StudyTopicSchedule {
+                ease_factor: 2.5,
+                interval_days: 3,
+                repetitions: 1,
+                stability: None,
+                difficulty: None,
+                next_due_date: Some("2025-01-04".to_string()),
+                last_review_date: Some("2025-01-01".to_string()),
+            }),
+            ReviewLogInfo {
+                study_topic_id: 1,
+                flashcard_id: None,
+                study_session_id: Some(1),
+                scheduled_date: Some("2025-01-01".to_string()),
+                review_date: "2025-01-01".to_string(),
+                completed_at: None,
+                grade: Some(ReviewGrade::Good),
+                time_spent_seconds: None,
+            },
+        )
+        .await
+        .unwrap();
+        // Scales has a pending session from the 4th.
+        repo.create_study_session(
+            USER,
+            3,
+            None,
+            "2025-01-04".to_string(),
+            0,
+            "2025-01-04".to_string(),
+        )
+        .await
+        .unwrap();
+        repo.create_study_session(
+            USER,
+            4,
+            None,
+            "2025-01-02".to_string(),
+            0,
+            "2025-01-02".to_string(),
+        )
+        .await
+        .unwrap();
+    }
+
+    fn ids(study_topics: &[crate::domain::StudyTopic]) -> Vec<i64> {
+        study_topics
+            .iter()
+            .map(|study_topic| study_topic.id)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn review_dates_match_the_schedulers() {
+        let repo = memory_repository().await;
+        let reference = InMemoryRepository::new();
+        seed(&repo).await;
+        seed(&reference).await;
+
+        let start = NaiveDate::from_ymd_opt(2024, 12, 30).unwrap();
+        for day in 0..120 {
+            let query = StudyTopicQuery {
+                review_on: Some(format_date(start + Duration::days(day))),
+                ..Default::default()
+            };
+
+            assert_eq!(
+                ids(&repo
+                    .query_study_topics(USER, query.clone(), None, 100)
+                    .await
+                    .unwrap()),
+                ids(&reference
+                    .query_study_topics(USER, query.clone(), None, 100)
+                    .await
+                    .unwrap()),
+                "topics with a review on {:?}",
+                query.review_on
+            );
+        }
+    }
+
+    /// Every scheduler with the default, a repeating and a finite interval
+    /// schedule, each on topics that are new, have a session, were graded
+    /// and are due later, are overdue or had the overdue review covered.
+    /// The same again scheduled card by card, where the first card of a
+    /// topic goes through those states next to a card added later, and the
+    /// new topics have no cards.
+    async fn seed_review_grid(repo: &dyn StudyRepository) {
+        let mut interval_schedule_ids = vec![None];
+        for (name, offsets, repeat_every_days) in [
+            ("weekly", vec![0, 7, 14], Some(7)),
+            ("once", vec![2, 5], None),
+        ] {
+            let interval_schedule_id = repo
+                .add_interval_schedule(
+                    USER,
+                    IntervalScheduleInfo {
+                        name: name.to_string(),
+                        offsets,
+                        repeat_every_days,
+                    },
+                )
+                .await
+                .unwrap();
+            interval_schedule_ids.push(Some(interval_schedule_id));
+        }
+
+        // Graded on `date` and next due on `next_due_date`.
+        let grade = |study_topic_id: i64,
+                     flashcard_id: Option<i64>,
+                     date: &'static str,
+                     next_due_date: &'static str| async move {
+            let study_session_id = repo
+                .get_pending_study_sessions_for_study_topic(USER, study_topic_id)
+                .await
+                .unwrap()
+                .into_iter()
+                .find(|study_session| study_session.flashcard_id == flashcard_id)
+                .unwrap()
+                .id;
+            repo.complete_study_session(
+                USER,
+                study_session_id,
+                Some(StudyTopicSchedule {
+                    ease_factor: 2.5,
+                    interval_days: 3,
+                    repetitions: 1,
+                    stability: Some(3.0),
+                    difficulty: Some(5.0),
+                    next_due_date: Some(next_due_date.to_string()),
+                    last_review_date: Some(date.to_string()),
+                }),
+                ReviewLogInfo {
+                    study_topic_id,
+                    flashcard_id,
+                    study_session_id: Some(study_session_id),
+                    scheduled_date: Some(date.to_string()),
+                    review_date: date.to_string(),
+                    completed_at: None,
+                    grade: Some(ReviewGrade::Good),
+                    time_spent_seconds: None,
+                },
+            )
+            .await
+            .unwrap();
+        };
+        let session = |study_topic_id: i64, flashcard_id: Option<i64>, date: &'static str| async move {
+            repo.create_study_session(
+                USER,
+                study_topic_id,
+                flashcard_id,
+                date.to_string(),
+                0,
+                date.to_string(),
+            )
+            .await
+            .unwrap();
+        };
+
+        let mut study_topic_id = 0;
+        for (subject_name, scheduler, card_scheduling) in [
+            ("fixed", SchedulerKind::FixedOffsets, false),
+            ("sm2", SchedulerKind::Sm2, false),
+            ("fsrs", SchedulerKind::Fsrs, false),
+            ("fixed cards", SchedulerKind::FixedOffsets, true),
+            ("sm2 cards", SchedulerKind::Sm2, true),
+            ("fsrs cards", SchedulerKind::Fsrs, true),
+        ] {
+            repo.add_subject(USER, subject_name.to_string())
+                .await
+                .unwrap();
+            repo.update_subject_scheduler(
+                USER,
+                subject_name.to_string(),
+                scheduler,
+                0.9,
+                None,
+                card_scheduling,
+            )
+            .await
+            .unwrap();
+
+            for interval_schedule_id in &interval_schedule_ids {
+                for (state, creation_date) in [
+                    "2025-01-01",
+                    "2025-01-01",
+                    "2025-01-03",
+                    "2024-12-20",
+                    "2024-12-20",
+                ]
+                .into_iter()
+                .enumerate()
+                {
+                    repo.add_study_topic(
+                        USER,
+                        StudyTopicInfo {
+                            name: format!("topic {state}"),
+                            description: None,
+                            subject_name: subject_name.to_string(),
+                            interval_schedule_id: *interval_schedule_id,
+                        },
+                        creation_date.to_string(),
+                    )
+                    .await
+                    .unwrap();
+                    study_topic_id += 1;
+
+                    let mut flashcard_id = None;
+                    if card_scheduling && state > 0 {
+                        for created_at in [creation_date, "2025-01-10"] {
+                            let id = repo
+                                .add_flashcard(
+                                    USER,
+                                    study_topic_id,
+                                    FlashcardInfo {
+                                        front: created_at.to_string(),
+                                        back: "back".to_string(),
+                                        hint: None,
+                                    },
+                                    format!("{created_at}T09:00:00+00:00"),
+                                )
+                                .await
+                                .unwrap();
+                            flashcard_id = flashcard_id.or(Some(id));
+                        }
+                    }
+
+                    match state {
+                        1 => session(study_topic_id, flashcard_id, "2025-01-08").await,
+                        2 => {
+                            session(study_topic_id, flashcard_id, "2025-01-03").await;
+                            grade(study_topic_id, flashcard_id, "2025-01-03", "2025-01-13").await;
+                        }
+                        3 | 4 => {
+                            session(study_topic_id, flashcard_id, "2024-12-20").await;
+                            grade(study_topic_id, flashcard_id, "2024-12-20", "2025-01-02").await;
+                            if state == 4 {
+                                session(study_topic_id, flashcard_id, "2025-01-05").await;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn review_dates_match_the_schedulers_over_a_grid() {
+        let repo = memory_repository().await;
+        let reference = InMemoryRepository::new();
+        seed_review_grid(&repo).await;
+        seed_review_grid(&reference).await;
+
+        let start = NaiveDate::from_ymd_opt(2024, 12, 15).unwrap();
+        let mut reviews = 0;
+        for day in 0..150 {
+            let query = StudyTopicQuery {
+                review_on: Some(format_date(start + Duration::days(day))),
+                ..Default::default()
+            };
+            let study_topic_ids = ids(&repo
+                .query_study_topics(USER, query.clone(), None, 100)
+                .await
+                .unwrap());
+
+            assert_eq!(
+                study_topic_ids,
+                ids(&reference
+                    .query_study_topics(USER, query.clone(), None, 100)
+                    .await
+                    .unwrap()),
+                "topics with a review on {:?}",
+                query.review_on
+            );
+            reviews += study_topic_ids.len();
+        }
+        assert!(reviews > 0);
+    }
+
+    #[tokio::test]
+    async fn queries_filter_sort_and_page_in_sql() {
+        let repo = memory_repository().await;
+        let reference = InMemoryRepository::new();
+        seed(&repo).await;
+        seed(&reference).await;
+
+        let queries = [
+            StudyTopicQuery::default(),
+            StudyTopicQuery {
+                subject_name: Some("music".to_string()),
+                sort: StudyTopicSort::Name,
+                ..Default::default()
+            },
+            StudyTopicQuery {
+                created_from: Some("2025-01-02".to_string()),
+                created_to: Some("2025-01-03".to_string()),
+                sort: StudyTopicSort::CreationDate,
+                order: SortOrder::Desc,
+                ..Default::default()
+            },
+            StudyTopicQuery {
+                due_before: Some("2025-01-04".to_string()),
+                ..Default::default()
+            },
+            StudyTopicQuery {
+                has_pending_session: Some(false),
+                sort: StudyTopicSort::LastSessionDate,
+                ..Default::default()
+            },
+            StudyTopicQuery {
+                sort: StudyTopicSort::CompletionRatio,
+                order: SortOrder::Desc,
+                ..Default::default()
+            },
+        ];
+
+        for query in queries {
+            // Pages of two rows, continuing from the last row of each page.
+            let mut pages = Vec::new();
+            let mut after = None;
+            loop {
+                let page = repo
+                    .query_study_topics(USER, query.clone(), after.clone(), 2)
+                    .await
+                    .unwrap();
+                let more = page.len() > 2;
+                let page = &page[..page.len().min(2)];
+                pages.extend(ids(page));
+
+                match page.last() {
+                    Some(last) if more => {
+                        after = Some(Cursor {
+                            sort: query.sort.as_str().to_string(),
+                            key: query.sort.key(last),
+                            id: last.id,
+                        })
+                    }
+                    _ => break,
+                }
+            }
+
+            let expected = reference
+                .query_study_topics(USER, query.clone(), None, 100)
+                .await
+                .unwrap();
+            assert_eq!(pages, ids(&expected), "{query:?}");
+        }
+
+        assert_eq!(
+            ids(&repo
+                .query_study_topics(
+                    USER,
+                    StudyTopicQuery {
+                        due_before: Some("2025-01-04".to_string()),
+                        ..Default::default()
+                    },
+                    None,
+                    100,
+                )
+                .await
+                .unwrap()),
+            vec![1, 3, 4]
+        );
+
+        let query = StudySessionQuery {
+            due_before: Some("2025-01-03".to_string()),
+            sort: StudySessionSort::StudyTopicName,
+            ..Default::default()
+        };
+        let study_sessions = repo
+            .query_study_sessions_for_subject(USER, "music".to_string(), query.clone(), None, 100)
+            .await
+            .unwrap();
+        assert_eq!(study_sessions.len(), 1);
+        assert_eq!(study_sessions[0].study_topic_name, "chords");
+    }
+
+    #[tokio::test]
+    async fn search_results_escape_topic_text() {
+        let repo = memory_repository().await;
+        let reference = InMemoryRepository::new();
+        reference
+            .add_user("ada".to_string(), String::new(), String::new())
+            .await
+            .unwrap();
+
+        let repos: [&dyn StudyRepository; 2] = [&repo, &reference];
+        for repo in repos {
+            repo.add_subject(USER, "math".to_string()).await.unwrap();
+            repo.add_study_topic(
+                USER,
+                StudyTopicInfo {
+                    name: "<script>alert(1)</script> limits".to_string(),
+                    description: Some("limits & <b>bounds</b>".to_string()),
+                    subject_name: "math".to_string(),
+                    interval_schedule_id: None,
+                },
+                "2025-01-01".to_string(),
+            )
+            .await
+            .unwrap();
+
+            let results = repo
+                .search_study_topics(USER, vec!["limits".to_string()], 10)
+                .await
+                .unwrap();
+            assert_eq!(results[0].name, "<script>alert(1)</script> limits");
+            assert_eq!(
+                results[0].name_highlight,
+                "&lt;script&gt;alert(1)&lt;/script&gt; <mark>limits</mark>"
+            );
+            assert_eq!(
+                results[0].description_snippet.as_deref(),
+                Some("<mark>limits</mark> &amp; &lt;b&gt;bounds&lt;/b&gt;")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn search_index_follows_topic_changes() {
+        let repo = memory_repository().await;
+
+        repo.add_subject(USER, "math".to_string()).await.unwrap();
+        repo.add_subject(USER, "physics".to_string()).await.unwrap();
+        let study_topics = [
+            ("Limits", Some("Epsilon delta definition of limits"), "math"),
+            (
+                "Series",
+                Some("Convergence tests, limit comparison"),
+                "math",
+            ),
+            ("Optics", None, "physics"),
+        ];
+        for (name, description, subject_name) in study_topics {
+            repo.add_study_topic(
+                USER,
+                StudyTopicInfo {
+                    name: name.to_string(),
+                    description: description.map(str::to_string),
+                    subject_name: subject_name.to_string(),
+                    interval_schedule_id: None,
+                },
+                "2025-01-01".to_string(),
+            )
+            .await
+            .unwrap();
+        }
+
+        let search = |terms: &[&str]| {
+            let repo = repo.clone();
+            let terms = terms.iter().map(|term| term.to_string()).collect();
+            async move { repo.search_study_topics(USER, terms, 10).await.unwrap() }
+        };
+
+        // The topic named after the term ranks above a passing mention.
+        let results = search(&["limit"]).await;
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| result.study_topic_id)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(results[0].name_highlight, "<mark>Limits</mark>");
+        assert_eq!(results[0].subject_name, "math");
+        assert_eq!(results[1].name_highlight, "Series");
+        assert!(results[1]
+            .description_snippet
+            .as_deref()
+            .unwrap()
+            .contains("<mark>limit</mark>"));
+
+        assert_eq!(search(&["limit", "convergence"]).await.len(), 1);
+        assert_eq!(search(&["optics"]).await[0].description_snippet, None);
+        // FTS5 syntax is matched literally instead of failing the query.
+        assert!(search(&["\"limits", "OR", "NEAR("]).await.is_empty());
+
+        repo.update_study_topic(
+            USER,
+            3,
+            StudyTopicUpdate {
+                name: Some("Óptica".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert!(search(&["optics"]).await.is_empty());
+        assert_eq!(search(&["optica"]).await[0].study_topic_id, 3);
+
+        repo.delete_study_topic(USER, 1).await.unwrap();
+        assert_eq!(search(&["limit"]).await.len(), 1);
+
+        repo.delete_subject(USER, "math".to_string()).await.unwrap();
+        assert!(search(&["limit"]).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn shared_subjects_keep_progress_per_member() {
+        let repo = memory_repository().await;
+        let member = repo
+            .add_user("grace".to_string(), String::new(), String::new())
+            .await
+            .unwrap();
+
+        repo.add_subject(USER, "math".to_string()).await.unwrap();
+        repo.add_study_topic(
+            USER,
+            StudyTopicInfo {
+                name: "limits".to_string(),
+                description: None,
+                subject_name: "math".to_string(),
+                interval_schedule_id: None,
+            },
+            "2025-01-01".to_string(),
+        )
+        .await
+        .unwrap();
+
+        let invitation_id = repo
+            .add_subject_member(
+                USER,
+                "math".to_string(),
+                member,
+                SubjectRole::Viewer,
+                "2025-01-01T00:00:00+00:00".to_string(),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            repo.add_subject_member(
+                USER,
+                "math".to_string(),
+                member,
+                SubjectRole::Editor,
+                "2025-01-01T00:00:00+00:00".to_string(),
+            )
+            .await,
+            Err(RepositoryError::Conflict(_))
+        ));
+        assert!(repo.get_subjects(member).await.unwrap().is_empty());
+
+        // The member's own subject of the same name blocks accepting.
+        repo.add_subject(member, "math".to_string()).await.unwrap();
+        assert!(matches!(
+            repo.accept_subject_invitation(
+                member,
+                invitation_id,
+                "2025-01-02T00:00:00+00:00".to_string()
+            )
+            .await,
+            Err(RepositoryError::Conflict(_))
+        ));
+        repo.delete_subject(member, "math".to_string())
+            .await
+            .unwrap();
+        repo.accept_subject_invitation(
+            member,
+            invitation_id,
+            "2025-01-02T00:00:00+00:00".to_string(),
+        )
+        .await
+        .unwrap();
+        assert!(repo
+            .get_subject_invitations(member)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let subject = repo
+            .get_subject(member, "math".to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(subject.user_id, USER);
+        assert_eq!(subject.role, SubjectRole::Viewer);
+
+        // Both study the shared topic on their own sessions.
+        for user_id in [USER, member] {
+            assert!(repo
+                .create_study_session(
+                    user_id,
+                    1,
+                    None,
+                    "2025-01-02".to_string(),
+                    0,
+                    "2025-01-02".to_string()
+                )
+                .await
+                .unwrap());
+        }
+        let study_session = repo
+            .get_pending_study_sessions_for_study_topic(member, 1)
+            .await
+            .unwrap()
+            .remove(0);
+        assert_eq!(study_session.user_id, member);
+        assert!(repo
+            .complete_study_session(
+                member,
+                study_session.id,
+                None,
+                ReviewLogInfo {
+                    study_topic_id: 1,
+                    flashcard_id: None,
+                    study_session_id: Some(study_session.id),
+                    scheduled_date: Some("2025-01-02".to_string()),
+                    review_date: "2025-01-02".to_string(),
+                    completed_at: None,
+                    grade: None,
+                    time_spent_seconds: None,
+                },
+            )
+            .await
+            .unwrap());
+
+        let repo = &repo;
+        let completed_sessions = |user_id| async move {
+            repo.get_study_topic(user_id, 1)
+                .await
+                .unwrap()
+                .map(|study_topic| study_topic.completed_sessions)
+        };
+        assert_eq!(completed_sessions(member).await, Some(1));
+        assert_eq!(completed_sessions(USER).await, Some(0));
+
+        repo.delete_subject_member(USER, "math".to_string(), member)
+            .await
+            .unwrap();
+        assert_eq!(completed_sessions(member).await, None);
+        assert_eq!(completed_sessions(USER).await, Some(0));
+        assert_eq!(
+            repo.get_study_sessions_for_subject(USER, "math".to_string())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn flashcards_belong_to_visible_topics() {
+        let repo = memory_repository().await;
+        let other = repo
+            .add_user("grace".to_string(), String::new(), String::new())
+            .await
+            .unwrap();
+
+        repo.add_subject(USER, "music".to_string()).await.unwrap();
+        for name in ["chords", "scales"] {
+            repo.add_study_topic(
+                USER,
+                StudyTopicInfo {
+                    name: name.to_string(),
+                    description: None,
+                    subject_name: "music".to_string(),
+                    interval_schedule_id: None,
+                },
+                "2025-01-01".to_string(),
+            )
+            .await
+            .unwrap();
+        }
+
+        let flashcard = |front: &str| FlashcardInfo {
+            front: front.to_string(),
+            back: "back".to_string(),
+            hint: None,
+        };
+        let created_at = "2025-01-01T00:00:00+00:00".to_string();
+        repo.add_flashcard(USER, 2, flashcard("C major"), created_at.clone())
+            .await
+            .unwrap();
+        let first = repo
+            .add_flashcard(USER, 1, flashcard("C"), created_at.clone())
+            .await
+            .unwrap();
+        repo.add_flashcard(USER, 1, flashcard("G"), created_at.clone())
+            .await
+            .unwrap();
+        assert!(matches!(
+            repo.add_flashcard(other, 1, flashcard("D"), created_at.clone())
+                .await,
+            Err(RepositoryError::NotFound(_))
+        ));
+
+        let fronts = |user_id, study_topic_ids| {
+            let repo = &repo;
+            async move {
+                repo.get_flashcards(user_id, study_topic_ids)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|flashcard| flashcard.front)
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(fronts(USER, vec![2, 1]).await, ["C", "G", "C major"]);
+        assert!(fronts(other, vec![1, 2]).await.is_empty());
+
+        repo.update_flashcard(
+            USER,
+            first,
+            FlashcardUpdate {
+                back: Some("C E G".to_string()),
+                hint: Some(Some("triad".to_string())),
+                ..Default::default()
+            },
+            "2025-01-02T00:00:00+00:00".to_string(),
+        )
+        .await
+        .unwrap();
+        let updated = repo.get_flashcard(USER, first).await.unwrap().unwrap();
+        assert_eq!(updated.front, "C");
+        assert_eq!(updated.back, "C E G");
+        assert_eq!(updated.hint.as_deref(), Some("triad"));
+        assert_eq!(updated.created_at, created_at);
+        assert_eq!(updated.updated_at, "2025-01-02T00:00:00+00:00");
+
+        assert!(repo.get_flashcard(other, first).await.unwrap().is_none());
+        assert!(matches!(
+            repo.delete_flashcard(other, first).await,
+            Err(RepositoryError::NotFound(_))
+        ));
+        repo.delete_flashcard(USER, first).await.unwrap();
+
+        repo.delete_study_topic(USER, 1).await.unwrap();
+        assert_eq!(fronts(USER, vec![1, 2]).await, ["C major"]);
+    }
+
+    #[tokio::test]
+    async fn imports_subjects_with_history() {
+        let repo = memory_repository().await;
+
+        let review = |review_date: &str, grade| ReviewImport {
+            review_date: review_date.to_string(),
+            completed_at: format!("{review_date}T10:00:00+00:00"),
+            grade,
+            time_spent_seconds: Some(5),
+        };
+        let subject = SubjectImport {
+            subject_name: "music".to_string(),
+            scheduler: SchedulerKind::Fsrs,
+            study_topics: vec![StudyTopicImport {
+                name: "chords".to_string(),
+                description: None,
+                creation_date: "2025-01-01".to_string(),
+                flashcards: vec![
+                    FlashcardImport {
+                        flashcard: FlashcardInfo {
+                            front: "C".to_string(),
+                            back: "C E G".to_string(),
+                            hint: None,
+                        },
+                        created_at: "2025-01-01T00:00:00+00:00".to_string(),
+                        schedule: Some(StudyTopicSchedule {
+                            ease_factor: 2.5,
+                            interval_days: 3,
+                            repetitions: 1,
+                            stability: Some(3.2),
+                            difficulty: Some(5.0),
+                            next_due_date: Some("2025-01-06".to_string()),
+                            last_review_date: Some("2025-01-03".to_string()),
+                        }),
+                        reviews: vec![
+                            review("2025-01-02", ReviewGrade::Again),
+                            review("2025-01-03", ReviewGrade::Good),
+                        ],
+                    },
+                    FlashcardImport {
+                        flashcard: FlashcardInfo {
+                            front: "G".to_string(),
+                            back: "G B D".to_string(),
+                            hint: None,
+                        },
+                        created_at: "2025-01-01T00:00:00+00:00".to_string(),
+                        schedule: None,
+                        reviews: Vec::new(),
+                    },
+                ],
+            }],
+        };
+        repo.import_subjects(USER, vec![subject.clone()])
+            .await
+            .unwrap();
+
+        let imported = repo
+            .get_subject(USER, "music".to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(imported.scheduler, SchedulerKind::Fsrs);
+        assert!(imported.card_scheduling);
+
+        let study_topic = repo.get_study_topic(USER, 1).await.unwrap().unwrap();
+        assert_eq!(study_topic.completed_sessions, 2);
+        let progress = repo.get_flashcard_progress(USER, vec![1]).await.unwrap();
+        assert_eq!(progress[0].next_due_date.as_deref(), Some("2025-01-06"));
+        assert_eq!(progress[0].stability, Some(3.2));
+        assert_eq!(progress[0].completed_sessions, 2);
+        assert_eq!(progress[1].next_due_date, None);
+        let review_logs = repo
+            .get_review_logs_for_subject(USER, "music".to_string())
+            .await
+            .unwrap();
+        assert_eq!(review_logs.len(), 2);
+        assert_eq!(review_logs[1].grade, Some(ReviewGrade::Good));
+        assert_eq!(review_logs[1].flashcard_id, Some(progress[0].flashcard_id));
+
+        // A second subject in the same import must not be left behind when
+        // a later one conflicts.
+        let other = SubjectImport {
+            subject_name: "art".to_string(),
+            ..subject.clone()
+        };
+        let err = repo
+            .import_subjects(USER, vec![other, subject])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RepositoryError::Conflict(_)));
+        assert!(repo
+            .get_subject(USER, "art".to_string())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn card_sessions_schedule_their_card() {
+        let repo = memory_repository().await;
+
+        repo.add_subject(USER, "music".to_string()).await.unwrap();
+        for name in ["chords", "scales"] {
+            repo.add_study_topic(
+                USER,
+                StudyTopicInfo {
+                    name: name.to_string(),
+                    description: None,
+                    subject_name: "music".to_string(),
+                    interval_schedule_id: None,
+                },
+                "2025-01-01".to_string(),
+            )
+            .await
+            .unwrap();
+        }
+        repo.update_subject_scheduler(
+            USER,
+            "music".to_string(),
+            SchedulerKind::Sm2,
+            0.9,
+            None,
+            true,
+        )
+        .await
+        .unwrap();
+
+        let mut flashcard_ids = Vec::new();
+        for (study_topic_id, front) in [(1, "C"), (1, "G"), (2, "C major")] {
+            let flashcard_id = repo
+                .add_flashcard(
+                    USER,
+                    study_topic_id,
+                    FlashcardInfo {
+                        front: front.to_string(),
+                        back: "back".to_string(),
+                        hint: None,
+                    },
+                    "2025-01-01T00:00:00+00:00".to_string(),
+                )
+                .await
+                .unwrap();
+            flashcard_ids.push(flashcard_id);
+        }
+
+        // A card only has sessions within its own topic.
+        assert!(!repo
+            .create_study_session(
+                USER,
+                2,
+                Some(flashcard_ids[0]),
+                "2025-01-01".to_string(),
+                0,
+                "2025-01-01".to_string(),
+            )
+            .await
+            .unwrap());
+        for flashcard_id in &flashcard_ids[..2] {
+            assert!(repo
+                .create_study_session(
+                    USER,
+                    1,
+                    Some(*flashcard_id),
+                    "2025-01-01".to_string(),
+                    0,
+                    "2025-01-01".to_string(),
+                )
+                .await
+                .unwrap());
+        }
+
+        let due_flashcards = repo
+            .query_flashcard_sessions(
+                USER,
+                StudySessionQuery {
+                    due_before: Some("2025-01-01".to_string()),
+                    ..Default::default()
+                },
+                None,
+                10,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            due_flashcards
+                .iter()
+                .map(|study_session| study_session.flashcard_id)
+                .collect::<Vec<_>>(),
+            [Some(flashcard_ids[0]), Some(flashcard_ids[1])]
+        );
+        assert_eq!(due_flashcards[0].subject_name, "music");
+
+        let study_session_id = due_flashcards[0].id;
+        assert!(repo
+            .complete_study_session(
+                USER,
+                study_session_id,
+                Some(StudyTopicSchedule {
+                    ease_factor: 2.5,
+                    interval_days: 2,
+                    repetitions: 1,
+                    stability: None,
+                    difficulty: None,
+                    next_due_date: Some("2025-01-03".to_string()),
+                    last_review_date: Some("2025-01-01".to_string()),
+                }),
+                ReviewLogInfo {
+                    study_topic_id: 1,
+                    flashcard_id: Some(flashcard_ids[0]),
+                    study_session_id: Some(study_session_id),
+                    scheduled_date: Some("2025-01-01".to_string()),
+                    review_date: "2025-01-01".to_string(),
+                    completed_at: Some("2025-01-01T10:00:00+00:00".to_string()),
+                    grade: Some(ReviewGrade::Good),
+                    time_spent_seconds: None,
+                },
+            )
+            .await
+            .unwrap());
+
+        let progress = repo.get_flashcard_progress(USER, vec![1, 2]).await.unwrap();
+        assert_eq!(progress.len(), 3);
+        assert_eq!(
+            progress
+                .iter()
+                .map(|progress| (
+                    progress.flashcard_id,
+                    progress.total_sessions,
+                    progress.completed_sessions,
+                    progress.next_due_date.as_deref()
+                ))
+                .collect::<Vec<_>>(),
+            [
+                (flashcard_ids[0], 1, 1, Some("2025-01-03")),
+                (flashcard_ids[1], 1, 0, None),
+                (flashcard_ids[2], 0, 0, None),
+            ]
+        );
+
+        // The topic counts the sessions of its cards but keeps its schedule.
+        let study_topic = repo.get_study_topic(USER, 1).await.unwrap().unwrap();
+        assert_eq!(study_topic.total_sessions, 2);
+        assert_eq!(study_topic.completed_sessions, 1);
+        assert!(study_topic.next_due_date.is_none());
+        let review_logs = repo.get_review_logs_for_study_topic(USER, 1).await.unwrap();
+        assert_eq!(review_logs.len(), 1);
+        assert_eq!(review_logs[0].flashcard_id, Some(flashcard_ids[0]));
+
+        let reviewed_on = |date: &str| {
+            let repo = &repo;
+            let query = StudyTopicQuery {
+                review_on: Some(date.to_string()),
+                ..Default::default()
+            };
+            async move {
+                repo.query_study_topics(USER, query, None, 10)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|study_topic| study_topic.id)
+                    .collect::<Vec<_>>()
+            }
+        };
+        // Ungraded cards follow the fixed offsets from the day they were
+        // added, the graded one its own due date.
+        assert_eq!(reviewed_on("2025-01-02").await, [1, 2]);
+        assert_eq!(reviewed_on("2025-01-03").await, [1, 2]);
+
+        repo.delete_flashcard(USER, flashcard_ids[1]).await.unwrap();
+        assert_eq!(reviewed_on("2025-01-02").await, [2]);
+        assert_eq!(reviewed_on("2025-01-03").await, [1, 2]);
+    }
+}
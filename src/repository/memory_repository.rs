@@ -0,0 +1,1988 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate};
+
+use crate::{
+    clock::{format_date, DATE_FORMAT},
+    domain::{
+        ApiKey, ApiKeyScope, Flashcard, FlashcardInfo, FlashcardProgress, FlashcardUpdate,
+        IntervalSchedule, IntervalScheduleInfo, ReviewLog, ReviewLogInfo, SchedulerKind,
+        SearchResult, StudySession, StudySessionInfo, StudySessionQuery, StudyTopic,
+        StudyTopicInfo, StudyTopicQuery, StudyTopicSchedule, StudyTopicUpdate, Subject,
+        SubjectImport, SubjectInvitation, SubjectMember, SubjectRole, User,
+    },
+    err::{RepoResult, RepositoryError},
+    pagination::{Cursor, SortKey, SortOrder},
+    repository::{escape_html, StudyRepository},
+    scheduling::{scheduler_for, FixedOffsets},
+};
+
+/// `subject_member` row.
+struct SubjectMemberRow {
+    id: i64,
+    subject_owner_id: i64,
+    subject_name: String,
+    user_id: i64,
+    role: SubjectRole,
+    invited_at: String,
+    accepted_at: Option<String>,
+}
+
+/// `study_topic_progress` or `flashcard_progress` row, the defaults are the
+/// column defaults.
+#[derive(Clone)]
+struct StudyTopicProgress {
+    last_session_date: Option<String>,
+    total_sessions: i64,
+    completed_sessions: i64,
+    ease_factor: f64,
+    interval_days: i64,
+    repetitions: i64,
+    next_due_date: Option<String>,
+    stability: Option<f64>,
+    difficulty: Option<f64>,
+    last_review_date: Option<String>,
+}
+
+impl Default for StudyTopicProgress {
+    fn default() -> Self {
+        Self {
+            last_session_date: None,
+            total_sessions: 0,
+            completed_sessions: 0,
+            ease_factor: 2.5,
+            interval_days: 0,
+            repetitions: 0,
+            next_due_date: None,
+            stability: None,
+            difficulty: None,
+            last_review_date: None,
+        }
+    }
+}
+
+impl StudyTopicProgress {
+    fn apply(self, study_topic: &mut StudyTopic) {
+        study_topic.last_session_date = self.last_session_date;
+        study_topic.total_sessions = self.total_sessions;
+        study_topic.completed_sessions = self.completed_sessions;
+        study_topic.ease_factor = self.ease_factor;
+        study_topic.interval_days = self.interval_days;
+        study_topic.repetitions = self.repetitions;
+        study_topic.next_due_date = self.next_due_date;
+        study_topic.stability = self.stability;
+        study_topic.difficulty = self.difficulty;
+        study_topic.last_review_date = self.last_review_date;
+    }
+}
+
+#[derive(Default)]
+struct MemoryState {
+    subjects: Vec<Subject>,
+    subject_members: Vec<SubjectMemberRow>,
+    /// Shared content only, the progress fields are kept per user in
+    /// `progress` like in the libsql schema.
+    study_topics: Vec<StudyTopic>,
+    /// Keyed by topic and user.
+    progress: HashMap<(i64, i64), StudyTopicProgress>,
+    flashcards: Vec<Flashcard>,
+    /// Keyed by card and user.
+    flashcard_progress: HashMap<(i64, i64), StudyTopicProgress>,
+    study_sessions: Vec<StudySession>,
+    review_logs: Vec<ReviewLog>,
+    interval_schedules: Vec<IntervalSchedule>,
+    users: Vec<User>,
+    api_keys: Vec<ApiKey>,
+    last_subject_member_id: i64,
+    last_study_topic_id: i64,
+    last_flashcard_id: i64,
+    last_study_session_id: i64,
+    last_review_log_id: i64,
+    last_interval_schedule_id: i64,
+    last_user_id: i64,
+    last_api_key_id: i64,
+}
+
+/// Repository that keeps every row in process memory, mirroring the
+/// behaviour of the libsql schema (autoincrement ids, cascading deletes).
+#[derive(Clone, Default)]
+pub struct InMemoryRepository {
+    state: Arc<Mutex<MemoryState>>,
+}
+
+impl InMemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, MemoryState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl MemoryState {
+    /// Role of the user in the subject, `None` when they cannot see it.
+    fn subject_role(&self, user_id: i64, owner_id: i64, subject_name: &str) -> Option<SubjectRole> {
+        if user_id == owner_id {
+            return self
+                .subjects
+                .iter()
+                .any(|subject| subject.user_id == owner_id && subject.subject_name == subject_name)
+                .then_some(SubjectRole::Owner);
+        }
+
+        self.subject_members
+            .iter()
+            .find(|member| {
+                member.subject_owner_id == owner_id
+                    && member.subject_name == subject_name
+                    && member.user_id == user_id
+                    && member.accepted_at.is_some()
+            })
+            .map(|member| member.role)
+    }
+
+    /// Subjects the user can see, with their role in each, like
+    /// `subject_view`.
+    fn visible_subjects(&self, user_id: i64) -> Vec<Subject> {
+        self.subjects
+            .iter()
+            .filter_map(|subject| {
+                let role = self.subject_role(user_id, subject.user_id, &subject.subject_name)?;
+
+                Some(Subject {
+                    role,
+                    ..subject.clone()
+                })
+            })
+            .collect()
+    }
+
+    /// The topic with the user's progress when they can see it, like
+    /// `study_topic_view`.
+    fn study_topic_for(&self, user_id: i64, study_topic: &StudyTopic) -> Option<StudyTopic> {
+        self.subject_role(user_id, study_topic.user_id, &study_topic.subject_name)?;
+
+        let mut study_topic = study_topic.clone();
+        self.progress
+            .get(&(study_topic.id, user_id))
+            .cloned()
+            .unwrap_or_default()
+            .apply(&mut study_topic);
+
+        Some(study_topic)
+    }
+
+    fn visible_study_topics(&self, user_id: i64) -> Vec<StudyTopic> {
+        self.study_topics
+            .iter()
+            .filter_map(|study_topic| self.study_topic_for(user_id, study_topic))
+            .collect()
+    }
+
+    fn visible_study_topic(&self, user_id: i64, study_topic_id: i64) -> Option<StudyTopic> {
+        self.study_topics
+            .iter()
+            .find(|study_topic| study_topic.id == study_topic_id)
+            .and_then(|study_topic| self.study_topic_for(user_id, study_topic))
+    }
+
+    fn sees_study_topic(&self, user_id: i64, study_topic_id: i64) -> bool {
+        self.visible_study_topic(user_id, study_topic_id).is_some()
+    }
+
+    /// Drops the topics' sessions, reviews and progress, of every user or
+    /// only of `user_id`.
+    fn delete_study_topic_rows(&mut self, study_topic_ids: &[i64], user_id: Option<i64>) {
+        let matches = |study_topic_id: i64, row_user_id: i64| {
+            study_topic_ids.contains(&study_topic_id)
+                && user_id.is_none_or(|user_id| user_id == row_user_id)
+        };
+
+        self.study_sessions
+            .retain(|study_session| !matches(study_session.study_topic_id, study_session.user_id));
+        self.review_logs
+            .retain(|review_log| !matches(review_log.study_topic_id, review_log.user_id));
+        self.progress
+            .retain(|(study_topic_id, row_user_id), _| !matches(*study_topic_id, *row_user_id));
+
+        let flashcard_ids: Vec<i64> = self
+            .flashcards
+            .iter()
+            .filter(|flashcard| study_topic_ids.contains(&flashcard.study_topic_id))
+            .map(|flashcard| flashcard.id)
+            .collect();
+        self.flashcard_progress
+            .retain(|(flashcard_id, row_user_id), _| {
+                !flashcard_ids.contains(flashcard_id)
+                    || user_id.is_some_and(|user_id| user_id != *row_user_id)
+            });
+
+        // Cards are content of the topic, they only go with the topic.
+        if user_id.is_none() {
+            self.flashcards
+                .retain(|flashcard| !study_topic_ids.contains(&flashcard.study_topic_id));
+        }
+    }
+
+    /// Pending sessions of the user on the topics they see that `keep`
+    /// accepts.
+    fn pending_study_sessions(
+        &self,
+        user_id: i64,
+        keep: impl Fn(&StudyTopic, &StudySession) -> bool,
+    ) -> Vec<StudySessionInfo> {
+        let study_topics = self.visible_study_topics(user_id);
+
+        self.study_sessions
+            .iter()
+            .filter(|study_session| {
+                study_session.user_id == user_id && study_session.completed_at.is_none()
+            })
+            .filter_map(|study_session| {
+                study_topics
+                    .iter()
+                    .find(|study_topic| {
+                        study_topic.id == study_session.study_topic_id
+                            && keep(study_topic, study_session)
+                    })
+                    .map(|study_topic| StudySessionInfo {
+                        id: study_session.id,
+                        study_topic_id: study_session.study_topic_id,
+                        flashcard_id: study_session.flashcard_id,
+                        due_date: study_session.due_date.clone(),
+                        subject_name: study_topic.subject_name.clone(),
+                        study_topic_name: study_topic.name.clone(),
+                        missed_reviews: study_session.missed_reviews,
+                    })
+            })
+            .collect()
+    }
+
+    /// Whether the topic is scheduled card by card: its subject says so and
+    /// it has cards.
+    fn scheduled_by_card(&self, study_topic: &StudyTopic) -> bool {
+        self.subjects.iter().any(|subject| {
+            subject.user_id == study_topic.user_id
+                && subject.subject_name == study_topic.subject_name
+                && subject.card_scheduling
+        }) && self
+            .flashcards
+            .iter()
+            .any(|flashcard| flashcard.study_topic_id == study_topic.id)
+    }
+
+    /// Whether the topic has a review planned or due on `date`, the rule
+    /// the SQL of the libsql repository implements, decided by the topic's
+    /// scheduler. Topics scheduled card by card have one when any of their
+    /// cards does, each card scheduled like the service does.
+    fn reviewed_on(&self, user_id: i64, study_topic: &StudyTopic, date: &str) -> bool {
+        let Ok(day) = NaiveDate::parse_from_str(date, DATE_FORMAT) else {
+            return false;
+        };
+
+        let scheduled = if self.scheduled_by_card(study_topic) {
+            self.flashcards
+                .iter()
+                .filter(|flashcard| flashcard.study_topic_id == study_topic.id)
+                .filter_map(|flashcard| {
+                    let created_at = DateTime::parse_from_rfc3339(&flashcard.created_at).ok()?;
+                    let mut card = StudyTopic {
+                        creation_date: format_date(created_at.date_naive()),
+                        ..study_topic.clone()
+                    };
+                    self.flashcard_progress
+                        .get(&(flashcard.id, user_id))
+                        .cloned()
+                        .unwrap_or_default()
+                        .apply(&mut card);
+                    Some(card)
+                })
+                .collect()
+        } else {
+            vec![study_topic.clone()]
+        };
+
+        let Some(subject) = self.subjects.iter().find(|subject| {
+            subject.user_id == study_topic.user_id
+                && subject.subject_name == study_topic.subject_name
+        }) else {
+            return false;
+        };
+
+        let intervals = study_topic
+            .interval_schedule_id
+            .or(subject.interval_schedule_id)
+            .and_then(|interval_schedule_id| {
+                self.interval_schedules
+                    .iter()
+                    .find(|interval_schedule| interval_schedule.id == interval_schedule_id)
+            })
+            .map_or_else(FixedOffsets::default, FixedOffsets::from);
+
+        let Ok(scheduler) = scheduler_for(subject, intervals) else {
+            return false;
+        };
+
+        // A review is due by then, or one is planned for that very day even
+        // if a session already covered it.
+        scheduled.iter().any(|study_topic| {
+            let without_sessions = StudyTopic {
+                last_session_date: None,
+                ..study_topic.clone()
+            };
+            matches!(scheduler.due_review(study_topic, day), Ok(Some(_)))
+                || matches!(
+                    scheduler.next_review_date(&without_sessions, day),
+                    Ok(Some(review_date)) if review_date == day
+                )
+        })
+    }
+}
+
+/// Escapes the text for HTML and wraps the words starting with one of the
+/// lowercase `terms` in `<mark>` tags, returning it and the term each
+/// marked word matched.
+fn highlight(text: &str, terms: &[String]) -> (String, Vec<String>) {
+    let mut highlighted = String::new();
+    let mut matches = Vec::new();
+
+    for (index, word) in text.split(' ').enumerate() {
+        if index > 0 {
+            highlighted.push(' ');
+        }
+
+        let lowercase = word.to_lowercase();
+        match terms
+            .iter()
+            .find(|term| lowercase.starts_with(term.as_str()))
+        {
+            Some(term) => {
+                highlighted.push_str(&format!("<mark>{}</mark>", escape_html(word)));
+                matches.push(term.clone());
+            }
+            None => highlighted.push_str(&escape_html(word)),
+        }
+    }
+
+    (highlighted, matches)
+}
+
+/// Page of pending sessions matching `query`.
+fn study_session_page(
+    study_sessions: Vec<StudySessionInfo>,
+    query: StudySessionQuery,
+    after: Option<Cursor>,
+    limit: u32,
+) -> Vec<StudySessionInfo> {
+    let study_sessions = study_sessions
+        .into_iter()
+        .filter(|study_session| {
+            query
+                .due_before
+                .as_ref()
+                .is_none_or(|due_before| &study_session.due_date <= due_before)
+        })
+        .map(|study_session| {
+            (
+                query.sort.key(&study_session),
+                study_session.id,
+                study_session,
+            )
+        })
+        .collect();
+
+    page_of(study_sessions, query.order, after, limit)
+}
+
+/// Sorts rows by key and then id, keeps those after the cursor and takes one
+/// more than `limit`, like the keyset queries of the libsql repository.
+fn page_of<T>(
+    mut rows: Vec<(SortKey, i64, T)>,
+    order: SortOrder,
+    after: Option<Cursor>,
+    limit: u32,
+) -> Vec<T> {
+    let compare = |(key, id): (&SortKey, i64), (other_key, other_id): (&SortKey, i64)| {
+        let ordering = key
+            .partial_cmp(other_key)
+            .unwrap_or(Ordering::Equal)
+            .then(id.cmp(&other_id));
+
+        match order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    };
+
+    rows.sort_by(|a, b| compare((&a.0, a.1), (&b.0, b.1)));
+
+    rows.into_iter()
+        .filter(|(key, id, _)| {
+            after.as_ref().is_none_or(|after| {
+                compare((key, *id), (&after.key, after.id)) == Ordering::Greater
+            })
+        })
+        .take(limit as usize + 1)
+        .map(|(_, _, row)| row)
+        .collect()
+}
+
+#[async_trait]
+impl StudyRepository for InMemoryRepository {
+    async fn get_study_session(
+        &self,
+        user_id: i64,
+        study_session_id: i64,
+    ) -> RepoResult<Option<StudySession>> {
+        let state = self.state();
+
+        let study_session = state
+            .study_sessions
+            .iter()
+            .find(|study_session| {
+                study_session.id == study_session_id
+                    && study_session.user_id == user_id
+                    && state.sees_study_topic(user_id, study_session.study_topic_id)
+            })
+            .cloned();
+
+        Ok(study_session)
+    }
+
+    async fn get_study_topic(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+    ) -> RepoResult<Option<StudyTopic>> {
+        Ok(self.state().visible_study_topic(user_id, study_topic_id))
+    }
+
+    async fn get_pending_study_sessions_for_study_topic(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+    ) -> RepoResult<Vec<StudySession>> {
+        let state = self.state();
+
+        let mut study_sessions: Vec<StudySession> = state
+            .study_sessions
+            .iter()
+            .filter(|study_session| {
+                study_session.study_topic_id == study_topic_id
+                    && study_session.user_id == user_id
+                    && study_session.completed_at.is_none()
+                    && state.sees_study_topic(user_id, study_topic_id)
+            })
+            .cloned()
+            .collect();
+        study_sessions.sort_by(|a, b| a.due_date.cmp(&b.due_date));
+
+        Ok(study_sessions)
+    }
+
+    async fn create_study_session(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+        flashcard_id: Option<i64>,
+        due_date: String,
+        missed_reviews: i64,
+        created_on: String,
+    ) -> RepoResult<bool> {
+        let mut state = self.state();
+
+        let flashcard_of_topic = flashcard_id.is_none_or(|flashcard_id| {
+            state.flashcards.iter().any(|flashcard| {
+                flashcard.id == flashcard_id && flashcard.study_topic_id == study_topic_id
+            })
+        });
+
+        if !state.sees_study_topic(user_id, study_topic_id)
+            || !flashcard_of_topic
+            || state.study_sessions.iter().any(|study_session| {
+                study_session.study_topic_id == study_topic_id
+                    && study_session.flashcard_id == flashcard_id
+                    && study_session.user_id == user_id
+                    && study_session.due_date == due_date
+            })
+        {
+            return Ok(false);
+        }
+
+        state.last_study_session_id += 1;
+        let id = state.last_study_session_id;
+
+        state.study_sessions.push(StudySession {
+            id,
+            study_topic_id,
+            user_id,
+            flashcard_id,
+            due_date,
+            missed_reviews,
+            completed_at: None,
+        });
+
+        let progress = state.progress.entry((study_topic_id, user_id)).or_default();
+        progress.last_session_date = Some(created_on.clone());
+        progress.total_sessions += 1;
+
+        if let Some(flashcard_id) = flashcard_id {
+            let progress = state
+                .flashcard_progress
+                .entry((flashcard_id, user_id))
+                .or_default();
+            progress.last_session_date = Some(created_on);
+            progress.total_sessions += 1;
+        }
+
+        Ok(true)
+    }
+
+    async fn complete_study_session(
+        &self,
+        user_id: i64,
+        study_session_id: i64,
+        schedule: Option<StudyTopicSchedule>,
+        review_log: ReviewLogInfo,
+    ) -> RepoResult<bool> {
+        let mut state = self.state();
+
+        let visible_sessions: Vec<i64> = state
+            .study_sessions
+            .iter()
+            .filter(|study_session| {
+                study_session.user_id == user_id
+                    && state.sees_study_topic(user_id, study_session.study_topic_id)
+            })
+            .map(|study_session| study_session.id)
+            .collect();
+
+        let Some(study_session) = state.study_sessions.iter_mut().find(|study_session| {
+            study_session.id == study_session_id
+                && study_session.completed_at.is_none()
+                && visible_sessions.contains(&study_session.id)
+        }) else {
+            return Ok(false);
+        };
+        study_session.completed_at = review_log.completed_at.clone();
+        // The review is logged for the session's own topic and card, not
+        // whatever the caller passed.
+        let study_topic_id = study_session.study_topic_id;
+        let user_id = study_session.user_id;
+        let flashcard_id = study_session.flashcard_id;
+
+        state
+            .progress
+            .entry((study_topic_id, user_id))
+            .or_default()
+            .completed_sessions += 1;
+
+        // A card session schedules the card, the topic only counts it.
+        let progress = match flashcard_id {
+            Some(flashcard_id) => {
+                let progress = state
+                    .flashcard_progress
+                    .entry((flashcard_id, user_id))
+                    .or_default();
+                progress.completed_sessions += 1;
+                progress
+            }
+            None => state.progress.entry((study_topic_id, user_id)).or_default(),
+        };
+
+        if let Some(schedule) = schedule {
+            progress.ease_factor = schedule.ease_factor;
+            progress.interval_days = schedule.interval_days;
+            progress.repetitions = schedule.repetitions;
+            progress.stability = schedule.stability;
+            progress.difficulty = schedule.difficulty;
+            progress.next_due_date = schedule.next_due_date;
+            progress.last_review_date = schedule.last_review_date;
+        }
+
+        state.last_review_log_id += 1;
+        let id = state.last_review_log_id;
+
+        state.review_logs.push(ReviewLog {
+            id,
+            study_topic_id,
+            user_id,
+            flashcard_id,
+            study_session_id: Some(study_session_id),
+            scheduled_date: review_log.scheduled_date,
+            review_date: review_log.review_date,
+            completed_at: review_log.completed_at,
+            grade: review_log.grade,
+            time_spent_seconds: review_log.time_spent_seconds,
+        });
+
+        Ok(true)
+    }
+
+    async fn get_subjects(&self, user_id: i64) -> RepoResult<Vec<Subject>> {
+        Ok(self.state().visible_subjects(user_id))
+    }
+
+    async fn get_subject(&self, user_id: i64, subject_name: String) -> RepoResult<Option<Subject>> {
+        let subject = self
+            .state()
+            .visible_subjects(user_id)
+            .into_iter()
+            .find(|subject| subject.subject_name == subject_name);
+
+        Ok(subject)
+    }
+
+    async fn add_subject(&self, user_id: i64, subject_name: String) -> RepoResult<()> {
+        let mut state = self.state();
+
+        if state
+            .visible_subjects(user_id)
+            .iter()
+            .any(|subject| subject.subject_name == subject_name)
+        {
+            return Err(RepositoryError::Conflict(format!("subject {subject_name}")));
+        }
+
+        state.subjects.push(Subject {
+            user_id,
+            subject_name,
+            scheduler: SchedulerKind::default(),
+            desired_retention: 0.9,
+            fsrs_parameters: None,
+            interval_schedule_id: None,
+            card_scheduling: false,
+            role: SubjectRole::Owner,
+        });
+
+        Ok(())
+    }
+
+    async fn import_subjects(&self, user_id: i64, subjects: Vec<SubjectImport>) -> RepoResult<()> {
+        let mut state = self.state();
+
+        // Checked up front so a conflict leaves nothing behind.
+        let mut subject_names: Vec<String> = state
+            .visible_subjects(user_id)
+            .into_iter()
+            .map(|subject| subject.subject_name)
+            .collect();
+        for subject in &subjects {
+            if subject_names.contains(&subject.subject_name) {
+                return Err(RepositoryError::Conflict(format!(
+                    "subject {}",
+                    subject.subject_name
+                )));
+            }
+            subject_names.push(subject.subject_name.clone());
+        }
+
+        for subject in subjects {
+            state.subjects.push(Subject {
+                user_id,
+                subject_name: subject.subject_name.clone(),
+                scheduler: subject.scheduler,
+                desired_retention: 0.9,
+                fsrs_parameters: None,
+                interval_schedule_id: None,
+                card_scheduling: true,
+                role: SubjectRole::Owner,
+            });
+
+            for study_topic in subject.study_topics {
+                state.last_study_topic_id += 1;
+                let study_topic_id = state.last_study_topic_id;
+                state.study_topics.push(StudyTopic {
+                    id: study_topic_id,
+                    user_id,
+                    name: study_topic.name,
+                    description: study_topic.description,
+                    creation_date: study_topic.creation_date,
+                    subject_name: subject.subject_name.clone(),
+                    last_session_date: None,
+                    total_sessions: 0,
+                    completed_sessions: 0,
+                    ease_factor: 2.5,
+                    interval_days: 0,
+                    repetitions: 0,
+                    next_due_date: None,
+                    stability: None,
+                    difficulty: None,
+                    last_review_date: None,
+                    interval_schedule_id: None,
+                });
+
+                for flashcard in study_topic.flashcards {
+                    state.last_flashcard_id += 1;
+                    let flashcard_id = state.last_flashcard_id;
+                    state.flashcards.push(Flashcard {
+                        id: flashcard_id,
+                        study_topic_id,
+                        front: flashcard.flashcard.front,
+                        back: flashcard.flashcard.back,
+                        hint: flashcard.flashcard.hint,
+                        created_at: flashcard.created_at.clone(),
+                        updated_at: flashcard.created_at,
+                    });
+
+                    let reviews = flashcard.reviews.len() as i64;
+                    let last_review_date = flashcard
+                        .reviews
+                        .last()
+                        .map(|review| review.review_date.clone());
+                    for review in flashcard.reviews {
+                        state.last_review_log_id += 1;
+                        let id = state.last_review_log_id;
+                        state.review_logs.push(ReviewLog {
+                            id,
+                            study_topic_id,
+                            user_id,
+                            flashcard_id: Some(flashcard_id),
+                            study_session_id: None,
+                            scheduled_date: None,
+                            review_date: review.review_date,
+                            completed_at: Some(review.completed_at),
+                            grade: Some(review.grade),
+                            time_spent_seconds: review.time_spent_seconds,
+                        });
+                    }
+
+                    if reviews > 0 {
+                        let progress = state.progress.entry((study_topic_id, user_id)).or_default();
+                        progress.total_sessions += reviews;
+                        progress.completed_sessions += reviews;
+                        progress.last_session_date = progress
+                            .last_session_date
+                            .take()
+                            .max(last_review_date.clone());
+                    }
+                    if flashcard.schedule.is_none() && reviews == 0 {
+                        continue;
+                    }
+
+                    let progress = state
+                        .flashcard_progress
+                        .entry((flashcard_id, user_id))
+                        .or_default();
+                    progress.last_session_date = last_review_date;
+                    progress.total_sessions = reviews;
+                    progress.completed_sessions = reviews;
+                    if let Some(schedule) = flashcard.schedule {
+                        progress.ease_factor = schedule.ease_factor;
+                        progress.interval_days = schedule.interval_days;
+                        progress.repetitions = schedule.repetitions;
+                        progress.stability = schedule.stability;
+                        progress.difficulty = schedule.difficulty;
+                        progress.next_due_date = schedule.next_due_date;
+                        progress.last_review_date = schedule.last_review_date;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn update_subject_scheduler(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        scheduler: SchedulerKind,
+        desired_retention: f64,
+        fsrs_parameters: Option<String>,
+        card_scheduling: bool,
+    ) -> RepoResult<()> {
+        let mut state = self.state();
+
+        let Some(subject) = state
+            .subjects
+            .iter_mut()
+            .find(|subject| subject.user_id == user_id && subject.subject_name == subject_name)
+        else {
+            return Err(RepositoryError::NotFound(format!("subject {subject_name}")));
+        };
+
+        subject.scheduler = scheduler;
+        subject.desired_retention = desired_retention;
+        subject.fsrs_parameters = fsrs_parameters;
+        subject.card_scheduling = card_scheduling;
+
+        Ok(())
+    }
+
+    async fn update_subject_interval_schedule(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        interval_schedule_id: Option<i64>,
+    ) -> RepoResult<()> {
+        let mut state = self.state();
+
+        let Some(subject) = state
+            .subjects
+            .iter_mut()
+            .find(|subject| subject.user_id == user_id && subject.subject_name == subject_name)
+        else {
+            return Err(RepositoryError::NotFound(format!("subject {subject_name}")));
+        };
+
+        subject.interval_schedule_id = interval_schedule_id;
+
+        Ok(())
+    }
+
+    async fn rename_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        new_subject_name: String,
+    ) -> RepoResult<()> {
+        let mut state = self.state();
+
+        let taken = state
+            .visible_subjects(user_id)
+            .iter()
+            .any(|subject| subject.subject_name == new_subject_name)
+            || state
+                .subject_members
+                .iter()
+                .filter(|member| {
+                    member.subject_owner_id == user_id
+                        && member.subject_name == subject_name
+                        && member.accepted_at.is_some()
+                })
+                .any(|member| {
+                    state
+                        .visible_subjects(member.user_id)
+                        .iter()
+                        .any(|subject| subject.subject_name == new_subject_name)
+                });
+
+        if taken {
+            return Err(RepositoryError::Conflict(format!(
+                "subject {new_subject_name}"
+            )));
+        }
+
+        let Some(subject) = state
+            .subjects
+            .iter_mut()
+            .find(|subject| subject.user_id == user_id && subject.subject_name == subject_name)
+        else {
+            return Err(RepositoryError::NotFound(format!("subject {subject_name}")));
+        };
+
+        subject.subject_name = new_subject_name.clone();
+
+        for study_topic in state.study_topics.iter_mut().filter(|study_topic| {
+            study_topic.user_id == user_id && study_topic.subject_name == subject_name
+        }) {
+            study_topic.subject_name = new_subject_name.clone();
+        }
+
+        for member in state.subject_members.iter_mut().filter(|member| {
+            member.subject_owner_id == user_id && member.subject_name == subject_name
+        }) {
+            member.subject_name = new_subject_name.clone();
+        }
+
+        Ok(())
+    }
+
+    async fn delete_subject(&self, user_id: i64, subject_name: String) -> RepoResult<()> {
+        let mut state = self.state();
+
+        if !state
+            .subjects
+            .iter()
+            .any(|subject| subject.user_id == user_id && subject.subject_name == subject_name)
+        {
+            return Err(RepositoryError::NotFound(format!("subject {subject_name}")));
+        }
+
+        state
+            .subjects
+            .retain(|subject| subject.user_id != user_id || subject.subject_name != subject_name);
+
+        let deleted_topic_ids: Vec<i64> = state
+            .study_topics
+            .iter()
+            .filter(|study_topic| {
+                study_topic.user_id == user_id && study_topic.subject_name == subject_name
+            })
+            .map(|study_topic| study_topic.id)
+            .collect();
+
+        state.study_topics.retain(|study_topic| {
+            study_topic.user_id != user_id || study_topic.subject_name != subject_name
+        });
+        state.subject_members.retain(|member| {
+            member.subject_owner_id != user_id || member.subject_name != subject_name
+        });
+        state.delete_study_topic_rows(&deleted_topic_ids, None);
+
+        Ok(())
+    }
+
+    async fn get_subject_members(
+        &self,
+        user_id: i64,
+        subject_name: String,
+    ) -> RepoResult<Vec<SubjectMember>> {
+        let state = self.state();
+
+        let Some(subject) = state
+            .visible_subjects(user_id)
+            .into_iter()
+            .find(|subject| subject.subject_name == subject_name)
+        else {
+            return Ok(Vec::new());
+        };
+
+        let subject_members = state
+            .subject_members
+            .iter()
+            .filter(|member| {
+                member.subject_owner_id == subject.user_id && member.subject_name == subject_name
+            })
+            .filter_map(|member| {
+                let user = state.users.iter().find(|user| user.id == member.user_id)?;
+
+                Some(SubjectMember {
+                    user_id: member.user_id,
+                    username: user.username.clone(),
+                    role: member.role,
+                    invited_at: Some(member.invited_at.clone()),
+                    accepted_at: member.accepted_at.clone(),
+                })
+            })
+            .collect();
+
+        Ok(subject_members)
+    }
+
+    async fn add_subject_member(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        member_id: i64,
+        role: SubjectRole,
+        invited_at: String,
+    ) -> RepoResult<i64> {
+        let mut state = self.state();
+
+        if !state
+            .subjects
+            .iter()
+            .any(|subject| subject.user_id == user_id && subject.subject_name == subject_name)
+        {
+            return Err(RepositoryError::NotFound(format!("subject {subject_name}")));
+        }
+        if !state.users.iter().any(|user| user.id == member_id) {
+            return Err(RepositoryError::NotFound(format!("user {member_id}")));
+        }
+        if state.subject_members.iter().any(|member| {
+            member.subject_owner_id == user_id
+                && member.subject_name == subject_name
+                && member.user_id == member_id
+        }) {
+            return Err(RepositoryError::Conflict(format!(
+                "member {member_id} of subject {subject_name}"
+            )));
+        }
+
+        state.last_subject_member_id += 1;
+        let id = state.last_subject_member_id;
+
+        state.subject_members.push(SubjectMemberRow {
+            id,
+            subject_owner_id: user_id,
+            subject_name,
+            user_id: member_id,
+            role,
+            invited_at,
+            accepted_at: None,
+        });
+
+        Ok(id)
+    }
+
+    async fn delete_subject_member(
+        &self,
+        owner_id: i64,
+        subject_name: String,
+        member_id: i64,
+    ) -> RepoResult<()> {
+        let mut state = self.state();
+
+        let is_member = |member: &SubjectMemberRow| {
+            member.subject_owner_id == owner_id
+                && member.subject_name == subject_name
+                && member.user_id == member_id
+        };
+
+        if !state.subject_members.iter().any(is_member) {
+            return Err(RepositoryError::NotFound(format!(
+                "member {member_id} of subject {subject_name}"
+            )));
+        }
+
+        state.subject_members.retain(|member| !is_member(member));
+
+        let study_topic_ids: Vec<i64> = state
+            .study_topics
+            .iter()
+            .filter(|study_topic| {
+                study_topic.user_id == owner_id && study_topic.subject_name == subject_name
+            })
+            .map(|study_topic| study_topic.id)
+            .collect();
+        state.delete_study_topic_rows(&study_topic_ids, Some(member_id));
+
+        Ok(())
+    }
+
+    async fn get_subject_invitations(&self, user_id: i64) -> RepoResult<Vec<SubjectInvitation>> {
+        let state = self.state();
+
+        let subject_invitations = state
+            .subject_members
+            .iter()
+            .filter(|member| member.user_id == user_id && member.accepted_at.is_none())
+            .filter_map(|member| {
+                let owner = state
+                    .users
+                    .iter()
+                    .find(|user| user.id == member.subject_owner_id)?;
+
+                Some(SubjectInvitation {
+                    id: member.id,
+                    subject_name: member.subject_name.clone(),
+                    owner_username: owner.username.clone(),
+                    role: member.role,
+                    invited_at: member.invited_at.clone(),
+                })
+            })
+            .collect();
+
+        Ok(subject_invitations)
+    }
+
+    async fn accept_subject_invitation(
+        &self,
+        user_id: i64,
+        invitation_id: i64,
+        accepted_at: String,
+    ) -> RepoResult<()> {
+        let mut state = self.state();
+
+        let Some(subject_name) = state
+            .subject_members
+            .iter()
+            .find(|member| {
+                member.id == invitation_id
+                    && member.user_id == user_id
+                    && member.accepted_at.is_none()
+            })
+            .map(|member| member.subject_name.clone())
+        else {
+            return Err(RepositoryError::NotFound(format!(
+                "invitation {invitation_id}"
+            )));
+        };
+
+        if state
+            .visible_subjects(user_id)
+            .iter()
+            .any(|subject| subject.subject_name == subject_name)
+        {
+            return Err(RepositoryError::Conflict(format!("subject {subject_name}")));
+        }
+
+        if let Some(member) = state
+            .subject_members
+            .iter_mut()
+            .find(|member| member.id == invitation_id)
+        {
+            member.accepted_at = Some(accepted_at);
+        }
+
+        Ok(())
+    }
+
+    async fn delete_subject_invitation(&self, user_id: i64, invitation_id: i64) -> RepoResult<()> {
+        let mut state = self.state();
+
+        let is_invitation = |member: &SubjectMemberRow| {
+            member.id == invitation_id && member.user_id == user_id && member.accepted_at.is_none()
+        };
+
+        if !state.subject_members.iter().any(is_invitation) {
+            return Err(RepositoryError::NotFound(format!(
+                "invitation {invitation_id}"
+            )));
+        }
+
+        state
+            .subject_members
+            .retain(|member| !is_invitation(member));
+
+        Ok(())
+    }
+
+    async fn get_study_topics_for_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+    ) -> RepoResult<Vec<StudyTopic>> {
+        let study_topics = self
+            .state()
+            .visible_study_topics(user_id)
+            .into_iter()
+            .filter(|study_topic| study_topic.subject_name == subject_name)
+            .collect();
+
+        Ok(study_topics)
+    }
+
+    async fn get_study_sessions_for_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+    ) -> RepoResult<Vec<StudySessionInfo>> {
+        Ok(self
+            .state()
+            .pending_study_sessions(user_id, |study_topic, _| {
+                study_topic.subject_name == subject_name
+            }))
+    }
+
+    async fn query_study_sessions_for_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        query: StudySessionQuery,
+        after: Option<Cursor>,
+        limit: u32,
+    ) -> RepoResult<Vec<StudySessionInfo>> {
+        let study_sessions = self
+            .get_study_sessions_for_subject(user_id, subject_name)
+            .await?;
+
+        Ok(study_session_page(study_sessions, query, after, limit))
+    }
+
+    async fn query_flashcard_sessions(
+        &self,
+        user_id: i64,
+        query: StudySessionQuery,
+        after: Option<Cursor>,
+        limit: u32,
+    ) -> RepoResult<Vec<StudySessionInfo>> {
+        let study_sessions = self
+            .state()
+            .pending_study_sessions(user_id, |_, study_session| {
+                study_session.flashcard_id.is_some()
+            });
+
+        Ok(study_session_page(study_sessions, query, after, limit))
+    }
+
+    async fn get_study_topics(&self, user_id: i64) -> RepoResult<Vec<StudyTopic>> {
+        Ok(self.state().visible_study_topics(user_id))
+    }
+
+    async fn query_study_topics(
+        &self,
+        user_id: i64,
+        query: StudyTopicQuery,
+        after: Option<Cursor>,
+        limit: u32,
+    ) -> RepoResult<Vec<StudyTopic>> {
+        let state = self.state();
+
+        let has_pending_session = |study_topic: &StudyTopic, due_before: Option<&String>| {
+            state.study_sessions.iter().any(|study_session| {
+                study_session.study_topic_id == study_topic.id
+                    && study_session.user_id == user_id
+                    && study_session.completed_at.is_none()
+                    && due_before.is_none_or(|due_before| &study_session.due_date <= due_before)
+            })
+        };
+
+        let study_topics = state
+            .visible_study_topics(user_id)
+            .iter()
+            .filter(|study_topic| {
+                query
+                    .subject_name
+                    .as_ref()
+                    .is_none_or(|subject_name| &study_topic.subject_name == subject_name)
+                    && query
+                        .created_from
+                        .as_ref()
+                        .is_none_or(|created_from| &study_topic.creation_date >= created_from)
+                    && query
+                        .created_to
+                        .as_ref()
+                        .is_none_or(|created_to| &study_topic.creation_date <= created_to)
+                    && query.due_before.as_ref().is_none_or(|due_before| {
+                        study_topic
+                            .next_due_date
+                            .as_ref()
+                            .is_some_and(|next_due_date| next_due_date <= due_before)
+                            || has_pending_session(study_topic, Some(due_before))
+                    })
+                    && query.has_pending_session.is_none_or(|has_pending| {
+                        has_pending_session(study_topic, None) == has_pending
+                    })
+                    && query
+                        .review_on
+                        .as_ref()
+                        .is_none_or(|review_on| state.reviewed_on(user_id, study_topic, review_on))
+            })
+            .map(|study_topic| {
+                (
+                    query.sort.key(study_topic),
+                    study_topic.id,
+                    study_topic.clone(),
+                )
+            })
+            .collect();
+
+        Ok(page_of(study_topics, query.order, after, limit))
+    }
+
+    async fn search_study_topics(
+        &self,
+        user_id: i64,
+        terms: Vec<String>,
+        limit: u32,
+    ) -> RepoResult<Vec<SearchResult>> {
+        let state = self.state();
+        let terms: Vec<String> = terms.iter().map(|term| term.to_lowercase()).collect();
+
+        let mut search_results: Vec<SearchResult> = state
+            .visible_study_topics(user_id)
+            .iter()
+            .filter_map(|study_topic| {
+                let (name_highlight, name_matches) = highlight(&study_topic.name, &terms);
+                let (description_snippet, description_matches) = match &study_topic.description {
+                    Some(description) => {
+                        let (snippet, matches) = highlight(description, &terms);
+                        (Some(snippet), matches)
+                    }
+                    None => (None, Vec::new()),
+                };
+
+                let all_terms_match = terms
+                    .iter()
+                    .all(|term| name_matches.contains(term) || description_matches.contains(term));
+
+                all_terms_match.then(|| SearchResult {
+                    study_topic_id: study_topic.id,
+                    name: study_topic.name.clone(),
+                    subject_name: study_topic.subject_name.clone(),
+                    name_highlight,
+                    description_snippet,
+                    rank: -((name_matches.len() + description_matches.len()) as f64),
+                })
+            })
+            .collect();
+
+        search_results.sort_by(|a, b| {
+            a.rank
+                .partial_cmp(&b.rank)
+                .unwrap_or(Ordering::Equal)
+                .then(a.study_topic_id.cmp(&b.study_topic_id))
+        });
+        search_results.truncate(limit as usize);
+
+        Ok(search_results)
+    }
+
+    async fn add_study_topic(
+        &self,
+        user_id: i64,
+        study_topic: StudyTopicInfo,
+        creation_date: String,
+    ) -> RepoResult<i64> {
+        let mut state = self.state();
+
+        let Some(subject) = state
+            .visible_subjects(user_id)
+            .into_iter()
+            .find(|subject| subject.subject_name == study_topic.subject_name)
+        else {
+            return Err(RepositoryError::NotFound(format!(
+                "subject {}",
+                study_topic.subject_name
+            )));
+        };
+
+        state.last_study_topic_id += 1;
+        let id = state.last_study_topic_id;
+
+        state.study_topics.push(StudyTopic {
+            id,
+            user_id: subject.user_id,
+            name: study_topic.name,
+            description: study_topic.description,
+            creation_date,
+            subject_name: study_topic.subject_name,
+            last_session_date: None,
+            total_sessions: 0,
+            completed_sessions: 0,
+            ease_factor: 2.5,
+            interval_days: 0,
+            repetitions: 0,
+            next_due_date: None,
+            stability: None,
+            difficulty: None,
+            last_review_date: None,
+            interval_schedule_id: study_topic.interval_schedule_id,
+        });
+
+        Ok(id)
+    }
+
+    async fn update_study_topic(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+        update: StudyTopicUpdate,
+    ) -> RepoResult<()> {
+        let mut state = self.state();
+
+        let Some(owner_id) = state
+            .visible_study_topic(user_id, study_topic_id)
+            .map(|study_topic| study_topic.user_id)
+        else {
+            return Err(RepositoryError::NotFound(format!(
+                "study topic {study_topic_id}"
+            )));
+        };
+
+        if let Some(subject_name) = &update.subject_name {
+            if !state
+                .subjects
+                .iter()
+                .any(|subject| subject.user_id == owner_id && &subject.subject_name == subject_name)
+            {
+                return Err(RepositoryError::NotFound(format!("subject {subject_name}")));
+            }
+        }
+
+        let Some(study_topic) = state
+            .study_topics
+            .iter_mut()
+            .find(|study_topic| study_topic.id == study_topic_id)
+        else {
+            return Err(RepositoryError::NotFound(format!(
+                "study topic {study_topic_id}"
+            )));
+        };
+
+        if let Some(name) = update.name {
+            study_topic.name = name;
+        }
+        if let Some(description) = update.description {
+            study_topic.description = description;
+        }
+        if let Some(subject_name) = update.subject_name {
+            study_topic.subject_name = subject_name;
+        }
+
+        Ok(())
+    }
+
+    async fn update_study_topic_interval_schedule(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+        interval_schedule_id: Option<i64>,
+    ) -> RepoResult<()> {
+        let mut state = self.state();
+
+        let visible = state.sees_study_topic(user_id, study_topic_id);
+        let Some(study_topic) = state
+            .study_topics
+            .iter_mut()
+            .find(|study_topic| study_topic.id == study_topic_id && visible)
+        else {
+            return Err(RepositoryError::NotFound(format!(
+                "study topic {study_topic_id}"
+            )));
+        };
+
+        study_topic.interval_schedule_id = interval_schedule_id;
+
+        Ok(())
+    }
+
+    async fn delete_study_topic(&self, user_id: i64, study_topic_id: i64) -> RepoResult<()> {
+        let mut state = self.state();
+
+        if !state.sees_study_topic(user_id, study_topic_id) {
+            return Err(RepositoryError::NotFound(format!(
+                "study topic {study_topic_id}"
+            )));
+        }
+
+        state
+            .study_topics
+            .retain(|study_topic| study_topic.id != study_topic_id);
+        state.delete_study_topic_rows(&[study_topic_id], None);
+
+        Ok(())
+    }
+
+    async fn get_flashcards(
+        &self,
+        user_id: i64,
+        study_topic_ids: Vec<i64>,
+    ) -> RepoResult<Vec<Flashcard>> {
+        let state = self.state();
+
+        let mut flashcards: Vec<Flashcard> = state
+            .flashcards
+            .iter()
+            .filter(|flashcard| {
+                study_topic_ids.contains(&flashcard.study_topic_id)
+                    && state.sees_study_topic(user_id, flashcard.study_topic_id)
+            })
+            .cloned()
+            .collect();
+        flashcards.sort_by_key(|flashcard| (flashcard.study_topic_id, flashcard.id));
+
+        Ok(flashcards)
+    }
+
+    async fn get_flashcard(
+        &self,
+        user_id: i64,
+        flashcard_id: i64,
+    ) -> RepoResult<Option<Flashcard>> {
+        let state = self.state();
+
+        Ok(state
+            .flashcards
+            .iter()
+            .find(|flashcard| {
+                flashcard.id == flashcard_id
+                    && state.sees_study_topic(user_id, flashcard.study_topic_id)
+            })
+            .cloned())
+    }
+
+    async fn get_flashcard_progress(
+        &self,
+        user_id: i64,
+        study_topic_ids: Vec<i64>,
+    ) -> RepoResult<Vec<FlashcardProgress>> {
+        let flashcards = self.get_flashcards(user_id, study_topic_ids).await?;
+        let state = self.state();
+
+        let flashcard_progress = flashcards
+            .into_iter()
+            .map(|flashcard| {
+                let progress = state
+                    .flashcard_progress
+                    .get(&(flashcard.id, user_id))
+                    .cloned()
+                    .unwrap_or_default();
+
+                FlashcardProgress {
+                    flashcard_id: flashcard.id,
+                    study_topic_id: flashcard.study_topic_id,
+                    created_at: flashcard.created_at,
+                    last_session_date: progress.last_session_date,
+                    total_sessions: progress.total_sessions,
+                    completed_sessions: progress.completed_sessions,
+                    ease_factor: progress.ease_factor,
+                    interval_days: progress.interval_days,
+                    repetitions: progress.repetitions,
+                    next_due_date: progress.next_due_date,
+                    stability: progress.stability,
+                    difficulty: progress.difficulty,
+                    last_review_date: progress.last_review_date,
+                }
+            })
+            .collect();
+
+        Ok(flashcard_progress)
+    }
+
+    async fn add_flashcard(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+        flashcard: FlashcardInfo,
+        created_at: String,
+    ) -> RepoResult<i64> {
+        let mut state = self.state();
+
+        if !state.sees_study_topic(user_id, study_topic_id) {
+            return Err(RepositoryError::NotFound(format!(
+                "study topic {study_topic_id}"
+            )));
+        }
+
+        state.last_flashcard_id += 1;
+        let id = state.last_flashcard_id;
+        state.flashcards.push(Flashcard {
+            id,
+            study_topic_id,
+            front: flashcard.front,
+            back: flashcard.back,
+            hint: flashcard.hint,
+            created_at: created_at.clone(),
+            updated_at: created_at,
+        });
+
+        Ok(id)
+    }
+
+    async fn update_flashcard(
+        &self,
+        user_id: i64,
+        flashcard_id: i64,
+        update: FlashcardUpdate,
+        updated_at: String,
+    ) -> RepoResult<()> {
+        let mut state = self.state();
+
+        let Some(index) = state.flashcards.iter().position(|flashcard| {
+            flashcard.id == flashcard_id
+                && state.sees_study_topic(user_id, flashcard.study_topic_id)
+        }) else {
+            return Err(RepositoryError::NotFound(format!(
+                "flashcard {flashcard_id}"
+            )));
+        };
+        let flashcard = &mut state.flashcards[index];
+
+        if let Some(front) = update.front {
+            flashcard.front = front;
+        }
+        if let Some(back) = update.back {
+            flashcard.back = back;
+        }
+        if let Some(hint) = update.hint {
+            flashcard.hint = hint;
+        }
+        flashcard.updated_at = updated_at;
+
+        Ok(())
+    }
+
+    async fn delete_flashcard(&self, user_id: i64, flashcard_id: i64) -> RepoResult<()> {
+        let mut state = self.state();
+
+        let Some(index) = state.flashcards.iter().position(|flashcard| {
+            flashcard.id == flashcard_id
+                && state.sees_study_topic(user_id, flashcard.study_topic_id)
+        }) else {
+            return Err(RepositoryError::NotFound(format!(
+                "flashcard {flashcard_id}"
+            )));
+        };
+        state.flashcards.remove(index);
+        state
+            .study_sessions
+            .retain(|study_session| study_session.flashcard_id != Some(flashcard_id));
+        state
+            .review_logs
+            .retain(|review_log| review_log.flashcard_id != Some(flashcard_id));
+        state
+            .flashcard_progress
+            .retain(|(progress_flashcard_id, _), _| *progress_flashcard_id != flashcard_id);
+
+        Ok(())
+    }
+
+    async fn get_review_logs_for_study_topic(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+    ) -> RepoResult<Vec<ReviewLog>> {
+        let state = self.state();
+
+        let mut review_logs: Vec<ReviewLog> = state
+            .review_logs
+            .iter()
+            .filter(|review_log| {
+                review_log.study_topic_id == study_topic_id
+                    && review_log.user_id == user_id
+                    && state.sees_study_topic(user_id, study_topic_id)
+            })
+            .cloned()
+            .collect();
+
+        review_logs.sort_by(|a, b| (&a.review_date, a.id).cmp(&(&b.review_date, b.id)));
+
+        Ok(review_logs)
+    }
+
+    async fn get_review_logs_for_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+    ) -> RepoResult<Vec<ReviewLog>> {
+        let state = self.state();
+
+        let study_topics = state.visible_study_topics(user_id);
+
+        let mut review_logs: Vec<ReviewLog> = state
+            .review_logs
+            .iter()
+            .filter(|review_log| {
+                review_log.user_id == user_id
+                    && study_topics.iter().any(|study_topic| {
+                        study_topic.id == review_log.study_topic_id
+                            && study_topic.subject_name == subject_name
+                    })
+            })
+            .cloned()
+            .collect();
+
+        review_logs.sort_by(|a, b| {
+            (a.study_topic_id, a.flashcard_id, &a.review_date, a.id).cmp(&(
+                b.study_topic_id,
+                b.flashcard_id,
+                &b.review_date,
+                b.id,
+            ))
+        });
+
+        Ok(review_logs)
+    }
+
+    async fn get_interval_schedules(&self, user_id: i64) -> RepoResult<Vec<IntervalSchedule>> {
+        let state = self.state();
+
+        let interval_schedules = state
+            .interval_schedules
+            .iter()
+            .filter(|interval_schedule| interval_schedule.user_id == user_id)
+            .cloned()
+            .collect();
+
+        Ok(interval_schedules)
+    }
+
+    async fn get_interval_schedule(
+        &self,
+        user_id: i64,
+        interval_schedule_id: i64,
+    ) -> RepoResult<Option<IntervalSchedule>> {
+        let state = self.state();
+
+        let interval_schedule = state
+            .interval_schedules
+            .iter()
+            .find(|interval_schedule| {
+                interval_schedule.id == interval_schedule_id && interval_schedule.user_id == user_id
+            })
+            .cloned();
+
+        Ok(interval_schedule)
+    }
+
+    async fn add_interval_schedule(
+        &self,
+        user_id: i64,
+        interval_schedule: IntervalScheduleInfo,
+    ) -> RepoResult<i64> {
+        let mut state = self.state();
+
+        if state
+            .interval_schedules
+            .iter()
+            .any(|stored| stored.user_id == user_id && stored.name == interval_schedule.name)
+        {
+            return Err(RepositoryError::Conflict(format!(
+                "interval schedule {}",
+                interval_schedule.name
+            )));
+        }
+
+        state.last_interval_schedule_id += 1;
+        let id = state.last_interval_schedule_id;
+
+        state.interval_schedules.push(IntervalSchedule {
+            id,
+            user_id,
+            name: interval_schedule.name,
+            offsets: interval_schedule.offsets,
+            repeat_every_days: interval_schedule.repeat_every_days,
+        });
+
+        Ok(id)
+    }
+
+    async fn update_interval_schedule(
+        &self,
+        user_id: i64,
+        interval_schedule_id: i64,
+        interval_schedule: IntervalScheduleInfo,
+    ) -> RepoResult<()> {
+        let mut state = self.state();
+
+        if state.interval_schedules.iter().any(|stored| {
+            stored.id != interval_schedule_id
+                && stored.user_id == user_id
+                && stored.name == interval_schedule.name
+        }) {
+            return Err(RepositoryError::Conflict(format!(
+                "interval schedule {}",
+                interval_schedule.name
+            )));
+        }
+
+        let Some(stored) = state
+            .interval_schedules
+            .iter_mut()
+            .find(|stored| stored.id == interval_schedule_id && stored.user_id == user_id)
+        else {
+            return Err(RepositoryError::NotFound(format!(
+                "interval schedule {interval_schedule_id}"
+            )));
+        };
+
+        stored.name = interval_schedule.name;
+        stored.offsets = interval_schedule.offsets;
+        stored.repeat_every_days = interval_schedule.repeat_every_days;
+
+        Ok(())
+    }
+
+    async fn add_user(
+        &self,
+        username: String,
+        password_hash: String,
+        created_at: String,
+    ) -> RepoResult<i64> {
+        let mut state = self.state();
+
+        // The column is COLLATE NOCASE, which only folds ASCII.
+        if state
+            .users
+            .iter()
+            .any(|user| user.username.eq_ignore_ascii_case(&username))
+        {
+            return Err(RepositoryError::Conflict(format!("user {username}")));
+        }
+
+        state.last_user_id += 1;
+        let id = state.last_user_id;
+
+        state.users.push(User {
+            id,
+            username,
+            password_hash,
+            created_at,
+            time_zone: None,
+        });
+
+        Ok(id)
+    }
+
+    async fn get_user_by_username(&self, username: String) -> RepoResult<Option<User>> {
+        let state = self.state();
+
+        let user = state
+            .users
+            .iter()
+            .find(|user| user.username.eq_ignore_ascii_case(&username))
+            .cloned();
+
+        Ok(user)
+    }
+
+    async fn get_user(&self, user_id: i64) -> RepoResult<Option<User>> {
+        let state = self.state();
+
+        Ok(state.users.iter().find(|user| user.id == user_id).cloned())
+    }
+
+    async fn get_users(&self) -> RepoResult<Vec<User>> {
+        Ok(self.state().users.clone())
+    }
+
+    async fn update_user_password(&self, user_id: i64, password_hash: String) -> RepoResult<()> {
+        let mut state = self.state();
+
+        let Some(user) = state.users.iter_mut().find(|user| user.id == user_id) else {
+            return Err(RepositoryError::NotFound(format!("user {user_id}")));
+        };
+
+        user.password_hash = password_hash;
+
+        Ok(())
+    }
+
+    async fn update_user_time_zone(
+        &self,
+        user_id: i64,
+        time_zone: Option<String>,
+    ) -> RepoResult<()> {
+        let mut state = self.state();
+
+        let Some(user) = state.users.iter_mut().find(|user| user.id == user_id) else {
+            return Err(RepositoryError::NotFound(format!("user {user_id}")));
+        };
+
+        user.time_zone = time_zone;
+
+        Ok(())
+    }
+
+    async fn add_api_key(
+        &self,
+        user_id: i64,
+        name: String,
+        prefix: String,
+        key_hash: String,
+        scope: ApiKeyScope,
+        created_at: String,
+    ) -> RepoResult<i64> {
+        let mut state = self.state();
+
+        if state
+            .api_keys
+            .iter()
+            .any(|api_key| api_key.prefix == prefix)
+        {
+            return Err(RepositoryError::Conflict(format!("API key {prefix}")));
+        }
+        if !state.users.iter().any(|user| user.id == user_id) {
+            return Err(RepositoryError::NotFound(format!("user {user_id}")));
+        }
+
+        state.last_api_key_id += 1;
+        let id = state.last_api_key_id;
+
+        state.api_keys.push(ApiKey {
+            id,
+            user_id,
+            name,
+            prefix,
+            key_hash,
+            scope,
+            created_at,
+            last_used_at: None,
+            revoked_at: None,
+        });
+
+        Ok(id)
+    }
+
+    async fn get_api_keys(&self, user_id: i64) -> RepoResult<Vec<ApiKey>> {
+        let state = self.state();
+
+        let api_keys = state
+            .api_keys
+            .iter()
+            .filter(|api_key| api_key.user_id == user_id)
+            .cloned()
+            .collect();
+
+        Ok(api_keys)
+    }
+
+    async fn get_api_key_by_prefix(&self, prefix: String) -> RepoResult<Option<ApiKey>> {
+        let state = self.state();
+
+        let api_key = state
+            .api_keys
+            .iter()
+            .find(|api_key| api_key.prefix == prefix)
+            .cloned();
+
+        Ok(api_key)
+    }
+
+    async fn update_api_key_last_used(
+        &self,
+        api_key_id: i64,
+        last_used_at: String,
+    ) -> RepoResult<()> {
+        let mut state = self.state();
+
+        let Some(api_key) = state
+            .api_keys
+            .iter_mut()
+            .find(|api_key| api_key.id == api_key_id)
+        else {
+            return Err(RepositoryError::NotFound(format!("API key {api_key_id}")));
+        };
+
+        api_key.last_used_at = Some(last_used_at);
+
+        Ok(())
+    }
+
+    async fn revoke_api_key(
+        &self,
+        user_id: i64,
+        api_key_id: i64,
+        revoked_at: String,
+    ) -> RepoResult<()> {
+        let mut state = self.state();
+
+        let Some(api_key) = state
+            .api_keys
+            .iter_mut()
+            .find(|api_key| api_key.id == api_key_id && api_key.user_id == user_id)
+        else {
+            return Err(RepositoryError::NotFound(format!("API key {api_key_id}")));
+        };
+
+        api_key.revoked_at.get_or_insert(revoked_at);
+
+        Ok(())
+    }
+
+    async fn delete_interval_schedule(
+        &self,
+        user_id: i64,
+        interval_schedule_id: i64,
+    ) -> RepoResult<()> {
+        let mut state = self.state();
+
+        if !state.interval_schedules.iter().any(|interval_schedule| {
+            interval_schedule.id == interval_schedule_id && interval_schedule.user_id == user_id
+        }) {
+            return Err(RepositoryError::NotFound(format!(
+                "interval schedule {interval_schedule_id}"
+            )));
+        }
+
+        state
+            .interval_schedules
+            .retain(|interval_schedule| interval_schedule.id != interval_schedule_id);
+
+        for subject in state.subjects.iter_mut() {
+            if subject.interval_schedule_id == Some(interval_schedule_id) {
+                subject.interval_schedule_id = None;
+            }
+        }
+
+        for study_topic in state.study_topics.iter_mut() {
+            if study_topic.interval_schedule_id == Some(interval_schedule_id) {
+                study_topic.interval_schedule_id = None;
+            }
+        }
+
+        Ok(())
+    }
+}
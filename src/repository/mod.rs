@@ -0,0 +1,418 @@
+use async_trait::async_trait;
+
+use crate::{
+    domain::{
+        ApiKey, ApiKeyScope, Flashcard, FlashcardInfo, FlashcardProgress, FlashcardUpdate,
+        IntervalSchedule, IntervalScheduleInfo, ReviewLog, ReviewLogInfo, SchedulerKind,
+        SearchResult, StudySession, StudySessionInfo, StudySessionQuery, StudyTopic,
+        StudyTopicInfo, StudyTopicQuery, StudyTopicSchedule, StudyTopicUpdate, Subject,
+        SubjectImport, SubjectInvitation, SubjectMember, SubjectRole, User,
+    },
+    err::RepoResult,
+    pagination::Cursor,
+};
+
+mod libsql_repository;
+#[cfg(test)]
+mod memory_repository;
+
+pub use libsql_repository::{DatabaseConfig, DatabaseMode, LibSqlRepository};
+#[cfg(test)]
+pub use memory_repository::InMemoryRepository;
+
+/// Persistence operations used by the study service.
+///
+/// `LibSqlRepository` is the production implementation, `InMemoryRepository`
+/// keeps everything in process memory so the service logic can be tested
+/// without a database.
+///
+/// Apart from the user accounts themselves, every method only sees the rows
+/// `user_id` has access to: another user's rows behave as if they did not
+/// exist. Subjects, with their topics, are visible to their owner and to
+/// the members they are shared with, sessions, reviews and the progress
+/// fields of a topic only to the member they belong to. Changing a subject
+/// itself is limited to its owner; which member may change which topic is
+/// up to the caller.
+///
+/// Updates and deletes of a row that does not exist fail with
+/// [`RepositoryError::NotFound`](crate::err::RepositoryError::NotFound).
+#[async_trait]
+pub trait StudyRepository: Send + Sync {
+    async fn get_study_session(
+        &self,
+        user_id: i64,
+        study_session_id: i64,
+    ) -> RepoResult<Option<StudySession>>;
+
+    async fn get_study_topic(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+    ) -> RepoResult<Option<StudyTopic>>;
+
+    /// Pending sessions of the topic, oldest due date first.
+    async fn get_pending_study_sessions_for_study_topic(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+    ) -> RepoResult<Vec<StudySession>>;
+
+    /// Creates a pending session scheduled for `due_date`, of the card
+    /// `flashcard_id` of the topic or of the whole topic; `missed_reviews`
+    /// counts the earlier review points that passed without a session and
+    /// were collapsed into this one. In the same transaction the topic's
+    /// `last_session_date` is set to `created_on` and its `total_sessions`
+    /// increased, and so are the card's.
+    ///
+    /// Returns `false` without touching anything when the topic or card
+    /// already has a session for that date, which keeps concurrent
+    /// generation idempotent.
+    async fn create_study_session(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+        flashcard_id: Option<i64>,
+        due_date: String,
+        missed_reviews: i64,
+        created_on: String,
+    ) -> RepoResult<bool>;
+
+    /// Marks a pending session as completed at `review_log.completed_at`,
+    /// stores the new `schedule` of the topic, or of the card for a card
+    /// session, if it was graded, increases the `completed_sessions` of both
+    /// and logs the review, all in one transaction. The review is logged for
+    /// the session's own topic, card and user, the ids in `review_log` are
+    /// ignored.
+    ///
+    /// Returns `false` without touching anything when the session does not
+    /// exist or was already completed, so repeated completions count once.
+    async fn complete_study_session(
+        &self,
+        user_id: i64,
+        study_session_id: i64,
+        schedule: Option<StudyTopicSchedule>,
+        review_log: ReviewLogInfo,
+    ) -> RepoResult<bool>;
+
+    async fn get_subjects(&self, user_id: i64) -> RepoResult<Vec<Subject>>;
+
+    async fn get_subject(&self, user_id: i64, subject_name: String) -> RepoResult<Option<Subject>>;
+
+    /// Fails with a conflict when the user already sees a subject with that
+    /// name, shared subjects included.
+    async fn add_subject(&self, user_id: i64, subject_name: String) -> RepoResult<()>;
+
+    /// Adds the subjects with their topics, cards, the user's progress on
+    /// the cards and their review history in one transaction. The subjects
+    /// schedule their cards one by one. Fails with a conflict, adding
+    /// nothing, when the user already sees a subject of one of the names.
+    async fn import_subjects(&self, user_id: i64, subjects: Vec<SubjectImport>) -> RepoResult<()>;
+
+    async fn update_subject_scheduler(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        scheduler: SchedulerKind,
+        desired_retention: f64,
+        fsrs_parameters: Option<String>,
+        card_scheduling: bool,
+    ) -> RepoResult<()>;
+
+    async fn update_subject_interval_schedule(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        interval_schedule_id: Option<i64>,
+    ) -> RepoResult<()>;
+
+    /// Renames the subject, moving its topics and members to the new name in
+    /// the same transaction. Fails with a conflict when the owner or a
+    /// member already sees a subject named `new_subject_name`.
+    async fn rename_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        new_subject_name: String,
+    ) -> RepoResult<()>;
+
+    async fn delete_subject(&self, user_id: i64, subject_name: String) -> RepoResult<()>;
+
+    /// Members of the subject and pending invitations to it, oldest first.
+    /// The owner is not part of the list.
+    async fn get_subject_members(
+        &self,
+        user_id: i64,
+        subject_name: String,
+    ) -> RepoResult<Vec<SubjectMember>>;
+
+    /// Invites `member_id` to a subject of `user_id` and returns the id of
+    /// the invitation. Fails with a conflict when they were already invited.
+    async fn add_subject_member(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        member_id: i64,
+        role: SubjectRole,
+        invited_at: String,
+    ) -> RepoResult<i64>;
+
+    /// Removes a member or invitation from the subject owned by `owner_id`,
+    /// deleting the member's sessions, reviews and progress on its topics.
+    async fn delete_subject_member(
+        &self,
+        owner_id: i64,
+        subject_name: String,
+        member_id: i64,
+    ) -> RepoResult<()>;
+
+    /// Invitations the user has not accepted yet, oldest first.
+    async fn get_subject_invitations(&self, user_id: i64) -> RepoResult<Vec<SubjectInvitation>>;
+
+    /// Accepts a pending invitation of the user. Fails with a conflict when
+    /// they already see a subject with the same name.
+    async fn accept_subject_invitation(
+        &self,
+        user_id: i64,
+        invitation_id: i64,
+        accepted_at: String,
+    ) -> RepoResult<()>;
+
+    /// Declines a pending invitation of the user.
+    async fn delete_subject_invitation(&self, user_id: i64, invitation_id: i64) -> RepoResult<()>;
+
+    async fn get_study_topics_for_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+    ) -> RepoResult<Vec<StudyTopic>>;
+
+    /// Pending sessions of every topic in the subject.
+    async fn get_study_sessions_for_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+    ) -> RepoResult<Vec<StudySessionInfo>>;
+
+    /// Up to `limit` pending sessions of the subject matching `query`, like
+    /// [`StudyRepository::query_study_topics`].
+    async fn query_study_sessions_for_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        query: StudySessionQuery,
+        after: Option<Cursor>,
+        limit: u32,
+    ) -> RepoResult<Vec<StudySessionInfo>>;
+
+    /// Up to `limit` pending card sessions across all subjects the user
+    /// sees, matching `query` like
+    /// [`StudyRepository::query_study_sessions_for_subject`].
+    async fn query_flashcard_sessions(
+        &self,
+        user_id: i64,
+        query: StudySessionQuery,
+        after: Option<Cursor>,
+        limit: u32,
+    ) -> RepoResult<Vec<StudySessionInfo>>;
+
+    async fn get_study_topics(&self, user_id: i64) -> RepoResult<Vec<StudyTopic>>;
+
+    /// Up to `limit` topics matching `query` in its sort order, starting
+    /// after the `after` row. The caller resolves `query.cursor` and
+    /// `query.limit` into `after` and `limit`.
+    async fn query_study_topics(
+        &self,
+        user_id: i64,
+        query: StudyTopicQuery,
+        after: Option<Cursor>,
+        limit: u32,
+    ) -> RepoResult<Vec<StudyTopic>>;
+
+    /// Up to `limit` topics containing every term, or a word starting with
+    /// it, in their name or description, best match first.
+    async fn search_study_topics(
+        &self,
+        user_id: i64,
+        terms: Vec<String>,
+        limit: u32,
+    ) -> RepoResult<Vec<SearchResult>>;
+
+    /// Adds a topic to a subject the user can see and returns its id, the
+    /// topic belongs to the subject's owner.
+    async fn add_study_topic(
+        &self,
+        user_id: i64,
+        study_topic: StudyTopicInfo,
+        creation_date: String,
+    ) -> RepoResult<i64>;
+
+    /// Changes the fields present in `update`, leaving the rest as they are.
+    /// A topic only moves between subjects of the same owner.
+    async fn update_study_topic(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+        update: StudyTopicUpdate,
+    ) -> RepoResult<()>;
+
+    async fn update_study_topic_interval_schedule(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+        interval_schedule_id: Option<i64>,
+    ) -> RepoResult<()>;
+
+    async fn delete_study_topic(&self, user_id: i64, study_topic_id: i64) -> RepoResult<()>;
+
+    /// Cards of the given topics the user can see, by topic and then in
+    /// the order they were added.
+    async fn get_flashcards(
+        &self,
+        user_id: i64,
+        study_topic_ids: Vec<i64>,
+    ) -> RepoResult<Vec<Flashcard>>;
+
+    async fn get_flashcard(&self, user_id: i64, flashcard_id: i64)
+        -> RepoResult<Option<Flashcard>>;
+
+    /// Review state of the user for every card of the given topics, in the
+    /// order of [`StudyRepository::get_flashcards`].
+    async fn get_flashcard_progress(
+        &self,
+        user_id: i64,
+        study_topic_ids: Vec<i64>,
+    ) -> RepoResult<Vec<FlashcardProgress>>;
+
+    /// Adds a card to a topic the user can see and returns its id.
+    async fn add_flashcard(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+        flashcard: FlashcardInfo,
+        created_at: String,
+    ) -> RepoResult<i64>;
+
+    /// Changes the fields present in `update` and sets `updated_at`.
+    async fn update_flashcard(
+        &self,
+        user_id: i64,
+        flashcard_id: i64,
+        update: FlashcardUpdate,
+        updated_at: String,
+    ) -> RepoResult<()>;
+
+    async fn delete_flashcard(&self, user_id: i64, flashcard_id: i64) -> RepoResult<()>;
+
+    /// Reviews of the topic, oldest first.
+    async fn get_review_logs_for_study_topic(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+    ) -> RepoResult<Vec<ReviewLog>>;
+
+    /// The user's reviews of every topic in the subject, ordered by topic,
+    /// then by card with the reviews of the whole topic first, and then
+    /// chronologically.
+    async fn get_review_logs_for_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+    ) -> RepoResult<Vec<ReviewLog>>;
+
+    async fn get_interval_schedules(&self, user_id: i64) -> RepoResult<Vec<IntervalSchedule>>;
+
+    async fn get_interval_schedule(
+        &self,
+        user_id: i64,
+        interval_schedule_id: i64,
+    ) -> RepoResult<Option<IntervalSchedule>>;
+
+    /// Stores a new interval schedule and returns its id.
+    async fn add_interval_schedule(
+        &self,
+        user_id: i64,
+        interval_schedule: IntervalScheduleInfo,
+    ) -> RepoResult<i64>;
+
+    async fn update_interval_schedule(
+        &self,
+        user_id: i64,
+        interval_schedule_id: i64,
+        interval_schedule: IntervalScheduleInfo,
+    ) -> RepoResult<()>;
+
+    /// Stores a new user and returns its id. Usernames are unique ignoring
+    /// case.
+    async fn add_user(
+        &self,
+        username: String,
+        password_hash: String,
+        created_at: String,
+    ) -> RepoResult<i64>;
+
+    /// Looks a user up by username, ignoring case.
+    async fn get_user_by_username(&self, username: String) -> RepoResult<Option<User>>;
+
+    async fn get_user(&self, user_id: i64) -> RepoResult<Option<User>>;
+
+    async fn get_users(&self) -> RepoResult<Vec<User>>;
+
+    async fn update_user_password(&self, user_id: i64, password_hash: String) -> RepoResult<()>;
+
+    /// Stores the IANA time zone the user's days follow, `None` for the
+    /// server's default.
+    async fn update_user_time_zone(
+        &self,
+        user_id: i64,
+        time_zone: Option<String>,
+    ) -> RepoResult<()>;
+
+    /// Stores a new API key and returns its id. Prefixes are unique.
+    async fn add_api_key(
+        &self,
+        user_id: i64,
+        name: String,
+        prefix: String,
+        key_hash: String,
+        scope: ApiKeyScope,
+        created_at: String,
+    ) -> RepoResult<i64>;
+
+    /// Keys of the user, revoked ones included, oldest first.
+    async fn get_api_keys(&self, user_id: i64) -> RepoResult<Vec<ApiKey>>;
+
+    /// Looks a key up by the prefix of a presented key, whoever owns it.
+    async fn get_api_key_by_prefix(&self, prefix: String) -> RepoResult<Option<ApiKey>>;
+
+    async fn update_api_key_last_used(
+        &self,
+        api_key_id: i64,
+        last_used_at: String,
+    ) -> RepoResult<()>;
+
+    /// Marks the key as revoked at `revoked_at`. Revoking it again keeps
+    /// the first revocation time.
+    async fn revoke_api_key(
+        &self,
+        user_id: i64,
+        api_key_id: i64,
+        revoked_at: String,
+    ) -> RepoResult<()>;
+
+    /// Deletes the schedule, subjects and topics using it go back to the
+    /// default offsets.
+    async fn delete_interval_schedule(
+        &self,
+        user_id: i64,
+        interval_schedule_id: i64,
+    ) -> RepoResult<()>;
+}
+
+/// Text made safe to show as HTML, for the highlighted search results.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
@@ -0,0 +1,167 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+use crate::{clock::Clock, study_service::StudyService};
+
+/// Background job that generates each day's study sessions at a fixed local
+/// time, plus once on startup so a restart never leaves a day without them.
+/// It runs at that time in the default time zone and in every time zone a
+/// user set, each run creating the sessions of the users whose day began.
+pub struct SessionScheduler {
+    study_service: StudyService,
+    clock: Arc<dyn Clock>,
+    time_zone: Tz,
+    generation_time: NaiveTime,
+}
+
+impl SessionScheduler {
+    pub fn new(
+        study_service: StudyService,
+        clock: Arc<dyn Clock>,
+        time_zone: Tz,
+        generation_time: NaiveTime,
+    ) -> Self {
+        Self {
+            study_service,
+            clock,
+            time_zone,
+            generation_time,
+        }
+    }
+
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                match self.study_service.generate_study_sessions().await {
+                    Ok(created_sessions) => {
+                        info!("Scheduler created {created_sessions} study sessions")
+                    }
+                    Err(err) => error!("Scheduler failed to create study sessions: {err}"),
+                }
+
+                let time_zones = self.study_service.time_zones().await.unwrap_or_else(|err| {
+                    error!("Scheduler failed to read the users' time zones: {err}");
+                    Vec::new()
+                });
+
+                let now = self.clock.now();
+                let next_run =
+                    next_run_in_any(now, self.generation_time, self.time_zone, &time_zones);
+                info!("Next study session generation at {next_run}");
+
+                let wait = (next_run - now).to_std().unwrap_or_default();
+                tokio::time::sleep(wait).await;
+            }
+        })
+    }
+}
+
+/// First instant after `now` at which the local clock in `time_zone` or in
+/// one of `other_time_zones` reads `generation_time`.
+fn next_run_in_any(
+    now: DateTime<Utc>,
+    generation_time: NaiveTime,
+    time_zone: Tz,
+    other_time_zones: &[Tz],
+) -> DateTime<Utc> {
+    other_time_zones
+        .iter()
+        .map(|other_time_zone| next_run_after(now, generation_time, *other_time_zone))
+        .fold(
+            next_run_after(now, generation_time, time_zone),
+            DateTime::min,
+        )
+}
+
+/// First instant after `now` at which the local clock in `time_zone` reads
+/// `generation_time`.
+fn next_run_after(now: DateTime<Utc>, generation_time: NaiveTime, time_zone: Tz) -> DateTime<Utc> {
+    let mut date = now.with_timezone(&time_zone).date_naive();
+
+    loop {
+        let local_run = date.and_time(generation_time);
+
+        // Times skipped by a DST jump resolve to the next hour.
+        let run = time_zone
+            .from_local_datetime(&local_run)
+            .earliest()
+            .or_else(|| {
+                time_zone
+                    .from_local_datetime(&(local_run + Duration::hours(1)))
+                    .earliest()
+            });
+
+        if let Some(run) = run {
+            let run = run.with_timezone(&Utc);
+            if run > now {
+                return run;
+            }
+        }
+
+        date += Duration::days(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{NaiveTime, TimeZone, Utc};
+    use chrono_tz::{America::Bogota, Europe::Madrid};
+
+    use crate::scheduler::{next_run_after, next_run_in_any};
+
+    #[test]
+    fn next_run_is_later_today() {
+        // 08:00 in Bogotá.
+        let now = Utc.with_ymd_and_hms(2025, 3, 10, 13, 0, 0).unwrap();
+        let generation_time = NaiveTime::from_hms_opt(23, 0, 0).unwrap();
+
+        assert_eq!(
+            next_run_after(now, generation_time, Bogota),
+            Utc.with_ymd_and_hms(2025, 3, 11, 4, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn next_run_is_tomorrow_after_generation_time() {
+        // 23:30 UTC on 2025-03-10 is already past midnight in Madrid.
+        let now = Utc.with_ymd_and_hms(2025, 3, 10, 23, 30, 0).unwrap();
+        let generation_time = NaiveTime::from_hms_opt(0, 0, 0).unwrap();
+
+        assert_eq!(
+            next_run_after(now, generation_time, Madrid),
+            Utc.with_ymd_and_hms(2025, 3, 11, 23, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn next_run_is_the_first_day_to_begin() {
+        // 20:00 in Bogotá, 02:00 the next day in Madrid.
+        let now = Utc.with_ymd_and_hms(2025, 3, 11, 1, 0, 0).unwrap();
+        let generation_time = NaiveTime::from_hms_opt(0, 0, 0).unwrap();
+
+        assert_eq!(
+            next_run_in_any(now, generation_time, Madrid, &[Bogota]),
+            Utc.with_ymd_and_hms(2025, 3, 11, 5, 0, 0).unwrap()
+        );
+        assert_eq!(
+            next_run_in_any(now, generation_time, Madrid, &[]),
+            Utc.with_ymd_and_hms(2025, 3, 11, 23, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn next_run_skips_dst_gap() {
+        // Madrid jumps from 02:00 to 03:00 on 2025-03-30.
+        let now = Utc.with_ymd_and_hms(2025, 3, 29, 12, 0, 0).unwrap();
+        let generation_time = NaiveTime::from_hms_opt(2, 30, 0).unwrap();
+
+        assert_eq!(
+            next_run_after(now, generation_time, Madrid),
+            Utc.with_ymd_and_hms(2025, 3, 30, 1, 30, 0).unwrap()
+        );
+    }
+}
@@ -0,0 +1,332 @@
+use chrono::{Duration, NaiveDate};
+
+use crate::{
+    clock::{format_date, DATE_FORMAT},
+    domain::{IntervalSchedule, ReviewGrade, StudyTopic, StudyTopicSchedule},
+    err::StudyServiceResult,
+    scheduling::{current_schedule, parse_optional_date, DueReview, Scheduler},
+};
+
+const DEFAULT_OFFSETS: [u32; 8] = [0, 1, 3, 7, 21, 30, 45, 60];
+const DEFAULT_REPEAT_EVERY_DAYS: u32 = 60;
+
+/// Reviews on fixed offsets from the topic's creation date, regardless of
+/// grades, optionally repeating every `repeat_every_days` after the last
+/// offset. Defaults to 0, 1, 3, 7, 21, 30, 45 and 60 days, then every 60
+/// days.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixedOffsets {
+    offsets: Vec<u32>,
+    repeat_every_days: Option<u32>,
+}
+
+impl Default for FixedOffsets {
+    fn default() -> Self {
+        FixedOffsets {
+            offsets: DEFAULT_OFFSETS.to_vec(),
+            repeat_every_days: Some(DEFAULT_REPEAT_EVERY_DAYS),
+        }
+    }
+}
+
+impl From<&IntervalSchedule> for FixedOffsets {
+    fn from(interval_schedule: &IntervalSchedule) -> Self {
+        FixedOffsets {
+            offsets: interval_schedule.offsets.clone(),
+            repeat_every_days: interval_schedule.repeat_every_days,
+        }
+    }
+}
+
+impl Scheduler for FixedOffsets {
+    /// The latest review point that passed without a session is due, any
+    /// earlier uncovered points are collapsed into `missed_reviews`.
+    fn due_review(
+        &self,
+        study_topic: &StudyTopic,
+        today: NaiveDate,
+    ) -> StudyServiceResult<Option<DueReview>> {
+        let creation_date = NaiveDate::parse_from_str(&study_topic.creation_date, DATE_FORMAT)?;
+
+        let days = today.signed_duration_since(creation_date).num_days();
+        if days < 0 {
+            return Ok(None);
+        }
+
+        let Some(review_point) = self.latest_review_point(days as u32) else {
+            return Ok(None);
+        };
+        let due_date = creation_date + Duration::days(review_point as i64);
+
+        let covered_points = match parse_optional_date(study_topic.last_session_date.as_deref())? {
+            Some(last_session_date) => {
+                if last_session_date >= due_date {
+                    return Ok(None);
+                }
+
+                let last_session_days = last_session_date
+                    .signed_duration_since(creation_date)
+                    .num_days();
+                if last_session_days < 0 {
+                    0
+                } else {
+                    self.review_points_up_to(last_session_days as u32)
+                }
+            }
+            None => 0,
+        };
+
+        let uncovered_points = self.review_points_up_to(review_point) - covered_points;
+
+        Ok(Some(DueReview {
+            due_date,
+            missed_reviews: uncovered_points.saturating_sub(1) as i64,
+        }))
+    }
+
+    fn next_review_date(
+        &self,
+        study_topic: &StudyTopic,
+        today: NaiveDate,
+    ) -> StudyServiceResult<Option<NaiveDate>> {
+        if let Some(due_review) = self.due_review(study_topic, today)? {
+            return Ok(Some(due_review.due_date));
+        }
+
+        let creation_date = NaiveDate::parse_from_str(&study_topic.creation_date, DATE_FORMAT)?;
+
+        // Review points up to today are either covered by a session or, with
+        // no session yet, returned above as due.
+        let days = today.signed_duration_since(creation_date).num_days() + 1;
+
+        Ok(self
+            .earliest_review_point_from(days.max(0) as u32)
+            .map(|review_point| creation_date + Duration::days(review_point as i64)))
+    }
+
+    fn review(
+        &self,
+        study_topic: &StudyTopic,
+        _grade: ReviewGrade,
+        today: NaiveDate,
+    ) -> StudyServiceResult<StudyTopicSchedule> {
+        Ok(StudyTopicSchedule {
+            last_review_date: Some(format_date(today)),
+            ..current_schedule(study_topic)
+        })
+    }
+}
+
+impl FixedOffsets {
+    /// Checks that the offsets are strictly increasing and the repetition
+    /// period is positive, the invariants the review point maths rely on.
+    pub fn validate(offsets: &[u32], repeat_every_days: Option<u32>) -> Result<(), String> {
+        if offsets.is_empty() {
+            return Err("at least one offset is required".to_string());
+        }
+
+        if offsets.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err("offsets must be strictly increasing".to_string());
+        }
+
+        if repeat_every_days == Some(0) {
+            return Err("repeat_every_days must be positive".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Most recent review point (in days since creation) at or before `days`.
+    fn latest_review_point(&self, days: u32) -> Option<u32> {
+        if let (Some(last_offset), Some(repeat_every_days)) =
+            (self.last_offset(), self.repeat_every_days)
+        {
+            if days >= last_offset {
+                return Some(days - (days - last_offset) % repeat_every_days);
+            }
+        }
+
+        self.offsets
+            .iter()
+            .copied()
+            .filter(|point| *point <= days)
+            .max()
+    }
+
+    /// First review point (in days since creation) at or after `days`.
+    fn earliest_review_point_from(&self, days: u32) -> Option<u32> {
+        if let Some(offset) = self.offsets.iter().copied().find(|offset| *offset >= days) {
+            return Some(offset);
+        }
+
+        let (last_offset, repeat_every_days) = (self.last_offset()?, self.repeat_every_days?);
+        let repeats = (days - last_offset).div_ceil(repeat_every_days);
+
+        Some(last_offset + repeats * repeat_every_days)
+    }
+
+    /// Number of review points at or before `days`.
+    fn review_points_up_to(&self, days: u32) -> u32 {
+        let fixed_points = self.offsets.iter().filter(|point| **point <= days).count() as u32;
+
+        let repeated_points = match (self.last_offset(), self.repeat_every_days) {
+            (Some(last_offset), Some(repeat_every_days)) if days >= last_offset => {
+                (days - last_offset) / repeat_every_days
+            }
+            _ => 0,
+        };
+
+        fixed_points + repeated_points
+    }
+
+    fn last_offset(&self) -> Option<u32> {
+        self.offsets.last().copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, NaiveDate};
+
+    use crate::{
+        domain::{IntervalSchedule, StudyTopic},
+        scheduling::{fixed::FixedOffsets, DueReview, Scheduler},
+    };
+
+    fn start_date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()
+    }
+
+    /// Topic created on [`start_date`] whose last session was on
+    /// `last_session_date`.
+    fn study_topic(last_session_date: Option<&str>) -> StudyTopic {
+        StudyTopic {
+            id: 1,
+            user_id: 1,
+            name: "limits".to_string(),
+            description: None,
+            creation_date: "2025-01-01".to_string(),
+            subject_name: "math".to_string(),
+            last_session_date: last_session_date.map(str::to_string),
+            total_sessions: 0,
+            completed_sessions: 0,
+            ease_factor: 2.5,
+            interval_days: 0,
+            repetitions: 0,
+            next_due_date: None,
+            stability: None,
+            difficulty: None,
+            last_review_date: None,
+            interval_schedule_id: None,
+        }
+    }
+
+    #[test]
+    fn has_to_study_today() {
+        let fixed_offsets = FixedOffsets::default();
+
+        // A review is due on the day of a review point, else the last point
+        // is still due.
+        for (days, review_point) in [
+            (0, 0),
+            (1, 1),
+            (3, 3),
+            (7, 7),
+            (60, 60),
+            (120, 120),
+            (2, 1),
+            (5, 3),
+            (22, 21),
+            (19, 7),
+        ] {
+            let due_review = fixed_offsets
+                .due_review(&study_topic(None), start_date() + Duration::days(days))
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                due_review.due_date,
+                start_date() + Duration::days(review_point)
+            );
+        }
+
+        // A session on a point covers it until the next one.
+        let study_topic = study_topic(Some("2025-01-04"));
+        assert_eq!(
+            fixed_offsets
+                .due_review(&study_topic, start_date() + Duration::days(6))
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            fixed_offsets
+                .due_review(&study_topic, start_date() + Duration::days(7))
+                .unwrap(),
+            Some(DueReview {
+                due_date: start_date() + Duration::days(7),
+                missed_reviews: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn finds_latest_review_point() {
+        let fixed_offsets = FixedOffsets::default();
+
+        assert_eq!(fixed_offsets.latest_review_point(0), Some(0));
+        assert_eq!(fixed_offsets.latest_review_point(2), Some(1));
+        assert_eq!(fixed_offsets.latest_review_point(10), Some(7));
+        assert_eq!(fixed_offsets.latest_review_point(59), Some(45));
+        assert_eq!(fixed_offsets.latest_review_point(60), Some(60));
+        assert_eq!(fixed_offsets.latest_review_point(150), Some(120));
+
+        assert_eq!(fixed_offsets.review_points_up_to(0), 1);
+        assert_eq!(fixed_offsets.review_points_up_to(7), 4);
+        assert_eq!(fixed_offsets.review_points_up_to(60), 8);
+        assert_eq!(fixed_offsets.review_points_up_to(130), 9);
+
+        assert_eq!(fixed_offsets.earliest_review_point_from(0), Some(0));
+        assert_eq!(fixed_offsets.earliest_review_point_from(2), Some(3));
+        assert_eq!(fixed_offsets.earliest_review_point_from(61), Some(120));
+        assert_eq!(fixed_offsets.earliest_review_point_from(120), Some(120));
+    }
+
+    #[test]
+    fn follows_custom_interval_schedule() {
+        let fixed_offsets = FixedOffsets::from(&IntervalSchedule {
+            id: 1,
+            user_id: 1,
+            name: "long term".to_string(),
+            offsets: vec![1, 7, 30, 90, 180],
+            repeat_every_days: None,
+        });
+
+        assert_eq!(fixed_offsets.latest_review_point(0), None);
+        assert_eq!(fixed_offsets.latest_review_point(90), Some(90));
+        assert_eq!(fixed_offsets.latest_review_point(360), Some(180));
+        assert_eq!(fixed_offsets.latest_review_point(400), Some(180));
+        assert_eq!(fixed_offsets.review_points_up_to(400), 5);
+
+        // Nothing is planned after the last offset.
+        let study_topic = study_topic(Some("2025-06-30"));
+        assert_eq!(
+            fixed_offsets
+                .due_review(&study_topic, start_date() + Duration::days(400))
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            fixed_offsets
+                .next_review_date(&study_topic, start_date() + Duration::days(400))
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_offsets() {
+        assert!(FixedOffsets::validate(&[0, 1, 2, 4, 7], None).is_ok());
+        assert!(FixedOffsets::validate(&[], None).is_err());
+        assert!(FixedOffsets::validate(&[0, 3, 3], None).is_err());
+        assert!(FixedOffsets::validate(&[0, 1], Some(0)).is_err());
+    }
+}
@@ -0,0 +1,432 @@
+use chrono::{Duration, NaiveDate};
+
+use crate::{
+    clock::format_date,
+    domain::{ReviewGrade, StudyTopic, StudyTopicSchedule},
+    err::StudyServiceResult,
+    scheduling::{
+        adaptive_due_review, adaptive_next_review_date, current_schedule, parse_optional_date,
+        DueReview, FixedOffsets, Scheduler,
+    },
+};
+
+/// Default FSRS-4.5 weights, fitted by the FSRS authors on a large corpus of
+/// Anki reviews.
+pub const DEFAULT_WEIGHTS: [f64; 17] = [
+    0.4872, 1.4003, 3.7145, 13.8206, 5.1618, 1.2298, 0.8975, 0.031, 1.6474, 0.1367, 1.0461, 2.1072,
+    0.0793, 0.3246, 1.587, 0.2272, 2.8755,
+];
+
+/// Ranges the optimiser keeps each weight in, as in the reference
+/// implementation.
+const WEIGHT_BOUNDS: [(f64, f64); 17] = [
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (1.0, 10.0),
+    (0.001, 4.0),
+    (0.001, 4.0),
+    (0.001, 0.75),
+    (0.0, 4.5),
+    (0.0, 0.8),
+    (0.001, 3.5),
+    (0.001, 5.0),
+    (0.001, 0.25),
+    (0.001, 0.9),
+    (0.0, 4.0),
+    (0.0, 1.0),
+    (1.0, 6.0),
+];
+
+const DECAY: f64 = -0.5;
+const FACTOR: f64 = 19.0 / 81.0;
+const MAX_INTERVAL_DAYS: i64 = 36500;
+const OPTIMIZER_ITERATIONS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FsrsParameters {
+    pub weights: [f64; 17],
+    /// Probability of recall the next review is scheduled at.
+    pub desired_retention: f64,
+}
+
+impl Default for FsrsParameters {
+    fn default() -> Self {
+        Self {
+            weights: DEFAULT_WEIGHTS,
+            desired_retention: 0.9,
+        }
+    }
+}
+
+/// Memory state of a topic: `stability` is the interval in days at which
+/// recall probability drops to 90%, `difficulty` ranges from 1 to 10.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FsrsState {
+    pub stability: f64,
+    pub difficulty: f64,
+}
+
+/// One graded review of a topic, `elapsed_days` after the previous one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FsrsReview {
+    pub elapsed_days: f64,
+    pub grade: ReviewGrade,
+}
+
+impl FsrsParameters {
+    /// Memory state after a review; `state` is `None` for the first review.
+    pub fn review(
+        &self,
+        state: Option<FsrsState>,
+        elapsed_days: f64,
+        grade: ReviewGrade,
+    ) -> FsrsState {
+        let grade_value = grade_value(grade);
+
+        let Some(state) = state else {
+            return FsrsState {
+                stability: self.weights[grade_value as usize - 1].max(0.1),
+                difficulty: self.initial_difficulty(grade_value),
+            };
+        };
+
+        let retrievability = retrievability(elapsed_days, state.stability);
+
+        let stability = match grade {
+            ReviewGrade::Again => self.stability_after_forgetting(state, retrievability),
+            _ => self.stability_after_recall(state, retrievability, grade),
+        };
+
+        FsrsState {
+            stability: stability.max(0.1),
+            difficulty: self.next_difficulty(state.difficulty, grade_value),
+        }
+    }
+
+    /// Days until recall probability drops to the desired retention.
+    pub fn next_interval_days(&self, stability: f64) -> i64 {
+        let interval = stability / FACTOR * (self.desired_retention.powf(1.0 / DECAY) - 1.0);
+
+        (interval.round() as i64).clamp(1, MAX_INTERVAL_DAYS)
+    }
+
+    fn initial_difficulty(&self, grade_value: f64) -> f64 {
+        (self.weights[4] - (grade_value - 3.0) * self.weights[5]).clamp(1.0, 10.0)
+    }
+
+    fn next_difficulty(&self, difficulty: f64, grade_value: f64) -> f64 {
+        let next_difficulty = difficulty - self.weights[6] * (grade_value - 3.0);
+        let mean_reverted = self.weights[7] * self.initial_difficulty(3.0)
+            + (1.0 - self.weights[7]) * next_difficulty;
+
+        mean_reverted.clamp(1.0, 10.0)
+    }
+
+    fn stability_after_recall(
+        &self,
+        state: FsrsState,
+        retrievability: f64,
+        grade: ReviewGrade,
+    ) -> f64 {
+        let w = &self.weights;
+        let hard_penalty = if grade == ReviewGrade::Hard {
+            w[15]
+        } else {
+            1.0
+        };
+        let easy_bonus = if grade == ReviewGrade::Easy {
+            w[16]
+        } else {
+            1.0
+        };
+
+        state.stability
+            * (1.0
+                + w[8].exp()
+                    * (11.0 - state.difficulty)
+                    * state.stability.powf(-w[9])
+                    * ((w[10] * (1.0 - retrievability)).exp() - 1.0)
+                    * hard_penalty
+                    * easy_bonus)
+    }
+
+    fn stability_after_forgetting(&self, state: FsrsState, retrievability: f64) -> f64 {
+        let w = &self.weights;
+
+        let stability = w[11]
+            * state.difficulty.powf(-w[12])
+            * ((state.stability + 1.0).powf(w[13]) - 1.0)
+            * (w[14] * (1.0 - retrievability)).exp();
+
+        stability.min(state.stability)
+    }
+}
+
+/// Probability of recalling a topic `elapsed_days` after its last review.
+pub fn retrievability(elapsed_days: f64, stability: f64) -> f64 {
+    (1.0 + FACTOR * elapsed_days / stability).powf(DECAY)
+}
+
+fn grade_value(grade: ReviewGrade) -> f64 {
+    match grade {
+        ReviewGrade::Again => 1.0,
+        ReviewGrade::Hard => 2.0,
+        ReviewGrade::Good => 3.0,
+        ReviewGrade::Easy => 4.0,
+    }
+}
+
+/// Mean log loss of the recall probability the weights predict for every
+/// review after the first one of each history, `None` without such reviews.
+pub fn log_loss(histories: &[Vec<FsrsReview>], weights: [f64; 17]) -> Option<f64> {
+    let parameters = FsrsParameters {
+        weights,
+        ..Default::default()
+    };
+
+    let mut total_loss = 0.0;
+    let mut predictions = 0;
+
+    for history in histories {
+        let mut state: Option<FsrsState> = None;
+
+        for review in history {
+            if let Some(current) = state {
+                let predicted =
+                    retrievability(review.elapsed_days, current.stability).clamp(0.0001, 0.9999);
+                let recalled = review.grade != ReviewGrade::Again;
+
+                total_loss -= if recalled {
+                    predicted.ln()
+                } else {
+                    (1.0 - predicted).ln()
+                };
+                predictions += 1;
+            }
+
+            state = Some(parameters.review(state, review.elapsed_days, review.grade));
+        }
+    }
+
+    (predictions > 0).then(|| total_loss / predictions as f64)
+}
+
+/// Fits the weights to a set of review histories by minimising
+/// [`log_loss`] with resilient backpropagation over a numerical gradient,
+/// starting from `initial` and never returning weights that do worse.
+pub fn optimize(histories: &[Vec<FsrsReview>], initial: [f64; 17]) -> [f64; 17] {
+    let Some(initial_loss) = log_loss(histories, initial) else {
+        return initial;
+    };
+
+    let mut weights = initial;
+    let mut best_weights = initial;
+    let mut best_loss = initial_loss;
+
+    let mut steps: [f64; 17] =
+        std::array::from_fn(|i| (WEIGHT_BOUNDS[i].1 - WEIGHT_BOUNDS[i].0) * 0.01);
+    let mut previous_gradient = [0.0; 17];
+
+    for _ in 0..OPTIMIZER_ITERATIONS {
+        let gradient: [f64; 17] = std::array::from_fn(|i| {
+            let epsilon = steps[i].max(1e-6) * 0.1;
+
+            let mut above = weights;
+            above[i] = (above[i] + epsilon).min(WEIGHT_BOUNDS[i].1);
+            let mut below = weights;
+            below[i] = (below[i] - epsilon).max(WEIGHT_BOUNDS[i].0);
+
+            match (log_loss(histories, above), log_loss(histories, below)) {
+                (Some(loss_above), Some(loss_below)) if above[i] > below[i] => {
+                    (loss_above - loss_below) / (above[i] - below[i])
+                }
+                _ => 0.0,
+            }
+        });
+
+        for i in 0..17 {
+            if gradient[i] * previous_gradient[i] > 0.0 {
+                steps[i] *= 1.2;
+            } else if gradient[i] * previous_gradient[i] < 0.0 {
+                steps[i] *= 0.5;
+            }
+
+            weights[i] = (weights[i] - gradient[i].signum() * steps[i])
+                .clamp(WEIGHT_BOUNDS[i].0, WEIGHT_BOUNDS[i].1);
+        }
+        previous_gradient = gradient;
+
+        if let Some(loss) = log_loss(histories, weights) {
+            if loss < best_loss {
+                best_loss = loss;
+                best_weights = weights;
+            }
+        }
+    }
+
+    best_weights
+}
+
+/// Free Spaced Repetition Scheduler: tracks stability and difficulty per
+/// topic and schedules the next review when the predicted probability of
+/// recall falls to the desired retention.
+pub struct Fsrs {
+    parameters: FsrsParameters,
+    /// Followed until the topic's first graded review.
+    intervals: FixedOffsets,
+}
+
+impl Fsrs {
+    pub fn new(parameters: FsrsParameters, intervals: FixedOffsets) -> Self {
+        Self {
+            parameters,
+            intervals,
+        }
+    }
+}
+
+impl Scheduler for Fsrs {
+    fn due_review(
+        &self,
+        study_topic: &StudyTopic,
+        today: NaiveDate,
+    ) -> StudyServiceResult<Option<DueReview>> {
+        adaptive_due_review(&self.intervals, study_topic, today)
+    }
+
+    fn next_review_date(
+        &self,
+        study_topic: &StudyTopic,
+        today: NaiveDate,
+    ) -> StudyServiceResult<Option<NaiveDate>> {
+        adaptive_next_review_date(&self.intervals, study_topic, today)
+    }
+
+    fn review(
+        &self,
+        study_topic: &StudyTopic,
+        grade: ReviewGrade,
+        today: NaiveDate,
+    ) -> StudyServiceResult<StudyTopicSchedule> {
+        let state = match (study_topic.stability, study_topic.difficulty) {
+            (Some(stability), Some(difficulty)) => Some(FsrsState {
+                stability,
+                difficulty,
+            }),
+            _ => None,
+        };
+
+        let elapsed_days = match parse_optional_date(study_topic.last_review_date.as_deref())? {
+            Some(last_review_date) => today
+                .signed_duration_since(last_review_date)
+                .num_days()
+                .max(0) as f64,
+            None => 0.0,
+        };
+
+        let state = self.parameters.review(state, elapsed_days, grade);
+        let interval_days = self.parameters.next_interval_days(state.stability);
+
+        let repetitions = match grade {
+            ReviewGrade::Again => 0,
+            _ => study_topic.repetitions + 1,
+        };
+
+        Ok(StudyTopicSchedule {
+            interval_days,
+            repetitions,
+            stability: Some(state.stability),
+            difficulty: Some(state.difficulty),
+            next_due_date: Some(format_date(today + Duration::days(interval_days))),
+            last_review_date: Some(format_date(today)),
+            ..current_schedule(study_topic)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        domain::ReviewGrade,
+        scheduling::fsrs::{
+            log_loss, optimize, retrievability, FsrsParameters, FsrsReview, DEFAULT_WEIGHTS,
+        },
+    };
+
+    #[test]
+    fn first_review_uses_initial_stability() {
+        let parameters = FsrsParameters::default();
+
+        let again = parameters.review(None, 0.0, ReviewGrade::Again);
+        let easy = parameters.review(None, 0.0, ReviewGrade::Easy);
+
+        assert_eq!(again.stability, DEFAULT_WEIGHTS[0]);
+        assert_eq!(easy.stability, DEFAULT_WEIGHTS[3]);
+        assert!(again.difficulty > easy.difficulty);
+    }
+
+    #[test]
+    fn recall_grows_stability_and_forgetting_shrinks_it() {
+        let parameters = FsrsParameters::default();
+        let state = parameters.review(None, 0.0, ReviewGrade::Good);
+
+        let recalled = parameters.review(Some(state), 4.0, ReviewGrade::Good);
+        let forgotten = parameters.review(Some(state), 4.0, ReviewGrade::Again);
+
+        assert!(recalled.stability > state.stability);
+        assert!(forgotten.stability < state.stability);
+        assert!(forgotten.difficulty > recalled.difficulty);
+    }
+
+    #[test]
+    fn interval_matches_stability_at_default_retention() {
+        let parameters = FsrsParameters::default();
+
+        assert_eq!(parameters.next_interval_days(10.0), 10);
+        assert!((retrievability(10.0, 10.0) - 0.9).abs() < 1e-9);
+
+        let relaxed = FsrsParameters {
+            desired_retention: 0.8,
+            ..Default::default()
+        };
+        assert!(relaxed.next_interval_days(10.0) > 10);
+    }
+
+    #[test]
+    fn optimizer_reduces_loss() {
+        // A learner who keeps remembering long after the default model
+        // expects them to forget.
+        let history = vec![
+            FsrsReview {
+                elapsed_days: 0.0,
+                grade: ReviewGrade::Good,
+            },
+            FsrsReview {
+                elapsed_days: 10.0,
+                grade: ReviewGrade::Good,
+            },
+            FsrsReview {
+                elapsed_days: 60.0,
+                grade: ReviewGrade::Good,
+            },
+            FsrsReview {
+                elapsed_days: 200.0,
+                grade: ReviewGrade::Good,
+            },
+        ];
+        let histories = vec![history; 20];
+
+        let default_loss = log_loss(&histories, DEFAULT_WEIGHTS).unwrap();
+        let optimized = optimize(&histories, DEFAULT_WEIGHTS);
+        let optimized_loss = log_loss(&histories, optimized).unwrap();
+
+        assert!(optimized_loss < default_loss);
+    }
+
+    #[test]
+    fn optimizer_keeps_weights_without_history() {
+        assert_eq!(optimize(&[], DEFAULT_WEIGHTS), DEFAULT_WEIGHTS);
+    }
+}
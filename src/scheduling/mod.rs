@@ -0,0 +1,151 @@
+use chrono::NaiveDate;
+
+use crate::{
+    clock::DATE_FORMAT,
+    domain::{ReviewGrade, SchedulerKind, StudyTopic, StudyTopicSchedule, Subject},
+    err::{StudyServiceError, StudyServiceResult},
+};
+
+pub mod fixed;
+pub mod fsrs;
+pub mod sm2;
+
+pub use fixed::FixedOffsets;
+pub use fsrs::{Fsrs, FsrsParameters};
+pub use sm2::Sm2;
+
+/// Review that is due for a topic and has no session yet.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DueReview {
+    pub due_date: NaiveDate,
+    /// Earlier review points that passed without a session and are
+    /// collapsed into this one.
+    pub missed_reviews: i64,
+}
+
+/// Decides when a topic has to be reviewed and how a graded review moves
+/// its next due date.
+pub trait Scheduler: Send + Sync {
+    /// Review that is due on or before `today` and has no session yet.
+    fn due_review(
+        &self,
+        study_topic: &StudyTopic,
+        today: NaiveDate,
+    ) -> StudyServiceResult<Option<DueReview>>;
+
+    /// Date of the next review that has no session yet: the due one if it
+    /// is overdue, else the first planned one after `today`. `None` when no
+    /// further review is planned.
+    fn next_review_date(
+        &self,
+        study_topic: &StudyTopic,
+        today: NaiveDate,
+    ) -> StudyServiceResult<Option<NaiveDate>>;
+
+    /// Scheduling state of the topic after a review graded on `today`.
+    fn review(
+        &self,
+        study_topic: &StudyTopic,
+        grade: ReviewGrade,
+        today: NaiveDate,
+    ) -> StudyServiceResult<StudyTopicSchedule>;
+}
+
+/// Builds the scheduler selected by a subject, with its FSRS parameters and
+/// the fixed `intervals` that apply to the topic.
+pub fn scheduler_for(
+    subject: &Subject,
+    intervals: FixedOffsets,
+) -> StudyServiceResult<Box<dyn Scheduler>> {
+    let scheduler: Box<dyn Scheduler> = match subject.scheduler {
+        SchedulerKind::FixedOffsets => Box::new(intervals),
+        SchedulerKind::Sm2 => Box::new(Sm2::new(intervals)),
+        SchedulerKind::Fsrs => {
+            let weights = match &subject.fsrs_parameters {
+                Some(fsrs_parameters) => serde_json::from_str(fsrs_parameters).map_err(|err| {
+                    StudyServiceError::InvalidSchedulerSettings(format!(
+                        "stored FSRS parameters of {} are invalid: {err}",
+                        subject.subject_name
+                    ))
+                })?,
+                None => fsrs::DEFAULT_WEIGHTS,
+            };
+
+            Box::new(Fsrs::new(
+                FsrsParameters {
+                    weights,
+                    desired_retention: subject.desired_retention,
+                },
+                intervals,
+            ))
+        }
+    };
+
+    Ok(scheduler)
+}
+
+/// Current scheduling state of a topic, the starting point for a review.
+fn current_schedule(study_topic: &StudyTopic) -> StudyTopicSchedule {
+    StudyTopicSchedule {
+        ease_factor: study_topic.ease_factor,
+        interval_days: study_topic.interval_days,
+        repetitions: study_topic.repetitions,
+        stability: study_topic.stability,
+        difficulty: study_topic.difficulty,
+        next_due_date: study_topic.next_due_date.clone(),
+        last_review_date: study_topic.last_review_date.clone(),
+    }
+}
+
+/// Shared due logic of the adaptive schedulers: a graded topic is due on its
+/// `next_due_date`, a topic that was never graded follows the fixed intervals.
+fn adaptive_due_review(
+    intervals: &FixedOffsets,
+    study_topic: &StudyTopic,
+    today: NaiveDate,
+) -> StudyServiceResult<Option<DueReview>> {
+    let Some(next_due_date) = &study_topic.next_due_date else {
+        return intervals.due_review(study_topic, today);
+    };
+
+    let due_date = NaiveDate::parse_from_str(next_due_date, DATE_FORMAT)?;
+    let last_session_date = parse_optional_date(study_topic.last_session_date.as_deref())?;
+
+    if due_date > today || last_session_date.is_some_and(|last| last >= due_date) {
+        return Ok(None);
+    }
+
+    Ok(Some(DueReview {
+        due_date,
+        missed_reviews: 0,
+    }))
+}
+
+/// A graded topic's next review is its `next_due_date` until a session
+/// covers it, an ungraded one follows the fixed intervals.
+fn adaptive_next_review_date(
+    intervals: &FixedOffsets,
+    study_topic: &StudyTopic,
+    today: NaiveDate,
+) -> StudyServiceResult<Option<NaiveDate>> {
+    let Some(next_due_date) = &study_topic.next_due_date else {
+        return intervals.next_review_date(study_topic, today);
+    };
+
+    let due_date = NaiveDate::parse_from_str(next_due_date, DATE_FORMAT)?;
+    let last_session_date = parse_optional_date(study_topic.last_session_date.as_deref())?;
+
+    if last_session_date.is_some_and(|last| last >= due_date) {
+        return Ok(None);
+    }
+
+    Ok(Some(due_date))
+}
+
+fn parse_optional_date(date: Option<&str>) -> StudyServiceResult<Option<NaiveDate>> {
+    let date = date
+        .map(|date| NaiveDate::parse_from_str(date, DATE_FORMAT))
+        .transpose()?;
+
+    Ok(date)
+}
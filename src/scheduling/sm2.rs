@@ -0,0 +1,180 @@
+use chrono::{Duration, NaiveDate};
+
+use crate::{
+    clock::format_date,
+    domain::{ReviewGrade, StudyTopic, StudyTopicSchedule},
+    err::StudyServiceResult,
+    scheduling::{
+        adaptive_due_review, adaptive_next_review_date, current_schedule, DueReview, FixedOffsets,
+        Scheduler,
+    },
+};
+
+const MIN_EASE_FACTOR: f64 = 1.3;
+
+/// Per-topic SuperMemo-2 state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sm2State {
+    pub ease_factor: f64,
+    pub interval_days: i64,
+    pub repetitions: i64,
+}
+
+impl Default for Sm2State {
+    fn default() -> Self {
+        Self {
+            ease_factor: 2.5,
+            interval_days: 0,
+            repetitions: 0,
+        }
+    }
+}
+
+impl Sm2State {
+    /// Applies a graded review and returns the new state, whose
+    /// `interval_days` is the number of days until the next review.
+    pub fn review(self, grade: ReviewGrade) -> Sm2State {
+        let quality = quality(grade);
+
+        let ease_factor = (self.ease_factor
+            + (0.1 - (5.0 - quality) * (0.08 + (5.0 - quality) * 0.02)))
+            .max(MIN_EASE_FACTOR);
+
+        let (repetitions, interval_days) = if quality < 3.0 {
+            (0, 1)
+        } else {
+            let repetitions = self.repetitions + 1;
+            let interval_days = match repetitions {
+                1 => 1,
+                2 => 6,
+                _ => (self.interval_days as f64 * ease_factor).round() as i64,
+            };
+
+            (repetitions, interval_days.max(1))
+        };
+
+        Sm2State {
+            ease_factor,
+            interval_days,
+            repetitions,
+        }
+    }
+}
+
+/// SuperMemo-2: the interval grows by a per-topic ease factor that graded
+/// reviews push up or down. Topics that were never graded follow the fixed
+/// `intervals`.
+pub struct Sm2 {
+    intervals: FixedOffsets,
+}
+
+impl Sm2 {
+    pub fn new(intervals: FixedOffsets) -> Self {
+        Self { intervals }
+    }
+}
+
+impl Scheduler for Sm2 {
+    fn due_review(
+        &self,
+        study_topic: &StudyTopic,
+        today: NaiveDate,
+    ) -> StudyServiceResult<Option<DueReview>> {
+        adaptive_due_review(&self.intervals, study_topic, today)
+    }
+
+    fn next_review_date(
+        &self,
+        study_topic: &StudyTopic,
+        today: NaiveDate,
+    ) -> StudyServiceResult<Option<NaiveDate>> {
+        adaptive_next_review_date(&self.intervals, study_topic, today)
+    }
+
+    fn review(
+        &self,
+        study_topic: &StudyTopic,
+        grade: ReviewGrade,
+        today: NaiveDate,
+    ) -> StudyServiceResult<StudyTopicSchedule> {
+        let sm2_state = Sm2State {
+            ease_factor: study_topic.ease_factor,
+            interval_days: study_topic.interval_days,
+            repetitions: study_topic.repetitions,
+        }
+        .review(grade);
+
+        Ok(StudyTopicSchedule {
+            ease_factor: sm2_state.ease_factor,
+            interval_days: sm2_state.interval_days,
+            repetitions: sm2_state.repetitions,
+            next_due_date: Some(format_date(today + Duration::days(sm2_state.interval_days))),
+            last_review_date: Some(format_date(today)),
+            ..current_schedule(study_topic)
+        })
+    }
+}
+
+/// Maps the four grades onto the 0-5 SM-2 quality scale.
+fn quality(grade: ReviewGrade) -> f64 {
+    match grade {
+        ReviewGrade::Again => 1.0,
+        ReviewGrade::Hard => 3.0,
+        ReviewGrade::Good => 4.0,
+        ReviewGrade::Easy => 5.0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{domain::ReviewGrade, scheduling::sm2::Sm2State};
+
+    #[test]
+    fn good_reviews_grow_interval() {
+        let state = Sm2State::default().review(ReviewGrade::Good);
+        assert_eq!(state.interval_days, 1);
+        assert_eq!(state.repetitions, 1);
+        assert!((state.ease_factor - 2.5).abs() < 1e-9);
+
+        let state = state.review(ReviewGrade::Good);
+        assert_eq!(state.interval_days, 6);
+
+        let state = state.review(ReviewGrade::Good);
+        assert_eq!(state.interval_days, 15);
+    }
+
+    #[test]
+    fn again_resets_repetitions_and_lowers_ease() {
+        let state = Sm2State::default()
+            .review(ReviewGrade::Good)
+            .review(ReviewGrade::Good)
+            .review(ReviewGrade::Again);
+
+        assert_eq!(state.repetitions, 0);
+        assert_eq!(state.interval_days, 1);
+        assert!(state.ease_factor < 2.5);
+    }
+
+    #[test]
+    fn easy_grows_faster_than_hard() {
+        let start = Sm2State::default()
+            .review(ReviewGrade::Good)
+            .review(ReviewGrade::Good);
+
+        let easy = start.review(ReviewGrade::Easy);
+        let hard = start.review(ReviewGrade::Hard);
+
+        assert!(easy.ease_factor > hard.ease_factor);
+        assert!(easy.interval_days > hard.interval_days);
+    }
+
+    #[test]
+    fn ease_factor_has_a_floor() {
+        let mut state = Sm2State::default();
+        for _ in 0..20 {
+            state = state.review(ReviewGrade::Again);
+        }
+
+        assert_eq!(state.ease_factor, 1.3);
+    }
+}
@@ -1,266 +1,3301 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
-use chrono::{NaiveDate, Utc};
+use bytes::Bytes;
+use chrono::{DateTime, NaiveDate};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
-use tokio::sync::Mutex;
 use tracing::{error, info};
 
 use crate::{
-    domain::{StudySessionInfo, StudyTopic, StudyTopicInfo, Subject},
-    err::StudyServiceResult,
-    repository::Repository,
+    anki::{self, DeckExport},
+    clock::{format_date, Clock, DATE_FORMAT},
+    domain::{
+        Flashcard, FlashcardInfo, FlashcardProgress, FlashcardUpdate, ImportedSubject,
+        IntervalSchedule, IntervalScheduleInfo, ReviewGrade, ReviewLog, ReviewLogInfo, SearchQuery,
+        SearchResult, StudySession, StudySessionCompletion, StudySessionInfo, StudySessionQuery,
+        StudySessionSort, StudyTopic, StudyTopicInfo, StudyTopicQuery, StudyTopicSchedule,
+        StudyTopicUpdate, Subject, SubjectInvitation, SubjectMember, SubjectMemberInfo,
+        SubjectRole, SubjectSchedulerSettings,
+    },
+    err::{RepositoryError, StudyServiceError, StudyServiceResult},
+    pagination::{into_page, page_size, Cursor, Page},
+    repository::StudyRepository,
+    scheduling::{
+        fsrs::{self, FsrsReview, DEFAULT_WEIGHTS},
+        scheduler_for, DueReview, FixedOffsets, Scheduler, Sm2,
+    },
 };
 
 #[derive(Clone)]
 pub struct StudyService {
-    repo: Repository,
-    study_session_creator: Arc<Mutex<StudySessionCreator>>,
+    repo: Arc<dyn StudyRepository>,
+    clock: Arc<dyn Clock>,
+    time_zone: Tz,
 }
 
-struct StudySessionCreator {}
+impl StudyService {
+    pub fn new(repo: Arc<dyn StudyRepository>, clock: Arc<dyn Clock>, time_zone: Tz) -> Self {
+        Self {
+            repo,
+            clock,
+            time_zone,
+        }
+    }
 
-impl StudySessionCreator {
-    pub async fn create_study_sessions_today(
-        &self,
-        study_service: &StudyService,
-        repo: &Repository,
-    ) -> StudyServiceResult<()> {
+    /// Time zone the user's days follow: the requested one, else the one
+    /// stored for the user, else the configured default.
+    async fn time_zone_for(&self, user_id: i64, time_zone: Option<Tz>) -> StudyServiceResult<Tz> {
+        if let Some(time_zone) = time_zone {
+            return Ok(time_zone);
+        }
+
+        match self
+            .repo
+            .get_user(user_id)
+            .await?
+            .and_then(|user| user.time_zone)
+        {
+            Some(time_zone) => time_zone.parse().map_err(|err| {
+                StudyServiceError::Internal(format!("Time zone of user {user_id}: {err}"))
+            }),
+            None => Ok(self.time_zone),
+        }
+    }
+
+    /// Today's date for the user, in the time zone of
+    /// [`StudyService::time_zone_for`].
+    async fn today(&self, user_id: i64, time_zone: Option<Tz>) -> StudyServiceResult<NaiveDate> {
+        Ok(self
+            .clock
+            .today_in(self.time_zone_for(user_id, time_zone).await?))
+    }
+
+    /// Time zones users set for their days, so the scheduler can start the
+    /// day in each of them.
+    pub async fn time_zones(&self) -> StudyServiceResult<Vec<Tz>> {
+        let mut time_zones = Vec::new();
+
+        for user in self.repo.get_users().await? {
+            if let Some(time_zone) = user
+                .time_zone
+                .and_then(|time_zone| time_zone.parse::<Tz>().ok())
+            {
+                if !time_zones.contains(&time_zone) {
+                    time_zones.push(time_zone);
+                }
+            }
+        }
+
+        Ok(time_zones)
+    }
+
+    /// Creates a session for every topic of every user with a due review
+    /// that has none yet, returning how many were created. Each user's day
+    /// is the one in their own time zone.
+    ///
+    /// Safe to run concurrently or repeatedly: the repository refuses a
+    /// second session for the same topic and due date, and counters are only
+    /// touched for sessions that were actually inserted.
+    pub async fn generate_study_sessions(&self) -> StudyServiceResult<usize> {
         info!("Creating study sessions");
 
-        let study_topics_today = study_service.get_study_topics_for_today().await?;
+        let mut created_sessions = 0;
+
+        for user in self.repo.get_users().await? {
+            created_sessions += self.generate_study_sessions_for_user(user.id).await?;
+        }
+
+        Ok(created_sessions)
+    }
+
+    /// Like [`StudyService::generate_study_sessions`] for the topics one
+    /// user sees, their own and those of subjects shared with them.
+    async fn generate_study_sessions_for_user(&self, user_id: i64) -> StudyServiceResult<usize> {
+        let study_topics = self.repo.get_study_topics(user_id).await?;
+        let scheduling_context = self.scheduling_context(user_id).await?;
+        let mut flashcard_progress = self
+            .flashcard_progress(user_id, &study_topics, &scheduling_context)
+            .await?;
+
+        let today = self.today(user_id, None).await?;
+
+        let mut created_sessions = 0;
 
-        info!("the study topics for today are: {study_topics_today:?}");
+        for study_topic in study_topics {
+            let flashcard_progress = flashcard_progress.remove(&study_topic.id);
+            created_sessions += self
+                .generate_study_sessions_for_study_topic(
+                    user_id,
+                    &study_topic,
+                    flashcard_progress,
+                    &scheduling_context,
+                    today,
+                )
+                .await?;
+        }
+
+        Ok(created_sessions)
+    }
+
+    /// Creates the user's due sessions of one topic. Topics scheduled card
+    /// by card, given with the `flashcard_progress` of their cards, get a
+    /// session for every due card instead of one for the topic.
+    async fn generate_study_sessions_for_study_topic(
+        &self,
+        user_id: i64,
+        study_topic: &StudyTopic,
+        flashcard_progress: Option<Vec<FlashcardProgress>>,
+        scheduling_context: &SchedulingContext,
+        today: NaiveDate,
+    ) -> StudyServiceResult<usize> {
+        let scheduled: Vec<(Option<i64>, StudyServiceResult<StudyTopic>)> = match flashcard_progress
+        {
+            Some(progress) => progress
+                .iter()
+                .map(|progress| {
+                    (
+                        Some(progress.flashcard_id),
+                        flashcard_as_study_topic(study_topic, progress),
+                    )
+                })
+                .collect(),
+            None => vec![(None, Ok(study_topic.clone()))],
+        };
 
-        let today = Utc::now().naive_utc().date().format("%Y-%m-%d").to_string();
+        let mut created_sessions = 0;
+
+        for (flashcard_id, scheduled_topic) in scheduled {
+            let due_review = match scheduled_topic.and_then(|scheduled_topic| {
+                scheduling_context
+                    .scheduler_for(study_topic)?
+                    .due_review(&scheduled_topic, today)
+            }) {
+                Ok(Some(due_review)) => due_review,
+                Ok(None) => continue,
+                Err(err) => {
+                    error!(
+                        "Error getting due review for study topic {} (flashcard {flashcard_id:?}): {err}",
+                        study_topic.id
+                    );
+                    continue;
+                }
+            };
 
-        let mut study_topics_to_process = Vec::new();
+            info!(
+                "Processing study topic: {study_topic:?}, flashcard: {flashcard_id:?}, due review: {due_review:?}"
+            );
 
-        for study_topic in study_topics_today {
-            if !repo
-                .exists_study_session_with(study_topic.id, today.clone())
+            if self
+                .create_study_session(
+                    user_id,
+                    study_topic.id,
+                    flashcard_id,
+                    due_review,
+                    format_date(today),
+                )
                 .await?
-                && (study_topic.last_session_date.is_none()
-                    || study_topic.last_session_date.clone().unwrap() != today)
             {
-                info!("Processed study topic: {study_topic:?}");
-                study_topics_to_process.push(study_topic);
+                created_sessions += 1;
             }
         }
 
-        for study_topic in study_topics_to_process {
-            self.create_study_session(study_topic.id, repo).await?;
+        Ok(created_sessions)
+    }
+
+    /// Review state of the user's cards in the topics that are scheduled
+    /// card by card, by topic. Topics without cards are left out and keep
+    /// being scheduled as a whole.
+    async fn flashcard_progress(
+        &self,
+        user_id: i64,
+        study_topics: &[StudyTopic],
+        scheduling_context: &SchedulingContext,
+    ) -> StudyServiceResult<HashMap<i64, Vec<FlashcardProgress>>> {
+        let study_topic_ids: Vec<i64> = study_topics
+            .iter()
+            .filter(|study_topic| scheduling_context.schedules_by_card(study_topic))
+            .map(|study_topic| study_topic.id)
+            .collect();
+
+        if study_topic_ids.is_empty() {
+            return Ok(HashMap::new());
         }
 
-        Ok(())
+        let mut flashcard_progress: HashMap<i64, Vec<FlashcardProgress>> = HashMap::new();
+        for progress in self
+            .repo
+            .get_flashcard_progress(user_id, study_topic_ids)
+            .await?
+        {
+            flashcard_progress
+                .entry(progress.study_topic_id)
+                .or_default()
+                .push(progress);
+        }
+
+        Ok(flashcard_progress)
     }
 
     async fn create_study_session(
         &self,
+        user_id: i64,
         study_topic_id: i64,
-        repo: &Repository,
-    ) -> StudyServiceResult<()> {
-        repo.create_study_session(study_topic_id).await?;
-        repo.update_last_session_date(study_topic_id).await?;
-        repo.increase_study_topic_total_sessions(study_topic_id)
+        flashcard_id: Option<i64>,
+        due_review: DueReview,
+        today: String,
+    ) -> StudyServiceResult<bool> {
+        let created = self
+            .repo
+            .create_study_session(
+                user_id,
+                study_topic_id,
+                flashcard_id,
+                format_date(due_review.due_date),
+                due_review.missed_reviews,
+                today,
+            )
             .await?;
 
-        Ok(())
+        Ok(created)
     }
-}
 
-impl StudyService {
-    pub fn new(repo: Repository) -> Self {
-        Self {
-            repo,
-            study_session_creator: Arc::new(Mutex::new(StudySessionCreator {})),
+    async fn scheduling_context(&self, user_id: i64) -> StudyServiceResult<SchedulingContext> {
+        let subjects: HashMap<String, Subject> = self
+            .repo
+            .get_subjects(user_id)
+            .await?
+            .into_iter()
+            .map(|subject| (subject.subject_name.clone(), subject))
+            .collect();
+
+        // Shared subjects follow the interval schedules of their owner.
+        let mut owner_ids: Vec<i64> = subjects.values().map(|subject| subject.user_id).collect();
+        owner_ids.push(user_id);
+        owner_ids.sort_unstable();
+        owner_ids.dedup();
+
+        let mut interval_schedules = HashMap::new();
+
+        for owner_id in owner_ids {
+            for interval_schedule in self.repo.get_interval_schedules(owner_id).await? {
+                interval_schedules.insert(interval_schedule.id, interval_schedule);
+            }
         }
+
+        Ok(SchedulingContext {
+            subjects,
+            interval_schedules,
+        })
     }
 
-    pub async fn add_subject(&self, subject_name: String) -> StudyServiceResult<()> {
-        self.repo.add_subject(subject_name).await?;
+    pub async fn add_subject(&self, user_id: i64, subject_name: String) -> StudyServiceResult<()> {
+        self.repo.add_subject(user_id, subject_name).await?;
 
         Ok(())
     }
 
-    pub async fn get_study_topics(&self) -> StudyServiceResult<Vec<StudyTopic>> {
-        let study_topics = self.repo.get_study_topics().await?;
+    /// Page of the topics matching the query.
+    pub async fn get_study_topics(
+        &self,
+        user_id: i64,
+        query: StudyTopicQuery,
+    ) -> StudyServiceResult<Page<StudyTopic>> {
+        for date in [&query.created_from, &query.created_to, &query.due_before]
+            .into_iter()
+            .flatten()
+        {
+            NaiveDate::parse_from_str(date, DATE_FORMAT)?;
+        }
+
+        let limit = page_size(query.limit)?;
+        let sort = query.sort;
+        let after = query
+            .cursor
+            .as_deref()
+            .map(|cursor| Cursor::decode(cursor, sort.as_str()))
+            .transpose()?;
 
-        Ok(study_topics)
+        let study_topics = self
+            .repo
+            .query_study_topics(user_id, query, after, limit)
+            .await?;
+
+        Ok(into_page(study_topics, limit, |study_topic| Cursor {
+            sort: sort.as_str().to_string(),
+            key: sort.key(study_topic),
+            id: study_topic.id,
+        }))
     }
 
-    pub async fn complete_study_session(&self, study_session_id: i64) -> StudyServiceResult<()> {
-        info!("Completing study session");
-        let study_topic_id = self
+    /// Completes a session and records it in the topic's review history.
+    /// When a grade is given the topic, or the card of a card session, is
+    /// rescheduled by its subject's scheduler, ungraded completions leave it
+    /// on its current schedule.
+    ///
+    /// Completing a session that was already completed does nothing, so a
+    /// retried request is only counted once.
+    pub async fn complete_study_session(
+        &self,
+        user_id: i64,
+        study_session_id: i64,
+        completion: StudySessionCompletion,
+        time_zone: Option<Tz>,
+    ) -> StudyServiceResult<()> {
+        info!("Completing study session with {completion:?}");
+        let Some(study_session) = self
+            .repo
+            .get_study_session(user_id, study_session_id)
+            .await?
+        else {
+            return Err(
+                RepositoryError::NotFound(format!("study session {study_session_id}")).into(),
+            );
+        };
+
+        if study_session.completed_at.is_some() {
+            info!("Study session {study_session_id} was already completed");
+            return Ok(());
+        }
+
+        let study_topic_id = study_session.study_topic_id;
+        let today = self.today(user_id, time_zone).await?;
+
+        let schedule = match completion.grade {
+            Some(grade) => {
+                self.review_study_topic(
+                    user_id,
+                    study_topic_id,
+                    study_session.flashcard_id,
+                    grade,
+                    today,
+                )
+                .await?
+            }
+            None => None,
+        };
+
+        let completed = self
             .repo
-            .get_study_topic_id_with_study_session(study_session_id)
+            .complete_study_session(
+                user_id,
+                study_session_id,
+                schedule,
+                ReviewLogInfo {
+                    study_topic_id,
+                    flashcard_id: study_session.flashcard_id,
+                    study_session_id: Some(study_session_id),
+                    scheduled_date: Some(study_session.due_date),
+                    review_date: format_date(today),
+                    completed_at: Some(self.clock.now().to_rfc3339()),
+                    grade: completion.grade,
+                    time_spent_seconds: completion.time_spent_seconds,
+                },
+            )
             .await?;
 
-        info!("The study topic id is: {study_topic_id}");
-        self.repo
-            .increase_study_topic_completed_sessions(study_topic_id)
+        if !completed {
+            info!("Study session {study_session_id} was completed concurrently");
+        }
+
+        Ok(())
+    }
+
+    /// Schedule of the topic, or of its card `flashcard_id`, after a review
+    /// with the given grade.
+    async fn review_study_topic(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+        flashcard_id: Option<i64>,
+        grade: ReviewGrade,
+        today: NaiveDate,
+    ) -> StudyServiceResult<Option<StudyTopicSchedule>> {
+        let Some(study_topic) = self.repo.get_study_topic(user_id, study_topic_id).await? else {
+            return Ok(None);
+        };
+
+        let scheduler = self
+            .scheduling_context(user_id)
+            .await?
+            .scheduler_for(&study_topic)?;
+
+        let reviewed = match flashcard_id {
+            Some(flashcard_id) => {
+                let Some(progress) = self
+                    .repo
+                    .get_flashcard_progress(user_id, vec![study_topic_id])
+                    .await?
+                    .into_iter()
+                    .find(|progress| progress.flashcard_id == flashcard_id)
+                else {
+                    return Ok(None);
+                };
+
+                flashcard_as_study_topic(&study_topic, &progress)?
+            }
+            None => study_topic,
+        };
+
+        Ok(Some(scheduler.review(&reviewed, grade, today)?))
+    }
+
+    /// Topics with all the words of `query.q` in their name or
+    /// description, best match first.
+    pub async fn search_study_topics(
+        &self,
+        user_id: i64,
+        query: SearchQuery,
+    ) -> StudyServiceResult<Vec<SearchResult>> {
+        let terms: Vec<String> = query
+            .q
+            .split_whitespace()
+            .filter(|term| term.chars().any(char::is_alphanumeric))
+            .map(str::to_string)
+            .collect();
+
+        if terms.is_empty() {
+            return Err(StudyServiceError::InvalidRequest(
+                "q must contain at least one word".to_string(),
+            ));
+        }
+
+        let limit = page_size(query.limit)?;
+
+        Ok(self.repo.search_study_topics(user_id, terms, limit).await?)
+    }
+
+    /// A topic with its pending sessions, the date its next review is due
+    /// and a summary of its review history.
+    pub async fn get_study_topic_detail(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+        time_zone: Option<Tz>,
+    ) -> StudyServiceResult<StudyTopicDetail> {
+        let Some(study_topic) = self.repo.get_study_topic(user_id, study_topic_id).await? else {
+            return Err(RepositoryError::NotFound(format!("study topic {study_topic_id}")).into());
+        };
+
+        let pending_sessions = self
+            .repo
+            .get_pending_study_sessions_for_study_topic(user_id, study_topic_id)
+            .await?;
+        let review_logs = self
+            .repo
+            .get_review_logs_for_study_topic(user_id, study_topic_id)
             .await?;
 
-        info!("increasing study topic completed sessions");
+        // A pending session is the review that is due, the scheduler only
+        // knows about reviews that have no session yet. Topics scheduled card
+        // by card are next due with their earliest card.
+        let next_due_date = match pending_sessions.first() {
+            Some(study_session) => Some(study_session.due_date.clone()),
+            None => {
+                let today = self.today(user_id, time_zone).await?;
+                let scheduling_context = self.scheduling_context(user_id).await?;
+                let scheduler = scheduling_context.scheduler_for(&study_topic)?;
+                let flashcard_progress = self
+                    .flashcard_progress(
+                        user_id,
+                        std::slice::from_ref(&study_topic),
+                        &scheduling_context,
+                    )
+                    .await?;
+
+                match flashcard_progress.get(&study_topic.id) {
+                    Some(progress) => {
+                        let mut next_due_date = None;
+                        for progress in progress {
+                            let flashcard = flashcard_as_study_topic(&study_topic, progress)?;
+                            if let Some(due_date) = scheduler.next_review_date(&flashcard, today)? {
+                                next_due_date = Some(
+                                    next_due_date
+                                        .map_or(due_date, |next: NaiveDate| next.min(due_date)),
+                                );
+                            }
+                        }
+                        next_due_date
+                    }
+                    None => scheduler.next_review_date(&study_topic, today)?,
+                }
+                .map(format_date)
+            }
+        };
 
-        self.repo.delete_study_session(study_session_id).await?;
+        Ok(StudyTopicDetail {
+            study_topic,
+            pending_sessions,
+            next_due_date,
+            review_history: ReviewHistorySummary::from(&review_logs),
+        })
+    }
 
-        info!("Deleting study session");
+    /// Completed reviews of a topic, oldest first.
+    pub async fn get_study_topic_history(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+    ) -> StudyServiceResult<Vec<ReviewLog>> {
+        if self
+            .repo
+            .get_study_topic(user_id, study_topic_id)
+            .await?
+            .is_none()
+        {
+            return Err(RepositoryError::NotFound(format!("study topic {study_topic_id}")).into());
+        }
 
-        Ok(())
+        Ok(self
+            .repo
+            .get_review_logs_for_study_topic(user_id, study_topic_id)
+            .await?)
     }
 
     pub async fn add_study_topic(
         &self,
+        user_id: i64,
         study_topic_info: StudyTopicInfo,
+        time_zone: Option<Tz>,
     ) -> StudyServiceResult<()> {
         info!("Adding study topic with study topic info: {study_topic_info:?}");
-        self.repo.add_study_topic(study_topic_info).await?;
+        let subject = self
+            .find_editable_subject(user_id, &study_topic_info.subject_name)
+            .await?;
+        self.check_interval_schedule_exists(subject.user_id, study_topic_info.interval_schedule_id)
+            .await?;
+        let today = self.today(user_id, time_zone).await?;
+        let study_topic_id = self
+            .repo
+            .add_study_topic(user_id, study_topic_info, format_date(today))
+            .await?;
 
-        Ok(())
-    }
+        // The first review is on the day the topic is created, so the caller
+        // gets that session now instead of on the next scheduled run. Other
+        // members get theirs from that run, on their own day. A new topic has
+        // no cards yet.
+        if let Some(study_topic) = self.repo.get_study_topic(user_id, study_topic_id).await? {
+            let scheduling_context = self.scheduling_context(user_id).await?;
+            self.generate_study_sessions_for_study_topic(
+                user_id,
+                &study_topic,
+                None,
+                &scheduling_context,
+                today,
+            )
+            .await?;
+        }
 
-    pub async fn delete_subject(&self, subject_name: String) -> StudyServiceResult<()> {
-        self.repo.delete_subject(subject_name).await?;
         Ok(())
     }
 
-    pub async fn get_study_subjects(&self) -> StudyServiceResult<Vec<Subject>> {
-        let subjects = self.repo.get_subjects().await?;
+    /// Edits a topic in place, keeping its sessions and review history when
+    /// it is renamed or moved to another subject.
+    pub async fn update_study_topic(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+        update: StudyTopicUpdate,
+    ) -> StudyServiceResult<()> {
+        info!("Updating study topic {study_topic_id} with {update:?}");
+        if update
+            .name
+            .as_deref()
+            .is_some_and(|name| name.trim().is_empty())
+        {
+            return Err(StudyServiceError::InvalidRequest(
+                "Study topic name must not be empty".to_string(),
+            ));
+        }
+
+        let (_, subject) = self
+            .find_editable_study_topic(user_id, study_topic_id)
+            .await?;
 
-        Ok(subjects)
+        if let Some(subject_name) = &update.subject_name {
+            let new_subject = self.find_editable_subject(user_id, subject_name).await?;
+
+            if new_subject.user_id != subject.user_id {
+                return Err(StudyServiceError::InvalidRequest(
+                    "Study topics only move between subjects of the same owner".to_string(),
+                ));
+            }
+        }
+
+        self.repo
+            .update_study_topic(user_id, study_topic_id, update)
+            .await?;
+
+        Ok(())
     }
 
-    pub async fn get_study_topics_for_subject(
+    /// Selects the scheduler of a subject and whether it schedules cards one
+    /// by one. FSRS weights must be the 17 FSRS-4.5 weights, omitting them
+    /// keeps the stored ones.
+    pub async fn update_subject_scheduler(
         &self,
+        user_id: i64,
         subject_name: String,
-    ) -> StudyServiceResult<Vec<StudyTopic>> {
-        let study_topics = self.repo.get_study_topics_for_subject(subject_name).await?;
+        settings: SubjectSchedulerSettings,
+    ) -> StudyServiceResult<()> {
+        let current_subject = self.find_owned_subject(user_id, &subject_name).await?;
+
+        let desired_retention = match settings.desired_retention {
+            Some(desired_retention) if desired_retention > 0.0 && desired_retention < 1.0 => {
+                desired_retention
+            }
+            Some(desired_retention) => {
+                return Err(StudyServiceError::InvalidSchedulerSettings(format!(
+                    "desired retention must be between 0 and 1, got {desired_retention}"
+                )))
+            }
+            None => current_subject.desired_retention,
+        };
+
+        let fsrs_parameters =
+            match settings.fsrs_parameters {
+                Some(weights) => {
+                    if weights.len() != DEFAULT_WEIGHTS.len()
+                        || weights.iter().any(|weight| !weight.is_finite())
+                    {
+                        return Err(StudyServiceError::InvalidSchedulerSettings(format!(
+                            "FSRS parameters must be {} finite numbers",
+                            DEFAULT_WEIGHTS.len()
+                        )));
+                    }
 
-        Ok(study_topics)
+                    Some(serde_json::to_string(&weights).map_err(|err| {
+                        StudyServiceError::InvalidSchedulerSettings(err.to_string())
+                    })?)
+                }
+                None => current_subject.fsrs_parameters,
+            };
+
+        self.repo
+            .update_subject_scheduler(
+                user_id,
+                subject_name,
+                settings.scheduler,
+                desired_retention,
+                fsrs_parameters,
+                settings
+                    .card_scheduling
+                    .unwrap_or(current_subject.card_scheduling),
+            )
+            .await?;
+
+        Ok(())
     }
 
-    pub async fn get_study_sessions_for_subject(
+    /// Fits the subject's FSRS weights to the owner's graded reviews of its
+    /// topics and stores them, returning the new weights.
+    pub async fn optimize_fsrs_parameters(
         &self,
+        user_id: i64,
         subject_name: String,
-    ) -> StudyServiceResult<Vec<StudySessionResponse>> {
-        self.study_session_creator
-            .lock()
-            .await
-            .create_study_sessions_today(self, &self.repo)
-            .await?;
+    ) -> StudyServiceResult<Vec<f64>> {
+        let subject = self.find_owned_subject(user_id, &subject_name).await?;
 
-        let study_sessions = self
+        let current_weights = match &subject.fsrs_parameters {
+            Some(fsrs_parameters) => serde_json::from_str(fsrs_parameters)
+                .map_err(|err| StudyServiceError::InvalidSchedulerSettings(err.to_string()))?,
+            None => DEFAULT_WEIGHTS,
+        };
+
+        let review_logs = self
             .repo
-            .get_study_sessions_for_subject(subject_name)
+            .get_review_logs_for_subject(user_id, subject_name.clone())
             .await?;
 
-        let mut study_sessions_response = Vec::new();
+        let mut histories: Vec<Vec<FsrsReview>> = Vec::new();
+        let mut previous: Option<((i64, Option<i64>), NaiveDate)> = None;
 
-        for study_session in study_sessions {
-            let study_session_response = StudySessionResponse::from(study_session)?;
-            study_sessions_response.push(study_session_response);
+        // Ungraded completions say nothing about recall, only graded reviews
+        // make up the histories. Each card reviewed on its own has a history
+        // of its own.
+        for review_log in review_logs {
+            let Some(grade) = review_log.grade else {
+                continue;
+            };
+            let review_date = NaiveDate::parse_from_str(&review_log.review_date, DATE_FORMAT)?;
+
+            let reviewed = (review_log.study_topic_id, review_log.flashcard_id);
+            let elapsed_days = match previous {
+                Some((previous_reviewed, previous_date)) if previous_reviewed == reviewed => {
+                    review_date.signed_duration_since(previous_date).num_days() as f64
+                }
+                _ => {
+                    histories.push(Vec::new());
+                    0.0
+                }
+            };
+
+            if let Some(history) = histories.last_mut() {
+                history.push(FsrsReview {
+                    elapsed_days,
+                    grade,
+                });
+            }
+
+            previous = Some((reviewed, review_date));
         }
 
-        Ok(study_sessions_response)
-    }
+        info!(
+            "Optimizing FSRS parameters of {subject_name} with {} review histories",
+            histories.len()
+        );
 
-    pub async fn delete_study_topic(&self, study_topic_id: i64) -> StudyServiceResult<()> {
-        self.repo.delete_study_topic(study_topic_id).await?;
+        // Fitting takes many passes over every history, so it runs off the
+        // async workers.
+        let weights =
+            tokio::task::spawn_blocking(move || fsrs::optimize(&histories, current_weights))
+                .await
+                .map_err(|err| {
+                    StudyServiceError::Internal(format!("Optimizing FSRS parameters: {err}"))
+                })?
+                .to_vec();
 
-        Ok(())
+        self.repo
+            .update_subject_scheduler(
+                user_id,
+                subject_name,
+                subject.scheduler,
+                subject.desired_retention,
+                Some(
+                    serde_json::to_string(&weights).map_err(|err| {
+                        StudyServiceError::InvalidSchedulerSettings(err.to_string())
+                    })?,
+                ),
+                subject.card_scheduling,
+            )
+            .await?;
+
+        Ok(weights)
     }
 
-    pub async fn get_study_topics_for_today(&self) -> StudyServiceResult<Vec<StudyTopic>> {
-        let study_topics = self.repo.get_study_topics().await?;
+    pub async fn get_interval_schedules(
+        &self,
+        user_id: i64,
+    ) -> StudyServiceResult<Vec<IntervalSchedule>> {
+        Ok(self.repo.get_interval_schedules(user_id).await?)
+    }
 
-        let study_topics_for_today = study_topics
-            .into_iter()
-            .filter(|study_topic| {
-                match get_days_since_creation(study_topic.creation_date.clone()) {
-                    Ok(days) => study_for_today(days),
-                    Err(err) => {
-                        error!("Error getting days since creation: {err}");
-                        false
-                    }
-                }
-            })
-            .collect();
+    pub async fn add_interval_schedule(
+        &self,
+        user_id: i64,
+        interval_schedule_info: IntervalScheduleInfo,
+    ) -> StudyServiceResult<IntervalSchedule> {
+        validate_interval_schedule(&interval_schedule_info)?;
+
+        let id = self
+            .repo
+            .add_interval_schedule(user_id, interval_schedule_info.clone())
+            .await?;
 
-        Ok(study_topics_for_today)
+        Ok(IntervalSchedule {
+            id,
+            user_id,
+            name: interval_schedule_info.name,
+            offsets: interval_schedule_info.offsets,
+            repeat_every_days: interval_schedule_info.repeat_every_days,
+        })
     }
-}
 
-fn get_days_since_creation(date: String) -> StudyServiceResult<u32> {
-    let parsed_date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")?;
+    pub async fn update_interval_schedule(
+        &self,
+        user_id: i64,
+        interval_schedule_id: i64,
+        interval_schedule_info: IntervalScheduleInfo,
+    ) -> StudyServiceResult<()> {
+        validate_interval_schedule(&interval_schedule_info)?;
 
-    let today = Utc::now().naive_utc().date();
+        self.repo
+            .update_interval_schedule(user_id, interval_schedule_id, interval_schedule_info)
+            .await?;
 
-    let days_diff = today.signed_duration_since(parsed_date).num_days();
+        Ok(())
+    }
 
-    Ok(days_diff as u32)
-}
+    pub async fn delete_interval_schedule(
+        &self,
+        user_id: i64,
+        interval_schedule_id: i64,
+    ) -> StudyServiceResult<()> {
+        self.repo
+            .delete_interval_schedule(user_id, interval_schedule_id)
+            .await?;
 
-fn study_for_today(days: u32) -> bool {
-    match days {
-        0 | 1 | 3 | 7 | 21 | 30 | 45 | 60 => true,
-        n if n % 60 == 0 => true,
-        _ => false,
+        Ok(())
     }
-}
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
-pub struct StudySessionResponse {
-    pub id: i64,
-    pub study_topic_name: String,
-    pub days_passed: u32,
-}
+    /// Assigns an interval schedule to every topic of the subject that has
+    /// none of its own, `None` restores the default offsets.
+    pub async fn set_subject_interval_schedule(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        interval_schedule_id: Option<i64>,
+    ) -> StudyServiceResult<()> {
+        self.find_owned_subject(user_id, &subject_name).await?;
+        self.check_interval_schedule_exists(user_id, interval_schedule_id)
+            .await?;
 
-impl StudySessionResponse {
-    fn from(study_session: StudySessionInfo) -> StudyServiceResult<StudySessionResponse> {
-        let study_session_response = StudySessionResponse {
-            id: study_session.id,
-            study_topic_name: study_session.study_topic_name,
-            days_passed: get_days_since_creation(study_session.due_date)?,
-        };
+        self.repo
+            .update_subject_interval_schedule(user_id, subject_name, interval_schedule_id)
+            .await?;
 
-        Ok(study_session_response)
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod test {
-    use chrono::Utc;
+    /// Overrides the subject's interval schedule for a single topic, `None`
+    /// makes it follow the subject again. The schedule has to be one of the
+    /// subject owner's.
+    pub async fn set_study_topic_interval_schedule(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+        interval_schedule_id: Option<i64>,
+    ) -> StudyServiceResult<()> {
+        let (_, subject) = self
+            .find_editable_study_topic(user_id, study_topic_id)
+            .await?;
+        self.check_interval_schedule_exists(subject.user_id, interval_schedule_id)
+            .await?;
 
-    use crate::study_service::{get_days_since_creation, study_for_today};
+        self.repo
+            .update_study_topic_interval_schedule(user_id, study_topic_id, interval_schedule_id)
+            .await?;
 
-    #[test]
-    fn has_to_study_today() {
-        assert!(study_for_today(0));
-        assert!(study_for_today(1));
-        assert!(study_for_today(3));
-        assert!(study_for_today(7));
-        assert!(study_for_today(60));
-        assert!(study_for_today(120));
+        Ok(())
+    }
 
-        assert!(!study_for_today(2));
-        assert!(!study_for_today(5));
-        assert!(!study_for_today(22));
-        assert!(!study_for_today(19));
+    /// The subject with the given name, or a not found error.
+    async fn find_subject(&self, user_id: i64, subject_name: &str) -> StudyServiceResult<Subject> {
+        self.repo
+            .get_subject(user_id, subject_name.to_string())
+            .await?
+            .ok_or_else(|| RepositoryError::NotFound(format!("subject {subject_name}")).into())
     }
 
-    #[test]
-    fn test_get_days_since_creation_today() {
-        let today = Utc::now().naive_utc().date().format("%Y-%m-%d").to_string();
-        let result = get_days_since_creation(today);
+    /// The subject if the user owns it: renaming, deleting and sharing it
+    /// and its scheduling settings are up to the owner.
+    async fn find_owned_subject(
+        &self,
+        user_id: i64,
+        subject_name: &str,
+    ) -> StudyServiceResult<Subject> {
+        let subject = self.find_subject(user_id, subject_name).await?;
 
-        assert_eq!(result.unwrap(), 0);
+        if subject.role != SubjectRole::Owner {
+            return Err(StudyServiceError::Forbidden(format!(
+                "Only the owner of subject {subject_name} can change it"
+            )));
+        }
+
+        Ok(subject)
     }
 
-    #[test]
-    fn test_get_days_since_creation_past() {
-        let past_date = "2023-01-01".to_string();
-        let result = get_days_since_creation(past_date);
+    /// The subject if the user may add, edit and delete its topics.
+    async fn find_editable_subject(
+        &self,
+        user_id: i64,
+        subject_name: &str,
+    ) -> StudyServiceResult<Subject> {
+        let subject = self.find_subject(user_id, subject_name).await?;
+
+        if !subject.role.can_edit_study_topics() {
+            return Err(StudyServiceError::Forbidden(format!(
+                "Viewers of subject {subject_name} cannot change its topics"
+            )));
+        }
 
-        assert!(result.unwrap() > 0);
+        Ok(subject)
+    }
+
+    /// The topic, with its subject, if the user may edit it.
+    async fn find_editable_study_topic(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+    ) -> StudyServiceResult<(StudyTopic, Subject)> {
+        let Some(study_topic) = self.repo.get_study_topic(user_id, study_topic_id).await? else {
+            return Err(RepositoryError::NotFound(format!("study topic {study_topic_id}")).into());
+        };
+
+        let subject = self
+            .find_editable_subject(user_id, &study_topic.subject_name)
+            .await?;
+
+        Ok((study_topic, subject))
+    }
+
+    async fn check_interval_schedule_exists(
+        &self,
+        user_id: i64,
+        interval_schedule_id: Option<i64>,
+    ) -> StudyServiceResult<()> {
+        let Some(interval_schedule_id) = interval_schedule_id else {
+            return Ok(());
+        };
+
+        if self
+            .repo
+            .get_interval_schedule(user_id, interval_schedule_id)
+            .await?
+            .is_none()
+        {
+            return Err(RepositoryError::NotFound(format!(
+                "interval schedule {interval_schedule_id}"
+            ))
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Renames a subject, its topics follow it to the new name.
+    pub async fn rename_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        new_subject_name: String,
+    ) -> StudyServiceResult<()> {
+        if new_subject_name.trim().is_empty() {
+            return Err(StudyServiceError::InvalidRequest(
+                "Subject name must not be empty".to_string(),
+            ));
+        }
+
+        self.find_owned_subject(user_id, &subject_name).await?;
+
+        if new_subject_name == subject_name {
+            return Ok(());
+        }
+
+        self.repo
+            .rename_subject(user_id, subject_name, new_subject_name)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+    ) -> StudyServiceResult<()> {
+        self.find_owned_subject(user_id, &subject_name).await?;
+        self.repo.delete_subject(user_id, subject_name).await?;
+        Ok(())
+    }
+
+    /// The owner of the subject followed by its members and the users
+    /// invited to it.
+    pub async fn get_subject_members(
+        &self,
+        user_id: i64,
+        subject_name: String,
+    ) -> StudyServiceResult<Vec<SubjectMember>> {
+        let subject = self.find_subject(user_id, &subject_name).await?;
+
+        let owner =
+            self.repo.get_user(subject.user_id).await?.ok_or_else(|| {
+                RepositoryError::NotFound(format!("owner of subject {subject_name}"))
+            })?;
+
+        let mut subject_members = vec![SubjectMember {
+            user_id: owner.id,
+            username: owner.username,
+            role: SubjectRole::Owner,
+            invited_at: None,
+            accepted_at: None,
+        }];
+        subject_members.extend(self.repo.get_subject_members(user_id, subject_name).await?);
+
+        Ok(subject_members)
+    }
+
+    /// Invites a user to a subject of the caller. The subject only shows up
+    /// for them once they accept.
+    pub async fn invite_subject_member(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        member_info: SubjectMemberInfo,
+    ) -> StudyServiceResult<SubjectMember> {
+        self.find_owned_subject(user_id, &subject_name).await?;
+
+        if member_info.role == SubjectRole::Owner {
+            return Err(StudyServiceError::InvalidRequest(
+                "A subject has a single owner, invite members as editor or viewer".to_string(),
+            ));
+        }
+
+        let Some(member) = self
+            .repo
+            .get_user_by_username(member_info.username.trim().to_string())
+            .await?
+        else {
+            return Err(RepositoryError::NotFound(format!("user {}", member_info.username)).into());
+        };
+
+        if member.id == user_id {
+            return Err(StudyServiceError::InvalidRequest(
+                "The owner of a subject cannot be invited to it".to_string(),
+            ));
+        }
+
+        let invited_at = self.clock.now().to_rfc3339();
+
+        self.repo
+            .add_subject_member(
+                user_id,
+                subject_name.clone(),
+                member.id,
+                member_info.role,
+                invited_at.clone(),
+            )
+            .await?;
+
+        info!(
+            "Invited user {} to subject {subject_name} of user {user_id}",
+            member.id
+        );
+
+        Ok(SubjectMember {
+            user_id: member.id,
+            username: member.username,
+            role: member_info.role,
+            invited_at: Some(invited_at),
+            accepted_at: None,
+        })
+    }
+
+    /// Revokes a member's access, or withdraws their invitation. The owner
+    /// removes anyone, members can only remove themselves. The member's
+    /// sessions and progress on the subject's topics go with them.
+    pub async fn remove_subject_member(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        member_id: i64,
+    ) -> StudyServiceResult<()> {
+        let subject = self.find_subject(user_id, &subject_name).await?;
+
+        if member_id == subject.user_id {
+            return Err(StudyServiceError::InvalidRequest(
+                "The owner cannot leave a subject, delete it instead".to_string(),
+            ));
+        }
+
+        if subject.role != SubjectRole::Owner && member_id != user_id {
+            return Err(StudyServiceError::Forbidden(format!(
+                "Only the owner of subject {subject_name} can remove other members"
+            )));
+        }
+
+        self.repo
+            .delete_subject_member(subject.user_id, subject_name, member_id)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_subject_invitations(
+        &self,
+        user_id: i64,
+    ) -> StudyServiceResult<Vec<SubjectInvitation>> {
+        Ok(self.repo.get_subject_invitations(user_id).await?)
+    }
+
+    pub async fn accept_subject_invitation(
+        &self,
+        user_id: i64,
+        invitation_id: i64,
+    ) -> StudyServiceResult<()> {
+        self.repo
+            .accept_subject_invitation(user_id, invitation_id, self.clock.now().to_rfc3339())
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn decline_subject_invitation(
+        &self,
+        user_id: i64,
+        invitation_id: i64,
+    ) -> StudyServiceResult<()> {
+        self.repo
+            .delete_subject_invitation(user_id, invitation_id)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_study_subjects(&self, user_id: i64) -> StudyServiceResult<Vec<Subject>> {
+        let subjects = self.repo.get_subjects(user_id).await?;
+
+        Ok(subjects)
+    }
+
+    /// A subject with the number of its topics, its sessions due today or
+    /// earlier and the share of its sessions that were completed.
+    pub async fn get_subject_detail(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        time_zone: Option<Tz>,
+    ) -> StudyServiceResult<SubjectDetail> {
+        let subject = self.find_subject(user_id, &subject_name).await?;
+
+        let study_topics = self
+            .repo
+            .get_study_topics_for_subject(user_id, subject_name.clone())
+            .await?;
+        let study_sessions = self
+            .repo
+            .get_study_sessions_for_subject(user_id, subject_name)
+            .await?;
+
+        let today = format_date(self.today(user_id, time_zone).await?);
+        let due_sessions = study_sessions
+            .iter()
+            .filter(|study_session| study_session.due_date <= today)
+            .count();
+
+        let total_sessions: i64 = study_topics
+            .iter()
+            .map(|study_topic| study_topic.total_sessions)
+            .sum();
+        let completed_sessions: i64 = study_topics
+            .iter()
+            .map(|study_topic| study_topic.completed_sessions)
+            .sum();
+        let completion_rate =
+            (total_sessions > 0).then(|| completed_sessions as f64 / total_sessions as f64);
+
+        Ok(SubjectDetail {
+            subject,
+            topic_count: study_topics.len(),
+            due_sessions,
+            completion_rate,
+        })
+    }
+
+    pub async fn get_study_topics_for_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        query: StudyTopicQuery,
+    ) -> StudyServiceResult<Page<StudyTopic>> {
+        self.find_subject(user_id, &subject_name).await?;
+
+        self.get_study_topics(
+            user_id,
+            StudyTopicQuery {
+                subject_name: Some(subject_name),
+                ..query
+            },
+        )
+        .await
+    }
+
+    pub async fn get_study_sessions_for_subject(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        query: StudySessionQuery,
+        time_zone: Option<Tz>,
+    ) -> StudyServiceResult<Page<StudySessionResponse>> {
+        self.find_subject(user_id, &subject_name).await?;
+        if let Some(due_before) = &query.due_before {
+            NaiveDate::parse_from_str(due_before, DATE_FORMAT)?;
+        }
+
+        let limit = page_size(query.limit)?;
+        let sort = query.sort;
+        let after = query
+            .cursor
+            .as_deref()
+            .map(|cursor| Cursor::decode(cursor, sort.as_str()))
+            .transpose()?;
+
+        let study_sessions = self
+            .repo
+            .query_study_sessions_for_subject(user_id, subject_name, query, after, limit)
+            .await?;
+
+        self.study_session_page(user_id, study_sessions, limit, sort, time_zone)
+            .await
+    }
+
+    /// Pending card sessions across all the user's subjects, due today
+    /// unless an other `due_before` date is asked for.
+    pub async fn get_due_flashcards(
+        &self,
+        user_id: i64,
+        mut query: StudySessionQuery,
+        time_zone: Option<Tz>,
+    ) -> StudyServiceResult<Page<StudySessionResponse>> {
+        match &query.due_before {
+            Some(due_before) => {
+                NaiveDate::parse_from_str(due_before, DATE_FORMAT)?;
+            }
+            None => query.due_before = Some(format_date(self.today(user_id, time_zone).await?)),
+        }
+
+        let limit = page_size(query.limit)?;
+        let sort = query.sort;
+        let after = query
+            .cursor
+            .as_deref()
+            .map(|cursor| Cursor::decode(cursor, sort.as_str()))
+            .transpose()?;
+
+        let study_sessions = self
+            .repo
+            .query_flashcard_sessions(user_id, query, after, limit)
+            .await?;
+
+        self.study_session_page(user_id, study_sessions, limit, sort, time_zone)
+            .await
+    }
+
+    /// Page of session responses, with the cards to go through: all the cards
+    /// of the topic, or only its own card for a card session.
+    async fn study_session_page(
+        &self,
+        user_id: i64,
+        study_sessions: Vec<StudySessionInfo>,
+        limit: u32,
+        sort: StudySessionSort,
+        time_zone: Option<Tz>,
+    ) -> StudyServiceResult<Page<StudySessionResponse>> {
+        let page = into_page(study_sessions, limit, |study_session| Cursor {
+            sort: sort.as_str().to_string(),
+            key: sort.key(study_session),
+            id: study_session.id,
+        });
+
+        let study_topic_ids = page
+            .items
+            .iter()
+            .map(|study_session| study_session.study_topic_id)
+            .collect();
+        let mut flashcards: HashMap<i64, Vec<Flashcard>> = HashMap::new();
+        for flashcard in self.repo.get_flashcards(user_id, study_topic_ids).await? {
+            flashcards
+                .entry(flashcard.study_topic_id)
+                .or_default()
+                .push(flashcard);
+        }
+
+        let today = self.today(user_id, time_zone).await?;
+        let mut study_sessions_response = Vec::new();
+
+        for study_session in page.items {
+            let study_session_flashcards = flashcards
+                .get(&study_session.study_topic_id)
+                .into_iter()
+                .flatten()
+                .filter(|flashcard| {
+                    study_session
+                        .flashcard_id
+                        .is_none_or(|flashcard_id| flashcard_id == flashcard.id)
+                })
+                .cloned()
+                .collect();
+            let study_session_response =
+                StudySessionResponse::from(study_session, study_session_flashcards, today)?;
+            study_sessions_response.push(study_session_response);
+        }
+
+        Ok(Page {
+            items: study_sessions_response,
+            next_cursor: page.next_cursor,
+        })
+    }
+
+    pub async fn delete_study_topic(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+    ) -> StudyServiceResult<()> {
+        self.find_editable_study_topic(user_id, study_topic_id)
+            .await?;
+        self.repo
+            .delete_study_topic(user_id, study_topic_id)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Cards of a topic in the order they were added.
+    pub async fn get_flashcards(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+    ) -> StudyServiceResult<Vec<Flashcard>> {
+        if self
+            .repo
+            .get_study_topic(user_id, study_topic_id)
+            .await?
+            .is_none()
+        {
+            return Err(RepositoryError::NotFound(format!("study topic {study_topic_id}")).into());
+        }
+
+        Ok(self
+            .repo
+            .get_flashcards(user_id, vec![study_topic_id])
+            .await?)
+    }
+
+    pub async fn get_flashcard(
+        &self,
+        user_id: i64,
+        flashcard_id: i64,
+    ) -> StudyServiceResult<Flashcard> {
+        self.repo
+            .get_flashcard(user_id, flashcard_id)
+            .await?
+            .ok_or_else(|| RepositoryError::NotFound(format!("flashcard {flashcard_id}")).into())
+    }
+
+    pub async fn add_flashcard(
+        &self,
+        user_id: i64,
+        study_topic_id: i64,
+        flashcard_info: FlashcardInfo,
+    ) -> StudyServiceResult<Flashcard> {
+        let flashcard_info = flashcard_info.normalized();
+        check_flashcard_side("front", Some(&flashcard_info.front))?;
+        check_flashcard_side("back", Some(&flashcard_info.back))?;
+        self.find_editable_study_topic(user_id, study_topic_id)
+            .await?;
+
+        let created_at = self.clock.now().to_rfc3339();
+        let id = self
+            .repo
+            .add_flashcard(
+                user_id,
+                study_topic_id,
+                flashcard_info.clone(),
+                created_at.clone(),
+            )
+            .await?;
+
+        Ok(Flashcard {
+            id,
+            study_topic_id,
+            front: flashcard_info.front,
+            back: flashcard_info.back,
+            hint: flashcard_info.hint,
+            created_at: created_at.clone(),
+            updated_at: created_at,
+        })
+    }
+
+    pub async fn update_flashcard(
+        &self,
+        user_id: i64,
+        flashcard_id: i64,
+        update: FlashcardUpdate,
+    ) -> StudyServiceResult<()> {
+        let update = update.normalized();
+        check_flashcard_side("front", update.front.as_ref())?;
+        check_flashcard_side("back", update.back.as_ref())?;
+        let flashcard = self.get_flashcard(user_id, flashcard_id).await?;
+        self.find_editable_study_topic(user_id, flashcard.study_topic_id)
+            .await?;
+
+        self.repo
+            .update_flashcard(user_id, flashcard_id, update, self.clock.now().to_rfc3339())
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_flashcard(
+        &self,
+        user_id: i64,
+        flashcard_id: i64,
+    ) -> StudyServiceResult<()> {
+        let flashcard = self.get_flashcard(user_id, flashcard_id).await?;
+        self.find_editable_study_topic(user_id, flashcard.study_topic_id)
+            .await?;
+
+        self.repo.delete_flashcard(user_id, flashcard_id).await?;
+
+        Ok(())
+    }
+
+    /// Adds every deck of an Anki package as a subject of the user, with
+    /// its notes as topics and their cards. Cards keep their review state
+    /// and history.
+    pub async fn import_anki_package(
+        &self,
+        user_id: i64,
+        package: Bytes,
+        time_zone: Option<Tz>,
+    ) -> StudyServiceResult<Vec<ImportedSubject>> {
+        let subjects =
+            anki::read_package(package, self.time_zone_for(user_id, time_zone).await?).await?;
+        if subjects.is_empty() {
+            return Err(StudyServiceError::InvalidRequest(
+                "The Anki package holds no cards".to_string(),
+            ));
+        }
+
+        let imported = subjects
+            .iter()
+            .map(|subject| {
+                let flashcards = subject
+                    .study_topics
+                    .iter()
+                    .flat_map(|study_topic| &study_topic.flashcards);
+                ImportedSubject {
+                    subject_name: subject.subject_name.clone(),
+                    study_topics: subject.study_topics.len(),
+                    flashcards: flashcards.clone().count(),
+                    review_logs: flashcards.map(|flashcard| flashcard.reviews.len()).sum(),
+                }
+            })
+            .collect();
+        self.repo.import_subjects(user_id, subjects).await?;
+
+        Ok(imported)
+    }
+
+    /// Anki package of a subject the user can see, with the user's own
+    /// progress and review history.
+    pub async fn export_anki_package(
+        &self,
+        user_id: i64,
+        subject_name: String,
+        time_zone: Option<Tz>,
+    ) -> StudyServiceResult<Vec<u8>> {
+        self.find_subject(user_id, &subject_name).await?;
+
+        let study_topics = self
+            .repo
+            .get_study_topics_for_subject(user_id, subject_name.clone())
+            .await?;
+        let study_topic_ids: Vec<i64> = study_topics
+            .iter()
+            .map(|study_topic| study_topic.id)
+            .collect();
+        let flashcards = self
+            .repo
+            .get_flashcards(user_id, study_topic_ids.clone())
+            .await?;
+        let flashcard_progress = self
+            .repo
+            .get_flashcard_progress(user_id, study_topic_ids)
+            .await?;
+        let review_logs = self
+            .repo
+            .get_review_logs_for_subject(user_id, subject_name.clone())
+            .await?;
+
+        anki::write_package(
+            DeckExport {
+                subject_name,
+                study_topics,
+                flashcards,
+                flashcard_progress,
+                review_logs,
+            },
+            self.clock.now(),
+            self.time_zone_for(user_id, time_zone).await?,
+        )
+        .await
+    }
+
+    /// Page of the topics with a review planned or due today.
+    pub async fn get_study_topics_for_today(
+        &self,
+        user_id: i64,
+        query: StudyTopicQuery,
+        time_zone: Option<Tz>,
+    ) -> StudyServiceResult<Page<StudyTopic>> {
+        self.get_study_topics(
+            user_id,
+            StudyTopicQuery {
+                review_on: Some(format_date(self.today(user_id, time_zone).await?)),
+                ..query
+            },
+        )
+        .await
+    }
+}
+
+fn get_days_since_creation(date: String, today: NaiveDate) -> StudyServiceResult<u32> {
+    let parsed_date = NaiveDate::parse_from_str(&date, DATE_FORMAT)?;
+
+    let days_diff = today.signed_duration_since(parsed_date).num_days();
+
+    Ok(days_diff.max(0) as u32)
+}
+
+fn validate_interval_schedule(
+    interval_schedule_info: &IntervalScheduleInfo,
+) -> StudyServiceResult<()> {
+    if interval_schedule_info.name.trim().is_empty() {
+        return Err(StudyServiceError::InvalidIntervalSchedule(
+            "name must not be empty".to_string(),
+        ));
+    }
+
+    FixedOffsets::validate(
+        &interval_schedule_info.offsets,
+        interval_schedule_info.repeat_every_days,
+    )
+    .map_err(StudyServiceError::InvalidIntervalSchedule)
+}
+
+/// Rejects an empty front or back of a normalised flashcard, `None` is an
+/// update leaving the side unchanged.
+fn check_flashcard_side(side: &str, content: Option<&String>) -> StudyServiceResult<()> {
+    if content.is_some_and(|content| content.is_empty()) {
+        return Err(StudyServiceError::InvalidRequest(format!(
+            "Flashcard {side} must not be empty"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Subjects and interval schedules the scheduler of a topic is built from.
+struct SchedulingContext {
+    subjects: HashMap<String, Subject>,
+    interval_schedules: HashMap<i64, IntervalSchedule>,
+}
+
+impl SchedulingContext {
+    /// Scheduler of the topic's subject (SM-2 if the subject is unknown),
+    /// following the topic's interval schedule, else the subject's one.
+    fn scheduler_for(&self, study_topic: &StudyTopic) -> StudyServiceResult<Box<dyn Scheduler>> {
+        let subject = self.subjects.get(&study_topic.subject_name);
+
+        let intervals = study_topic
+            .interval_schedule_id
+            .or(subject.and_then(|subject| subject.interval_schedule_id))
+            .and_then(|interval_schedule_id| self.interval_schedules.get(&interval_schedule_id))
+            .map_or_else(FixedOffsets::default, FixedOffsets::from);
+
+        match subject {
+            Some(subject) => scheduler_for(subject, intervals),
+            None => Ok(Box::new(Sm2::new(intervals))),
+        }
+    }
+
+    /// Whether the topic's subject schedules its cards one by one.
+    fn schedules_by_card(&self, study_topic: &StudyTopic) -> bool {
+        self.subjects
+            .get(&study_topic.subject_name)
+            .is_some_and(|subject| subject.card_scheduling)
+    }
+}
+
+/// The card scheduled as if it were its topic: from the day it was added,
+/// with its own review state.
+fn flashcard_as_study_topic(
+    study_topic: &StudyTopic,
+    progress: &FlashcardProgress,
+) -> StudyServiceResult<StudyTopic> {
+    let created_at = DateTime::parse_from_rfc3339(&progress.created_at)?;
+
+    Ok(StudyTopic {
+        creation_date: format_date(created_at.date_naive()),
+        last_session_date: progress.last_session_date.clone(),
+        total_sessions: progress.total_sessions,
+        completed_sessions: progress.completed_sessions,
+        ease_factor: progress.ease_factor,
+        interval_days: progress.interval_days,
+        repetitions: progress.repetitions,
+        next_due_date: progress.next_due_date.clone(),
+        stability: progress.stability,
+        difficulty: progress.difficulty,
+        last_review_date: progress.last_review_date.clone(),
+        ..study_topic.clone()
+    })
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct StudySessionResponse {
+    pub id: i64,
+    pub study_topic_id: i64,
+    pub study_topic_name: String,
+    pub subject_name: String,
+    /// Card reviewed on its own in this session, if any.
+    pub flashcard_id: Option<i64>,
+    pub days_passed: u32,
+    /// Whether the review was due on an earlier day than today.
+    pub overdue: bool,
+    /// Review points that were skipped and collapsed into this session.
+    pub missed_reviews: i64,
+    /// Cards to go through in this review: the session's own card, else all
+    /// the cards of the topic.
+    pub flashcards: Vec<Flashcard>,
+}
+
+impl StudySessionResponse {
+    fn from(
+        study_session: StudySessionInfo,
+        flashcards: Vec<Flashcard>,
+        today: NaiveDate,
+    ) -> StudyServiceResult<StudySessionResponse> {
+        let days_passed = get_days_since_creation(study_session.due_date, today)?;
+
+        let study_session_response = StudySessionResponse {
+            id: study_session.id,
+            study_topic_id: study_session.study_topic_id,
+            study_topic_name: study_session.study_topic_name,
+            subject_name: study_session.subject_name,
+            flashcard_id: study_session.flashcard_id,
+            days_passed,
+            overdue: days_passed > 0,
+            missed_reviews: study_session.missed_reviews,
+            flashcards,
+        };
+
+        Ok(study_session_response)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct StudyTopicDetail {
+    pub study_topic: StudyTopic,
+    pub pending_sessions: Vec<StudySession>,
+    /// Due date of the oldest pending session, else of the next planned
+    /// review. `None` when nothing else is planned.
+    pub next_due_date: Option<String>,
+    pub review_history: ReviewHistorySummary,
+}
+
+/// Totals over the review log of a topic.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct ReviewHistorySummary {
+    pub total_reviews: usize,
+    pub again: usize,
+    pub hard: usize,
+    pub good: usize,
+    pub easy: usize,
+    pub total_time_spent_seconds: u64,
+    pub last_review_date: Option<String>,
+    pub last_grade: Option<ReviewGrade>,
+}
+
+impl ReviewHistorySummary {
+    /// Summarises reviews ordered oldest first, as the repository returns
+    /// them.
+    fn from(review_logs: &[ReviewLog]) -> ReviewHistorySummary {
+        let mut summary = ReviewHistorySummary {
+            total_reviews: review_logs.len(),
+            ..Default::default()
+        };
+
+        for review_log in review_logs {
+            match review_log.grade {
+                Some(ReviewGrade::Again) => summary.again += 1,
+                Some(ReviewGrade::Hard) => summary.hard += 1,
+                Some(ReviewGrade::Good) => summary.good += 1,
+                Some(ReviewGrade::Easy) => summary.easy += 1,
+                None => {}
+            }
+            summary.total_time_spent_seconds +=
+                u64::from(review_log.time_spent_seconds.unwrap_or_default());
+        }
+
+        if let Some(last_review) = review_logs.last() {
+            summary.last_review_date = Some(last_review.review_date.clone());
+            summary.last_grade = last_review.grade;
+        }
+
+        summary
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct SubjectDetail {
+    pub subject: Subject,
+    pub topic_count: usize,
+    pub due_sessions: usize,
+    /// Completed over created sessions, `None` before the first session.
+    pub completion_rate: Option<f64>,
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use chrono::{Duration, NaiveDate, TimeZone, Utc};
+    use chrono_tz::{America::Bogota, Tz};
+
+    use crate::{
+        clock::{format_date, FixedClock},
+        domain::{
+            FlashcardInfo, FlashcardUpdate, ImportedSubject, IntervalScheduleInfo, ReviewGrade,
+            SchedulerKind, SearchQuery, StudySessionCompletion, StudySessionQuery, StudyTopicInfo,
+            StudyTopicQuery, StudyTopicUpdate, SubjectMemberInfo, SubjectRole,
+            SubjectSchedulerSettings,
+        },
+        err::{RepositoryError, StudyServiceError},
+        repository::{InMemoryRepository, StudyRepository},
+        study_service::{
+            get_days_since_creation, ReviewHistorySummary, StudyService, StudySessionResponse,
+        },
+    };
+
+    const USER: i64 = 1;
+
+    /// Service over an empty repository holding only the user [`USER`].
+    async fn empty_service(clock: Arc<FixedClock>) -> StudyService {
+        let repo = InMemoryRepository::new();
+        repo.add_user("ada".to_string(), String::new(), String::new())
+            .await
+            .unwrap();
+
+        StudyService::new(Arc::new(repo), clock, Tz::UTC)
+    }
+
+    fn start_date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()
+    }
+
+    async fn service_with_topic(clock: Arc<FixedClock>) -> StudyService {
+        let study_service = empty_service(clock).await;
+
+        study_service
+            .add_subject(USER, "math".to_string())
+            .await
+            .unwrap();
+        study_service
+            .add_study_topic(
+                USER,
+                StudyTopicInfo {
+                    name: "limits".to_string(),
+                    description: None,
+                    subject_name: "math".to_string(),
+                    interval_schedule_id: None,
+                },
+                None,
+            )
+            .await
+            .unwrap();
+
+        study_service
+    }
+
+    fn graded(grade: ReviewGrade) -> StudySessionCompletion {
+        StudySessionCompletion {
+            grade: Some(grade),
+            ..Default::default()
+        }
+    }
+
+    /// Generates the day's sessions and lists them, like the scheduler
+    /// followed by the client opening the app.
+    async fn open_sessions(
+        study_service: &StudyService,
+        time_zone: Option<Tz>,
+    ) -> Vec<StudySessionResponse> {
+        study_service.generate_study_sessions().await.unwrap();
+
+        study_service
+            .get_study_sessions_for_subject(
+                USER,
+                "math".to_string(),
+                StudySessionQuery::default(),
+                time_zone,
+            )
+            .await
+            .unwrap()
+            .items
+    }
+
+    #[test]
+    fn test_get_days_since_creation_today() {
+        let today = start_date();
+        let result = get_days_since_creation(format_date(today), today);
+
+        assert_eq!(result.unwrap(), 0);
+    }
+
+    #[test]
+    fn test_get_days_since_creation_past() {
+        let past_date = "2023-01-01".to_string();
+        let result = get_days_since_creation(past_date, start_date());
+
+        assert_eq!(result.unwrap(), 731);
+    }
+
+    #[tokio::test]
+    async fn creates_one_session_for_new_topic() {
+        let study_service = service_with_topic(Arc::new(FixedClock::at_date(start_date()))).await;
+
+        open_sessions(&study_service, None).await;
+        let study_sessions = open_sessions(&study_service, None).await;
+
+        assert_eq!(study_sessions.len(), 1);
+        assert_eq!(study_sessions[0].study_topic_name, "limits");
+
+        let study_topic = &study_service
+            .get_study_topics(USER, StudyTopicQuery::default())
+            .await
+            .unwrap()
+            .items[0];
+        assert_eq!(study_topic.total_sessions, 1);
+        assert_eq!(study_topic.completed_sessions, 0);
+    }
+
+    #[tokio::test]
+    async fn completing_session_updates_topic() {
+        let study_service = service_with_topic(Arc::new(FixedClock::at_date(start_date()))).await;
+
+        let study_sessions = open_sessions(&study_service, None).await;
+        study_service
+            .complete_study_session(
+                USER,
+                study_sessions[0].id,
+                StudySessionCompletion::default(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        let study_topic = &study_service
+            .get_study_topics(USER, StudyTopicQuery::default())
+            .await
+            .unwrap()
+            .items[0];
+        assert_eq!(study_topic.total_sessions, 1);
+        assert_eq!(study_topic.completed_sessions, 1);
+
+        let study_sessions = open_sessions(&study_service, None).await;
+        assert!(study_sessions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn simulates_review_schedule_over_months() {
+        let clock = Arc::new(FixedClock::at_date(start_date()));
+        let study_service = service_with_topic(clock.clone()).await;
+
+        let mut review_days = Vec::new();
+
+        for day in 0..=200 {
+            let study_sessions = open_sessions(&study_service, None).await;
+
+            for study_session in study_sessions {
+                assert_eq!(study_session.days_passed, 0);
+                study_service
+                    .complete_study_session(
+                        USER,
+                        study_session.id,
+                        StudySessionCompletion::default(),
+                        None,
+                    )
+                    .await
+                    .unwrap();
+                review_days.push(day);
+            }
+
+            clock.advance_days(1);
+        }
+
+        assert_eq!(review_days, vec![0, 1, 3, 7, 21, 30, 45, 60, 120, 180]);
+
+        let study_topic = &study_service
+            .get_study_topics(USER, StudyTopicQuery::default())
+            .await
+            .unwrap()
+            .items[0];
+        assert_eq!(study_topic.creation_date, "2025-01-01");
+        assert_eq!(study_topic.total_sessions, 10);
+        assert_eq!(study_topic.completed_sessions, 10);
+        assert_eq!(study_topic.last_session_date.as_deref(), Some("2025-06-30"));
+    }
+
+    #[tokio::test]
+    async fn sessions_are_generated_for_every_user() {
+        let repo = InMemoryRepository::new();
+        let clock = Arc::new(FixedClock::at_date(start_date()));
+        let study_service = StudyService::new(Arc::new(repo.clone()), clock.clone(), Tz::UTC);
+
+        for username in ["ada", "grace"] {
+            let user_id = repo
+                .add_user(username.to_string(), String::new(), String::new())
+                .await
+                .unwrap();
+            study_service
+                .add_subject(user_id, "math".to_string())
+                .await
+                .unwrap();
+            study_service
+                .add_study_topic(
+                    user_id,
+                    StudyTopicInfo {
+                        name: format!("limits for {username}"),
+                        description: None,
+                        subject_name: "math".to_string(),
+                        interval_schedule_id: None,
+                    },
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+
+        // Each user got the first session of their topic when adding it.
+        assert_eq!(study_service.generate_study_sessions().await.unwrap(), 0);
+        clock.advance_days(1);
+        assert_eq!(study_service.generate_study_sessions().await.unwrap(), 2);
+
+        let sessions = study_service
+            .get_study_sessions_for_subject(
+                2,
+                "math".to_string(),
+                StudySessionQuery::default(),
+                None,
+            )
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].study_topic_name, "limits for grace");
+
+        // Another user's session cannot be completed.
+        assert!(study_service
+            .complete_study_session(1, sessions[0].id, StudySessionCompletion::default(), None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn new_topic_is_due_right_away() {
+        let study_service = service_with_topic(Arc::new(FixedClock::at_date(start_date()))).await;
+
+        // No generation run in between.
+        let sessions = study_service
+            .get_study_sessions_for_subject(
+                USER,
+                "math".to_string(),
+                StudySessionQuery::default(),
+                None,
+            )
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].study_topic_id, 1);
+        assert_eq!(sessions[0].days_passed, 0);
+        assert!(!sessions[0].overdue);
+        assert_eq!(sessions[0].missed_reviews, 0);
+
+        let study_topics = study_service
+            .get_study_topics_for_today(USER, StudyTopicQuery::default(), None)
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(study_topics.len(), 1);
+        assert_eq!(study_topics[0].name, "limits");
+    }
+
+    #[tokio::test]
+    async fn sessions_follow_request_time_zone() {
+        // 23:30 in Bogotá on 2025-01-01 is already 2025-01-02 in UTC.
+        let clock = Arc::new(FixedClock::new(
+            Utc.with_ymd_and_hms(2025, 1, 2, 4, 30, 0).unwrap(),
+        ));
+        let study_service = empty_service(clock.clone()).await;
+
+        study_service
+            .add_subject(USER, "math".to_string())
+            .await
+            .unwrap();
+        study_service
+            .add_study_topic(
+                USER,
+                StudyTopicInfo {
+                    name: "limits".to_string(),
+                    description: None,
+                    subject_name: "math".to_string(),
+                    interval_schedule_id: None,
+                },
+                Some(Bogota),
+            )
+            .await
+            .unwrap();
+
+        let study_topic = &study_service
+            .get_study_topics(USER, StudyTopicQuery::default())
+            .await
+            .unwrap()
+            .items[0];
+        assert_eq!(study_topic.creation_date, "2025-01-01");
+
+        // Day 1 in UTC, but still the creation day in Bogotá.
+        let study_sessions = study_service
+            .get_study_sessions_for_subject(
+                USER,
+                "math".to_string(),
+                StudySessionQuery::default(),
+                Some(Bogota),
+            )
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(study_sessions.len(), 1);
+        assert_eq!(study_sessions[0].days_passed, 0);
+
+        let study_topics_today = study_service
+            .get_study_topics_for_today(USER, StudyTopicQuery::default(), Some(Bogota))
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(study_topics_today.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn sessions_follow_user_time_zone() {
+        // 23:30 in Bogotá on 2025-01-01 is already 2025-01-02 in UTC.
+        let clock = Arc::new(FixedClock::new(
+            Utc.with_ymd_and_hms(2025, 1, 2, 4, 30, 0).unwrap(),
+        ));
+        let repo = InMemoryRepository::new();
+        repo.add_user("ada".to_string(), String::new(), String::new())
+            .await
+            .unwrap();
+        repo.update_user_time_zone(USER, Some("America/Bogota".to_string()))
+            .await
+            .unwrap();
+        let study_service = StudyService::new(Arc::new(repo), clock.clone(), Tz::UTC);
+
+        study_service
+            .add_subject(USER, "math".to_string())
+            .await
+            .unwrap();
+        study_service
+            .add_study_topic(
+                USER,
+                StudyTopicInfo {
+                    name: "limits".to_string(),
+                    description: None,
+                    subject_name: "math".to_string(),
+                    interval_schedule_id: None,
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        let study_session = open_sessions(&study_service, None).await.remove(0);
+        assert_eq!(study_session.days_passed, 0);
+        study_service
+            .complete_study_session(USER, study_session.id, graded(ReviewGrade::Good), None)
+            .await
+            .unwrap();
+
+        // Day 1 is already due in UTC, not yet in Bogotá.
+        assert_eq!(study_service.generate_study_sessions().await.unwrap(), 0);
+
+        clock.advance_days(1);
+        let study_sessions = open_sessions(&study_service, None).await;
+        assert_eq!(study_sessions.len(), 1);
+        assert_eq!(study_sessions[0].days_passed, 0);
+        assert!(!study_sessions[0].overdue);
+
+        // The header still overrides the user's time zone.
+        let study_sessions = study_service
+            .get_study_sessions_for_subject(
+                USER,
+                "math".to_string(),
+                StudySessionQuery::default(),
+                Some(Tz::UTC),
+            )
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(study_sessions[0].days_passed, 1);
+        assert!(study_sessions[0].overdue);
+
+        assert_eq!(study_service.time_zones().await.unwrap(), vec![Bogota]);
+    }
+
+    #[tokio::test]
+    async fn catches_up_missed_review_days() {
+        let clock = Arc::new(FixedClock::at_date(start_date()));
+        let study_service = service_with_topic(clock.clone()).await;
+
+        let study_sessions = open_sessions(&study_service, None).await;
+        assert_eq!(study_sessions.len(), 1);
+        assert!(!study_sessions[0].overdue);
+        study_service
+            .complete_study_session(
+                USER,
+                study_sessions[0].id,
+                StudySessionCompletion::default(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        // Nobody opens the app on days 1, 3 and 7.
+        clock.advance_days(10);
+
+        let study_topics_today = study_service
+            .get_study_topics_for_today(USER, StudyTopicQuery::default(), None)
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(study_topics_today.len(), 1);
+
+        let study_sessions = open_sessions(&study_service, None).await;
+        assert_eq!(study_sessions.len(), 1);
+        assert!(study_sessions[0].overdue);
+        assert_eq!(study_sessions[0].days_passed, 3);
+        assert_eq!(study_sessions[0].missed_reviews, 2);
+
+        study_service
+            .complete_study_session(
+                USER,
+                study_sessions[0].id,
+                StudySessionCompletion::default(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        let study_sessions = open_sessions(&study_service, None).await;
+        assert!(study_sessions.is_empty());
+        assert!(study_service
+            .get_study_topics_for_today(USER, StudyTopicQuery::default(), None)
+            .await
+            .unwrap()
+            .items
+            .is_empty());
+
+        let study_topic = &study_service
+            .get_study_topics(USER, StudyTopicQuery::default())
+            .await
+            .unwrap()
+            .items[0];
+        assert_eq!(study_topic.total_sessions, 2);
+        assert_eq!(study_topic.completed_sessions, 2);
+    }
+
+    #[tokio::test]
+    async fn concurrent_generation_creates_each_session_once() {
+        let clock = Arc::new(FixedClock::at_date(start_date()));
+        let study_service = service_with_topic(clock.clone()).await;
+
+        clock.advance_days(1);
+        let (first, second) = tokio::join!(
+            study_service.generate_study_sessions(),
+            study_service.generate_study_sessions()
+        );
+        assert_eq!(first.unwrap() + second.unwrap(), 1);
+        assert_eq!(study_service.generate_study_sessions().await.unwrap(), 0);
+
+        let study_topic = &study_service
+            .get_study_topics(USER, StudyTopicQuery::default())
+            .await
+            .unwrap()
+            .items[0];
+        assert_eq!(study_topic.total_sessions, 2);
+    }
+
+    #[tokio::test]
+    async fn double_completion_counts_once() {
+        let study_service = service_with_topic(Arc::new(FixedClock::at_date(start_date()))).await;
+
+        let study_session_id = open_sessions(&study_service, None).await[0].id;
+
+        let (first, second) = tokio::join!(
+            study_service.complete_study_session(
+                USER,
+                study_session_id,
+                graded(ReviewGrade::Good),
+                None
+            ),
+            study_service.complete_study_session(
+                USER,
+                study_session_id,
+                graded(ReviewGrade::Good),
+                None
+            )
+        );
+        first.unwrap();
+        second.unwrap();
+
+        let study_topic = &study_service
+            .get_study_topics(USER, StudyTopicQuery::default())
+            .await
+            .unwrap()
+            .items[0];
+        assert_eq!(study_topic.completed_sessions, 1);
+        assert_eq!(study_topic.repetitions, 1);
+        assert_eq!(
+            study_service
+                .get_study_topic_history(USER, study_topic.id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn graded_reviews_follow_sm2_intervals() {
+        let clock = Arc::new(FixedClock::at_date(start_date()));
+        let study_service = service_with_topic(clock.clone()).await;
+
+        let mut review_days = Vec::new();
+
+        for day in 0..=60 {
+            for study_session in open_sessions(&study_service, None).await {
+                let grade = if day == 7 {
+                    ReviewGrade::Again
+                } else {
+                    ReviewGrade::Good
+                };
+
+                study_service
+                    .complete_study_session(USER, study_session.id, graded(grade), None)
+                    .await
+                    .unwrap();
+                review_days.push(day);
+            }
+
+            clock.advance_days(1);
+        }
+
+        // Good on days 0 and 1 (intervals 1, 6), forgotten on day 7 so it
+        // comes back the next day and the intervals start over with a lower
+        // ease factor.
+        assert_eq!(review_days, vec![0, 1, 7, 8, 9, 15, 27, 51]);
+
+        let study_topic = &study_service
+            .get_study_topics(USER, StudyTopicQuery::default())
+            .await
+            .unwrap()
+            .items[0];
+        assert_eq!(study_topic.repetitions, 5);
+        assert!(study_topic.ease_factor < 2.5);
+        assert_eq!(study_topic.next_due_date.as_deref(), Some("2025-04-09"));
+    }
+
+    #[tokio::test]
+    async fn fsrs_subject_schedules_from_stability() {
+        let clock = Arc::new(FixedClock::at_date(start_date()));
+        let study_service = service_with_topic(clock.clone()).await;
+
+        study_service
+            .update_subject_scheduler(
+                USER,
+                "math".to_string(),
+                SubjectSchedulerSettings {
+                    scheduler: SchedulerKind::Fsrs,
+                    desired_retention: Some(0.9),
+                    fsrs_parameters: None,
+                    card_scheduling: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let mut review_days = Vec::new();
+
+        for day in 0..=90 {
+            for study_session in open_sessions(&study_service, None).await {
+                study_service
+                    .complete_study_session(USER, study_session.id, graded(ReviewGrade::Good), None)
+                    .await
+                    .unwrap();
+                review_days.push(day);
+            }
+
+            clock.advance_days(1);
+        }
+
+        // Intervals keep growing as the stability of the memory increases.
+        assert!(review_days.len() >= 3);
+        let intervals: Vec<i64> = review_days.windows(2).map(|w| w[1] - w[0]).collect();
+        assert!(intervals.windows(2).all(|w| w[1] >= w[0]));
+
+        let study_topic = &study_service
+            .get_study_topics(USER, StudyTopicQuery::default())
+            .await
+            .unwrap()
+            .items[0];
+        assert!(study_topic.stability.is_some());
+        assert!(study_topic.difficulty.is_some());
+
+        let weights = study_service
+            .optimize_fsrs_parameters(USER, "math".to_string())
+            .await
+            .unwrap();
+        assert_eq!(weights.len(), 17);
+
+        let subject = &study_service.get_study_subjects(USER).await.unwrap()[0];
+        assert_eq!(subject.scheduler, SchedulerKind::Fsrs);
+        assert_eq!(
+            subject.fsrs_parameters,
+            Some(serde_json::to_string(&weights).unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_scheduler_settings() {
+        let clock = Arc::new(FixedClock::at_date(start_date()));
+        let study_service = service_with_topic(clock).await;
+
+        let invalid_settings = [
+            SubjectSchedulerSettings {
+                scheduler: SchedulerKind::Fsrs,
+                desired_retention: Some(1.5),
+                fsrs_parameters: None,
+                card_scheduling: None,
+            },
+            SubjectSchedulerSettings {
+                scheduler: SchedulerKind::Fsrs,
+                desired_retention: None,
+                fsrs_parameters: Some(vec![1.0; 3]),
+                card_scheduling: None,
+            },
+        ];
+
+        for settings in invalid_settings {
+            let result = study_service
+                .update_subject_scheduler(USER, "math".to_string(), settings)
+                .await;
+            assert!(matches!(
+                result,
+                Err(StudyServiceError::InvalidSchedulerSettings(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn follows_subject_and_topic_interval_schedules() {
+        let clock = Arc::new(FixedClock::at_date(start_date()));
+        let study_service = service_with_topic(clock.clone()).await;
+
+        let exam_cram = study_service
+            .add_interval_schedule(
+                USER,
+                IntervalScheduleInfo {
+                    name: "exam cram".to_string(),
+                    offsets: vec![0, 1, 2, 4, 7],
+                    repeat_every_days: None,
+                },
+            )
+            .await
+            .unwrap();
+        let long_term = study_service
+            .add_interval_schedule(
+                USER,
+                IntervalScheduleInfo {
+                    name: "long term".to_string(),
+                    offsets: vec![1, 7, 30, 90, 180],
+                    repeat_every_days: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        study_service
+            .set_subject_interval_schedule(USER, "math".to_string(), Some(exam_cram.id))
+            .await
+            .unwrap();
+        study_service
+            .add_study_topic(
+                USER,
+                StudyTopicInfo {
+                    name: "series".to_string(),
+                    description: None,
+                    subject_name: "math".to_string(),
+                    interval_schedule_id: Some(long_term.id),
+                },
+                None,
+            )
+            .await
+            .unwrap();
+
+        let mut review_days: Vec<(String, i64)> = Vec::new();
+
+        for day in 0..=30 {
+            for study_session in open_sessions(&study_service, None).await {
+                study_service
+                    .complete_study_session(
+                        USER,
+                        study_session.id,
+                        StudySessionCompletion::default(),
+                        None,
+                    )
+                    .await
+                    .unwrap();
+                review_days.push((study_session.study_topic_name, day));
+            }
+
+            clock.advance_days(1);
+        }
+
+        let days_of = |name: &str| -> Vec<i64> {
+            review_days
+                .iter()
+                .filter(|(study_topic_name, _)| study_topic_name == name)
+                .map(|(_, day)| *day)
+                .collect()
+        };
+
+        assert_eq!(days_of("limits"), vec![0, 1, 2, 4, 7]);
+        assert_eq!(days_of("series"), vec![1, 7, 30]);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_interval_schedules() {
+        let clock = Arc::new(FixedClock::at_date(start_date()));
+        let study_service = service_with_topic(clock).await;
+
+        let result = study_service
+            .add_interval_schedule(
+                USER,
+                IntervalScheduleInfo {
+                    name: "backwards".to_string(),
+                    offsets: vec![7, 3, 1],
+                    repeat_every_days: None,
+                },
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(StudyServiceError::InvalidIntervalSchedule(_))
+        ));
+
+        let result = study_service
+            .set_subject_interval_schedule(USER, "math".to_string(), Some(42))
+            .await;
+        assert!(matches!(
+            result,
+            Err(StudyServiceError::RepositoryError(
+                RepositoryError::NotFound(_)
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn completed_sessions_are_kept_in_review_history() {
+        let clock = Arc::new(FixedClock::at_date(start_date()));
+        let study_service = service_with_topic(clock.clone()).await;
+
+        let study_sessions = open_sessions(&study_service, None).await;
+        study_service
+            .complete_study_session(USER, study_sessions[0].id, graded(ReviewGrade::Good), None)
+            .await
+            .unwrap();
+
+        // The review due the next day is done two days late.
+        clock.advance_days(3);
+
+        let study_sessions = open_sessions(&study_service, None).await;
+        let late_session_id = study_sessions[0].id;
+        let completion = StudySessionCompletion {
+            grade: None,
+            time_spent_seconds: Some(300),
+        };
+        study_service
+            .complete_study_session(USER, late_session_id, completion.clone(), None)
+            .await
+            .unwrap();
+        // Completing it again must not log a second review.
+        study_service
+            .complete_study_session(USER, late_session_id, completion, None)
+            .await
+            .unwrap();
+
+        let study_topic = &study_service
+            .get_study_topics(USER, StudyTopicQuery::default())
+            .await
+            .unwrap()
+            .items[0];
+        let history = study_service
+            .get_study_topic_history(USER, study_topic.id)
+            .await
+            .unwrap();
+
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].grade, Some(ReviewGrade::Good));
+        assert_eq!(history[0].review_date, "2025-01-01");
+
+        assert_eq!(history[1].study_session_id, Some(late_session_id));
+        assert_eq!(history[1].scheduled_date.as_deref(), Some("2025-01-02"));
+        assert_eq!(history[1].review_date, "2025-01-04");
+        assert_eq!(history[1].grade, None);
+        assert_eq!(history[1].time_spent_seconds, Some(300));
+        assert!(history[1].completed_at.is_some());
+
+        assert_eq!(study_topic.completed_sessions, 2);
+        assert!(open_sessions(&study_service, None).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn missing_rows_are_reported_as_not_found() {
+        let study_service = service_with_topic(Arc::new(FixedClock::at_date(start_date()))).await;
+
+        let not_found = |result: Result<(), StudyServiceError>| {
+            matches!(
+                result,
+                Err(StudyServiceError::RepositoryError(
+                    RepositoryError::NotFound(_)
+                ))
+            )
+        };
+
+        assert!(not_found(
+            study_service
+                .complete_study_session(USER, 42, StudySessionCompletion::default(), None)
+                .await
+        ));
+        assert!(not_found(study_service.delete_study_topic(USER, 42).await));
+        assert!(not_found(
+            study_service
+                .delete_subject(USER, "physics".to_string())
+                .await
+        ));
+        assert!(not_found(
+            study_service
+                .get_study_topics_for_subject(
+                    USER,
+                    "physics".to_string(),
+                    StudyTopicQuery::default()
+                )
+                .await
+                .map(|_| ())
+        ));
+        assert!(not_found(
+            study_service
+                .get_study_topic_history(USER, 42)
+                .await
+                .map(|_| ())
+        ));
+    }
+
+    #[tokio::test]
+    async fn moved_and_renamed_topics_keep_their_progress() {
+        let study_service = service_with_topic(Arc::new(FixedClock::at_date(start_date()))).await;
+
+        let study_session_id = open_sessions(&study_service, None).await[0].id;
+        study_service
+            .complete_study_session(USER, study_session_id, graded(ReviewGrade::Good), None)
+            .await
+            .unwrap();
+
+        study_service
+            .add_subject(USER, "physics".to_string())
+            .await
+            .unwrap();
+        study_service
+            .update_study_topic(
+                USER,
+                1,
+                StudyTopicUpdate {
+                    name: Some("kinematics".to_string()),
+                    subject_name: Some("physics".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        study_service
+            .rename_subject(USER, "physics".to_string(), "mechanics".to_string())
+            .await
+            .unwrap();
+
+        let study_topics = study_service
+            .get_study_topics_for_subject(USER, "mechanics".to_string(), StudyTopicQuery::default())
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(study_topics.len(), 1);
+        assert_eq!(study_topics[0].name, "kinematics");
+        assert_eq!(study_topics[0].repetitions, 1);
+        assert_eq!(
+            study_service
+                .get_study_topic_history(USER, study_topics[0].id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        assert!(matches!(
+            study_service
+                .update_study_topic(
+                    USER,
+                    1,
+                    StudyTopicUpdate {
+                        name: Some(" ".to_string()),
+                        ..Default::default()
+                    },
+                )
+                .await,
+            Err(StudyServiceError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            study_service
+                .rename_subject(USER, "mechanics".to_string(), "math".to_string())
+                .await,
+            Err(StudyServiceError::RepositoryError(
+                RepositoryError::Conflict(_)
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn study_topic_and_subject_details() {
+        let clock = Arc::new(FixedClock::at_date(start_date()));
+        let study_service = service_with_topic(clock.clone()).await;
+
+        let detail = study_service
+            .get_study_topic_detail(USER, 1, None)
+            .await
+            .unwrap();
+        assert_eq!(detail.pending_sessions.len(), 1);
+        assert_eq!(detail.next_due_date.as_deref(), Some("2025-01-01"));
+        assert_eq!(detail.review_history, ReviewHistorySummary::default());
+
+        let study_session_id = detail.pending_sessions[0].id;
+
+        let subject = study_service
+            .get_subject_detail(USER, "math".to_string(), None)
+            .await
+            .unwrap();
+        assert_eq!(subject.topic_count, 1);
+        assert_eq!(subject.due_sessions, 1);
+        assert_eq!(subject.completion_rate, Some(0.0));
+
+        study_service
+            .complete_study_session(
+                USER,
+                study_session_id,
+                StudySessionCompletion {
+                    grade: Some(ReviewGrade::Good),
+                    time_spent_seconds: Some(120),
+                },
+                None,
+            )
+            .await
+            .unwrap();
+
+        let detail = study_service
+            .get_study_topic_detail(USER, 1, None)
+            .await
+            .unwrap();
+        assert!(detail.pending_sessions.is_empty());
+        assert_eq!(detail.next_due_date.as_deref(), Some("2025-01-02"));
+        assert_eq!(detail.review_history.total_reviews, 1);
+        assert_eq!(detail.review_history.good, 1);
+        assert_eq!(detail.review_history.total_time_spent_seconds, 120);
+        assert_eq!(detail.review_history.last_grade, Some(ReviewGrade::Good));
+
+        let subject = study_service
+            .get_subject_detail(USER, "math".to_string(), None)
+            .await
+            .unwrap();
+        assert_eq!(subject.due_sessions, 0);
+        assert_eq!(subject.completion_rate, Some(1.0));
+
+        assert!(matches!(
+            study_service.get_study_topic_detail(USER, 42, None).await,
+            Err(StudyServiceError::RepositoryError(
+                RepositoryError::NotFound(_)
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn searches_topics_by_words() {
+        let study_service = service_with_topic(Arc::new(FixedClock::at_date(start_date()))).await;
+
+        let search_results = study_service
+            .search_study_topics(
+                USER,
+                SearchQuery {
+                    q: "  lim ".to_string(),
+                    limit: None,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(search_results.len(), 1);
+        assert_eq!(search_results[0].name_highlight, "<mark>limits</mark>");
+
+        assert!(matches!(
+            study_service
+                .search_study_topics(
+                    USER,
+                    SearchQuery {
+                        q: " * - ".to_string(),
+                        limit: None,
+                    }
+                )
+                .await,
+            Err(StudyServiceError::InvalidRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn shared_subjects_follow_member_roles() {
+        let repo = InMemoryRepository::new();
+        let clock = Arc::new(FixedClock::at_date(start_date()));
+        let study_service = StudyService::new(Arc::new(repo.clone()), clock, Tz::UTC);
+
+        let mut user_ids = Vec::new();
+        for username in ["ada", "grace", "alan"] {
+            user_ids.push(
+                repo.add_user(username.to_string(), String::new(), String::new())
+                    .await
+                    .unwrap(),
+            );
+        }
+        let [ada, grace, alan] = user_ids[..] else {
+            unreachable!()
+        };
+
+        study_service
+            .add_subject(ada, "math".to_string())
+            .await
+            .unwrap();
+        let study_topic_info = |name: &str| StudyTopicInfo {
+            name: name.to_string(),
+            description: None,
+            subject_name: "math".to_string(),
+            interval_schedule_id: None,
+        };
+        study_service
+            .add_study_topic(ada, study_topic_info("limits"), None)
+            .await
+            .unwrap();
+        let limits = study_service
+            .get_study_topics(ada, StudyTopicQuery::default())
+            .await
+            .unwrap()
+            .items
+            .remove(0);
+
+        for (username, role) in [
+            ("grace", SubjectRole::Editor),
+            ("alan", SubjectRole::Viewer),
+        ] {
+            study_service
+                .invite_subject_member(
+                    ada,
+                    "math".to_string(),
+                    SubjectMemberInfo {
+                        username: username.to_string(),
+                        role,
+                    },
+                )
+                .await
+                .unwrap();
+        }
+
+        // Invitations only share the subject once accepted.
+        assert!(study_service
+            .get_study_subjects(grace)
+            .await
+            .unwrap()
+            .is_empty());
+        for user_id in [grace, alan] {
+            let invitations = study_service
+                .get_subject_invitations(user_id)
+                .await
+                .unwrap();
+            assert_eq!(invitations.len(), 1);
+            assert_eq!(invitations[0].owner_username, "ada");
+            study_service
+                .accept_subject_invitation(user_id, invitations[0].id)
+                .await
+                .unwrap();
+        }
+
+        // Nor can a member take the name for a subject of their own.
+        study_service
+            .add_subject(grace, "notes".to_string())
+            .await
+            .unwrap();
+        assert!(matches!(
+            study_service
+                .rename_subject(grace, "notes".to_string(), "math".to_string())
+                .await,
+            Err(StudyServiceError::RepositoryError(
+                RepositoryError::Conflict(_)
+            ))
+        ));
+
+        let subject_members = study_service
+            .get_subject_members(alan, "math".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            subject_members
+                .iter()
+                .map(|member| (member.username.as_str(), member.role))
+                .collect::<Vec<_>>(),
+            vec![
+                ("ada", SubjectRole::Owner),
+                ("grace", SubjectRole::Editor),
+                ("alan", SubjectRole::Viewer)
+            ]
+        );
+
+        // Editors change topics, viewers only study them.
+        study_service
+            .add_study_topic(grace, study_topic_info("derivatives"), None)
+            .await
+            .unwrap();
+        assert!(matches!(
+            study_service
+                .add_study_topic(alan, study_topic_info("integrals"), None)
+                .await,
+            Err(StudyServiceError::Forbidden(_))
+        ));
+        assert!(matches!(
+            study_service.delete_study_topic(alan, limits.id).await,
+            Err(StudyServiceError::Forbidden(_))
+        ));
+        assert!(matches!(
+            study_service
+                .rename_subject(grace, "math".to_string(), "calculus".to_string())
+                .await,
+            Err(StudyServiceError::Forbidden(_))
+        ));
+
+        // Every member studies on their own sessions and progress. Whoever
+        // adds a topic gets its first session right away, the others on the
+        // next run.
+        assert_eq!(study_service.generate_study_sessions().await.unwrap(), 4);
+        let sessions = study_service
+            .get_study_sessions_for_subject(
+                alan,
+                "math".to_string(),
+                StudySessionQuery::default(),
+                None,
+            )
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(sessions.len(), 2);
+        assert!(study_service
+            .complete_study_session(ada, sessions[0].id, StudySessionCompletion::default(), None)
+            .await
+            .is_err());
+        study_service
+            .complete_study_session(
+                alan,
+                sessions[0].id,
+                StudySessionCompletion::default(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        for (user_id, completed_sessions) in [(alan, 1), (ada, 0)] {
+            let study_topics = study_service
+                .get_study_topics(user_id, StudyTopicQuery::default())
+                .await
+                .unwrap()
+                .items;
+            assert_eq!(
+                study_topics
+                    .iter()
+                    .map(|study_topic| study_topic.completed_sessions)
+                    .sum::<i64>(),
+                completed_sessions
+            );
+        }
+
+        // Members can leave, removing the subject and their progress.
+        assert!(matches!(
+            study_service
+                .remove_subject_member(grace, "math".to_string(), alan)
+                .await,
+            Err(StudyServiceError::Forbidden(_))
+        ));
+        study_service
+            .remove_subject_member(alan, "math".to_string(), alan)
+            .await
+            .unwrap();
+        assert!(study_service
+            .get_study_subjects(alan)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            study_service
+                .get_study_topics(grace, StudyTopicQuery::default())
+                .await
+                .unwrap()
+                .items
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn sessions_come_with_the_cards_of_their_topic() {
+        let study_service = service_with_topic(Arc::new(FixedClock::at_date(start_date()))).await;
+
+        let flashcard = study_service
+            .add_flashcard(
+                USER,
+                1,
+                FlashcardInfo {
+                    front: "What is $\\lim_{x \\to 0} \\frac{\\sin x}{x}$?".to_string(),
+                    back: "**1**".to_string(),
+                    hint: Some("Squeeze theorem".to_string()),
+                },
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            study_service
+                .add_flashcard(
+                    USER,
+                    1,
+                    FlashcardInfo {
+                        front: " ".to_string(),
+                        back: "1".to_string(),
+                        hint: None,
+                    },
+                )
+                .await,
+            Err(StudyServiceError::InvalidRequest(_))
+        ));
+
+        study_service
+            .update_flashcard(
+                USER,
+                flashcard.id,
+                FlashcardUpdate {
+                    hint: Some(None),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let study_sessions = open_sessions(&study_service, None).await;
+        assert_eq!(study_sessions.len(), 1);
+        assert_eq!(
+            study_sessions[0].flashcards,
+            vec![study_service
+                .get_flashcard(USER, flashcard.id)
+                .await
+                .unwrap()]
+        );
+        assert_eq!(study_sessions[0].flashcards[0].back, "**1**");
+        assert!(study_sessions[0].flashcards[0].hint.is_none());
+
+        study_service.delete_study_topic(USER, 1).await.unwrap();
+        assert!(matches!(
+            study_service.get_flashcard(USER, flashcard.id).await,
+            Err(StudyServiceError::RepositoryError(
+                RepositoryError::NotFound(_)
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn flashcards_store_normalized_markdown() {
+        let study_service = service_with_topic(Arc::new(FixedClock::at_date(start_date()))).await;
+
+        let flashcard = study_service
+            .add_flashcard(
+                USER,
+                1,
+                FlashcardInfo {
+                    front: "\r\n\r\n    let x = 1;\r\nx  \r\n".to_string(),
+                    back: " 1 ".to_string(),
+                    hint: Some(" \n ".to_string()),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(flashcard.front, "    let x = 1;\nx");
+        assert_eq!(flashcard.back, " 1");
+        assert!(flashcard.hint.is_none());
+
+        study_service
+            .update_flashcard(
+                USER,
+                flashcard.id,
+                FlashcardUpdate {
+                    back: Some("- one\r- two\r".to_string()),
+                    hint: Some(Some("\tSqueeze\n".to_string())),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let flashcard = study_service
+            .get_flashcard(USER, flashcard.id)
+            .await
+            .unwrap();
+        assert_eq!(flashcard.back, "- one\n- two");
+        assert_eq!(flashcard.hint.as_deref(), Some("\tSqueeze"));
+
+        assert!(matches!(
+            study_service
+                .update_flashcard(
+                    USER,
+                    flashcard.id,
+                    FlashcardUpdate {
+                        back: Some("\r\n".to_string()),
+                        ..Default::default()
+                    },
+                )
+                .await,
+            Err(StudyServiceError::InvalidRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn cards_are_scheduled_one_by_one() {
+        let clock = Arc::new(FixedClock::at_date(start_date()));
+        let study_service = service_with_topic(clock.clone()).await;
+
+        // The topic's first review is created with it, before it has cards.
+        let study_sessions = open_sessions(&study_service, None).await;
+        assert_eq!(study_sessions.len(), 1);
+        assert_eq!(study_sessions[0].flashcard_id, None);
+        study_service
+            .complete_study_session(USER, study_sessions[0].id, graded(ReviewGrade::Good), None)
+            .await
+            .unwrap();
+
+        study_service
+            .update_subject_scheduler(
+                USER,
+                "math".to_string(),
+                SubjectSchedulerSettings {
+                    scheduler: SchedulerKind::Sm2,
+                    desired_retention: None,
+                    fsrs_parameters: None,
+                    card_scheduling: Some(true),
+                },
+            )
+            .await
+            .unwrap();
+        let mut flashcard_ids = Vec::new();
+        for front in ["sin", "cos"] {
+            let flashcard = study_service
+                .add_flashcard(
+                    USER,
+                    1,
+                    FlashcardInfo {
+                        front: format!("Derivative of {front}?"),
+                        back: "...".to_string(),
+                        hint: None,
+                    },
+                )
+                .await
+                .unwrap();
+            flashcard_ids.push(flashcard.id);
+        }
+
+        // Every card gets a session of its own, carrying only that card.
+        let study_sessions = open_sessions(&study_service, None).await;
+        assert_eq!(study_sessions.len(), 2);
+        for (study_session, flashcard_id) in study_sessions.iter().zip(&flashcard_ids) {
+            assert_eq!(study_session.flashcard_id, Some(*flashcard_id));
+            assert_eq!(study_session.flashcards.len(), 1);
+            assert_eq!(study_session.flashcards[0].id, *flashcard_id);
+        }
+
+        let due_flashcards = study_service
+            .get_due_flashcards(USER, StudySessionQuery::default(), None)
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(due_flashcards.len(), 2);
+        assert!(due_flashcards
+            .iter()
+            .all(|study_session| study_session.subject_name == "math"));
+
+        for (study_session, grade) in study_sessions
+            .iter()
+            .zip([ReviewGrade::Good, ReviewGrade::Again])
+        {
+            study_service
+                .complete_study_session(USER, study_session.id, graded(grade), None)
+                .await
+                .unwrap();
+        }
+
+        let study_topic = study_service
+            .get_study_topic_detail(USER, 1, None)
+            .await
+            .unwrap()
+            .study_topic;
+        assert_eq!(study_topic.total_sessions, 3);
+        assert_eq!(study_topic.completed_sessions, 3);
+
+        clock.advance_days(1);
+        for study_session in open_sessions(&study_service, None).await {
+            study_service
+                .complete_study_session(USER, study_session.id, graded(ReviewGrade::Good), None)
+                .await
+                .unwrap();
+        }
+
+        // The forgotten card comes back sooner than the remembered one.
+        let detail = study_service
+            .get_study_topic_detail(USER, 1, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            detail.next_due_date,
+            Some(format_date(start_date() + Duration::days(2)))
+        );
+
+        clock.advance_days(1);
+        let study_sessions = open_sessions(&study_service, None).await;
+        assert_eq!(study_sessions.len(), 1);
+        assert_eq!(study_sessions[0].flashcard_id, Some(flashcard_ids[1]));
+    }
+
+    #[tokio::test]
+    async fn anki_export_imports_back() {
+        let clock = Arc::new(FixedClock::at_date(start_date()));
+        let study_service = service_with_topic(clock.clone()).await;
+
+        let study_session_id = open_sessions(&study_service, None).await[0].id;
+        study_service
+            .complete_study_session(USER, study_session_id, graded(ReviewGrade::Good), None)
+            .await
+            .unwrap();
+        study_service
+            .update_subject_scheduler(
+                USER,
+                "math".to_string(),
+                SubjectSchedulerSettings {
+                    scheduler: SchedulerKind::Sm2,
+                    desired_retention: None,
+                    fsrs_parameters: None,
+                    card_scheduling: Some(true),
+                },
+            )
+            .await
+            .unwrap();
+        for (front, hint) in [("sin", None), ("cos", Some("Mind the sign"))] {
+            study_service
+                .add_flashcard(
+                    USER,
+                    1,
+                    FlashcardInfo {
+                        front: format!("Derivative of {front}?"),
+                        back: "...".to_string(),
+                        hint: hint.map(str::to_string),
+                    },
+                )
+                .await
+                .unwrap();
+        }
+        study_service
+            .add_study_topic(
+                USER,
+                StudyTopicInfo {
+                    name: "series".to_string(),
+                    description: Some("Convergence <tests>\nand sums".to_string()),
+                    subject_name: "math".to_string(),
+                    interval_schedule_id: None,
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        for (study_session, grade) in open_sessions(&study_service, None)
+            .await
+            .iter()
+            .filter(|study_session| study_session.flashcard_id.is_some())
+            .zip([ReviewGrade::Good, ReviewGrade::Again])
+        {
+            study_service
+                .complete_study_session(USER, study_session.id, graded(grade), None)
+                .await
+                .unwrap();
+        }
+        let exported_detail = study_service
+            .get_study_topic_detail(USER, 1, None)
+            .await
+            .unwrap();
+
+        let package = Bytes::from(
+            study_service
+                .export_anki_package(USER, "math".to_string(), None)
+                .await
+                .unwrap(),
+        );
+
+        let other_service = empty_service(clock).await;
+        let imported = other_service
+            .import_anki_package(USER, package.clone(), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            imported,
+            vec![ImportedSubject {
+                subject_name: "math".to_string(),
+                study_topics: 2,
+                flashcards: 2,
+                review_logs: 2,
+            }]
+        );
+
+        let subjects = other_service.get_study_subjects(USER).await.unwrap();
+        assert!(subjects[0].card_scheduling);
+        let study_topics = other_service
+            .get_study_topics_for_subject(USER, "math".to_string(), StudyTopicQuery::default())
+            .await
+            .unwrap()
+            .items;
+        let limits = study_topics
+            .iter()
+            .find(|study_topic| study_topic.name == "limits")
+            .unwrap();
+        let series = study_topics
+            .iter()
+            .find(|study_topic| study_topic.name == "series")
+            .unwrap();
+        assert_eq!(
+            series.description.as_deref(),
+            Some("Convergence <tests>\nand sums")
+        );
+
+        let flashcards = other_service.get_flashcards(USER, limits.id).await.unwrap();
+        assert_eq!(flashcards.len(), 2);
+        assert_eq!(flashcards[1].front, "Derivative of cos?");
+        assert_eq!(flashcards[1].hint.as_deref(), Some("Mind the sign"));
+
+        // Both cards keep their history and when they are due.
+        let imported_detail = other_service
+            .get_study_topic_detail(USER, limits.id, None)
+            .await
+            .unwrap();
+        assert_eq!(imported_detail.next_due_date, exported_detail.next_due_date);
+        assert_eq!(imported_detail.review_history.good, 1);
+        assert_eq!(imported_detail.review_history.again, 1);
+
+        // Importing the same deck twice would duplicate the subject.
+        let err = other_service
+            .import_anki_package(USER, package, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            StudyServiceError::RepositoryError(RepositoryError::Conflict(_))
+        ));
     }
 }